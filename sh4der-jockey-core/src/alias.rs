@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde_yaml::Value;
+
+lazy_static! {
+    static ref ALIAS_RE: Regex = Regex::new(r"\$\{([^}]*)\}").unwrap();
+}
+
+/// Replaces all `${NAME}` in a string with the path of that alias.
+pub fn expand_aliases(text: &str, aliases: &HashMap<String, String>) -> Result<String, String> {
+    let mut unknown = None;
+    let out = ALIAS_RE.replace_all(text, |caps: &Captures| match aliases.get(&caps[1]) {
+        Some(path) => path.clone(),
+        None => {
+            unknown.get_or_insert_with(|| caps[1].to_owned());
+            String::new()
+        }
+    });
+
+    match unknown {
+        Some(name) => Err(format!(
+            "Unknown path alias {:?} in {:?}, add it to \"paths\" in config.yaml",
+            name, text
+        )),
+        None => Ok(out.into_owned()),
+    }
+}
+
+/// Expands aliases in all strings of a yaml document, keys are left as is.
+pub fn expand_yaml_aliases(
+    value: &mut Value,
+    aliases: &HashMap<String, String>,
+) -> Result<(), String> {
+    match value {
+        Value::String(s) if s.contains("${") => *s = expand_aliases(s, aliases)?,
+        Value::Sequence(seq) => {
+            for item in seq {
+                expand_yaml_aliases(item, aliases)?;
+            }
+        }
+        Value::Mapping(map) => {
+            for (_, item) in map.iter_mut() {
+                expand_yaml_aliases(item, aliases)?;
+            }
+        }
+        Value::Tagged(tagged) => expand_yaml_aliases(&mut tagged.value, aliases)?,
+        _ => (),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expands_paths() {
+        let aliases = HashMap::from([("ASSETS".to_owned(), "/mnt/ssd/assets".to_owned())]);
+        let mut object: Value =
+            serde_yaml::from_str("images: [{path: \"${ASSETS}/tex.png\", name: tex}]").unwrap();
+
+        expand_yaml_aliases(&mut object, &aliases).unwrap();
+        assert_eq!(
+            object["images"][0]["path"].as_str(),
+            Some("/mnt/ssd/assets/tex.png")
+        );
+        assert!(expand_aliases("${VIDEOS}/a.png", &aliases).is_err());
+    }
+}
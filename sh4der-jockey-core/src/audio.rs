@@ -1,16 +1,22 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
+use anyhow::{bail, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use num_complex::Complex;
 use rustfft::{Fft, FftPlanner};
 
-use super::Config;
-use crate::util::RingBuffer;
+use crate::{util::RingBuffer, BeatHistory, JackConfig, JackInput, SpectralFeatures};
 
 pub const AUDIO_SAMPLES: usize = 512;
 pub const FFT_ATTACK: f32 = 0.5;
 pub const FFT_DECAY: f32 = 0.5;
 
+/// Longest delay of the audio analysis in milliseconds
+pub const MAX_AUDIO_LATENCY: f32 = 2000.0;
+
 pub enum Channels {
     None,
     Mono,
@@ -45,20 +51,62 @@ pub struct Audio {
     pub bass_smooth_integrated: [f32; 3],
     pub mid_smooth_integrated: [f32; 3],
     pub high_smooth_integrated: [f32; 3],
+    /// Features of the spectrum of both channels combined.
+    pub features: SpectralFeatures,
+    /// Band energies of the last few beats.
+    pub history: BeatHistory,
     l_fft: Vec<Complex<f32>>,
     r_fft: Vec<Complex<f32>>,
     l_samples: Arc<Mutex<RingBuffer<f32>>>,
     r_samples: Arc<Mutex<RingBuffer<f32>>>,
     stream: Option<cpal::Stream>,
+    jack: Option<JackInput>,
+    offline: bool,
     channels: Channels,
     sample_freq: usize,
+    latency: f32,
     pub attack: f32,
     pub decay: f32,
     fft: Arc<dyn Fft<f32>>,
 }
 
 impl Audio {
-    pub fn new(window_size: usize, config: &Config) -> Self {
+    /// Creates the analyzer and connects it to the first input device whose
+    /// name contains `device`, or to the default input device.
+    pub fn new(window_size: usize, device: Option<&str>) -> Self {
+        let mut this = Self::with_size(window_size);
+
+        if let Err(err) = this.connect(device) {
+            log::error!("Error connecting to audio input device: {}", err);
+        }
+
+        this
+    }
+
+    /// Creates the analyzer and records the JACK ports set in `config`,
+    /// which works with PipeWire as well.
+    pub fn with_jack(window_size: usize, config: &JackConfig) -> Self {
+        let mut this = Self::with_size(window_size);
+
+        if let Err(err) = this.connect_jack(config) {
+            log::error!("Error connecting to JACK: {}", err);
+        }
+
+        this
+    }
+
+    /// Creates an audio module which is not connected to any input device.
+    ///
+    /// Samples have to be pushed manually using [`Audio::feed`].
+    pub fn offline(window_size: usize, sample_freq: usize) -> Self {
+        let mut this = Self::with_size(window_size);
+        this.offline = true;
+        this.channels = Channels::Stereo;
+        this.sample_freq = sample_freq;
+        this
+    }
+
+    fn with_size(window_size: usize) -> Self {
         let size = window_size;
         let spec_size = size / 2;
         let bands = 100;
@@ -66,7 +114,7 @@ impl Audio {
         let mut planner = FftPlanner::<f32>::new();
         let fft = planner.plan_fft_forward(size);
 
-        let mut this = Self {
+        Self {
             size,
             l_signal: vec![0.0; size],
             r_signal: vec![0.0; size],
@@ -86,6 +134,8 @@ impl Audio {
             bass_smooth_integrated: [0.0; 3],
             mid_smooth_integrated: [0.0; 3],
             high_smooth_integrated: [0.0; 3],
+            features: SpectralFeatures::default(),
+            history: BeatHistory::default(),
             l_raw_spectrum: vec![0.0; spec_size],
             r_raw_spectrum: vec![0.0; spec_size],
             l_spectrum: vec![0.0; bands],
@@ -99,18 +149,15 @@ impl Audio {
             l_samples: Arc::new(Mutex::new(RingBuffer::new(size))),
             r_samples: Arc::new(Mutex::new(RingBuffer::new(size))),
             stream: None,
+            jack: None,
+            offline: false,
             channels: Channels::None,
             fft,
             attack: 0.5,
             decay: 0.5,
             sample_freq: 0,
-        };
-
-        if let Err(err) = this.connect(config) {
-            log::error!("Error connecting to audio input device: {}", err);
+            latency: 0.0,
         }
-
-        this
     }
 
     pub fn resize(&mut self, new_size: usize) {
@@ -126,14 +173,35 @@ impl Audio {
         self.r_fft = vec![Complex::new(0.0, 0.0); new_size];
         self.l_raw_spectrum = vec![0.0; spec_size];
         self.r_raw_spectrum = vec![0.0; spec_size];
-        *self.l_samples.lock().unwrap() = RingBuffer::new(new_size);
-        *self.r_samples.lock().unwrap() = RingBuffer::new(new_size);
+        *self.l_samples.lock().unwrap() = RingBuffer::new(new_size + self.delay());
+        *self.r_samples.lock().unwrap() = RingBuffer::new(new_size + self.delay());
     }
 
-    pub fn connect(&mut self, config: &Config) -> Result<(), String> {
+    /// Delays the analysis by `latency` milliseconds, so reactions line up
+    /// with the sound of a PA which lags behind the audio input.
+    ///
+    /// Has to be set again when a different input is connected.
+    pub fn set_latency(&mut self, latency: f32) {
+        self.latency = latency.clamp(0.0, MAX_AUDIO_LATENCY);
+        let len = self.size + self.delay();
+        self.l_samples.lock().unwrap().resize(len);
+        self.r_samples.lock().unwrap().resize(len);
+    }
+
+    /// Delay of the analysis in milliseconds.
+    pub fn latency(&self) -> f32 {
+        self.latency
+    }
+
+    /// Delay of the analysis in samples.
+    fn delay(&self) -> usize {
+        (self.latency / 1000.0 * self.sample_freq as f32).round() as _
+    }
+
+    pub fn connect(&mut self, device: Option<&str>) -> Result<(), String> {
         let host = cpal::default_host();
         log::info!("Available Hosts: {:?}", cpal::available_hosts());
-        let device = match &config.audio_device {
+        let device = match device {
             None => host
                 .default_input_device()
                 .ok_or("No input device is available".to_string()),
@@ -223,26 +291,50 @@ impl Audio {
         Ok(())
     }
 
+    pub fn connect_jack(&mut self, config: &JackConfig) -> Result<(), String> {
+        let input = JackInput::connect(config, self.l_samples.clone(), self.r_samples.clone())?;
+
+        self.channels = match input.sources.len() {
+            1 => Channels::Mono,
+            _ => Channels::Stereo,
+        };
+        self.sample_freq = input.sample_rate as _;
+        self.jack = Some(input);
+        Ok(())
+    }
+
+    fn is_active(&self) -> bool {
+        self.stream.is_some() || self.jack.is_some() || self.offline
+    }
+
+    /// Pushes samples into the analysis window of an offline audio module.
+    pub fn feed(&mut self, left: &[f32], right: &[f32]) {
+        debug_assert!(self.offline);
+
+        self.l_samples.lock().unwrap().push_slice(left);
+        self.r_samples.lock().unwrap().push_slice(right);
+    }
+
     pub fn update_samples(&mut self) {
-        if self.stream.is_none() {
+        if !self.is_active() {
             return;
         }
 
+        let delay = self.delay();
         let l_samples_p = Arc::clone(&self.l_samples);
         let l_samples = l_samples_p.lock().unwrap();
-        l_samples.copy_to_slice(&mut self.l_signal);
+        l_samples.copy_delayed(&mut self.l_signal, delay);
 
         // calculate volume with RMS
         self.volume[1] =
-            (self.l_signal.iter().map(|&x| x.powi(2)).sum::<f32>() / l_samples.size as f32).sqrt();
+            (self.l_signal.iter().map(|&x| x.powi(2)).sum::<f32>() / self.size as f32).sqrt();
 
         if let Channels::Stereo = self.channels {
             let r_samples_p = self.r_samples.clone();
             let r_samples = r_samples_p.lock().unwrap();
-            r_samples.copy_to_slice(&mut self.r_signal);
-            self.volume[2] = (self.r_signal.iter().map(|&x| x.powi(2)).sum::<f32>()
-                / l_samples.size as f32)
-                .sqrt();
+            r_samples.copy_delayed(&mut self.r_signal, delay);
+            self.volume[2] =
+                (self.r_signal.iter().map(|&x| x.powi(2)).sum::<f32>() / self.size as f32).sqrt();
             self.volume[0] = (self.volume[1] + self.volume[2]) / 2.0;
         } else {
             self.volume[0] = self.volume[1];
@@ -255,7 +347,7 @@ impl Audio {
     }
 
     pub fn update_fft(&mut self) {
-        if self.stream.is_none() {
+        if !self.is_active() {
             return;
         }
 
@@ -293,10 +385,21 @@ impl Audio {
         self.update_nice_fft();
         self.update_smooth_fft();
         self.update_bass_mid_high();
+
+        let power: Vec<f32> = match self.channels {
+            Channels::Stereo => self
+                .l_raw_spectrum
+                .iter()
+                .zip(&self.r_raw_spectrum)
+                .map(|(l, r)| l + r)
+                .collect(),
+            _ => self.l_raw_spectrum.clone(),
+        };
+        self.features.update(&power, self.sample_freq);
     }
 
     fn update_nice_fft(&mut self) {
-        if self.stream.is_none() {
+        if !self.is_active() {
             return;
         }
         let n = self.l_raw_spectrum.len() * 2;
@@ -470,6 +573,12 @@ impl Audio {
             .for_each(sum_left);
     }
 
+    /// Adds the current band energies to the beat history.
+    pub fn update_history(&mut self, beat: f32) {
+        let bands = [self.bass[0], self.mid[0], self.high[0], self.volume[0]];
+        self.history.push(beat, bands);
+    }
+
     #[allow(dead_code)]
     pub fn get_samples(&mut self, left: &mut [f32], right: &mut [f32]) {
         self.update_samples();
@@ -481,3 +590,51 @@ impl Audio {
 fn sum_left((acc, val): (&mut f32, &f32)) {
     *acc += val;
 }
+
+/// A decoded audio file, split into left and right channels.
+#[derive(Debug, Clone)]
+pub struct AudioTrack {
+    pub left: Vec<f32>,
+    pub right: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+impl AudioTrack {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        let channels = spec.channels as usize;
+
+        let samples: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .into_samples::<i32>()
+                    .map(|x| x.map(|x| x as f32 * scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+
+        let left: Vec<f32> = match channels {
+            0 => bail!("Audio file does not contain any channels"),
+            _ => samples.iter().step_by(channels).copied().collect(),
+        };
+
+        let right = match channels {
+            1 => left.clone(),
+            _ => samples.iter().skip(1).step_by(channels).copied().collect(),
+        };
+
+        Ok(Self {
+            left,
+            right,
+            sample_rate: spec.sample_rate,
+        })
+    }
+
+    /// Duration of the track in seconds
+    pub fn duration(&self) -> f32 {
+        self.left.len() as f32 / self.sample_rate as f32
+    }
+}
@@ -0,0 +1,419 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    ffi::CString,
+    hash::{Hash, Hasher},
+    sync::mpsc::{channel, Sender},
+    thread::JoinHandle,
+    time::Instant,
+};
+
+use gl::types::*;
+use serde_yaml::Value;
+
+use crate::util::{supports_images, PixelBuffer, Texture};
+
+/// Number of pixel buffers, i.e. how many captures can be in flight at once
+const SLOT_COUNT: usize = 2;
+
+/// File format screenshots are saved in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// 8 bit per channel, values are clamped to the range from 0 to 1
+    #[default]
+    Png,
+    /// Radiance HDR, keeps the float values of the source
+    ///
+    /// This is the only float format `image` 0.23 can encode, OpenEXR
+    /// support was added in a later version.
+    Hdr,
+}
+
+impl CaptureFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "png" => Some(Self::Png),
+            "hdr" => Some(Self::Hdr),
+            _ => None,
+        }
+    }
+
+    fn pixel_type(self) -> GLenum {
+        match self {
+            Self::Png => gl::UNSIGNED_BYTE,
+            Self::Hdr => gl::FLOAT,
+        }
+    }
+
+    fn pixel_size(self, alpha: bool) -> usize {
+        let channels = if alpha { 4 } else { 3 };
+        match self {
+            Self::Png => channels,
+            Self::Hdr => channels * std::mem::size_of::<f32>(),
+        }
+    }
+}
+
+/// Screenshot settings of a pipeline.
+#[derive(Debug, Default, Clone)]
+pub struct ScreenshotConfig {
+    /// Render target to capture instead of the final image
+    pub source: Option<CString>,
+    pub format: CaptureFormat,
+    /// Keep the alpha channel instead of dropping it
+    pub alpha: bool,
+}
+
+impl ScreenshotConfig {
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let source = match object.get("source") {
+            Some(Value::String(s)) => Some(CString::new(s.as_str()).unwrap()),
+            None => None,
+            s => return Err(format!("Expected \"source\" to be a string, got {:?}", s)),
+        };
+
+        let format = match object.get("format") {
+            Some(Value::String(s)) => match CaptureFormat::parse(s) {
+                Some(format) => format,
+                None => return Err(format!("Expected \"format\" to be png or hdr, got {:?}", s)),
+            },
+            None => CaptureFormat::Png,
+            s => return Err(format!("Expected \"format\" to be a string, got {:?}", s)),
+        };
+
+        let alpha = match object.get("alpha") {
+            Some(Value::Bool(b)) => *b,
+            None => false,
+            s => return Err(format!("Expected \"alpha\" to be a bool, got {:?}", s)),
+        };
+
+        if alpha && format == CaptureFormat::Hdr {
+            return Err("Radiance HDR files can't store an alpha channel".into());
+        }
+
+        Ok(Self {
+            source,
+            format,
+            alpha,
+        })
+    }
+}
+
+/// A frame that has been read back from the GPU, waiting to be encoded.
+struct Frame {
+    width: u32,
+    height: u32,
+    format: CaptureFormat,
+    alpha: bool,
+    pixels: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    width: u32,
+    height: u32,
+    format: CaptureFormat,
+    alpha: bool,
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    buffer: PixelBuffer,
+    pending: Option<Pending>,
+}
+
+/// Saves screenshots without stalling the render loop.
+///
+/// The framebuffer is copied into one of two pixel buffers, which are only
+/// read once the GPU has signaled that the copy is done, usually a frame
+/// later. Flipping and encoding the image is done on a worker thread.
+#[derive(Debug)]
+pub struct Capture {
+    slots: [Slot; SLOT_COUNT],
+    next: usize,
+    requested: bool,
+    sender: Option<Sender<Frame>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        let slots: [Slot; SLOT_COUNT] = Default::default();
+
+        let (sender, receiver) = channel::<Frame>();
+        let worker = std::thread::spawn(move || {
+            for frame in receiver {
+                save_frame(frame);
+            }
+        });
+
+        Self {
+            slots,
+            next: 0,
+            requested: false,
+            sender: Some(sender),
+            worker: Some(worker),
+        }
+    }
+
+    /// Queues a capture of the next frame.
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    /// Returns `true` if the current frame should be captured.
+    pub fn is_requested(&self) -> bool {
+        self.requested
+    }
+
+    /// Starts reading back the given framebuffer, if a capture was requested.
+    pub fn capture(&mut self, fb_id: GLuint, width: u32, height: u32, config: &ScreenshotConfig) {
+        let (format, alpha) = (config.format, config.alpha);
+        let index = match self.begin(width, height, format, alpha) {
+            Some(k) => k,
+            None => return,
+        };
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fb_id);
+            gl::ReadPixels(
+                0,
+                0,
+                width as _,
+                height as _,
+                pixel_format(alpha),
+                format.pixel_type(),
+                std::ptr::null_mut(),
+            );
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        }
+
+        self.end(index, width, height, format, alpha);
+    }
+
+    /// Starts reading back a 2D texture, if a capture was requested.
+    pub fn capture_texture(&mut self, tex: &dyn Texture, config: &ScreenshotConfig) {
+        let (format, alpha) = (config.format, config.alpha);
+        let [width, height, _] = tex.resolution();
+        let index = match self.begin(width, height, format, alpha) {
+            Some(k) => k,
+            None => return,
+        };
+
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0);
+            tex.bind(0);
+            gl::GetTexImage(
+                gl::TEXTURE_2D,
+                0,
+                pixel_format(alpha),
+                format.pixel_type(),
+                std::ptr::null_mut(),
+            );
+        }
+
+        self.end(index, width, height, format, alpha);
+    }
+
+    /// Binds a pixel buffer large enough for the capture.
+    fn begin(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: CaptureFormat,
+        alpha: bool,
+    ) -> Option<usize> {
+        if !self.requested {
+            return None;
+        }
+
+        self.requested = false;
+        let index = self.next;
+        self.next = (self.next + 1) % SLOT_COUNT;
+
+        // only wait if both buffers are still busy
+        self.finish(index, true);
+
+        let slot = &mut self.slots[index];
+        let size = format.pixel_size(alpha) * width as usize * height as usize;
+        unsafe {
+            // make sure all writes from compute shaders have landed
+            if supports_images() {
+                gl::MemoryBarrier(gl::ALL_BARRIER_BITS);
+            }
+        }
+
+        slot.buffer.begin(size);
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        }
+
+        Some(index)
+    }
+
+    fn end(&mut self, index: usize, width: u32, height: u32, format: CaptureFormat, alpha: bool) {
+        let slot = &mut self.slots[index];
+        slot.buffer.end();
+        slot.pending = Some(Pending {
+            width,
+            height,
+            format,
+            alpha,
+        });
+    }
+
+    /// Hands all finished read backs over to the worker thread.
+    pub fn poll(&mut self) {
+        for index in 0..SLOT_COUNT {
+            self.finish(index, false);
+        }
+    }
+
+    fn finish(&mut self, index: usize, wait: bool) {
+        let slot = &mut self.slots[index];
+        if !slot.buffer.poll(wait) {
+            // a failed read back leaves the buffer idle
+            if slot.buffer.is_idle() {
+                slot.pending = None;
+            }
+
+            return;
+        }
+
+        let Pending {
+            width,
+            height,
+            format,
+            alpha,
+        } = match slot.pending.take() {
+            Some(s) => s,
+            None => return,
+        };
+
+        let mut pixels = vec![0_u8; format.pixel_size(alpha) * width as usize * height as usize];
+        slot.buffer.read(&mut pixels);
+
+        let frame = Frame {
+            width,
+            height,
+            format,
+            alpha,
+            pixels,
+        };
+
+        if let Some(sender) = &self.sender {
+            sender.send(frame).ok();
+        }
+    }
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        // save the screenshots that are still in flight
+        for index in 0..SLOT_COUNT {
+            self.finish(index, true);
+        }
+
+        drop(self.sender.take());
+        if let Some(worker) = self.worker.take() {
+            worker.join().ok();
+        }
+    }
+}
+
+fn pixel_format(alpha: bool) -> GLenum {
+    match alpha {
+        true => gl::RGBA,
+        false => gl::RGB,
+    }
+}
+
+fn save_frame(frame: Frame) {
+    let mut hasher = DefaultHasher::new();
+    Instant::now().hash(&mut hasher);
+    frame.pixels.hash(&mut hasher);
+    let hash = hasher.finish();
+
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let row_size = frame.format.pixel_size(frame.alpha) * width;
+
+    // images are stored top to bottom, while OpenGL reads bottom to top
+    let pixels: Vec<u8> = frame
+        .pixels
+        .chunks(row_size)
+        .rev()
+        .flatten()
+        .copied()
+        .collect();
+
+    let (file_name, result) = match frame.format {
+        CaptureFormat::Png => {
+            let file_name = format!("frame-{}.png", hash);
+            let color = match frame.alpha {
+                true => image::ColorType::Rgba8,
+                false => image::ColorType::Rgb8,
+            };
+
+            let result = image::save_buffer(&file_name, &pixels, frame.width, frame.height, color)
+                .map_err(|e| e.to_string());
+            (file_name, result)
+        }
+        CaptureFormat::Hdr => {
+            let file_name = format!("frame-{}.hdr", hash);
+            let texels: Vec<_> = pixels
+                .chunks(3 * std::mem::size_of::<f32>())
+                .map(|px| {
+                    let mut rgb = [0.0_f32; 3];
+                    for (x, bytes) in rgb.iter_mut().zip(px.chunks(4)) {
+                        *x = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    }
+                    image::Rgb(rgb)
+                })
+                .collect();
+
+            let result = std::fs::File::create(&file_name)
+                .map_err(|e| e.to_string())
+                .and_then(|file| {
+                    image::codecs::hdr::HdrEncoder::new(file)
+                        .encode(&texels, width, height)
+                        .map_err(|e| e.to_string())
+                });
+            (file_name, result)
+        }
+    };
+
+    match result {
+        Ok(()) => log::info!("Saved screenshot to {:?}", file_name),
+        Err(err) => log::error!("Failed to save screenshot to {:?}: {}", file_name, err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_screenshot_config() {
+        let object = serde_yaml::from_str("{source: accum, format: hdr}").unwrap();
+        let config = ScreenshotConfig::from_yaml(&object).unwrap();
+        assert_eq!(config.source, Some(CString::new("accum").unwrap()));
+        assert_eq!(config.format, CaptureFormat::Hdr);
+
+        let object = serde_yaml::from_str("{format: exr}").unwrap();
+        assert!(ScreenshotConfig::from_yaml(&object).is_err());
+    }
+
+    #[test]
+    fn alpha_requires_png() {
+        let object = serde_yaml::from_str("{alpha: true}").unwrap();
+        assert!(ScreenshotConfig::from_yaml(&object).unwrap().alpha);
+
+        let object = serde_yaml::from_str("{format: hdr, alpha: true}").unwrap();
+        assert!(ScreenshotConfig::from_yaml(&object).is_err());
+    }
+}
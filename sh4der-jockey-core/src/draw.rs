@@ -0,0 +1,669 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    mem::MaybeUninit,
+    rc::Rc,
+};
+
+use gl::types::*;
+
+use crate::{util::*, *};
+
+/// Values of the built-in uniforms which are the same for every stage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameUniforms {
+    pub time: f32,
+    pub time_since_build: f32,
+    pub delta: f32,
+    pub frame: u32,
+    pub beat: f32,
+    pub quality: f32,
+    /// -1 for the left eye and 1 for the right eye in stereo mode, 0 otherwise
+    pub eye: f32,
+    pub projection_mode: i32,
+    pub prev_frame_luma: f32,
+    pub exposure: f32,
+}
+
+fn audio_tex_update(
+    buffers: &mut HashMap<CString, Rc<dyn Texture>>,
+    name: &CString,
+    left: &[f32],
+    right: &[f32],
+) {
+    if let Some(tex) = buffers.get_mut(name) {
+        unsafe {
+            alloca::with_slice(left.len() + right.len(), |buffer| {
+                let buffer = &mut *(buffer as *mut [MaybeUninit<f32>] as *mut _);
+
+                interlace(left, right, buffer);
+                Rc::get_mut(tex)
+                    .unwrap()
+                    .as_any_mut()
+                    .downcast_mut::<Texture1D>()
+                    .unwrap()
+                    .write(buffer.as_ptr() as _);
+            })
+        }
+    }
+}
+
+impl Pipeline {
+    /// Writes the latest audio samples and spectra into their textures.
+    pub fn update_audio_textures(&mut self, audio: &Audio) {
+        audio_tex_update(
+            &mut self.buffers,
+            &SAMPLES_NAME,
+            &audio.l_signal,
+            &audio.r_signal,
+        );
+        audio_tex_update(
+            &mut self.buffers,
+            &SPECTRUM_RAW_NAME,
+            &audio.l_raw_spectrum,
+            &audio.r_raw_spectrum,
+        );
+        audio_tex_update(
+            &mut self.buffers,
+            &SPECTRUM_NAME,
+            &audio.l_spectrum,
+            &audio.r_spectrum,
+        );
+        audio_tex_update(
+            &mut self.buffers,
+            &SPECTRUM_SMOOTH_NAME,
+            &audio.l_spectrum_smooth,
+            &audio.r_spectrum_smooth,
+        );
+        audio_tex_update(
+            &mut self.buffers,
+            &SPECTRUM_SMOOTH_INTEGRATED_NAME,
+            &audio.l_spectrum_smooth_integrated,
+            &audio.r_spectrum_smooth_integrated,
+        );
+        audio_tex_update(
+            &mut self.buffers,
+            &SPECTRUM_INTEGRATED_NAME,
+            &audio.l_spectrum_integrated,
+            &audio.r_spectrum_integrated,
+        );
+
+        if let Some(tex) = self.buffers.get_mut(&*BEAT_HISTORY_NAME) {
+            let tex = Rc::get_mut(tex)
+                .unwrap()
+                .as_any_mut()
+                .downcast_mut::<Texture2D>()
+                .unwrap();
+
+            // the history is created with the size and format of the texture
+            unsafe { tex.write(audio.history.data().as_ptr() as _) };
+        }
+    }
+
+    /// Size of the target stage `index` renders into, or `None` if the stage
+    /// can be skipped because its target is never used.
+    ///
+    /// Buffers without a fixed size are `buffer_size` large and stages
+    /// without a target render at `screen_size`.
+    pub fn target_resolution(
+        &self,
+        index: usize,
+        buffer_size: (u32, u32),
+        screen_size: (u32, u32),
+    ) -> Option<[u32; 3]> {
+        let stage = &self.stages[index];
+        if !matches!(stage.kind, StageKind::Comp { .. }) {
+            if let Some(name) = &stage.target {
+                if !self.buffers.contains_key(name) {
+                    return None;
+                }
+            }
+        }
+
+        let res = match (stage.resolution(), &stage.target) {
+            (Some(s), _) => s,
+            (None, Some(name)) => match self.buffers.get(name) {
+                Some(tex) => tex.resolution(),
+                None => [buffer_size.0, buffer_size.1, 0],
+            },
+            (None, None) => [screen_size.0, screen_size.1, 0],
+        };
+
+        Some(res)
+    }
+
+    /// Renders stage `index` after its uniforms have been uploaded and its
+    /// inputs have been bound.
+    ///
+    /// Stages without a target render into their layer or, if the pipeline
+    /// has no layers, into `screen_fb`.
+    pub fn render_stage(
+        &mut self,
+        index: usize,
+        eye: usize,
+        screen_fb: GLuint,
+        target_res: [u32; 3],
+        vao: GLuint,
+    ) {
+        let stage = &mut self.stages[index];
+        match &stage.kind {
+            StageKind::Comp { dispatch, .. } => unsafe {
+                gl::DispatchCompute(dispatch[0], dispatch[1], dispatch[2]);
+                gl::MemoryBarrier(
+                    gl::TEXTURE_UPDATE_BARRIER_BIT
+                        | gl::TEXTURE_FETCH_BARRIER_BIT
+                        | gl::SHADER_IMAGE_ACCESS_BARRIER_BIT,
+                );
+                gl_debug_check!();
+            },
+            StageKind::Feedback { name, .. } => {
+                let buffer = self.vertex_buffers.get_mut(name).unwrap();
+                buffer.capture(stage.prog_id, vao);
+            }
+            _ => unsafe {
+                debug_assert_eq!(target_res[2], 0);
+
+                // get render target id
+                let (target_tex, target_fb) = if let Some(name) = &stage.target {
+                    let tex = self.buffers.get(name).unwrap();
+                    let tex_id = tex.texture_id();
+                    let fb_id = tex
+                        .framebuffer_id()
+                        .expect("Render target should be a framebuffer");
+                    (tex_id, fb_id)
+                } else if let Some(fb_id) = self
+                    .layers
+                    .as_ref()
+                    .and_then(|layers| layers.framebuffer(index, eye))
+                {
+                    (0, fb_id)
+                } else {
+                    (0, screen_fb)
+                };
+
+                // render into a multisampled buffer, which is resolved afterwards
+                let msaa = match stage.kind {
+                    StageKind::Vert { msaa, .. } if target_tex != 0 => msaa,
+                    _ => 1,
+                };
+
+                let render_fb = if msaa > 1 {
+                    let res = [target_res[0], target_res[1]];
+                    if stage.multisample.as_ref().is_none_or(|b| b.res != res) {
+                        stage.multisample = Some(MultisampleBuffer::new(
+                            res[0],
+                            res[1],
+                            msaa,
+                            stage.builder.float,
+                        ));
+                    }
+                    stage.multisample.as_ref().unwrap().fb_id
+                } else {
+                    target_fb
+                };
+
+                // Specify render target
+                gl::BindFramebuffer(gl::FRAMEBUFFER, render_fb);
+                gl::Viewport(0, 0, target_res[0] as _, target_res[1] as _);
+                gl_debug_check!();
+
+                // Specify fragment shader color output
+                gl::BindFragDataLocation(stage.prog_id, 0, OUT_COLOR_NAME.as_ptr());
+                gl_debug_check!();
+
+                // Specify the layout of the vertex data
+                let pos_attr = gl::GetAttribLocation(stage.prog_id, POSITION_NAME.as_ptr());
+                if pos_attr != -1 {
+                    gl_debug_check!();
+                    gl::EnableVertexAttribArray(pos_attr as GLuint);
+                    gl_debug_check!();
+                    gl::VertexAttribPointer(
+                        pos_attr as GLuint,
+                        2,
+                        gl::FLOAT,
+                        gl::FALSE as GLboolean,
+                        0,
+                        std::ptr::null(),
+                    );
+                }
+                gl_debug_check!();
+
+                // Set blend mode
+                if self.blending {
+                    let (src, dst) = stage.blend.unwrap_or((gl::ONE, gl::ZERO));
+                    gl::BlendFunc(src, dst);
+                    gl_debug_check!();
+                }
+
+                // Count samples written by this stage
+                let counting = match &mut stage.samples {
+                    Some(counter) => counter.begin(),
+                    None => false,
+                };
+
+                // Draw stuff
+                if let StageKind::Vert {
+                    count,
+                    mode,
+                    thickness,
+                    ref vertices,
+                    polygon_mode,
+                    cull,
+                    ref sprite,
+                    program_point_size,
+                    ref indices,
+                    ..
+                } = stage.kind
+                {
+                    gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+                    gl::Clear(gl::COLOR_BUFFER_BIT);
+                    gl_debug_check!();
+
+                    gl::PointSize(thickness);
+                    gl::LineWidth(thickness);
+
+                    let line_loc = stage.uploaded.location(stage.prog_id, &THICK_LINE_NAME);
+                    stage.uploaded.set(
+                        stage.prog_id,
+                        line_loc,
+                        Uniform::Vec3(target_res[0] as _, target_res[1] as _, thickness),
+                    );
+                    if program_point_size {
+                        gl::Enable(gl::PROGRAM_POINT_SIZE);
+                    }
+
+                    // bind the sprite after all other textures
+                    if let Some(name) = sprite {
+                        let unit = stage.deps.len();
+                        let tex = self.buffers.get(name).unwrap();
+                        let loc = stage.uploaded.location(stage.prog_id, &SPRITE_NAME);
+
+                        gl::ActiveTexture(gl::TEXTURE0 + unit as GLenum);
+                        tex.bind(unit as _);
+                        stage.uploaded.set_ints(stage.prog_id, loc, &[unit as _]);
+                        gl::ActiveTexture(gl::TEXTURE0);
+                        gl::PointParameteri(gl::POINT_SPRITE_COORD_ORIGIN, gl::LOWER_LEFT as _);
+                        gl_debug_check!();
+                    }
+
+                    gl::PolygonMode(gl::FRONT_AND_BACK, polygon_mode);
+                    if let Some(face) = cull {
+                        gl::Enable(gl::CULL_FACE);
+                        gl::CullFace(face);
+                    }
+                    gl_debug_check!();
+
+                    match (vertices, indices) {
+                        (Some(name), _) => {
+                            let buffer = self.vertex_buffers.get(name).unwrap();
+                            buffer.draw(stage.prog_id, vao, mode);
+                        }
+                        (None, Some(buffer)) => buffer.draw(vao, mode),
+                        (None, None) => draw_vertices(vao, count, mode),
+                    }
+                    gl_debug_check!();
+
+                    gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+                    gl::Disable(gl::CULL_FACE);
+                    gl::Disable(gl::PROGRAM_POINT_SIZE);
+                    gl::PointParameteri(gl::POINT_SPRITE_COORD_ORIGIN, gl::UPPER_LEFT as _);
+                    gl_debug_check!();
+                } else {
+                    draw_fullscreen(vao);
+                    gl_debug_check!();
+                }
+
+                if counting {
+                    stage.samples.as_ref().unwrap().end();
+                }
+
+                if let Some(buffer) = stage.multisample.as_ref().filter(|_| msaa > 1) {
+                    buffer.resolve(target_fb);
+                }
+
+                // Generate mip maps, only for targets which are sampled with them
+                let mipmapped = &self.mipmapped;
+                if target_tex != 0
+                    && stage
+                        .target
+                        .as_ref()
+                        .is_some_and(|name| mipmapped.contains(name))
+                {
+                    gl::BindTexture(gl::TEXTURE_2D, target_tex);
+                    gl::GenerateMipmap(gl::TEXTURE_2D);
+                    gl_debug_check!();
+                }
+
+                // swap buffers
+                if let Some(name) = &stage.target {
+                    self.buffers.get(name).unwrap().swap();
+                }
+            },
+        }
+
+        // release the filtering overrides, so they don't affect later passes
+        for (k, name) in stage.deps.iter().enumerate() {
+            if stage.samplers.contains_key(name) {
+                unsafe { gl::BindSampler(k as _, 0) };
+            }
+        }
+    }
+}
+
+impl Stage {
+    /// Uploads the resolution, time, audio levels and the other built-in
+    /// uniforms of this frame.
+    pub fn upload_frame_uniforms(
+        &mut self,
+        pass_index: usize,
+        target_res: [u32; 3],
+        uniforms: &FrameUniforms,
+        audio: &Audio,
+    ) {
+        let FrameUniforms {
+            time,
+            time_since_build,
+            delta,
+            frame,
+            beat,
+            quality,
+            eye: eye_value,
+            projection_mode,
+            prev_frame_luma,
+            exposure,
+        } = *uniforms;
+
+        let prog_id = self.prog_id;
+        // Add time, beat, resolution and volume
+        let r_loc = self.uploaded.location(prog_id, &R_NAME);
+        let k_loc = self.uploaded.location(prog_id, &K_NAME);
+        let res_loc = self.uploaded.location(prog_id, &RESOLUTION_NAME);
+        let pass_loc = self.uploaded.location(prog_id, &PASS_INDEX_NAME);
+        let time_loc = self.uploaded.location(prog_id, &TIME_NAME);
+        let time_since_build_loc = self.uploaded.location(prog_id, &TIME_SINCE_BUILD_NAME);
+        let frame_loc = self.uploaded.location(prog_id, &FRAME_COUNT_NAME);
+        let delta_loc = self.uploaded.location(prog_id, &TIME_DELTA_NAME);
+        let beat_loc = self.uploaded.location(prog_id, &BEAT_NAME);
+        let quality_loc = self.uploaded.location(prog_id, &QUALITY_NAME);
+        let eye_loc = self.uploaded.location(prog_id, &EYE_NAME);
+        let projection_loc = self.uploaded.location(prog_id, &PROJECTION_MODE_NAME);
+        let luma_loc = self.uploaded.location(prog_id, &PREV_FRAME_LUMA_NAME);
+        let exposure_loc = self.uploaded.location(prog_id, &EXPOSURE_NAME);
+        let volume_loc = self.uploaded.location(prog_id, &VOLUME_NAME);
+        let volume_integrated_loc = self.uploaded.location(prog_id, &VOLUME_INTEGRATED_NAME);
+        let bass_loc = self.uploaded.location(prog_id, &BASS_NAME);
+        let mid_loc = self.uploaded.location(prog_id, &MID_NAME);
+        let high_loc = self.uploaded.location(prog_id, &HIGH_NAME);
+        let smooth_bass_loc = self.uploaded.location(prog_id, &BASS_SMOOTH_NAME);
+        let smooth_mid_loc = self.uploaded.location(prog_id, &MID_SMOOTH_NAME);
+        let smooth_high_loc = self.uploaded.location(prog_id, &HIGH_SMOOTH_NAME);
+
+        let bass_integrated_loc = self.uploaded.location(prog_id, &BASS_INTEGRATED_NAME);
+        let mid_integrated_loc = self.uploaded.location(prog_id, &MID_INTEGRATED_NAME);
+        let high_integrated_loc = self.uploaded.location(prog_id, &HIGH_INTEGRATED_NAME);
+        let smooth_bass_integrated_loc = self
+            .uploaded
+            .location(prog_id, &BASS_SMOOTH_INTEGRATED_NAME);
+        let smooth_mid_integrated_loc =
+            self.uploaded.location(prog_id, &MID_SMOOTH_INTEGRATED_NAME);
+        let smooth_high_integrated_loc = self
+            .uploaded
+            .location(prog_id, &HIGH_SMOOTH_INTEGRATED_NAME);
+
+        let centroid_loc = self.uploaded.location(prog_id, &SPECTRAL_CENTROID_NAME);
+        let flatness_loc = self.uploaded.location(prog_id, &SPECTRAL_FLATNESS_NAME);
+        let rolloff_loc = self.uploaded.location(prog_id, &SPECTRAL_ROLLOFF_NAME);
+        let chroma_loc = self.uploaded.location(prog_id, &CHROMA_NAME);
+        let mfcc_loc = self.uploaded.location(prog_id, &MFCC_NAME);
+
+        self.uploaded.set(
+            prog_id,
+            res_loc,
+            Uniform::Vec4(
+                target_res[0] as f32,
+                target_res[1] as f32,
+                target_res[0] as f32 / target_res[1] as f32, // x/y
+                target_res[1] as f32 / target_res[0] as f32, // x/y
+            ),
+        );
+        self.uploaded.set(
+            prog_id,
+            r_loc,
+            Uniform::Vec3(target_res[0] as _, target_res[1] as _, time),
+        );
+        self.uploaded.set(
+            prog_id,
+            volume_loc,
+            Uniform::Vec3(
+                audio.volume[0], // average L/R
+                audio.volume[1], // L
+                audio.volume[2], // R
+            ),
+        );
+        self.uploaded.set(
+            prog_id,
+            bass_loc,
+            Uniform::Vec3(audio.bass[0], audio.bass[1], audio.bass[2]),
+        );
+        self.uploaded.set(
+            prog_id,
+            mid_loc,
+            Uniform::Vec3(audio.mid[0], audio.mid[1], audio.mid[2]),
+        );
+        self.uploaded.set(
+            prog_id,
+            high_loc,
+            Uniform::Vec3(audio.high[0], audio.high[1], audio.high[2]),
+        );
+        self.uploaded.set(
+            prog_id,
+            smooth_bass_loc,
+            Uniform::Vec3(
+                audio.bass_smooth[0],
+                audio.bass_smooth[1],
+                audio.bass_smooth[2],
+            ),
+        );
+        self.uploaded.set(
+            prog_id,
+            smooth_mid_loc,
+            Uniform::Vec3(
+                audio.mid_smooth[0],
+                audio.mid_smooth[1],
+                audio.mid_smooth[2],
+            ),
+        );
+        self.uploaded.set(
+            prog_id,
+            smooth_high_loc,
+            Uniform::Vec3(
+                audio.high_smooth[0],
+                audio.high_smooth[1],
+                audio.high_smooth[2],
+            ),
+        );
+        self.uploaded.set(
+            prog_id,
+            volume_integrated_loc,
+            Uniform::Vec3(
+                audio.volume_integrated[0], // average L/R
+                audio.volume_integrated[1], // L
+                audio.volume_integrated[2], // R
+            ),
+        );
+        self.uploaded.set(
+            prog_id,
+            bass_integrated_loc,
+            Uniform::Vec3(
+                audio.bass_integrated[0],
+                audio.bass_integrated[1],
+                audio.bass_integrated[2],
+            ),
+        );
+        self.uploaded.set(
+            prog_id,
+            mid_integrated_loc,
+            Uniform::Vec3(
+                audio.mid_integrated[0],
+                audio.mid_integrated[1],
+                audio.mid_integrated[2],
+            ),
+        );
+        self.uploaded.set(
+            prog_id,
+            high_integrated_loc,
+            Uniform::Vec3(
+                audio.high_integrated[0],
+                audio.high_integrated[1],
+                audio.high_integrated[2],
+            ),
+        );
+        self.uploaded.set(
+            prog_id,
+            smooth_bass_integrated_loc,
+            Uniform::Vec3(
+                audio.bass_smooth_integrated[0],
+                audio.bass_smooth_integrated[1],
+                audio.bass_smooth_integrated[2],
+            ),
+        );
+        self.uploaded.set(
+            prog_id,
+            smooth_mid_integrated_loc,
+            Uniform::Vec3(
+                audio.mid_smooth_integrated[0],
+                audio.mid_smooth_integrated[1],
+                audio.mid_smooth_integrated[2],
+            ),
+        );
+        self.uploaded.set(
+            prog_id,
+            smooth_high_integrated_loc,
+            Uniform::Vec3(
+                audio.high_smooth_integrated[0],
+                audio.high_smooth_integrated[1],
+                audio.high_smooth_integrated[2],
+            ),
+        );
+        self.uploaded
+            .set_ints(prog_id, k_loc, &[pass_index as _, frame as _]);
+        self.uploaded
+            .set_ints(prog_id, pass_loc, &[pass_index as _]);
+        self.uploaded.set_ints(prog_id, frame_loc, &[frame as _]);
+        self.uploaded.set(prog_id, time_loc, Uniform::Float(time));
+        self.uploaded.set(
+            prog_id,
+            time_since_build_loc,
+            Uniform::Float(time_since_build),
+        );
+        self.uploaded.set(prog_id, beat_loc, Uniform::Float(beat));
+        self.uploaded
+            .set(prog_id, quality_loc, Uniform::Float(quality));
+        self.uploaded
+            .set(prog_id, eye_loc, Uniform::Float(eye_value));
+        self.uploaded
+            .set_ints(prog_id, projection_loc, &[projection_mode]);
+        self.uploaded
+            .set(prog_id, luma_loc, Uniform::Float(prev_frame_luma));
+        self.uploaded
+            .set(prog_id, exposure_loc, Uniform::Float(exposure));
+        self.uploaded.set(prog_id, delta_loc, Uniform::Float(delta));
+        gl_debug_check!();
+
+        let features = &audio.features;
+        self.uploaded
+            .set(prog_id, centroid_loc, Uniform::Float(features.centroid));
+        self.uploaded
+            .set(prog_id, flatness_loc, Uniform::Float(features.flatness));
+        self.uploaded
+            .set(prog_id, rolloff_loc, Uniform::Float(features.rolloff));
+        self.uploaded
+            .set_floats(prog_id, chroma_loc, 1, &features.chroma);
+        self.uploaded
+            .set_floats(prog_id, mfcc_loc, 1, &features.mfcc);
+        gl_debug_check!();
+    }
+
+    /// Uploads the custom uniforms of the stage, binds its input textures
+    /// and activates its program.
+    pub fn bind_inputs(
+        &mut self,
+        buffers: &HashMap<CString, Rc<dyn Texture>>,
+        sample_counts: &[(CString, u32)],
+    ) {
+        let prog_id = self.prog_id;
+        unsafe {
+            // Add custom uniforms
+            for (name, uniform) in &self.unis {
+                let loc = self.uploaded.location(prog_id, name);
+                self.uploaded.set(prog_id, loc, *uniform);
+                gl_debug_check!();
+            }
+
+            // Add sample counts of the previous frame
+            for (name, samples) in sample_counts {
+                let loc = self.uploaded.location(prog_id, name);
+                self.uploaded
+                    .set(prog_id, loc, Uniform::Float(*samples as _));
+                gl_debug_check!();
+            }
+
+            // Add vertex count uniform
+            if let StageKind::Vert { count, .. } | StageKind::Feedback { count, .. } = self.kind {
+                let loc = self.uploaded.location(prog_id, &VERTEX_COUNT_NAME);
+                self.uploaded.set_ints(prog_id, loc, &[count as _]);
+                gl_debug_check!();
+            }
+
+            // Add and bind uniform texture dependencies
+            for (k, name) in self.deps.iter().enumerate() {
+                let tex = buffers.get(name).unwrap();
+                // quality variants may not use every texture, in which
+                // case the location is -1 and the uniform is ignored
+                let loc = self.uploaded.location(prog_id, name);
+
+                gl::ActiveTexture(gl::TEXTURE0 + k as GLenum);
+                gl_debug_check!();
+
+                tex.bind(k as _);
+                gl_debug_check!();
+
+                if let Some(sampler) = self.samplers.get(name) {
+                    sampler.bind(k as _);
+                }
+
+                self.uploaded.set_ints(prog_id, loc, &[k as _]);
+                gl_debug_check!();
+
+                let name_len = name.as_bytes().len();
+                let uploaded = &mut self.uploaded;
+                let res_loc = alloca::with_bytes_zeroed(name_len + 5, |res_name| {
+                    res_name[..name_len].copy_from_slice(name.as_bytes());
+                    res_name[name_len..].copy_from_slice("_res\0".as_bytes());
+
+                    let res_name = CStr::from_bytes_with_nul(res_name).unwrap();
+                    uploaded.location(prog_id, res_name)
+                });
+
+                let res = tex.resolution();
+                gl_debug_check!();
+
+                self.uploaded.set(
+                    prog_id,
+                    res_loc,
+                    Uniform::Vec4(
+                        res[0] as _,
+                        res[1] as _,
+                        res[2] as _,
+                        res[0] as f32 / res[1] as f32,
+                    ),
+                );
+                gl_debug_check!();
+            }
+
+            // Use shader program, after all uniforms are uploaded
+            gl::UseProgram(prog_id);
+            gl_debug_check!();
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    future::Future,
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+};
+
+use gl::types::*;
+
+use crate::{
+    util::{detect_gl_version, TargetPool},
+    *,
+};
+
+/// Number of texture units whose 2D texture binding is restored for the host.
+pub const RESTORED_TEXTURE_UNITS: usize = 16;
+
+/// Renders pipelines into framebuffers of a host program.
+///
+/// This is the part of the tool which can be embedded, without a window,
+/// MIDI or NDI. Every method expects the OpenGL context the engine was
+/// created in to be current, and restores the state listed in
+/// [`HostState`] before returning.
+pub struct Engine {
+    pub pipeline: Pipeline,
+    pub audio: Audio,
+    pub time: f32,
+    pub beat: f32,
+    pub frame: u32,
+    /// Uniforms set by the host, which are uploaded to every stage.
+    pub uniforms: HashMap<CString, Uniform>,
+    time_since_build: f32,
+    size: (u32, u32),
+    vao: GLuint,
+}
+
+impl std::fmt::Debug for Engine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(Engine))
+            .field("vao", &self.vao)
+            .field("pipeline", &self.pipeline)
+            .finish()
+    }
+}
+
+impl Engine {
+    /// Creates an engine showing the splash screen.
+    ///
+    /// Audio is not captured from an input device, the host passes samples
+    /// to [`Audio::feed`] instead.
+    pub fn new() -> Self {
+        detect_gl_version();
+        let _host = HostState::save();
+
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl_debug_check!();
+        }
+
+        Self {
+            pipeline: Pipeline::splash_screen(),
+            audio: Audio::offline(AUDIO_SAMPLES, 44100),
+            time: 0.0,
+            beat: 0.0,
+            frame: 0,
+            uniforms: HashMap::new(),
+            time_since_build: 0.0,
+            size: (0, 0),
+            vao,
+        }
+    }
+
+    /// Builds the pipeline at `path`, replacing the current one.
+    ///
+    /// Like in the tool, files referenced by the pipeline are relative to the
+    /// working directory. The current pipeline is kept if the build fails.
+    pub fn load(&mut self, path: impl AsRef<Path>, width: u32, height: u32) -> Result<(), String> {
+        let _host = HostState::save();
+        let size = (width.max(1), height.max(1));
+        let load = Pipeline::load(path, size, None, HashMap::new());
+        self.pipeline.lend_targets();
+        let (pipeline, update) = match block_on(load) {
+            Ok(s) => s,
+            Err(err) => {
+                TargetPool::reset();
+                return Err(err);
+            }
+        };
+
+        self.audio.attack = update.smoothing_attack;
+        self.audio.decay = update.smoothing_decay;
+        if update.audio_samples != self.audio.size {
+            self.audio.resize(update.audio_samples);
+        }
+
+        self.pipeline = pipeline;
+        TargetPool::collect();
+        self.size = size;
+        self.time_since_build = 0.0;
+        Ok(())
+    }
+
+    /// Sets a uniform of every stage, which stays set until it is changed.
+    pub fn set_uniform(&mut self, name: &str, value: Uniform) -> Result<(), String> {
+        let name = CString::new(name).map_err(|e| e.to_string())?;
+        self.uniforms.insert(name, value);
+        Ok(())
+    }
+
+    /// Renders a frame into the framebuffer `fbo` and advances the time by
+    /// `delta` seconds.
+    pub fn render(&mut self, fbo: GLuint, width: u32, height: u32, delta: f32) {
+        let _host = HostState::save();
+        unsafe {
+            match self.pipeline.blending {
+                true => gl::Enable(gl::BLEND),
+                false => gl::Disable(gl::BLEND),
+            }
+            gl_debug_check!();
+        }
+
+        let size = (width.max(1), height.max(1));
+        if size != self.size {
+            self.size = size;
+            self.pipeline.resize_buffers(size.0, size.1);
+        }
+
+        self.audio.update_samples();
+        self.audio.update_fft();
+        self.audio.update_history(self.beat);
+        self.pipeline.update_audio_textures(&self.audio);
+
+        let frame_uniforms = FrameUniforms {
+            time: self.time,
+            time_since_build: self.time_since_build,
+            delta,
+            frame: self.frame,
+            beat: self.beat,
+            quality: self.pipeline.quality.as_ref().map_or(1.0, |q| q.value),
+            eye: 0.0,
+            projection_mode: 0,
+            prev_frame_luma: 0.0,
+            exposure: 1.0,
+        };
+
+        self.time += delta;
+        self.time_since_build += delta;
+        self.frame = self.frame.wrapping_add(1);
+
+        if let Some(layers) = &mut self.pipeline.layers {
+            layers.begin_frame(size.0, size.1, 1);
+        }
+
+        for (binding, block) in self.pipeline.blocks.iter().enumerate() {
+            block.bind(binding as _);
+        }
+
+        let mut sample_counts = Vec::new();
+        for stage in self.pipeline.stages.iter_mut() {
+            if let Some(counter) = &mut stage.samples {
+                counter.poll();
+                sample_counts.push((counter.name.clone(), counter.samples));
+            }
+        }
+
+        for index in 0..self.pipeline.stages.len() {
+            if self.pipeline.stages[index].disabled {
+                continue;
+            }
+
+            let target_res = match self.pipeline.target_resolution(index, size, size) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            let stage = &mut self.pipeline.stages[index];
+            stage.upload_frame_uniforms(index, target_res, &frame_uniforms, &self.audio);
+
+            let prog_id = stage.prog_id;
+            for (name, uniform) in self.pipeline.variations.iter().flat_map(|v| v.uniforms()) {
+                let loc = stage.uploaded.location(prog_id, name);
+                stage.uploaded.set(prog_id, loc, *uniform);
+            }
+
+            for (name, uniform) in self.uniforms.iter() {
+                let loc = stage.uploaded.location(prog_id, name);
+                stage.uploaded.set(prog_id, loc, *uniform);
+            }
+
+            stage.bind_inputs(&self.pipeline.buffers, &sample_counts);
+            self.pipeline
+                .render_stage(index, 0, fbo, target_res, self.vao);
+        }
+
+        if let Some(layers) = &self.pipeline.layers {
+            layers.composite(self.vao, 0, &[], fbo, size.0, size.1);
+        }
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+/// OpenGL state of the host, which is saved before the engine changes it
+/// and restored when this is dropped.
+///
+/// Covers the framebuffer bindings, viewport, program, vertex array, array
+/// buffer, active texture unit, the 2D textures of the first
+/// [`RESTORED_TEXTURE_UNITS`] units, blending and the clear color. Other
+/// bindings, like uniform buffers or image units, are left as the pipeline
+/// set them.
+#[derive(Debug)]
+pub struct HostState {
+    draw_fbo: GLint,
+    read_fbo: GLint,
+    viewport: [GLint; 4],
+    program: GLint,
+    vao: GLint,
+    array_buffer: GLint,
+    active_texture: GLint,
+    textures: [GLint; RESTORED_TEXTURE_UNITS],
+    blend: bool,
+    blend_func: [GLint; 4],
+    blend_equation: [GLint; 2],
+    clear_color: [GLfloat; 4],
+}
+
+impl HostState {
+    pub fn save() -> Self {
+        let get = |name| {
+            let mut value = 0;
+            unsafe { gl::GetIntegerv(name, &mut value) };
+            value
+        };
+
+        let active_texture = get(gl::ACTIVE_TEXTURE);
+        let mut textures = [0; RESTORED_TEXTURE_UNITS];
+        for (unit, texture) in textures.iter_mut().enumerate() {
+            unsafe { gl::ActiveTexture(gl::TEXTURE0 + unit as GLenum) };
+            *texture = get(gl::TEXTURE_BINDING_2D);
+        }
+
+        let mut viewport = [0; 4];
+        let mut clear_color = [0.0; 4];
+        let blend;
+        unsafe {
+            gl::ActiveTexture(active_texture as _);
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+            gl::GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+            blend = gl::IsEnabled(gl::BLEND) == gl::TRUE;
+            gl_debug_check!();
+        }
+
+        Self {
+            draw_fbo: get(gl::DRAW_FRAMEBUFFER_BINDING),
+            read_fbo: get(gl::READ_FRAMEBUFFER_BINDING),
+            viewport,
+            program: get(gl::CURRENT_PROGRAM),
+            vao: get(gl::VERTEX_ARRAY_BINDING),
+            array_buffer: get(gl::ARRAY_BUFFER_BINDING),
+            active_texture,
+            textures,
+            blend,
+            blend_func: [
+                get(gl::BLEND_SRC_RGB),
+                get(gl::BLEND_DST_RGB),
+                get(gl::BLEND_SRC_ALPHA),
+                get(gl::BLEND_DST_ALPHA),
+            ],
+            blend_equation: [get(gl::BLEND_EQUATION_RGB), get(gl::BLEND_EQUATION_ALPHA)],
+            clear_color,
+        }
+    }
+}
+
+impl Drop for HostState {
+    fn drop(&mut self) {
+        unsafe {
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.draw_fbo as _);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.read_fbo as _);
+            let [x, y, w, h] = self.viewport;
+            gl::Viewport(x, y, w, h);
+            gl::UseProgram(self.program as _);
+            gl::BindVertexArray(self.vao as _);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.array_buffer as _);
+
+            for (unit, &texture) in self.textures.iter().enumerate() {
+                gl::ActiveTexture(gl::TEXTURE0 + unit as GLenum);
+                gl::BindTexture(gl::TEXTURE_2D, texture as _);
+            }
+            gl::ActiveTexture(self.active_texture as _);
+
+            match self.blend {
+                true => gl::Enable(gl::BLEND),
+                false => gl::Disable(gl::BLEND),
+            }
+            let [src_rgb, dst_rgb, src_alpha, dst_alpha] = self.blend_func;
+            gl::BlendFuncSeparate(src_rgb as _, dst_rgb as _, src_alpha as _, dst_alpha as _);
+            let [rgb, alpha] = self.blend_equation;
+            gl::BlendEquationSeparate(rgb as _, alpha as _);
+            let [r, g, b, a] = self.clear_color;
+            gl::ClearColor(r, g, b, a);
+        }
+    }
+}
+
+struct NoopWake;
+
+impl Wake for NoopWake {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Polls a future until it is done.
+///
+/// Pipelines are built by futures which yield regularly and never wait on
+/// a waker, so busy polling is enough to drive them.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Waker::from(Arc::new(NoopWake));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+
+    loop {
+        match Pin::as_mut(&mut future).poll(&mut cx) {
+            Poll::Ready(out) => return out,
+            Poll::Pending => std::thread::yield_now(),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+/// Number of pitch classes in the chroma vector, starting at C.
+pub const CHROMA_BINS: usize = 12;
+
+/// Number of cepstral coefficients, including the energy term.
+pub const MFCC_COUNT: usize = 13;
+
+/// Number of triangular filters the mel spectrum is made of.
+const MEL_FILTERS: usize = 26;
+
+/// Share of the spectral energy below the rolloff frequency.
+const ROLLOFF_SHARE: f32 = 0.85;
+
+/// Frequency range the chroma vector is gathered from, in Hz.
+const CHROMA_RANGE: (f32, f32) = (55.0, 5000.0);
+
+/// Describes the timbre and the harmony of a power spectrum.
+#[derive(Debug, Clone, Default)]
+pub struct SpectralFeatures {
+    /// Center of mass of the spectrum, as a fraction of the Nyquist
+    /// frequency. Brighter sounds have a higher centroid.
+    pub centroid: f32,
+    /// Ratio of the geometric and the arithmetic mean of the spectrum,
+    /// close to 1 for noise and close to 0 for tones.
+    pub flatness: f32,
+    /// Frequency below which most of the energy lies, as a fraction of the
+    /// Nyquist frequency.
+    pub rolloff: f32,
+    /// Energy of each pitch class from C to B, scaled so the loudest is 1.
+    pub chroma: [f32; CHROMA_BINS],
+    /// Mel-frequency cepstral coefficients, the first one being the log
+    /// energy and the others describing the shape of the spectrum.
+    pub mfcc: [f32; MFCC_COUNT],
+    filters: MelFilters,
+}
+
+/// Triangular filters over the bins of a spectrum, each a list of bins and
+/// their weights. Rebuilt when the spectrum size or sample rate changes.
+#[derive(Debug, Clone, Default)]
+struct MelFilters {
+    bins: usize,
+    sample_rate: usize,
+    weights: Vec<Vec<(usize, f32)>>,
+}
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+impl MelFilters {
+    fn new(bins: usize, sample_rate: usize) -> Self {
+        let bin_hz = sample_rate as f32 / (2 * bins) as f32;
+        let top = hz_to_mel(sample_rate as f32 / 2.0);
+        let edges: Vec<f32> = (0..MEL_FILTERS + 2)
+            .map(|k| mel_to_hz(top * k as f32 / (MEL_FILTERS + 1) as f32) / bin_hz)
+            .collect();
+
+        let weights = edges
+            .windows(3)
+            .map(|w| {
+                let (lo, mid, hi) = (w[0], w[1], w[2]);
+                (lo.ceil() as usize..=(hi.floor() as usize).min(bins - 1))
+                    .filter_map(|i| {
+                        let x = i as f32;
+                        let weight = match x <= mid {
+                            true => (x - lo) / (mid - lo).max(1e-6),
+                            false => (hi - x) / (hi - mid).max(1e-6),
+                        };
+                        (weight > 0.0).then_some((i, weight))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            bins,
+            sample_rate,
+            weights,
+        }
+    }
+}
+
+/// Type II discrete cosine transform of the first `out.len()` coefficients.
+fn dct(input: &[f32], out: &mut [f32]) {
+    let n = input.len() as f32;
+    for (k, c) in out.iter_mut().enumerate() {
+        *c = input
+            .iter()
+            .enumerate()
+            .map(|(m, x)| x * (std::f32::consts::PI * k as f32 * (m as f32 + 0.5) / n).cos())
+            .sum();
+    }
+}
+
+impl SpectralFeatures {
+    /// Analyzes the power spectrum of a signal, which holds the bins from 0
+    /// up to the Nyquist frequency.
+    pub fn update(&mut self, power: &[f32], sample_rate: usize) {
+        let bins = power.len();
+        if bins < 2 || sample_rate == 0 {
+            return;
+        }
+
+        let bin_hz = sample_rate as f32 / (2 * bins) as f32;
+        let total: f32 = power.iter().sum();
+
+        // the features of silence are undefined, keep the last ones instead
+        if total <= f32::EPSILON {
+            return;
+        }
+
+        let weighted: f32 = power.iter().enumerate().map(|(i, p)| i as f32 * p).sum();
+        self.centroid = weighted / total / bins as f32;
+
+        let log_mean = power.iter().map(|p| (p + 1e-12).ln()).sum::<f32>() / bins as f32;
+        self.flatness = (log_mean.exp() / (total / bins as f32)).min(1.0);
+
+        let mut acc = 0.0;
+        let rolloff_bin = power
+            .iter()
+            .position(|p| {
+                acc += p;
+                acc >= ROLLOFF_SHARE * total
+            })
+            .unwrap_or(bins - 1);
+        self.rolloff = rolloff_bin as f32 / bins as f32;
+
+        self.chroma = [0.0; CHROMA_BINS];
+        for (i, p) in power.iter().enumerate().skip(1) {
+            let freq = i as f32 * bin_hz;
+            if freq < CHROMA_RANGE.0 || freq > CHROMA_RANGE.1 {
+                continue;
+            }
+
+            // MIDI note numbers put C at multiples of 12
+            let note = (12.0 * (freq / 440.0).log2() + 69.0).round() as i32;
+            self.chroma[note.rem_euclid(CHROMA_BINS as _) as usize] += p;
+        }
+
+        let max = self.chroma.iter().cloned().fold(0.0, f32::max);
+        if max > 0.0 {
+            self.chroma.iter_mut().for_each(|c| *c /= max);
+        }
+
+        if self.filters.bins != bins || self.filters.sample_rate != sample_rate {
+            self.filters = MelFilters::new(bins, sample_rate);
+        }
+
+        let log_mel: Vec<f32> = self
+            .filters
+            .weights
+            .iter()
+            .map(|filter| {
+                let energy: f32 = filter.iter().map(|&(i, w)| power[i] * w).sum();
+                (energy + 1e-10).ln()
+            })
+            .collect();
+
+        dct(&log_mel, &mut self.mfcc);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Power spectrum of 256 bins at 512 Hz per bin with a single peak.
+    fn tone(bin: usize) -> Vec<f32> {
+        let mut power = vec![1e-6; 256];
+        power[bin] = 1.0;
+        power
+    }
+
+    #[test]
+    fn describes_tones_and_noise() {
+        let sample_rate = 2 * 256 * 512;
+        let mut features = SpectralFeatures::default();
+
+        features.update(&tone(64), sample_rate);
+        assert!((features.centroid - 0.25).abs() < 0.01);
+        assert!((features.rolloff - 0.25).abs() < 0.01);
+        assert!(features.flatness < 0.01);
+
+        features.update(&vec![1.0; 256], sample_rate);
+        assert!((features.centroid - 0.5).abs() < 0.01);
+        assert!((features.rolloff - 0.85).abs() < 0.01);
+        assert!(features.flatness > 0.99);
+
+        // silence keeps the previous features
+        features.update(&vec![0.0; 256], sample_rate);
+        assert!(features.flatness > 0.99);
+    }
+
+    #[test]
+    fn finds_pitch_classes() {
+        // 1 Hz per bin, A4 is at bin 440
+        let mut power = vec![0.0; 8000];
+        power[440] = 1.0;
+        power[330] = 0.5;
+
+        let mut features = SpectralFeatures::default();
+        features.update(&power, 16000);
+        assert_eq!(features.chroma[9], 1.0); // A
+        assert_eq!(features.chroma[4], 0.5); // E
+        assert_eq!(features.chroma[0], 0.0);
+    }
+
+    #[test]
+    fn cepstrum_of_flat_mel_spectrum() {
+        let mut out = [0.0; MFCC_COUNT];
+        dct(&[2.0; MEL_FILTERS], &mut out);
+        assert!((out[0] - 2.0 * MEL_FILTERS as f32).abs() < 1e-4);
+        assert!(out[1..].iter().all(|c| c.abs() < 1e-4));
+    }
+}
@@ -0,0 +1,148 @@
+use std::ffi::CString;
+
+use gl::types::*;
+
+/// Size of a single captured vertex output, all outputs are `vec4`s
+const VARYING_SIZE: usize = 4 * std::mem::size_of::<GLfloat>();
+
+/// A vertex buffer filled by a transform feedback stage.
+///
+/// The buffer holds the outputs of every vertex processed by the capturing
+/// stage, interleaved in the order they were declared. It is double buffered,
+/// so the capturing stage can read the vertices it produced in the previous
+/// frame, while writing the new ones.
+#[derive(Debug)]
+pub struct VertexBuffer {
+    pub count: GLsizei,
+    varyings: Vec<CString>,
+    prev_varyings: Vec<CString>,
+    vbos: [GLuint; 2],
+    current: usize,
+}
+
+impl VertexBuffer {
+    pub fn new(count: GLsizei, varyings: &[CString]) -> Self {
+        let prev_varyings = varyings
+            .iter()
+            .map(|name| {
+                let mut bytes = b"prev_".to_vec();
+                bytes.extend_from_slice(name.as_bytes());
+                CString::new(bytes).unwrap()
+            })
+            .collect();
+
+        let size = count as usize * varyings.len() * VARYING_SIZE;
+        let zeros = vec![0_u8; size];
+
+        let mut vbos = [0; 2];
+        unsafe {
+            gl::GenBuffers(2, vbos.as_mut_ptr());
+            for &vbo in vbos.iter() {
+                gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+                gl::BufferData(
+                    gl::ARRAY_BUFFER,
+                    size as _,
+                    zeros.as_ptr() as _,
+                    gl::DYNAMIC_COPY,
+                );
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl_debug_check!();
+        }
+
+        Self {
+            count,
+            varyings: varyings.to_vec(),
+            prev_varyings,
+            vbos,
+            current: 0,
+        }
+    }
+
+    /// Points the attributes with the given names at the given buffer.
+    ///
+    /// Returns the locations of all attributes that have been enabled.
+    fn bind_attributes(prog_id: GLuint, vbo: GLuint, names: &[CString]) -> Vec<GLuint> {
+        let stride = names.len() * VARYING_SIZE;
+        let mut enabled = Vec::with_capacity(names.len());
+
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            for (k, name) in names.iter().enumerate() {
+                let loc = gl::GetAttribLocation(prog_id, name.as_ptr());
+                if loc == -1 {
+                    continue;
+                }
+
+                gl::EnableVertexAttribArray(loc as _);
+                gl::VertexAttribPointer(
+                    loc as _,
+                    4,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    stride as _,
+                    (k * VARYING_SIZE) as _,
+                );
+                enabled.push(loc as _);
+            }
+            gl_debug_check!();
+        }
+
+        enabled
+    }
+
+    fn unbind_attributes(enabled: &[GLuint]) {
+        unsafe {
+            for &loc in enabled {
+                gl::DisableVertexAttribArray(loc);
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl_debug_check!();
+        }
+    }
+
+    /// Runs the given program over the vertices of the previous frame and
+    /// records its outputs.
+    pub fn capture(&mut self, prog_id: GLuint, vao: GLuint) {
+        let (read, write) = (self.vbos[self.current], self.vbos[1 - self.current]);
+
+        unsafe {
+            gl::BindVertexArray(vao);
+            let enabled = Self::bind_attributes(prog_id, read, &self.prev_varyings);
+
+            gl::Enable(gl::RASTERIZER_DISCARD);
+            gl::BindBufferBase(gl::TRANSFORM_FEEDBACK_BUFFER, 0, write);
+            gl::BeginTransformFeedback(gl::POINTS);
+            gl::DrawArrays(gl::POINTS, 0, self.count);
+            gl::EndTransformFeedback();
+            gl::BindBufferBase(gl::TRANSFORM_FEEDBACK_BUFFER, 0, 0);
+            gl::Disable(gl::RASTERIZER_DISCARD);
+            gl_debug_check!();
+
+            Self::unbind_attributes(&enabled);
+        }
+
+        self.current = 1 - self.current;
+    }
+
+    /// Draws the most recently captured vertices with the given program.
+    pub fn draw(&self, prog_id: GLuint, vao: GLuint, mode: GLenum) {
+        unsafe {
+            gl::BindVertexArray(vao);
+            let enabled = Self::bind_attributes(prog_id, self.vbos[self.current], &self.varyings);
+
+            gl::DrawArrays(mode, 0, self.count);
+            gl_debug_check!();
+
+            Self::unbind_attributes(&enabled);
+        }
+    }
+}
+
+impl Drop for VertexBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(2, self.vbos.as_ptr());
+        }
+    }
+}
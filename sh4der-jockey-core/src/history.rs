@@ -0,0 +1,103 @@
+/// Number of beats kept in the beat history, one row each.
+pub const HISTORY_BEATS: usize = 16;
+
+/// Number of steps each beat of the history is divided into.
+pub const HISTORY_STEPS: usize = 16;
+
+/// Band energies of the last few beats, laid out as a texture with the
+/// phase within a beat on the x axis and the number of beats ago on the
+/// y axis. Row 0 holds the current beat, which is filled in as it plays.
+///
+/// Each texel contains the average of `[bass, mid, high, volume]` over its
+/// step, so the history doesn't depend on the frame rate.
+#[derive(Debug, Clone)]
+pub struct BeatHistory {
+    data: Vec<[f32; 4]>,
+    beat: Option<i64>,
+    step: usize,
+    sum: [f32; 4],
+    count: u32,
+}
+
+impl Default for BeatHistory {
+    fn default() -> Self {
+        Self {
+            data: vec![[0.0; 4]; HISTORY_BEATS * HISTORY_STEPS],
+            beat: None,
+            step: 0,
+            sum: [0.0; 4],
+            count: 0,
+        }
+    }
+}
+
+impl BeatHistory {
+    /// The texels of the history, row by row.
+    pub fn data(&self) -> &[[f32; 4]] {
+        &self.data
+    }
+
+    /// Records the band energies at the given beat, which is the value of
+    /// the `beat` uniform.
+    pub fn push(&mut self, beat: f32, bands: [f32; 4]) {
+        let index = beat.floor() as i64;
+        let step = ((beat - beat.floor()) * HISTORY_STEPS as f32) as usize;
+        let step = step.min(HISTORY_STEPS - 1);
+
+        match self.beat {
+            Some(last) if index == last => (),
+            Some(last) if index > last => {
+                // scroll the rows, leaving beats without any frames empty
+                let beats = ((index - last) as usize).min(HISTORY_BEATS);
+                self.data.rotate_right(beats * HISTORY_STEPS);
+                self.data[..beats * HISTORY_STEPS].fill([0.0; 4]);
+            }
+            // the tempo has been reset, the old beats don't line up anymore
+            _ => self.data.fill([0.0; 4]),
+        }
+
+        if self.beat != Some(index) || self.step != step {
+            self.beat = Some(index);
+            self.step = step;
+            self.sum = [0.0; 4];
+            self.count = 0;
+        }
+
+        self.count += 1;
+        for (k, band) in bands.iter().enumerate() {
+            self.sum[k] += band;
+            self.data[step][k] = self.sum[k] / self.count as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scrolls_with_beats() {
+        let mut history = BeatHistory::default();
+        history.push(3.0, [1.0, 0.0, 0.0, 0.0]);
+        history.push(3.01, [3.0, 0.0, 0.0, 0.0]);
+        history.push(3.5, [0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(history.data()[0], [2.0, 0.0, 0.0, 0.0]);
+        assert_eq!(history.data()[HISTORY_STEPS / 2], [0.0, 1.0, 0.0, 0.0]);
+
+        // one beat later, the previous beat moved to the second row
+        history.push(4.0, [0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(history.data()[0], [0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(history.data()[HISTORY_STEPS / 2], [0.0; 4]);
+        assert_eq!(history.data()[HISTORY_STEPS], [2.0, 0.0, 0.0, 0.0]);
+
+        // skipped beats stay empty
+        history.push(6.0, [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(history.data()[HISTORY_STEPS], [0.0; 4]);
+        assert_eq!(history.data()[2 * HISTORY_STEPS], [0.0, 0.0, 1.0, 0.0]);
+
+        // going back in time clears the history
+        history.push(1.0, [1.0; 4]);
+        assert_eq!(history.data()[0], [1.0; 4]);
+        assert!(history.data()[1..].iter().all(|t| *t == [0.0; 4]));
+    }
+}
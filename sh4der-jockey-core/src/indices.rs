@@ -0,0 +1,209 @@
+use gl::types::*;
+use serde_yaml::Value;
+
+/// A list of vertex indices for a vertex stage.
+///
+/// Instead of drawing the vertices in order, primitives are assembled from
+/// the vertices in the list, which makes it possible to share vertices
+/// between triangles, e.g. when drawing meshes or grids.
+#[derive(Debug)]
+pub struct IndexBuffer {
+    ebo: GLuint,
+    pub count: GLsizei,
+    pub vertex_count: GLsizei,
+}
+
+impl IndexBuffer {
+    /// Loads indices from a file, or generates them from a pattern like
+    /// `"grid 128x128"`.
+    pub fn from_yaml(value: &Value, mode: GLenum) -> Result<Self, String> {
+        let source = match value.as_str() {
+            Some(s) => s.trim(),
+            None => {
+                return Err(format!(
+                    "Expected field \"indices\" to be a string, got {:?}",
+                    value
+                ))
+            }
+        };
+
+        let indices = match source.strip_prefix("grid ") {
+            Some(size) => {
+                let (width, height) = match size.trim().split_once('x') {
+                    Some((w, h)) => (w.trim().parse().ok(), h.trim().parse().ok()),
+                    None => (None, None),
+                };
+
+                match (width, height) {
+                    (Some(w), Some(h)) if w > 1 && h > 1 => grid_indices(w, h, mode)?,
+                    _ => {
+                        return Err(format!(
+                            "Expected grid size to look like \"grid 128x128\", got {:?}",
+                            source
+                        ))
+                    }
+                }
+            }
+            None => {
+                let text = std::fs::read_to_string(source)
+                    .map_err(|e| format!("Failed to read indices at {:?}: {}", source, e))?;
+                parse_indices(&text)?
+            }
+        };
+
+        Ok(Self::new(&indices))
+    }
+
+    pub fn new(indices: &[u32]) -> Self {
+        let vertex_count = indices.iter().max().map_or(0, |&n| n + 1);
+
+        let mut ebo = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut ebo);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+            gl::BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                std::mem::size_of_val(indices) as _,
+                indices.as_ptr() as _,
+                gl::STATIC_DRAW,
+            );
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+            gl_debug_check!();
+        }
+
+        Self {
+            ebo,
+            count: indices.len() as _,
+            vertex_count: vertex_count as _,
+        }
+    }
+
+    pub fn draw(&self, vao: GLuint, mode: GLenum) {
+        unsafe {
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
+            gl::DrawElements(mode, self.count, gl::UNSIGNED_INT, std::ptr::null());
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+            gl_debug_check!();
+        }
+    }
+}
+
+impl Drop for IndexBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.ebo);
+        }
+    }
+}
+
+/// Connects a grid of vertices, numbered row by row.
+///
+/// Generates two triangles per cell for `TRIANGLES` and the edges between
+/// neighboring vertices for `LINES`. Grids whose vertices can't be numbered
+/// with a `u32`, or whose indices can't be drawn in a single call, are
+/// rejected.
+pub fn grid_indices(width: u32, height: u32, mode: GLenum) -> Result<Vec<u32>, String> {
+    if width < 2 || height < 2 {
+        return Err(format!(
+            "Expected grid to be at least 2x2, got {}x{}",
+            width, height
+        ));
+    }
+
+    let cells = (width as u64 - 1) * (height as u64 - 1);
+    let index_count = match mode {
+        gl::TRIANGLES => 6 * cells,
+        gl::LINES => 2 * (cells + cells + (width as u64 - 1) + (height as u64 - 1)),
+        _ => return Err("Grid indices require \"mode\" to be TRIANGLES or LINES".into()),
+    };
+
+    if width.checked_mul(height).is_none() || index_count > GLsizei::MAX as u64 {
+        return Err(format!("Grid {}x{} has too many vertices", width, height));
+    }
+
+    let mut out = Vec::with_capacity(index_count as usize);
+    match mode {
+        gl::TRIANGLES => {
+            for y in 0..height - 1 {
+                for x in 0..width - 1 {
+                    let k = y * width + x;
+                    out.extend_from_slice(&[k, k + 1, k + width]);
+                    out.extend_from_slice(&[k + 1, k + width + 1, k + width]);
+                }
+            }
+        }
+        gl::LINES => {
+            for y in 0..height {
+                for x in 0..width {
+                    let k = y * width + x;
+                    if x + 1 < width {
+                        out.extend_from_slice(&[k, k + 1]);
+                    }
+                    if y + 1 < height {
+                        out.extend_from_slice(&[k, k + width]);
+                    }
+                }
+            }
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(out)
+}
+
+/// Parses a list of indices separated by whitespace or commas.
+///
+/// Everything after a `#` is treated as a comment.
+pub fn parse_indices(text: &str) -> Result<Vec<u32>, String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse()
+                .map_err(|_| format!("Expected index to be an unsigned int, got {:?}", s))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grid_triangles() {
+        let indices = grid_indices(3, 2, gl::TRIANGLES).unwrap();
+        assert_eq!(indices, vec![0, 1, 3, 1, 4, 3, 1, 2, 4, 2, 5, 4]);
+    }
+
+    #[test]
+    fn grid_lines() {
+        let indices = grid_indices(2, 2, gl::LINES).unwrap();
+        assert_eq!(indices, vec![0, 1, 0, 2, 1, 3, 2, 3]);
+    }
+
+    #[test]
+    fn grid_unsupported_mode() {
+        assert!(grid_indices(2, 2, gl::POINTS).is_err());
+    }
+
+    #[test]
+    fn grid_too_large() {
+        assert!(grid_indices(1, 2, gl::TRIANGLES).is_err());
+        assert!(grid_indices(65536, 65537, gl::LINES).is_err());
+        assert!(grid_indices(u32::MAX, 2, gl::TRIANGLES).is_err());
+        assert!(grid_indices(20000, 20000, gl::TRIANGLES).is_err());
+    }
+
+    #[test]
+    fn parse_list() {
+        let text = "# a quad\n0, 1, 2\n2 3 0 # second triangle\n";
+        assert_eq!(parse_indices(text).unwrap(), vec![0, 1, 2, 2, 3, 0]);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(parse_indices("0 1 -2").is_err());
+    }
+}
@@ -0,0 +1,319 @@
+use image::{imageops, RgbaImage};
+use serde_yaml::Value;
+
+/// How frames of a source are shown when it runs at a lower frame rate
+/// than the render loop.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FrameInterpolation {
+    /// Shows the latest frame until the next one arrives.
+    #[default]
+    Latest,
+    /// Crossfades from the previous to the latest frame over one frame
+    /// interval of the source, which delays the image by one source frame.
+    Blend,
+}
+
+/// How colors relate to the alpha channel of an image.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Colors are independent of alpha, which is how NDI sends them.
+    #[default]
+    Straight,
+    /// Colors have been multiplied by alpha, which allows filtering and
+    /// compositing without dark fringes around keyed edges.
+    Premultiplied,
+    /// Alpha is ignored and set to 1.
+    Opaque,
+}
+
+impl AlphaMode {
+    fn parse(object: &Value, key: &str, allow_opaque: bool) -> Result<Self, String> {
+        match object.get(key).map(Value::as_str) {
+            None | Some(Some("straight")) => Ok(Self::Straight),
+            Some(Some("premultiplied")) => Ok(Self::Premultiplied),
+            Some(Some("opaque")) if allow_opaque => Ok(Self::Opaque),
+            _ => Err(format!(
+                "Expected ndi.{} to be {}, got {:?}",
+                key,
+                match allow_opaque {
+                    true => "\"straight\", \"premultiplied\" or \"opaque\"",
+                    false => "\"straight\" or \"premultiplied\"",
+                },
+                object[key]
+            )),
+        }
+    }
+}
+
+/// Part of an input image which ends up in its texture.
+///
+/// Cropping and scaling happen before the upload, so a large source which
+/// is only partly sampled doesn't need to be uploaded at full size.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct InputRegion {
+    /// `[x, y, width, height]` as fractions of the image size, starting at
+    /// the top left corner like in image editors.
+    pub crop: Option<[f32; 4]>,
+    /// Factor applied to the size of the cropped image.
+    pub scale: Option<f32>,
+}
+
+impl InputRegion {
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let crop = match object.get("crop") {
+            None => None,
+            Some(s) => {
+                let err = || {
+                    format!(
+                        "Expected \"crop\" to be [x, y, width, height] within 0 to 1, got {:?}",
+                        s
+                    )
+                };
+
+                let rect = s
+                    .as_sequence()
+                    .and_then(|seq| seq.iter().map(Value::as_f64).collect::<Option<Vec<_>>>())
+                    .ok_or_else(err)?;
+
+                match *rect.as_slice() {
+                    [x, y, w, h]
+                        if x >= 0.0
+                            && y >= 0.0
+                            && w > 0.0
+                            && h > 0.0
+                            && x + w <= 1.0
+                            && y + h <= 1.0 =>
+                    {
+                        Some([x as f32, y as f32, w as f32, h as f32])
+                    }
+                    _ => return Err(err()),
+                }
+            }
+        };
+
+        let scale = match object.get("scale") {
+            None => None,
+            Some(s) => match s.as_f64() {
+                Some(f) if f > 0.0 => Some(f as f32),
+                _ => {
+                    return Err(format!(
+                        "Expected \"scale\" to be a positive number, got {:?}",
+                        s
+                    ))
+                }
+            },
+        };
+
+        Ok(Self { crop, scale })
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.crop.is_none() && self.scale.is_none()
+    }
+
+    /// Tells apart cached textures of the same file with different regions.
+    pub fn cache_key(&self, path: &str) -> String {
+        match self.is_full() {
+            true => path.to_owned(),
+            false => format!("{} {:?} {:?}", path, self.crop, self.scale),
+        }
+    }
+
+    /// Size of the region of an image of the given size.
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        let [_, _, w, h] = self.crop.unwrap_or([0.0, 0.0, 1.0, 1.0]);
+        let scale = self.scale.unwrap_or(1.0);
+        let size = |len: u32, frac: f32| ((len as f32 * frac * scale).round() as u32).max(1);
+        (size(width, w), size(height, h))
+    }
+
+    /// Crops and scales an image which has been flipped for the upload, so
+    /// its first row is the bottom of the picture.
+    pub fn apply(&self, image: RgbaImage) -> RgbaImage {
+        if self.is_full() {
+            return image;
+        }
+
+        let (width, height) = image.dimensions();
+        let image = match self.crop {
+            Some([x, y, w, h]) => {
+                let px = |len: u32, frac: f32| ((len as f32 * frac).round() as u32).min(len);
+                let (cw, ch) = (px(width, w).max(1), px(height, h).max(1));
+                let cx = px(width, x).min(width - cw);
+                let cy = (height - px(height, y)).saturating_sub(ch);
+                imageops::crop_imm(&image, cx, cy, cw, ch).to_image()
+            }
+            None => image,
+        };
+
+        let (w, h) = self.size(width, height);
+        match image.dimensions() == (w, h) {
+            true => image,
+            false => imageops::resize(&image, w, h, imageops::FilterType::Triangle),
+        }
+    }
+}
+
+/// A video feed requested in the `ndi` section of a pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NdiInput {
+    /// Part of the name of the source to connect to.
+    pub source: String,
+    pub interpolation: FrameInterpolation,
+    /// How the source encodes alpha, some graphics servers send
+    /// premultiplied colors although NDI expects straight ones.
+    pub source_alpha: AlphaMode,
+    /// How alpha is stored in the texture.
+    pub alpha: AlphaMode,
+    pub region: InputRegion,
+}
+
+impl NdiInput {
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let source = match object.get("source") {
+            Some(Value::String(s)) => s.clone(),
+            s => {
+                return Err(format!(
+                    "Expected ndi.source to be a string, got {:?} instead",
+                    s
+                ))
+            }
+        };
+
+        let interpolation = match object.get("interpolation").map(Value::as_str) {
+            None | Some(Some("latest")) => FrameInterpolation::Latest,
+            Some(Some("blend")) => FrameInterpolation::Blend,
+            Some(_) => {
+                return Err(format!(
+                    "Expected ndi.interpolation to be \"latest\" or \"blend\", got {:?}",
+                    object["interpolation"]
+                ))
+            }
+        };
+
+        Ok(Self {
+            source,
+            interpolation,
+            source_alpha: AlphaMode::parse(object, "source_alpha", false)?,
+            alpha: AlphaMode::parse(object, "alpha", true)?,
+            region: InputRegion::from_yaml(object)?,
+        })
+    }
+}
+
+/// Converts RGBA pixels from the alpha mode `from` into the mode `to`.
+pub fn convert_alpha(pixels: &mut [u8], from: AlphaMode, to: AlphaMode) {
+    match (from, to) {
+        (_, AlphaMode::Opaque) => pixels.chunks_exact_mut(4).for_each(|p| p[3] = 255),
+        (AlphaMode::Straight, AlphaMode::Premultiplied) => {
+            for p in pixels.chunks_exact_mut(4) {
+                let a = p[3] as u32;
+                for c in &mut p[..3] {
+                    *c = ((*c as u32 * a + 127) / 255) as u8;
+                }
+            }
+        }
+        (AlphaMode::Premultiplied, AlphaMode::Straight) => {
+            for p in pixels.chunks_exact_mut(4) {
+                let a = p[3] as u32;
+                for c in &mut p[..3] {
+                    *c = match a {
+                        0 => 0,
+                        a => ((*c as u32 * 255 + a / 2) / a).min(255) as u8,
+                    };
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Mixes two frames of the same size, `t = 0` gives `prev` and `t = 1`
+/// gives `next`.
+pub fn blend_frames(prev: &[u8], next: &[u8], t: f32, out: &mut [u8]) {
+    let t = (256.0 * t.clamp(0.0, 1.0)) as u16;
+    for ((a, b), c) in prev.iter().zip(next).zip(out) {
+        *c = ((*a as u16 * (256 - t) + *b as u16 * t) >> 8) as u8;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_ndi_input() {
+        let parse = |s: &str| NdiInput::from_yaml(&serde_yaml::from_str(s).unwrap());
+
+        let input = parse("{source: CAM, name: cam}").unwrap();
+        assert_eq!(input.source, "CAM");
+        assert_eq!(input.interpolation, FrameInterpolation::Latest);
+
+        let input = parse("{source: CAM, interpolation: blend}").unwrap();
+        assert_eq!(input.interpolation, FrameInterpolation::Blend);
+
+        assert_eq!(input.source_alpha, AlphaMode::Straight);
+        assert_eq!(input.alpha, AlphaMode::Straight);
+
+        let input = parse("{source: GFX, source_alpha: premultiplied, alpha: opaque}").unwrap();
+        assert_eq!(input.source_alpha, AlphaMode::Premultiplied);
+        assert_eq!(input.alpha, AlphaMode::Opaque);
+
+        assert!(parse("{source: GFX, source_alpha: opaque}").is_err());
+        assert!(parse("{source: CAM, interpolation: linear}").is_err());
+        assert!(parse("{name: cam}").is_err());
+    }
+
+    #[test]
+    fn blends_frames() {
+        let mut out = [0; 3];
+        blend_frames(&[0, 100, 255], &[255, 200, 255], 0.0, &mut out);
+        assert_eq!(out, [0, 100, 255]);
+        blend_frames(&[0, 100, 255], &[255, 200, 255], 0.5, &mut out);
+        assert_eq!(out, [127, 150, 255]);
+        blend_frames(&[0, 100, 255], &[255, 200, 255], 2.0, &mut out);
+        assert_eq!(out, [255, 200, 255]);
+    }
+
+    #[test]
+    fn crops_regions() {
+        let parse = |s: &str| InputRegion::from_yaml(&serde_yaml::from_str(s).unwrap());
+        assert!(parse("{}").unwrap().is_full());
+        assert!(parse("{crop: [0.5, 0.5, 0.75, 0.5]}").is_err());
+        assert!(parse("{crop: [0, 0, 1]}").is_err());
+        assert!(parse("{scale: 0}").is_err());
+
+        // top right quarter of an image which is flipped for the upload
+        let image = RgbaImage::from_fn(4, 4, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+        let region = parse("{crop: [0.5, 0, 0.5, 0.5]}").unwrap();
+        let out = region.apply(image.clone());
+        assert_eq!(out.dimensions(), (2, 2));
+        assert_eq!(out.get_pixel(0, 0).0, [2, 2, 0, 255]);
+        assert_eq!(out.get_pixel(1, 1).0, [3, 3, 0, 255]);
+
+        let region = parse("{crop: [0.5, 0, 0.5, 0.5], scale: 0.5}").unwrap();
+        assert_eq!(region.size(3840, 2160), (960, 540));
+        assert_eq!(region.apply(image).dimensions(), (1, 1));
+        assert_ne!(region.cache_key("a.png"), "a.png");
+    }
+
+    #[test]
+    fn converts_alpha() {
+        use AlphaMode::*;
+
+        let straight = [200, 100, 0, 128, 50, 60, 70, 0];
+        let mut pixels = straight;
+        convert_alpha(&mut pixels, Straight, Premultiplied);
+        assert_eq!(pixels, [100, 50, 0, 128, 0, 0, 0, 0]);
+
+        convert_alpha(&mut pixels, Premultiplied, Straight);
+        assert_eq!(pixels, [199, 100, 0, 128, 0, 0, 0, 0]);
+
+        convert_alpha(&mut pixels, Premultiplied, Opaque);
+        assert_eq!(pixels, [199, 100, 0, 255, 0, 0, 0, 255]);
+
+        let mut pixels = straight;
+        convert_alpha(&mut pixels, Straight, Straight);
+        assert_eq!(pixels, straight);
+    }
+}
@@ -0,0 +1,591 @@
+use std::collections::HashSet;
+
+use serde_yaml::{Mapping, Value};
+
+use super::StageSources;
+use crate::util::preprocess;
+
+/// Built-in variables and functions of ISF, expressed with the uniforms of
+/// this tool.
+const ISF_PRELUDE: &str = "\
+out vec4 out_color;
+uniform vec4 resolution;
+uniform float time;
+uniform float time_delta;
+uniform int frame_count;
+#define gl_FragColor out_color
+#define texture2D texture
+#define TIME time
+#define TIMEDELTA time_delta
+#define FRAMEINDEX frame_count
+#define RENDERSIZE resolution.xy
+#define isf_FragNormCoord (gl_FragCoord.xy / resolution.xy)
+#define vv_FragNormCoord isf_FragNormCoord
+#define IMG_SIZE(img) vec2(textureSize(img, 0))
+#define IMG_NORM_PIXEL(img, coord) texture(img, coord)
+#define IMG_PIXEL(img, coord) texture(img, (coord) / IMG_SIZE(img))
+#define IMG_THIS_NORM_PIXEL(img) IMG_NORM_PIXEL(img, isf_FragNormCoord)
+#define IMG_THIS_PIXEL(img) IMG_PIXEL(img, gl_FragCoord.xy)
+";
+
+/// Number of elements in the `sliders` uniform.
+const SLIDER_COUNT: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsfType {
+    Float,
+    Long,
+    Bool,
+    Event,
+    Color,
+    Point2D,
+    Image,
+}
+
+/// An entry of the `INPUTS` section of an ISF header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsfInput {
+    pub name: String,
+    pub kind: IsfType,
+    pub default: Option<Value>,
+    pub min: f32,
+    pub max: f32,
+    /// Allowed values of a `long` input, if it is a list of options.
+    pub values: Vec<i64>,
+}
+
+/// An entry of the `PASSES` section of an ISF header.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IsfPass {
+    pub target: Option<String>,
+    pub float: bool,
+    pub width: Option<Value>,
+    pub height: Option<Value>,
+}
+
+/// A fragment shader in the Interactive Shader Format, as used by VDMX and
+/// other VJ software.
+///
+/// The JSON header at the top of the file declares the inputs and render
+/// passes of the shader. The header is replaced by blank lines, so line
+/// numbers in error messages still match the file.
+#[derive(Debug, Clone)]
+pub struct IsfShader {
+    pub inputs: Vec<IsfInput>,
+    pub passes: Vec<IsfPass>,
+    /// Names of the images the shader expects to be imported.
+    pub imported: Vec<String>,
+    pub body: String,
+}
+
+impl IsfShader {
+    pub fn parse(code: &str) -> Result<Self, String> {
+        let start = code
+            .find("/*")
+            .filter(|&k| code[..k].trim().is_empty())
+            .ok_or("Expected ISF file to start with a JSON header comment")?;
+        let end = code[start..]
+            .find("*/")
+            .map(|k| start + k)
+            .ok_or("ISF header comment is never closed")?;
+
+        // JSON is valid YAML, so there is no need for another parser
+        let header: Value = serde_yaml::from_str(&code[start + 2..end])
+            .map_err(|e| format!("Failed to parse ISF header: {}", e))?;
+
+        let inputs = match header.get("INPUTS") {
+            Some(Value::Sequence(seq)) => seq
+                .iter()
+                .map(IsfInput::from_json)
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+            s => return Err(format!("Expected \"INPUTS\" to be an array, got {:?}", s)),
+        };
+
+        let passes = match header.get("PASSES") {
+            Some(Value::Sequence(seq)) if !seq.is_empty() => seq
+                .iter()
+                .map(IsfPass::from_json)
+                .collect::<Result<Vec<_>, _>>()?,
+            Some(Value::Sequence(_)) | None => vec![IsfPass::default()],
+            s => return Err(format!("Expected \"PASSES\" to be an array, got {:?}", s)),
+        };
+
+        let imported = match header.get("IMPORTED") {
+            Some(Value::Mapping(m)) => m
+                .keys()
+                .filter_map(Value::as_str)
+                .map(str::to_owned)
+                .collect(),
+            Some(Value::Sequence(seq)) => seq
+                .iter()
+                .filter_map(|v| v.get("NAME")?.as_str())
+                .map(str::to_owned)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let header_lines = code[..end + 2].matches('\n').count();
+        let body = "\n".repeat(header_lines) + &code[end + 2..];
+
+        Ok(Self {
+            inputs,
+            passes,
+            imported,
+            body,
+        })
+    }
+
+    /// Declarations of the inputs and pass targets, plus `#define`s for
+    /// inputs driven by sliders or renamed to another texture.
+    fn declarations(&self, object: &Value, pass: usize) -> Result<String, String> {
+        let sliders = string_map(object, "sliders")?;
+        let textures = string_map(object, "inputs")?;
+
+        let mut out = format!("#define PASSINDEX {}\n", pass);
+        if !sliders.is_empty() {
+            out += &format!("uniform float sliders[{}];\n", SLIDER_COUNT);
+        }
+
+        let mut samplers = HashSet::new();
+        let mut declare_sampler = |out: &mut String, name: &str| {
+            if samplers.insert(name.to_owned()) {
+                *out += &format!("uniform sampler2D {};\n", name);
+            }
+        };
+
+        for input in self.inputs.iter() {
+            let name = input.name.as_str();
+            if let Some((_, texture)) = textures.iter().find(|(k, _)| k == name) {
+                if input.kind != IsfType::Image {
+                    return Err(format!("ISF input {:?} is not an image", name));
+                }
+
+                declare_sampler(&mut out, texture);
+                out += &format!("#define {} {}\n", name, texture);
+                continue;
+            }
+
+            if let Some((_, slider)) = sliders.iter().find(|(k, _)| k == name) {
+                let slider = match slider.parse::<usize>() {
+                    Ok(k) if k < SLIDER_COUNT => format!("sliders[{}]", k),
+                    _ => {
+                        return Err(format!(
+                            "Expected slider of ISF input {:?} to be an index below {}, got {:?}",
+                            name, SLIDER_COUNT, slider
+                        ))
+                    }
+                };
+
+                let value = match input.kind {
+                    IsfType::Float => format!("mix({:?}, {:?}, {})", input.min, input.max, slider),
+                    IsfType::Bool | IsfType::Event => format!("({} > 0.5)", slider),
+                    IsfType::Long if !input.values.is_empty() => format!(
+                        "int[]({})[int(clamp({}, 0.0, 1.0) * {:?} + 0.5)]",
+                        join(&input.values),
+                        slider,
+                        (input.values.len() - 1) as f32
+                    ),
+                    IsfType::Long => format!(
+                        "int(round(mix({:?}, {:?}, {})))",
+                        input.min, input.max, slider
+                    ),
+                    _ => return Err(format!("ISF input {:?} can't be driven by a slider", name)),
+                };
+
+                out += &format!("#define {} {}\n", name, value);
+                continue;
+            }
+
+            match input.kind {
+                IsfType::Float => out += &format!("uniform float {};\n", name),
+                IsfType::Color => out += &format!("uniform vec4 {};\n", name),
+                IsfType::Point2D => out += &format!("uniform vec2 {};\n", name),
+                IsfType::Image => declare_sampler(&mut out, name),
+                IsfType::Long => {
+                    out += &format!("uniform float isf_{0};\n#define {0} int(isf_{0})\n", name)
+                }
+                IsfType::Bool | IsfType::Event => {
+                    out += &format!(
+                        "uniform float isf_{0};\n#define {0} (isf_{0} > 0.5)\n",
+                        name
+                    )
+                }
+            }
+        }
+
+        for name in self.imported.iter() {
+            declare_sampler(&mut out, name);
+        }
+
+        for target in self.passes.iter().filter_map(|p| p.target.as_ref()) {
+            declare_sampler(&mut out, target);
+        }
+
+        Ok(out)
+    }
+
+    /// Default values of the inputs declared as uniforms.
+    fn default_uniforms(&self) -> Mapping {
+        let mut out = Mapping::new();
+        for input in self.inputs.iter() {
+            let default = match (&input.default, input.kind) {
+                (_, IsfType::Image) => continue,
+                (Some(v), _) => v.clone(),
+                (None, IsfType::Float) => Value::from(input.min),
+                (None, IsfType::Long) => Value::from(input.values.first().copied().unwrap_or(0)),
+                (None, IsfType::Bool | IsfType::Event) => Value::from(false),
+                (None, IsfType::Color) => Value::from(vec![0.0, 0.0, 0.0, 1.0]),
+                (None, IsfType::Point2D) => Value::from(vec![0.0, 0.0]),
+            };
+
+            out.insert(input.uniform_name().into(), default);
+        }
+
+        out
+    }
+}
+
+impl IsfInput {
+    fn from_json(object: &Value) -> Result<Self, String> {
+        let name = match object.get("NAME") {
+            Some(Value::String(s)) => s.clone(),
+            s => {
+                return Err(format!(
+                    "Expected ISF input \"NAME\" to be a string, got {:?}",
+                    s
+                ))
+            }
+        };
+
+        let kind = match object.get("TYPE").and_then(Value::as_str) {
+            Some("float") => IsfType::Float,
+            Some("long") => IsfType::Long,
+            Some("bool") => IsfType::Bool,
+            Some("event") => IsfType::Event,
+            Some("color") => IsfType::Color,
+            Some("point2D") => IsfType::Point2D,
+            Some("image") => IsfType::Image,
+            s => {
+                return Err(format!(
+                    "Type {:?} of ISF input {:?} is not supported",
+                    s, name
+                ))
+            }
+        };
+
+        let number = |key: &str, default: f32| match object.get(key) {
+            None => Ok(default),
+            Some(v) => v.as_f64().map(|f| f as f32).ok_or_else(|| {
+                format!(
+                    "Expected {:?} of ISF input {:?} to be a number, got {:?}",
+                    key, name, v
+                )
+            }),
+        };
+
+        let min = number("MIN", 0.0)?;
+        let max = number("MAX", 1.0)?;
+
+        let values = match object.get("VALUES") {
+            Some(Value::Sequence(seq)) => seq
+                .iter()
+                .map(|v| v.as_i64())
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| {
+                    format!(
+                        "Expected \"VALUES\" of ISF input {:?} to be a list of integers",
+                        name
+                    )
+                })?,
+            _ => Vec::new(),
+        };
+
+        Ok(Self {
+            name,
+            kind,
+            default: object.get("DEFAULT").cloned(),
+            min,
+            max,
+            values,
+        })
+    }
+
+    /// Name of the uniform holding the value of this input.
+    fn uniform_name(&self) -> String {
+        match self.kind {
+            IsfType::Long | IsfType::Bool | IsfType::Event => format!("isf_{}", self.name),
+            _ => self.name.clone(),
+        }
+    }
+}
+
+impl IsfPass {
+    fn from_json(object: &Value) -> Result<Self, String> {
+        let target = match object.get("TARGET") {
+            Some(Value::String(s)) => Some(s.clone()),
+            None => None,
+            s => {
+                return Err(format!(
+                    "Expected ISF pass \"TARGET\" to be a string, got {:?}",
+                    s
+                ))
+            }
+        };
+
+        let float = match object.get("FLOAT") {
+            Some(Value::Bool(b)) => *b,
+            Some(Value::Number(n)) => n.as_f64() != Some(0.0),
+            _ => false,
+        };
+
+        Ok(Self {
+            target,
+            float,
+            width: object.get("WIDTH").cloned(),
+            height: object.get("HEIGHT").cloned(),
+        })
+    }
+
+    /// Size of the pass, translated to the expressions used by `resolution`.
+    fn resolution(&self) -> Result<Option<Value>, String> {
+        fn dimension(value: &Option<Value>, screen: &str) -> Result<Value, String> {
+            match value {
+                None => Ok(Value::from(screen)),
+                Some(Value::Number(n)) => Ok(Value::Number(n.clone())),
+                Some(Value::String(s)) => {
+                    let expr = s
+                        .replace("$WIDTH", "screen.x")
+                        .replace("$HEIGHT", "screen.y");
+                    match expr.contains('$') {
+                        true => Err(format!("ISF pass size {:?} is not supported", s)),
+                        false => Ok(Value::from(expr)),
+                    }
+                }
+                Some(s) => Err(format!(
+                    "Expected ISF pass size to be an expression, got {:?}",
+                    s
+                )),
+            }
+        }
+
+        if self.width.is_none() && self.height.is_none() {
+            return Ok(None);
+        }
+
+        let width = dimension(&self.width, "screen.x")?;
+        let height = dimension(&self.height, "screen.y")?;
+        Ok(Some(Value::Sequence(vec![width, height])))
+    }
+}
+
+/// Expands a stage with an `isf` field into one stage per pass of the shader.
+///
+/// All other fields of the stage apply to the last pass, which renders to the
+/// target of the stage, except for `uniforms`, which apply to every pass.
+pub fn isf_stages(object: &Value) -> Result<Vec<(Value, StageSources)>, String> {
+    let file = match object.get("isf") {
+        Some(Value::String(s)) => s,
+        s => return Err(format!("Expected \"isf\" to be a filename, got {:?}", s)),
+    };
+
+    let code = std::fs::read_to_string(file).map_err(|e| format!("{}, {}", e, file))?;
+    let shader = IsfShader::parse(&code).map_err(|e| format!("{}, {}", e, file))?;
+
+    let mut lut = Vec::new();
+    let body = preprocess(&shader.body, file, &mut lut)?;
+
+    // default values of the inputs, overridden by the values in the stage
+    let mut uniforms = shader.default_uniforms();
+    if let Some(Value::Mapping(m)) = object.get("uniforms") {
+        for (key, value) in m {
+            let name = key.as_str().unwrap_or_default();
+            let key = match shader.inputs.iter().find(|i| i.name == name) {
+                Some(input) => input.uniform_name().into(),
+                None => key.clone(),
+            };
+            uniforms.insert(key, value.clone());
+        }
+    }
+
+    let mut out = Vec::with_capacity(shader.passes.len() + 1);
+    for (k, pass) in shader.passes.iter().enumerate() {
+        let mut stage = Mapping::new();
+        stage.insert("isf".into(), file.as_str().into());
+        stage.insert("uniforms".into(), Value::Mapping(uniforms.clone()));
+        if let Some(target) = &pass.target {
+            stage.insert("target".into(), target.as_str().into());
+        }
+        if let Some(resolution) = pass.resolution()? {
+            stage.insert("resolution".into(), resolution);
+        }
+        if pass.float {
+            stage.insert("float".into(), true.into());
+        }
+
+        let fs = format!(
+            "#version 330\n{}{}{}",
+            ISF_PRELUDE,
+            shader.declarations(object, k)?,
+            body
+        );
+        let sources = StageSources {
+            fs: Some(fs),
+            lut: lut.clone(),
+            ..StageSources::default()
+        };
+
+        out.push((Value::Mapping(stage), sources));
+    }
+
+    // the output of the last pass has to be copied out of its own target
+    if let Some(target) = &shader.passes.last().unwrap().target {
+        let fs = format!(
+            "#version 330\n\
+             out vec4 out_color;\n\
+             uniform vec4 resolution;\n\
+             uniform sampler2D {};\n\
+             void main() {{\n    \
+                 out_color = texture({}, gl_FragCoord.xy / resolution.xy);\n\
+             }}\n",
+            target, target
+        );
+        let sources = StageSources {
+            fs: Some(fs),
+            lut: lut.clone(),
+            ..StageSources::default()
+        };
+
+        let mut stage = Mapping::new();
+        stage.insert("isf".into(), file.as_str().into());
+        out.push((Value::Mapping(stage), sources));
+    }
+
+    // the remaining fields of the stage belong to the last pass
+    if let Some((Value::Mapping(last), _)) = out.last_mut() {
+        if let Value::Mapping(m) = object {
+            for (key, value) in m {
+                let skip = ["isf", "sliders", "inputs", "uniforms"];
+                if !skip.iter().any(|s| key.as_str() == Some(s)) {
+                    last.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Reads a mapping of strings, like the `sliders` and `inputs` fields of an
+/// ISF stage.
+fn string_map(object: &Value, key: &str) -> Result<Vec<(String, String)>, String> {
+    let m = match object.get(key) {
+        Some(Value::Mapping(m)) => m,
+        None => return Ok(Vec::new()),
+        s => return Err(format!("Expected {:?} to be a mapping, got {:?}", key, s)),
+    };
+
+    let mut out = Vec::with_capacity(m.len());
+    for (k, v) in m {
+        let value = match v {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            s => {
+                return Err(format!(
+                    "Expected value of {:?} to be a name, got {:?}",
+                    k, s
+                ))
+            }
+        };
+
+        match k.as_str() {
+            Some(name) => out.push((name.to_owned(), value)),
+            None => {
+                return Err(format!(
+                    "Expected key of {:?} to be a string, got {:?}",
+                    key, k
+                ))
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn join(values: &[i64]) -> String {
+    let values: Vec<_> = values.iter().map(i64::to_string).collect();
+    values.join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SHADER: &str = "/*{
+\t\"DESCRIPTION\": \"test\",
+\t\"INPUTS\": [
+\t\t{\"NAME\": \"amount\", \"TYPE\": \"float\", \"DEFAULT\": 0.5, \"MIN\": 0.0, \"MAX\": 2.0},
+\t\t{\"NAME\": \"invert\", \"TYPE\": \"bool\", \"DEFAULT\": true},
+\t\t{\"NAME\": \"mode\", \"TYPE\": \"long\", \"VALUES\": [0, 1, 2], \"DEFAULT\": 1},
+\t\t{\"NAME\": \"inputImage\", \"TYPE\": \"image\"}
+\t],
+\t\"PASSES\": [
+\t\t{\"TARGET\": \"blurA\", \"WIDTH\": \"$WIDTH/2\", \"FLOAT\": true},
+\t\t{}
+\t]
+}*/
+void main() {
+\tgl_FragColor = IMG_THIS_PIXEL(blurA) * amount;
+}
+";
+
+    #[test]
+    fn parses_header() {
+        let shader = IsfShader::parse(SHADER).unwrap();
+        assert_eq!(shader.inputs.len(), 4);
+        assert_eq!(shader.inputs[0].max, 2.0);
+        assert_eq!(shader.inputs[2].values, vec![0, 1, 2]);
+        assert_eq!(shader.passes.len(), 2);
+        assert_eq!(shader.passes[0].target.as_deref(), Some("blurA"));
+        assert!(shader.passes[0].float);
+
+        // line numbers are kept intact
+        assert_eq!(shader.body.lines().nth(13), Some("void main() {"));
+
+        let res = shader.passes[0].resolution().unwrap().unwrap();
+        let expected: Value = serde_yaml::from_str("[screen.x/2, screen.y]").unwrap();
+        assert_eq!(res, expected);
+        assert_eq!(shader.passes[1].resolution().unwrap(), None);
+
+        let uniforms = shader.default_uniforms();
+        assert_eq!(uniforms.get("amount"), Some(&Value::from(0.5)));
+        assert_eq!(uniforms.get("isf_invert"), Some(&Value::from(true)));
+        assert_eq!(uniforms.get("isf_mode"), Some(&Value::from(1)));
+        assert_eq!(uniforms.get("inputImage"), None);
+
+        assert!(IsfShader::parse("void main() {}").is_err());
+    }
+
+    #[test]
+    fn maps_inputs() {
+        let shader = IsfShader::parse(SHADER).unwrap();
+        let object: Value = serde_yaml::from_str(
+            "{isf: a.fs, sliders: {amount: 3, mode: 4}, inputs: {inputImage: webcam}}",
+        )
+        .unwrap();
+
+        let decl = shader.declarations(&object, 1).unwrap();
+        assert!(decl.contains("#define PASSINDEX 1\n"));
+        assert!(decl.contains("uniform float sliders[32];\n"));
+        assert!(decl.contains("#define amount mix(0.0, 2.0, sliders[3])\n"));
+        assert!(decl.contains(
+            "#define mode int[](0, 1, 2)[int(clamp(sliders[4], 0.0, 1.0) * 2.0 + 0.5)]\n"
+        ));
+        assert!(decl.contains("uniform float isf_invert;\n#define invert (isf_invert > 0.5)\n"));
+        assert!(decl.contains("uniform sampler2D webcam;\n#define inputImage webcam\n"));
+        assert!(decl.contains("uniform sampler2D blurA;\n"));
+
+        let object: Value = serde_yaml::from_str("{isf: a.fs, inputs: {amount: webcam}}").unwrap();
+        assert!(shader.declarations(&object, 0).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    ffi::{c_void, CStr, CString},
+    os::raw::{c_char, c_int, c_ulong},
+    ptr,
+    sync::{Arc, Mutex},
+};
+
+use libloading::Library;
+
+use crate::util::RingBuffer;
+
+#[cfg(target_os = "windows")]
+const LIBRARY_NAMES: &[&str] = &["libjack64.dll", "libjack.dll"];
+#[cfg(target_os = "macos")]
+const LIBRARY_NAMES: &[&str] = &["libjack.0.dylib", "libjack.dylib"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const LIBRARY_NAMES: &[&str] = &["libjack.so.0", "libjack.so"];
+
+const AUDIO_PORT_TYPE: &[u8] = b"32 bit float mono audio\0";
+
+const JACK_NO_START_SERVER: c_int = 0x01;
+const JACK_PORT_IS_INPUT: c_ulong = 0x1;
+const JACK_PORT_IS_OUTPUT: c_ulong = 0x2;
+const JACK_PORT_IS_PHYSICAL: c_ulong = 0x4;
+
+type Client = c_void;
+type Port = c_void;
+type ProcessCallback = unsafe extern "C" fn(u32, *mut c_void) -> c_int;
+
+/// Where audio is taken from when using JACK.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JackConfig {
+    /// Name of the client shown in patchbays.
+    pub client_name: String,
+    /// One or two output ports to record, matched by part of their full
+    /// name like `"Ardour:Master/audio_out"`. The first two physical
+    /// capture ports are used if empty.
+    pub ports: Vec<String>,
+}
+
+impl Default for JackConfig {
+    fn default() -> Self {
+        Self {
+            client_name: "Sh4derJockey".into(),
+            ports: Vec::new(),
+        }
+    }
+}
+
+/// The functions of the JACK client library, which is loaded at runtime so
+/// the tool doesn't depend on it. PipeWire provides it as well.
+struct JackApi {
+    client_open: unsafe extern "C" fn(*const c_char, c_int, *mut c_int, ...) -> *mut Client,
+    client_close: unsafe extern "C" fn(*mut Client) -> c_int,
+    port_register: unsafe extern "C" fn(
+        *mut Client,
+        *const c_char,
+        *const c_char,
+        c_ulong,
+        c_ulong,
+    ) -> *mut Port,
+    port_name: unsafe extern "C" fn(*const Port) -> *const c_char,
+    port_get_buffer: unsafe extern "C" fn(*mut Port, u32) -> *mut c_void,
+    set_process_callback: unsafe extern "C" fn(*mut Client, ProcessCallback, *mut c_void) -> c_int,
+    activate: unsafe extern "C" fn(*mut Client) -> c_int,
+    deactivate: unsafe extern "C" fn(*mut Client) -> c_int,
+    connect: unsafe extern "C" fn(*mut Client, *const c_char, *const c_char) -> c_int,
+    get_ports: unsafe extern "C" fn(
+        *mut Client,
+        *const c_char,
+        *const c_char,
+        c_ulong,
+    ) -> *mut *const c_char,
+    get_sample_rate: unsafe extern "C" fn(*mut Client) -> u32,
+    free: unsafe extern "C" fn(*mut c_void),
+    _lib: Library,
+}
+
+impl JackApi {
+    fn load() -> Result<Self, String> {
+        let lib = LIBRARY_NAMES
+            .iter()
+            .find_map(|name| unsafe { Library::new(name) }.ok())
+            .ok_or_else(|| {
+                format!(
+                    "Failed to load the JACK library, tried {}",
+                    LIBRARY_NAMES.join(", ")
+                )
+            })?;
+
+        unsafe {
+            macro_rules! sym {
+                ($name:literal) => {
+                    *lib.get(concat!($name, "\0").as_bytes())
+                        .map_err(|e| format!("Failed to load {}: {}", $name, e))?
+                };
+            }
+
+            Ok(Self {
+                client_open: sym!("jack_client_open"),
+                client_close: sym!("jack_client_close"),
+                port_register: sym!("jack_port_register"),
+                port_name: sym!("jack_port_name"),
+                port_get_buffer: sym!("jack_port_get_buffer"),
+                set_process_callback: sym!("jack_set_process_callback"),
+                activate: sym!("jack_activate"),
+                deactivate: sym!("jack_deactivate"),
+                connect: sym!("jack_connect"),
+                get_ports: sym!("jack_get_ports"),
+                get_sample_rate: sym!("jack_get_sample_rate"),
+                free: sym!("jack_free"),
+                _lib: lib,
+            })
+        }
+    }
+}
+
+/// Data the process callback needs, which runs on the JACK thread.
+struct Process {
+    get_buffer: unsafe extern "C" fn(*mut Port, u32) -> *mut c_void,
+    ports: Vec<*mut Port>,
+    samples: [Arc<Mutex<RingBuffer<f32>>>; 2],
+}
+
+unsafe extern "C" fn process_callback(frames: u32, arg: *mut c_void) -> c_int {
+    let process = unsafe { &*(arg as *const Process) };
+    for (port, samples) in process.ports.iter().zip(process.samples.iter()) {
+        let buffer = unsafe { (process.get_buffer)(*port, frames) } as *const f32;
+        if buffer.is_null() {
+            continue;
+        }
+
+        let data = unsafe { std::slice::from_raw_parts(buffer, frames as _) };
+        if let Ok(mut samples) = samples.try_lock() {
+            samples.push_slice(data);
+        }
+    }
+
+    0
+}
+
+/// Picks the port for each pattern, in the order of the patterns.
+fn match_ports(available: &[String], patterns: &[String]) -> Result<Vec<String>, String> {
+    patterns
+        .iter()
+        .map(|pat| {
+            available
+                .iter()
+                .find(|name| name.contains(pat.as_str()))
+                .cloned()
+                .ok_or_else(|| format!("Failed to find JACK port {:?}", pat))
+        })
+        .collect()
+}
+
+/// A JACK client recording one or two ports into the sample buffers of the
+/// audio module.
+pub struct JackInput {
+    api: JackApi,
+    client: *mut Client,
+    process: Box<Process>,
+    pub sample_rate: u32,
+    /// Full names of the ports which are recorded.
+    pub sources: Vec<String>,
+}
+
+impl std::fmt::Debug for JackInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(JackInput))
+            .field("sample_rate", &self.sample_rate)
+            .field("sources", &self.sources)
+            .finish()
+    }
+}
+
+impl JackInput {
+    /// Opens a client and connects it to the ports set in `config`.
+    ///
+    /// The server is never started by the client, it has to be running
+    /// already, e.g. as part of PipeWire.
+    pub fn connect(
+        config: &JackConfig,
+        left: Arc<Mutex<RingBuffer<f32>>>,
+        right: Arc<Mutex<RingBuffer<f32>>>,
+    ) -> Result<Self, String> {
+        if config.ports.len() > 2 {
+            return Err(format!(
+                "Expected one or two JACK ports, got {}",
+                config.ports.len()
+            ));
+        }
+
+        let api = JackApi::load()?;
+        let name = CString::new(config.client_name.as_str()).map_err(|e| e.to_string())?;
+
+        let mut status = 0;
+        let client = unsafe { (api.client_open)(name.as_ptr(), JACK_NO_START_SERVER, &mut status) };
+        if client.is_null() {
+            return Err(format!(
+                "Failed to connect to the JACK server, status 0x{:x}",
+                status
+            ));
+        }
+
+        let process = Box::new(Process {
+            get_buffer: api.port_get_buffer,
+            ports: Vec::new(),
+            samples: [left, right],
+        });
+
+        // from here on, dropping the input closes the client
+        let mut this = Self {
+            api,
+            client,
+            process,
+            sample_rate: 0,
+            sources: Vec::new(),
+        };
+
+        unsafe {
+            // fall back to the first capture ports of the sound card
+            this.sources = match config.ports.as_slice() {
+                [] => {
+                    let mut ports = this.ports(JACK_PORT_IS_OUTPUT | JACK_PORT_IS_PHYSICAL);
+                    ports.truncate(2);
+                    if ports.is_empty() {
+                        return Err("No JACK capture ports found".into());
+                    }
+                    ports
+                }
+                patterns => {
+                    let ports = this.ports(JACK_PORT_IS_OUTPUT);
+                    match_ports(&ports, patterns)?
+                }
+            };
+
+            for k in 0..this.sources.len() {
+                let port_name = CString::new(format!("in_{}", k + 1)).unwrap();
+                let port = (this.api.port_register)(
+                    client,
+                    port_name.as_ptr(),
+                    AUDIO_PORT_TYPE.as_ptr() as _,
+                    JACK_PORT_IS_INPUT,
+                    0,
+                );
+
+                if port.is_null() {
+                    return Err(format!("Failed to register JACK port {:?}", port_name));
+                }
+
+                this.process.ports.push(port);
+            }
+
+            let arg = &*this.process as *const Process as *mut c_void;
+            if (this.api.set_process_callback)(client, process_callback, arg) != 0 {
+                return Err("Failed to set the JACK process callback".into());
+            }
+
+            if (this.api.activate)(client) != 0 {
+                return Err("Failed to activate the JACK client".into());
+            }
+
+            // ports can only be connected once the client is active
+            for (source, port) in this.sources.iter().zip(this.process.ports.iter()) {
+                let source_name = CString::new(source.as_str()).unwrap();
+                let own_name = (this.api.port_name)(*port);
+                if (this.api.connect)(client, source_name.as_ptr(), own_name) != 0 {
+                    return Err(format!("Failed to connect to JACK port {:?}", source));
+                }
+            }
+
+            this.sample_rate = (this.api.get_sample_rate)(client);
+        }
+
+        log::info!("Recording JACK ports {:?}", this.sources);
+        Ok(this)
+    }
+
+    /// Lists the full names of the audio ports with the given flags.
+    fn ports(&self, flags: c_ulong) -> Vec<String> {
+        let mut out = Vec::new();
+        unsafe {
+            let list = (self.api.get_ports)(
+                self.client,
+                ptr::null(),
+                AUDIO_PORT_TYPE.as_ptr() as _,
+                flags,
+            );
+
+            if list.is_null() {
+                return out;
+            }
+
+            let mut k = 0;
+            while !(*list.add(k)).is_null() {
+                out.push(CStr::from_ptr(*list.add(k)).to_string_lossy().into_owned());
+                k += 1;
+            }
+
+            (self.api.free)(list as _);
+        }
+
+        out
+    }
+}
+
+impl Drop for JackInput {
+    fn drop(&mut self) {
+        // the process callback must not run anymore once the ports are gone
+        unsafe {
+            (self.api.deactivate)(self.client);
+            (self.api.client_close)(self.client);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches_ports() {
+        let available = vec![
+            "system:capture_1".to_string(),
+            "system:capture_2".to_string(),
+            "Ardour:Master/audio_out 1".to_string(),
+            "Ardour:Master/audio_out 2".to_string(),
+        ];
+
+        let patterns = ["Master/audio_out 2".to_string(), "capture_1".to_string()];
+        assert_eq!(
+            match_ports(&available, &patterns).unwrap(),
+            ["Ardour:Master/audio_out 2", "system:capture_1"]
+        );
+
+        let err = match_ports(&available, &["Bitwig".to_string()]).unwrap_err();
+        assert_eq!(err, "Failed to find JACK port \"Bitwig\"");
+    }
+}
@@ -0,0 +1,497 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    ops::Range,
+};
+
+use gl::types::*;
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Value};
+
+use super::{apply_profile, expand_yaml_aliases, PASS_VERT};
+use crate::util::*;
+
+lazy_static! {
+    static ref LAYER_NAME: CString = CString::new("layer").unwrap();
+    static ref OPACITY_NAME: CString = CString::new("opacity").unwrap();
+    static ref MODE_NAME: CString = CString::new("mode").unwrap();
+}
+
+/// Sections of a layer which are merged into the combined pipeline
+const MERGED_SECTIONS: &[&str] = &["stages", "images", "ndi", "tbo", "ubo", "readback"];
+
+/// How a layer is combined with the layers below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerBlend {
+    Normal = 0,
+    Add = 1,
+    Multiply = 2,
+    Screen = 3,
+}
+
+impl LayerBlend {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "normal" => Some(Self::Normal),
+            "add" => Some(Self::Add),
+            "multiply" => Some(Self::Multiply),
+            "screen" => Some(Self::Screen),
+            _ => None,
+        }
+    }
+
+    fn blend_func(self) -> (GLenum, GLenum) {
+        match self {
+            Self::Normal => (gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA),
+            Self::Add => (gl::SRC_ALPHA, gl::ONE),
+            Self::Multiply => (gl::DST_COLOR, gl::ZERO),
+            Self::Screen => (gl::ONE, gl::ONE_MINUS_SRC_COLOR),
+        }
+    }
+}
+
+/// A pipeline rendered into its own buffer and mixed into the output.
+#[derive(Debug)]
+pub struct Layer {
+    pub pipeline: String,
+    pub blend: LayerBlend,
+    pub opacity: f32,
+    pub slider: Option<usize>,
+    pub stages: Range<usize>,
+    /// Opacity set in the pipeline file.
+    file_opacity: f32,
+    eyes: Vec<FrameBuffer>,
+}
+
+impl Layer {
+    fn from_yaml(object: &Value) -> Result<Self, String> {
+        let pipeline = match object.get("pipeline") {
+            Some(Value::String(s)) => s.clone(),
+            s => return Err(format!("Expected \"pipeline\" to be a string, got {:?}", s)),
+        };
+
+        let blend = match object.get("blend") {
+            Some(Value::String(s)) => LayerBlend::parse(s).ok_or_else(|| {
+                format!(
+                    "Expected \"blend\" to be one of normal, add, multiply or screen, got {:?}",
+                    s
+                )
+            })?,
+            None => LayerBlend::Normal,
+            s => return Err(format!("Expected \"blend\" to be a string, got {:?}", s)),
+        };
+
+        let opacity = match object.get("opacity") {
+            Some(s) => match s.as_f64() {
+                Some(x) if (0.0..=1.0).contains(&x) => x as f32,
+                _ => {
+                    return Err(format!(
+                        "Expected \"opacity\" to be a number between 0 and 1, got {:?}",
+                        s
+                    ))
+                }
+            },
+            None => 1.0,
+        };
+
+        let slider = match object.get("slider") {
+            Some(s) => match s.as_u64() {
+                Some(n) => Some(n as usize),
+                None => return Err(format!("Expected \"slider\" to be an index, got {:?}", s)),
+            },
+            None => None,
+        };
+
+        Ok(Self {
+            pipeline,
+            blend,
+            opacity,
+            slider,
+            stages: 0..0,
+            file_opacity: opacity,
+            eyes: Vec::new(),
+        })
+    }
+
+    /// Takes over the opacity of the same layer in the previous build, so
+    /// changes made in the UI survive a reload, unless the opacity in the
+    /// file has been changed.
+    pub fn keep_opacity(&mut self, old: &[Layer]) {
+        let same = old.iter().find(|layer| {
+            layer.pipeline == self.pipeline && layer.file_opacity == self.file_opacity
+        });
+
+        if let Some(layer) = same {
+            self.opacity = layer.opacity;
+        }
+    }
+
+    /// Opacity after applying the bound slider, if any.
+    pub fn effective_opacity(&self, sliders: &[f32]) -> f32 {
+        match self.slider.and_then(|k| sliders.get(k)) {
+            Some(value) => self.opacity * value,
+            None => self.opacity,
+        }
+    }
+}
+
+/// Reads the pipelines listed under `layers` and merges them into one.
+///
+/// The stages, textures and buffers of all layers end up in the combined
+/// pipeline, while the stages rendering to the screen are later redirected
+/// into the buffer of their layer. Texture names must be unique across
+/// layers, as they share a single namespace.
+///
+/// Returns the layers along with warnings about ignored options.
+pub fn merge_layers(
+    object: &mut Value,
+    profile: Option<&str>,
+    aliases: &HashMap<String, String>,
+) -> Result<(Vec<Layer>, Vec<String>), String> {
+    let root = match object {
+        Value::Mapping(m) => m,
+        _ => return Ok((Vec::new(), Vec::new())),
+    };
+
+    let list = match root.remove("layers") {
+        Some(Value::Sequence(s)) if !s.is_empty() => s,
+        Some(s) => {
+            return Err(format!(
+                "Expected \"layers\" to be a non-empty list, got {:?}",
+                s
+            ))
+        }
+        None => return Ok((Vec::new(), Vec::new())),
+    };
+
+    if root.contains_key("stages") {
+        return Err("A pipeline with \"layers\" can't have \"stages\" of its own".into());
+    }
+
+    let mut layers = Vec::new();
+    let mut warnings = Vec::new();
+    let mut owners = HashMap::<String, String>::new();
+    let mut stage_count = 0;
+
+    for item in list.iter() {
+        let mut layer = Layer::from_yaml(item)?;
+        let mut part = read_layer_file(&layer.pipeline)?;
+        apply_profile(&mut part, profile)?;
+        expand_yaml_aliases(&mut part, aliases)?;
+
+        let part = match part {
+            Value::Mapping(m) => m,
+            s => {
+                return Err(format!(
+                    "Expected layer {:?} to be a mapping, got {:?}",
+                    layer.pipeline, s
+                ))
+            }
+        };
+
+        for name in declared_names(&part) {
+            if let Some(owner) = owners.insert(name.clone(), layer.pipeline.clone()) {
+                return Err(format!(
+                    "Layers {:?} and {:?} both define {:?}, please rename one of them",
+                    owner, layer.pipeline, name
+                ));
+            }
+        }
+
+        for (key, value) in part {
+            let key = match key.as_str() {
+                Some("layers") => {
+                    return Err(format!(
+                        "Layer {:?} can't have layers of its own",
+                        layer.pipeline
+                    ))
+                }
+                Some(s) if MERGED_SECTIONS.contains(&s) => s.to_owned(),
+                _ => {
+                    warnings.push(format!(
+                        "Option {:?} of layer {:?} is ignored, set it next to \"layers\" instead",
+                        key, layer.pipeline
+                    ));
+                    continue;
+                }
+            };
+
+            if key == "stages" {
+                let count = value.as_sequence().map_or(0, Vec::len);
+                layer.stages = stage_count..stage_count + count;
+                stage_count += count;
+            }
+
+            match (root.get_mut(key.as_str()), value) {
+                (Some(Value::Sequence(all)), Value::Sequence(mut seq)) => all.append(&mut seq),
+                (Some(Value::Mapping(all)), Value::Mapping(map)) => all.extend(map),
+                (Some(_), _) => {
+                    return Err(format!(
+                        "Section {:?} of layer {:?} doesn't match the other layers",
+                        key, layer.pipeline
+                    ))
+                }
+                (None, value) => {
+                    root.insert(key.into(), value);
+                }
+            }
+        }
+
+        layers.push(layer);
+    }
+
+    Ok((layers, warnings))
+}
+
+fn read_layer_file(path: &str) -> Result<Value, String> {
+    let reader = std::fs::File::open(path).map_err(|e| format!("{}, {}", e, path))?;
+    serde_yaml::from_reader(reader).map_err(|e| format!("{}, {}", e, path))
+}
+
+/// Names of all textures and buffers a layer creates.
+fn declared_names(part: &Mapping) -> HashSet<String> {
+    let mut names = HashSet::new();
+
+    if let Some(Value::Sequence(stages)) = part.get("stages") {
+        for stage in stages {
+            for key in ["target", "feedback"] {
+                if let Some(name) = stage.get(key).and_then(Value::as_str) {
+                    names.insert(name.to_owned());
+                }
+            }
+        }
+    }
+
+    for section in ["images", "ndi", "tbo"] {
+        if let Some(Value::Sequence(items)) = part.get(section) {
+            for item in items {
+                if let Some(name) = item.get("name").and_then(Value::as_str) {
+                    names.insert(name.to_owned());
+                }
+            }
+        }
+    }
+
+    if let Some(Value::Mapping(blocks)) = part.get("ubo") {
+        names.extend(blocks.keys().filter_map(Value::as_str).map(String::from));
+    }
+
+    names
+}
+
+/// Buffers and the blend pass of a layered pipeline.
+#[derive(Debug)]
+pub struct Layers {
+    pub layers: Vec<Layer>,
+    prog_id: GLuint,
+    sh_ids: Vec<GLuint>,
+}
+
+impl Layers {
+    pub fn new(layers: Vec<Layer>) -> Self {
+        const LAYER_FRAG: &str = include_str!("shaders/layer.frag");
+
+        let sh_ids = vec![
+            compile_shader(PASS_VERT, gl::VERTEX_SHADER).unwrap(),
+            compile_shader(LAYER_FRAG, gl::FRAGMENT_SHADER).unwrap(),
+        ];
+
+        let prog_id = link_program(&sh_ids).unwrap();
+
+        Self {
+            layers,
+            prog_id,
+            sh_ids,
+        }
+    }
+
+    /// Resizes the layer buffers, if the output size has changed, and clears
+    /// them for the next frame.
+    pub fn begin_frame(&mut self, width: u32, height: u32, eye_count: usize) {
+        for layer in self.layers.iter_mut() {
+            let outdated = layer.eyes.len() != eye_count
+                || layer.eyes.iter().any(|fb| {
+                    let res = fb.resolution();
+                    res[0] != width || res[1] != height
+                });
+
+            if outdated {
+                layer.eyes = (0..eye_count)
+                    .map(|_| {
+                        FrameBuffer::with_params(
+                            width,
+                            height,
+                            gl::LINEAR,
+                            gl::LINEAR,
+                            gl::CLAMP_TO_EDGE,
+                            false,
+                            false,
+                        )
+                    })
+                    .collect();
+            }
+
+            for fb in layer.eyes.iter() {
+                unsafe {
+                    gl::BindFramebuffer(gl::FRAMEBUFFER, fb.fb_id);
+                    gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+                    gl::Clear(gl::COLOR_BUFFER_BIT);
+                }
+            }
+        }
+        gl_debug_check!();
+    }
+
+    /// Framebuffer a screen stage renders into instead of the output.
+    pub fn framebuffer(&self, stage: usize, eye: usize) -> Option<GLuint> {
+        self.layers
+            .iter()
+            .find(|layer| layer.stages.contains(&stage))
+            .and_then(|layer| layer.eyes.get(eye))
+            .map(|fb| fb.fb_id)
+    }
+
+    /// Blends all layers of an eye on top of each other into the given
+    /// framebuffer.
+    pub fn composite(
+        &self,
+        vao: GLuint,
+        eye: usize,
+        sliders: &[f32],
+        target_fb: GLuint,
+        width: u32,
+        height: u32,
+    ) {
+        unsafe {
+            let blending = gl::IsEnabled(gl::BLEND) != 0;
+            gl::Enable(gl::BLEND);
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, target_fb);
+            gl::Viewport(0, 0, width as _, height as _);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::UseProgram(self.prog_id);
+            gl_debug_check!();
+
+            let layer_loc = gl::GetUniformLocation(self.prog_id, LAYER_NAME.as_ptr());
+            let res_loc = gl::GetUniformLocation(self.prog_id, super::RESOLUTION_NAME.as_ptr());
+            let opacity_loc = gl::GetUniformLocation(self.prog_id, OPACITY_NAME.as_ptr());
+            let mode_loc = gl::GetUniformLocation(self.prog_id, MODE_NAME.as_ptr());
+
+            gl::Uniform1i(layer_loc, 0);
+            gl::Uniform2f(res_loc, width as _, height as _);
+
+            for layer in self.layers.iter() {
+                let fb = match layer.eyes.get(eye) {
+                    Some(fb) => fb,
+                    None => continue,
+                };
+
+                let (src, dst) = layer.blend.blend_func();
+                gl::BlendFunc(src, dst);
+
+                gl::ActiveTexture(gl::TEXTURE0);
+                fb.bind(0);
+                gl::Uniform1f(opacity_loc, layer.effective_opacity(sliders));
+                gl::Uniform1i(mode_loc, layer.blend as _);
+                gl_debug_check!();
+
+                draw_fullscreen_pass(self.prog_id, vao);
+            }
+
+            gl::BlendFunc(gl::ONE, gl::ZERO);
+            if !blending {
+                gl::Disable(gl::BLEND);
+            }
+            gl_debug_check!();
+        }
+    }
+}
+
+impl Drop for Layers {
+    fn drop(&mut self) {
+        unsafe {
+            for &id in self.sh_ids.iter() {
+                gl::DetachShader(self.prog_id, id);
+                gl::DeleteShader(id);
+            }
+
+            gl::DeleteProgram(self.prog_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_layer() {
+        let object = serde_yaml::from_str("{pipeline: a.yaml, blend: screen, slider: 2}").unwrap();
+        let layer = Layer::from_yaml(&object).unwrap();
+        assert_eq!(layer.blend, LayerBlend::Screen);
+        assert_eq!(layer.effective_opacity(&[1.0, 1.0, 0.25]), 0.25);
+
+        let object = serde_yaml::from_str("{pipeline: a.yaml, opacity: 2}").unwrap();
+        assert!(Layer::from_yaml(&object).is_err());
+    }
+
+    #[test]
+    fn keeps_opacity_on_reload() {
+        let parse = |yaml| Layer::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+
+        let mut old = parse("{pipeline: a.yaml, opacity: 0.5}");
+        old.opacity = 0.2;
+        let old = [old];
+
+        let mut layer = parse("{pipeline: a.yaml, opacity: 0.5, blend: add}");
+        layer.keep_opacity(&old);
+        assert_eq!(layer.opacity, 0.2);
+
+        // a new value in the file wins over the one from the UI
+        let mut layer = parse("{pipeline: a.yaml, opacity: 0.8}");
+        layer.keep_opacity(&old);
+        assert_eq!(layer.opacity, 0.8);
+
+        let mut layer = parse("{pipeline: b.yaml, opacity: 0.5}");
+        layer.keep_opacity(&old);
+        assert_eq!(layer.opacity, 0.5);
+    }
+
+    #[test]
+    fn merges_layer_files() {
+        let dir = std::env::temp_dir().join("sh4der-jockey-layers-test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let tunnel = dir.join("tunnel.yaml");
+        let particles = dir.join("particles.yaml");
+        std::fs::write(
+            &tunnel,
+            "stages: [{fs: a.frag, target: bloom}, {fs: b.frag}]",
+        )
+        .unwrap();
+        std::fs::write(
+            &particles,
+            "stages: [{fs: c.frag}]\naudio: {audio_samples: 4096}",
+        )
+        .unwrap();
+
+        let yaml = format!(
+            "layers: [{{pipeline: {:?}}}, {{pipeline: {:?}, blend: add}}]",
+            tunnel, particles
+        );
+        let mut object: Value = serde_yaml::from_str(&yaml).unwrap();
+        let (layers, warnings) = merge_layers(&mut object, None, &HashMap::new()).unwrap();
+
+        assert_eq!(object["stages"].as_sequence().unwrap().len(), 3);
+        assert_eq!(layers[0].stages, 0..2);
+        assert_eq!(layers[1].stages, 2..3);
+        assert_eq!(warnings.len(), 1);
+
+        // layers share one namespace, so their targets can't have the same name
+        std::fs::write(&particles, "stages: [{fs: c.frag, target: bloom}]").unwrap();
+        let mut object: Value = serde_yaml::from_str(&yaml).unwrap();
+        let err = merge_layers(&mut object, None, &HashMap::new()).unwrap_err();
+        assert!(err.contains("both define \"bloom\""), "{}", err);
+    }
+}
@@ -0,0 +1,90 @@
+//! The shader pipeline engine behind Sh4derJockey.
+//!
+//! This crate reads `pipeline.yaml` files, compiles their stages and renders
+//! them with OpenGL. It does not create windows or contexts on its own, so
+//! it can be embedded into any program which has a current OpenGL context
+//! and has loaded the function pointers with [`gl::load_with`].
+//!
+//! The [`Engine`] renders a pipeline into a framebuffer of the host:
+//!
+//! ```no_run
+//! use sh4der_jockey_core::{Engine, Uniform};
+//!
+//! # fn run() -> Result<(), String> {
+//! let mut engine = Engine::new();
+//! engine.load("pipeline.yaml", 1280, 720)?;
+//! engine.set_uniform("brightness", Uniform::Float(0.8))?;
+//!
+//! loop {
+//!     // draw into the default framebuffer at 60 fps
+//!     engine.render(0, 1280, 720, 1.0 / 60.0);
+//!     # break;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Programs which need more control can drive the [`Pipeline`] and its
+//! stages themselves, like the tool does for stereo and projection output.
+//!
+//! The `sh4der-jockey` binary is a thin frontend around this crate, which
+//! adds the window, the control panel, MIDI, NDI and hot reloading.
+
+#[macro_use]
+pub mod util;
+
+mod alias;
+mod audio;
+mod capture;
+mod draw;
+mod engine;
+mod features;
+mod feedback;
+mod history;
+mod indices;
+mod inputs;
+mod isf;
+mod jack;
+mod layers;
+mod lines;
+mod loader;
+mod macros;
+mod metering;
+mod mixer;
+mod pipeline;
+mod profile;
+mod quality;
+mod readback;
+mod shadertoy;
+mod stage;
+mod ubo;
+mod uniforms;
+mod variations;
+
+pub use alias::*;
+pub use audio::*;
+pub use capture::*;
+pub use draw::*;
+pub use engine::*;
+pub use features::*;
+pub use feedback::*;
+pub use history::*;
+pub use indices::*;
+pub use inputs::*;
+pub use isf::*;
+pub use jack::*;
+pub use layers::*;
+pub use lines::*;
+pub use loader::*;
+pub use macros::*;
+pub use metering::*;
+pub use mixer::*;
+pub use pipeline::*;
+pub use profile::*;
+pub use quality::*;
+pub use readback::*;
+pub use shadertoy::*;
+pub use stage::*;
+pub use ubo::*;
+pub use uniforms::*;
+pub use variations::*;
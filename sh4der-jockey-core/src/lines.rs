@@ -0,0 +1,160 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::util::insert_defines;
+
+/// Prefix for the vertex shader outputs, which are forwarded by the
+/// geometry shader under their original names
+const FORWARD_PREFIX: &str = "sj_vs_";
+
+/// A plain output variable declared by a vertex shader
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Output {
+    qualifier: String,
+    ty: String,
+    name: String,
+}
+
+fn find_outputs(vs: &str) -> Vec<Output> {
+    lazy_static! {
+        static ref OUT_RE: Regex = Regex::new(
+            r"^\s*(?P<qualifier>(?:flat|smooth|noperspective)\s+)?out\s+(?P<ty>\w+)\s+(?P<name>\w+)\s*;"
+        )
+        .expect("failed to compile regex");
+    }
+
+    vs.lines()
+        .filter_map(|line| OUT_RE.captures(line))
+        .map(|cap| Output {
+            qualifier: cap
+                .name("qualifier")
+                .map_or("", |m| m.as_str().trim())
+                .to_string(),
+            ty: cap["ty"].to_string(),
+            name: cap["name"].to_string(),
+        })
+        .filter(|out| !out.name.starts_with("gl_"))
+        .collect()
+}
+
+/// Expands lines into screen aligned quads of the given thickness.
+///
+/// `glLineWidth` is limited to a width of 1 by most core profile drivers,
+/// so thick lines are emulated with a geometry shader instead. Outputs of
+/// the vertex shader are renamed by a macro and forwarded by the geometry
+/// shader under their original names, so the fragment shader is unchanged.
+///
+/// Takes the preprocessed vertex shader and returns the modified vertex
+/// shader together with the matching geometry shader.
+pub fn thick_line_shaders(vs: &str) -> (String, String) {
+    let outputs = find_outputs(vs);
+
+    // rename outputs without shifting the line numbers of the source
+    let defines: String = outputs
+        .iter()
+        .map(|out| format!("#define {} {}{}\n", out.name, FORWARD_PREFIX, out.name))
+        .collect();
+
+    let vs = insert_defines(vs, &defines);
+
+    let mut declarations = String::new();
+    let mut assignments = String::new();
+    for out in outputs.iter() {
+        declarations += &format!(
+            "{q} in {ty} {p}{name}[];\n{q} out {ty} {name};\n",
+            q = out.qualifier,
+            ty = out.ty,
+            p = FORWARD_PREFIX,
+            name = out.name,
+        );
+        assignments += &format!(
+            "    {name} = {p}{name}[i];\n",
+            p = FORWARD_PREFIX,
+            name = out.name
+        );
+    }
+
+    let gs = format!(
+        r#"#version 150
+
+layout(lines) in;
+layout(triangle_strip, max_vertices = 4) out;
+
+// target width, target height, line thickness
+uniform vec3 thick_line_params;
+
+{declarations}
+void emit(int i, vec2 offset) {{
+{assignments}    gl_Position = gl_in[i].gl_Position + vec4(offset * gl_in[i].gl_Position.w, 0.0, 0.0);
+    EmitVertex();
+}}
+
+void main() {{
+    vec2 res = thick_line_params.xy;
+    vec2 a = gl_in[0].gl_Position.xy / gl_in[0].gl_Position.w;
+    vec2 b = gl_in[1].gl_Position.xy / gl_in[1].gl_Position.w;
+
+    vec2 dir = (b - a) * res;
+    dir = length(dir) > 0.0 ? normalize(dir) : vec2(1.0, 0.0);
+    vec2 offset = vec2(-dir.y, dir.x) * thick_line_params.z / res;
+
+    emit(0, offset);
+    emit(0, -offset);
+    emit(1, offset);
+    emit(1, -offset);
+    EndPrimitive();
+}}
+"#,
+        declarations = declarations,
+        assignments = assignments,
+    );
+
+    (vs, gs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VS: &str = "#version 440
+#define SH4DERJOCKEY 1
+#line 2 0
+uniform float time;
+out vec4 v_color;
+flat out int v_id;
+out float gl_ClipDistance[1];
+
+void main() {
+    v_color = vec4(1.0);
+    v_id = gl_VertexID;
+}";
+
+    #[test]
+    fn finds_plain_outputs() {
+        let outputs = find_outputs(VS);
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].name, "v_color");
+        assert_eq!(outputs[1].qualifier, "flat");
+        assert_eq!(outputs[1].ty, "int");
+    }
+
+    #[test]
+    fn renames_outputs_before_line_directive() {
+        let (vs, _) = thick_line_shaders(VS);
+        let lines: Vec<_> = vs.lines().collect();
+        assert_eq!(lines[0], "#version 440");
+        assert_eq!(lines[1], "#define v_color sj_vs_v_color");
+        assert_eq!(lines[2], "#define v_id sj_vs_v_id");
+        assert_eq!(lines[3], "#define SH4DERJOCKEY 1");
+        assert_eq!(lines[4], "#line 2 0");
+    }
+
+    #[test]
+    fn forwards_outputs() {
+        let (_, gs) = thick_line_shaders(VS);
+        assert!(gs.contains(" in vec4 sj_vs_v_color[];"));
+        assert!(gs.contains(" out vec4 v_color;"));
+        assert!(gs.contains("flat in int sj_vs_v_id[];"));
+        assert!(gs.contains("v_id = sj_vs_v_id[i];"));
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, TryRecvError},
+};
+
+use async_std::task::yield_now;
+use image::RgbaImage;
+use serde_yaml::Value;
+
+use super::{
+    apply_profile, expand_yaml_aliases, isf_stages, merge_layers, util::CubeLut, InputRegion,
+    Layer, StageSources,
+};
+
+/// Everything about a pipeline which can be prepared without OpenGL.
+///
+/// Reading, parsing and preprocessing the files happens on a worker thread,
+/// so the render thread only has to create the GL objects.
+pub struct PipelineSource {
+    pub object: Value,
+    pub profiles: Vec<String>,
+    pub layers: Vec<Layer>,
+    pub warnings: Vec<String>,
+    /// Sources of every stage, in the order of the stages section.
+    pub stages: Vec<Result<StageSources, String>>,
+    /// Decoded images by path, except for those found in the texture cache.
+    pub images: HashMap<String, Result<RgbaImage, String>>,
+}
+
+impl PipelineSource {
+    /// Loads a pipeline file on a worker thread and waits for it to finish.
+    ///
+    /// `cached` lists the paths of cached textures, which don't need to be
+    /// decoded again.
+    pub async fn load(
+        path: PathBuf,
+        profile: Option<String>,
+        aliases: HashMap<String, String>,
+        cached: HashSet<String>,
+    ) -> Result<Self, String> {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let source = Self::read(&path, profile.as_deref(), &aliases, &cached);
+            let _ = sender.send(source);
+        });
+
+        loop {
+            match receiver.try_recv() {
+                Ok(source) => return source,
+                Err(TryRecvError::Empty) => yield_now().await,
+                Err(TryRecvError::Disconnected) => {
+                    return Err("Pipeline loader stopped unexpectedly".into())
+                }
+            }
+        }
+    }
+
+    fn read(
+        path: &Path,
+        profile: Option<&str>,
+        aliases: &HashMap<String, String>,
+        cached: &HashSet<String>,
+    ) -> Result<Self, String> {
+        let reader = match std::fs::File::open(path) {
+            Ok(s) => s,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let mut object = match serde_yaml::from_reader(reader) {
+            Ok(s) => s,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let profiles = apply_profile(&mut object, profile)?;
+        expand_yaml_aliases(&mut object, aliases)?;
+        let (mut layers, mut warnings) = merge_layers(&mut object, profile, aliases)?;
+
+        if let Some(name) = profile {
+            if !profiles.iter().any(|s| s == name) {
+                warnings.push(format!(
+                    "Profile {:?} is not defined in this pipeline",
+                    name
+                ));
+            }
+        }
+
+        // errors in here are reported once the stage gets built
+        let (stages, offsets) = match object.get_mut("stages") {
+            Some(Value::Sequence(s)) => load_stages(s),
+            _ => (Vec::new(), vec![0]),
+        };
+
+        // ISF stages of a layer may have turned into several stages
+        for layer in layers.iter_mut() {
+            layer.stages = offsets[layer.stages.start]..offsets[layer.stages.end];
+        }
+
+        let images = decode_images(&object, cached);
+
+        Ok(Self {
+            object,
+            profiles,
+            layers,
+            warnings,
+            stages,
+            images,
+        })
+    }
+}
+
+/// Loads the sources of all stages, replacing ISF stages with one stage per
+/// render pass of the shader.
+///
+/// Also returns where each of the original stages ended up, with one more
+/// entry for the end of the list.
+fn load_stages(stages: &mut Vec<Value>) -> (Vec<Result<StageSources, String>>, Vec<usize>) {
+    let mut expanded = Vec::with_capacity(stages.len());
+    let mut sources = Vec::with_capacity(stages.len());
+    let mut offsets = Vec::with_capacity(stages.len() + 1);
+    for stage in stages.drain(..) {
+        offsets.push(expanded.len());
+        if stage.get("isf").is_none() {
+            sources.push(StageSources::load(&stage));
+            expanded.push(stage);
+            continue;
+        }
+
+        match isf_stages(&stage) {
+            Ok(passes) => {
+                for (pass, pass_sources) in passes {
+                    expanded.push(pass);
+                    sources.push(Ok(pass_sources));
+                }
+            }
+            Err(err) => {
+                expanded.push(stage);
+                sources.push(Err(err));
+            }
+        }
+    }
+
+    offsets.push(expanded.len());
+    *stages = expanded;
+    (sources, offsets)
+}
+
+/// Decodes all image files listed in the images section, skipping the ones
+/// which are cached already and lookup tables.
+///
+/// Malformed entries are left to the pipeline build, which reports them.
+fn decode_images(
+    object: &Value,
+    cached: &HashSet<String>,
+) -> HashMap<String, Result<RgbaImage, String>> {
+    let mut out = HashMap::new();
+    let images = match object.get("images") {
+        Some(Value::Sequence(s)) => s,
+        _ => return out,
+    };
+
+    for image in images {
+        let name = match image.get("name") {
+            Some(Value::String(s)) => s.as_str(),
+            _ => continue,
+        };
+
+        let paths: Vec<&str> = match image.get("path") {
+            Some(Value::String(s)) => vec![s.as_str()],
+            Some(Value::Sequence(seq)) => seq.iter().filter_map(Value::as_str).collect(),
+            _ => continue,
+        };
+
+        let is_lut = CubeLut::is_lut_path(&paths);
+        let region = InputRegion::from_yaml(image).unwrap_or_default();
+        if is_lut || cached.contains(&region.cache_key(&paths.join(";"))) {
+            continue;
+        }
+
+        for path in paths {
+            if !out.contains_key(path) {
+                out.insert(path.to_owned(), decode_image(name, path));
+            }
+        }
+    }
+
+    out
+}
+
+/// Reads an image file, flipped so the first row ends up at the bottom.
+pub fn decode_image(name: &str, path: &str) -> Result<RgbaImage, String> {
+    let reader = image::io::Reader::open(path)
+        .map_err(|_| format!("Failed to open image {:?} at {:?}", name, path))?;
+
+    let dyn_image = reader
+        .decode()
+        .map_err(|_| format!("Failed to decode image {:?} at {:?}", name, path))?;
+
+    Ok(dyn_image.flipv().to_rgba8())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn skips_cached_images() {
+        let object: Value = serde_yaml::from_str(
+            "images:\n\
+             - { name: cached, path: cached.png }\n\
+             - { name: lut, path: grade.cube }\n\
+             - { name: missing, path: [missing.png, missing.png] }\n",
+        )
+        .unwrap();
+
+        let mut cached = HashSet::new();
+        cached.insert("cached.png".to_owned());
+
+        let images = decode_images(&object, &cached);
+        assert_eq!(images.len(), 1);
+
+        let err = images["missing.png"].as_ref().unwrap_err();
+        assert_eq!(err, "Failed to open image \"missing\" at \"missing.png\"");
+    }
+
+    #[test]
+    fn expands_isf_stages_in_layers() {
+        let dir = std::env::temp_dir().join("sh4der-jockey-loader-test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let blur = dir.join("blur.fs");
+        std::fs::write(
+            &blur,
+            "/*{\"PASSES\": [{\"TARGET\": \"blurA\"}, {}]}*/\nvoid main() {}\n",
+        )
+        .unwrap();
+
+        let tunnel = dir.join("tunnel.yaml");
+        let particles = dir.join("particles.yaml");
+        let stages = format!("stages: [{{isf: {:?}}}, {{fs: a.frag}}]", blur);
+        std::fs::write(&tunnel, stages).unwrap();
+        std::fs::write(&particles, "stages: [{fs: c.frag}]").unwrap();
+
+        let pipeline = dir.join("pipeline.yaml");
+        let yaml = format!(
+            "layers: [{{pipeline: {:?}}}, {{pipeline: {:?}}}]",
+            tunnel, particles
+        );
+        std::fs::write(&pipeline, yaml).unwrap();
+
+        let source =
+            PipelineSource::read(&pipeline, None, &HashMap::new(), &HashSet::new()).unwrap();
+        assert_eq!(source.stages.len(), 4);
+        assert_eq!(source.layers[0].stages, 0..3);
+        assert_eq!(source.layers[1].stages, 3..4);
+    }
+}
@@ -0,0 +1,262 @@
+use std::ffi::CString;
+
+use serde_yaml::Value;
+
+use crate::Uniform;
+
+/// Curve a macro slider is mapped through before it reaches a target.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Starts slow and ends fast.
+    EaseIn,
+    /// Starts fast and ends slow.
+    EaseOut,
+    /// Starts and ends slow.
+    Smooth,
+    /// Jumps from the start to the end value halfway.
+    Step,
+}
+
+impl Easing {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "linear" => Some(Self::Linear),
+            "ease_in" => Some(Self::EaseIn),
+            "ease_out" => Some(Self::EaseOut),
+            "smooth" => Some(Self::Smooth),
+            "step" => Some(Self::Step),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Linear => "linear",
+            Self::EaseIn => "ease_in",
+            Self::EaseOut => "ease_out",
+            Self::Smooth => "smooth",
+            Self::Step => "step",
+        }
+    }
+
+    /// Maps `t` from 0 to 1 onto the curve.
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Self::Smooth => t * t * (3.0 - 2.0 * t),
+            Self::Step => (t >= 0.5) as u8 as f32,
+        }
+    }
+}
+
+/// A uniform driven by a macro, moving from `from` to `to` as the slider
+/// goes from 0 to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MacroTarget {
+    pub uniform: CString,
+    pub from: Vec<f32>,
+    pub to: Vec<f32>,
+    pub easing: Easing,
+}
+
+impl MacroTarget {
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let uniform = match object.get("uniform") {
+            Some(Value::String(s)) => CString::new(s.as_str()).unwrap(),
+            s => {
+                return Err(format!(
+                    "Expected macro target \"uniform\" to be a string, got {:?}",
+                    s
+                ))
+            }
+        };
+
+        let components = |key: &str| -> Result<Vec<f32>, String> {
+            let value = object.get(key).ok_or_else(|| {
+                format!(
+                    "Required field {:?} of macro target {:?} not found",
+                    key, uniform
+                )
+            })?;
+
+            let values = match value {
+                Value::Number(n) => n.as_f64().map(|f| vec![f as f32]),
+                Value::Sequence(seq) => seq
+                    .iter()
+                    .map(|v| v.as_f64().map(|f| f as f32))
+                    .collect::<Option<Vec<_>>>(),
+                _ => None,
+            };
+
+            match values {
+                Some(v) if (1..=4).contains(&v.len()) => Ok(v),
+                _ => Err(format!(
+                    "Expected {:?} of macro target {:?} to be a number or a vector of up to 4 numbers, got {:?}",
+                    key, uniform, value
+                )),
+            }
+        };
+
+        let from = components("from")?;
+        let to = components("to")?;
+        if from.len() != to.len() {
+            return Err(format!(
+                "Expected \"from\" and \"to\" of macro target {:?} to have the same size",
+                uniform
+            ));
+        }
+
+        let easing = match object.get("curve") {
+            None => Easing::default(),
+            Some(s) => s.as_str().and_then(Easing::parse).ok_or_else(|| {
+                format!(
+                    "Expected \"curve\" to be one of linear, ease_in, ease_out, smooth or step, got {:?}",
+                    s
+                )
+            })?,
+        };
+
+        Ok(Self {
+            uniform,
+            from,
+            to,
+            easing,
+        })
+    }
+
+    /// Value of the uniform for a slider position from 0 to 1.
+    pub fn value(&self, t: f32) -> Uniform {
+        let t = self.easing.apply(t);
+        let v: Vec<f32> = self
+            .from
+            .iter()
+            .zip(&self.to)
+            .map(|(a, b)| a + (b - a) * t)
+            .collect();
+
+        match *v.as_slice() {
+            [x] => Uniform::Float(x),
+            [x, y] => Uniform::Vec2(x, y),
+            [x, y, z] => Uniform::Vec3(x, y, z),
+            [x, y, z, w] => Uniform::Vec4(x, y, z, w),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// One slider driving several uniforms at once, so a single fader can
+/// perform a coordinated scene change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macro {
+    pub name: String,
+    /// Index of the slider in the `sliders` array.
+    pub slider: usize,
+    pub targets: Vec<MacroTarget>,
+}
+
+impl Macro {
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let name = match object.get("name") {
+            Some(Value::String(s)) => s.clone(),
+            s => {
+                return Err(format!(
+                    "Expected macro \"name\" to be a string, got {:?}",
+                    s
+                ))
+            }
+        };
+
+        let slider = match object.get("slider").and_then(Value::as_u64) {
+            Some(k) => k as usize,
+            None => {
+                return Err(format!(
+                    "Expected \"slider\" of macro {:?} to be a slider index, got {:?}",
+                    name,
+                    object.get("slider")
+                ))
+            }
+        };
+
+        let targets = match object.get("targets") {
+            Some(Value::Sequence(seq)) => seq
+                .iter()
+                .map(MacroTarget::from_yaml)
+                .collect::<Result<Vec<_>, _>>()?,
+            s => {
+                return Err(format!(
+                    "Expected \"targets\" of macro {:?} to be an array, got {:?}",
+                    name, s
+                ))
+            }
+        };
+
+        Ok(Self {
+            name,
+            slider,
+            targets,
+        })
+    }
+
+    /// Values of all targets for the current slider positions.
+    pub fn uniforms<'a>(
+        &'a self,
+        sliders: &[f32],
+    ) -> impl Iterator<Item = (&'a CString, Uniform)> + 'a {
+        let t = sliders.get(self.slider).copied().unwrap_or(0.0);
+        self.targets
+            .iter()
+            .map(move |target| (&target.uniform, target.value(t)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn drives_targets() {
+        let yaml = "
+            name: drop
+            slider: 2
+            targets:
+              - {uniform: zoom, from: 1, to: 3, curve: ease_in}
+              - {uniform: color, from: [1, 0, 0], to: [0, 0, 1]}
+        ";
+        let m = Macro::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        assert_eq!(m.slider, 2);
+
+        let out: Vec<_> = m.uniforms(&[0.0, 0.0, 0.5]).collect();
+        assert_eq!(out[0].0.to_str().unwrap(), "zoom");
+        assert_eq!(out[0].1, Uniform::Float(1.5));
+        assert_eq!(out[1].1, Uniform::Vec3(0.5, 0.0, 0.5));
+
+        let bad = "{name: a, slider: 0, targets: [{uniform: x, from: 0, to: [1, 2]}]}";
+        assert!(Macro::from_yaml(&serde_yaml::from_str(bad).unwrap()).is_err());
+        let bad = "{name: a, slider: 0, targets: [{uniform: x, from: 0, to: 1, curve: bouncy}]}";
+        assert!(Macro::from_yaml(&serde_yaml::from_str(bad).unwrap()).is_err());
+    }
+
+    #[test]
+    fn easing_curves() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::Smooth,
+        ] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            assert_eq!(Easing::parse(easing.name()), Some(easing));
+        }
+
+        assert!(Easing::EaseIn.apply(0.5) < 0.5);
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+        assert_eq!(Easing::Step.apply(0.49), 0.0);
+        assert_eq!(Easing::Step.apply(2.0), 1.0);
+    }
+}
@@ -0,0 +1,327 @@
+use std::ffi::CString;
+
+use gl::types::*;
+use lazy_static::lazy_static;
+use serde_yaml::Value;
+
+use crate::util::*;
+
+lazy_static! {
+    static ref IMAGE_NAME: CString = CString::new("image").unwrap();
+}
+
+/// Size of the buffer the image is scaled down to before averaging
+const METER_SIZE: u32 = 64;
+
+/// Mipmap level containing the single averaged pixel
+const METER_LEVEL: GLint = 6;
+
+/// Measures the average luminance of an image.
+///
+/// The image is scaled down into a small float buffer and averaged by
+/// generating mipmaps. The result is read back asynchronously, so it becomes
+/// available a frame later without stalling the render loop.
+#[derive(Debug)]
+pub struct LumaMeter {
+    buffer: FrameBuffer,
+    readback: PixelBuffer,
+    log_pass: Option<FullscreenPass>,
+    pub value: f32,
+}
+
+impl Default for LumaMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LumaMeter {
+    pub fn new() -> Self {
+        Self::with_log_pass(None)
+    }
+
+    /// Creates a meter for the log-average luminance of a texture.
+    pub fn log_average() -> Self {
+        const LOG_LUMA_FRAG: &str = include_str!("shaders/log_luma.frag");
+
+        let pass = FullscreenPass::new(LOG_LUMA_FRAG).unwrap();
+        Self::with_log_pass(Some(pass))
+    }
+
+    fn with_log_pass(log_pass: Option<FullscreenPass>) -> Self {
+        let buffer = FrameBuffer::with_params(
+            METER_SIZE,
+            METER_SIZE,
+            gl::LINEAR_MIPMAP_LINEAR,
+            gl::LINEAR,
+            gl::CLAMP_TO_EDGE,
+            true,
+            true,
+        );
+
+        Self {
+            buffer,
+            readback: PixelBuffer::new(),
+            log_pass,
+            value: 0.0,
+        }
+    }
+
+    /// Fetches the result of the last measurement, if it is ready.
+    pub fn poll(&mut self) {
+        if !self.readback.poll(false) {
+            return;
+        }
+
+        let mut rgba = [0.0_f32; 4];
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(
+                rgba.as_mut_ptr() as *mut u8,
+                std::mem::size_of_val(&rgba),
+            )
+        };
+        self.readback.read(bytes);
+
+        let luma = 0.2126 * rgba[0] + 0.7152 * rgba[1] + 0.0722 * rgba[2];
+        self.value = match self.log_pass {
+            Some(_) => luma.exp(),
+            None => luma,
+        };
+    }
+
+    /// Returns `true` if a new measurement is ready to be issued.
+    pub fn is_idle(&self) -> bool {
+        self.readback.is_idle()
+    }
+
+    /// Starts measuring the contents of the given framebuffer.
+    pub fn measure(&mut self, fb_id: GLuint, width: u32, height: u32) {
+        // don't queue up more than one measurement
+        if !self.is_idle() {
+            return;
+        }
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fb_id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.buffer.fb_id);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                width as _,
+                height as _,
+                0,
+                0,
+                METER_SIZE as _,
+                METER_SIZE as _,
+                gl::COLOR_BUFFER_BIT,
+                gl::LINEAR,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl_debug_check!();
+        }
+
+        self.read_back();
+    }
+
+    /// Starts measuring the log-average luminance of the given texture.
+    ///
+    /// Only available for meters created with [`LumaMeter::log_average`].
+    pub fn measure_texture(&mut self, vao: GLuint, texture: &dyn Texture) {
+        if !self.is_idle() {
+            return;
+        }
+
+        let pass = match &self.log_pass {
+            Some(s) => s,
+            None => return,
+        };
+
+        let fb_id = self.buffer.fb_id;
+        pass.draw(vao, fb_id, METER_SIZE, METER_SIZE, |pass| unsafe {
+            gl::ActiveTexture(gl::TEXTURE0);
+            texture.bind(0);
+            gl::Uniform1i(pass.location(&IMAGE_NAME), 0);
+        });
+
+        self.read_back();
+    }
+
+    /// Averages the buffer and starts reading back the result.
+    fn read_back(&mut self) {
+        self.readback.begin(4 * std::mem::size_of::<f32>());
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.buffer.tex_id);
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+            gl_debug_check!();
+
+            gl::GetTexImage(
+                gl::TEXTURE_2D,
+                METER_LEVEL,
+                gl::RGBA,
+                gl::FLOAT,
+                std::ptr::null_mut(),
+            );
+            gl_debug_check!();
+        }
+        self.readback.end();
+    }
+}
+
+/// Derives an exposure value from the brightness of a buffer.
+///
+/// The log-average luminance of the buffer is measured every frame and the
+/// exposure is adjusted smoothly, so that the average ends up at the `key`
+/// brightness.
+#[derive(Debug)]
+pub struct AutoExposure {
+    pub buffer: CString,
+    pub key: f32,
+    pub speed: f32,
+    meter: LumaMeter,
+    value: Option<f32>,
+}
+
+impl AutoExposure {
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let buffer = match object.get("buffer") {
+            Some(Value::String(s)) => CString::new(s.as_str()).unwrap(),
+            Some(s) => return Err(format!("Expected \"buffer\" to be a string, got {:?}", s)),
+            None => return Err("Required field \"buffer\" not found".to_string()),
+        };
+
+        let key = match object.get("key") {
+            Some(s) => match s.as_f64() {
+                Some(f) if f > 0.0 => f as f32,
+                _ => {
+                    return Err(format!(
+                        "Expected \"key\" to be a positive number, got {:?}",
+                        s
+                    ))
+                }
+            },
+            None => 0.18,
+        };
+
+        let speed = match object.get("speed") {
+            Some(s) => match s.as_f64() {
+                Some(f) if f > 0.0 => f as f32,
+                _ => {
+                    return Err(format!(
+                        "Expected \"speed\" to be a positive number, got {:?}",
+                        s
+                    ))
+                }
+            },
+            None => 1.0,
+        };
+
+        Ok(Self {
+            buffer,
+            key,
+            speed,
+            meter: LumaMeter::log_average(),
+            value: None,
+        })
+    }
+
+    /// Adjusts the exposure and starts measuring the buffer for the next frame.
+    pub fn update(&mut self, vao: GLuint, texture: &dyn Texture, delta: f32) {
+        let was_idle = self.meter.is_idle();
+        self.meter.poll();
+
+        // only react to fresh measurements
+        if !was_idle && self.meter.is_idle() {
+            let target = self.key / self.meter.value.max(1e-4);
+            self.value = Some(match self.value {
+                Some(value) => value + (target - value) * (1.0 - (-self.speed * delta).exp()),
+                None => target,
+            });
+        }
+
+        self.meter.measure_texture(vao, texture);
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.value.unwrap_or(1.0)
+    }
+}
+
+/// Counts the samples written by a stage with an occlusion query.
+#[derive(Debug)]
+pub struct SampleCounter {
+    pub name: CString,
+    query: GLuint,
+    pending: bool,
+    pub samples: u32,
+}
+
+impl SampleCounter {
+    /// Creates a counter, which is exposed to shaders as `<target>_samples`.
+    pub fn new(target: &CString) -> Self {
+        let mut bytes = target.as_bytes().to_vec();
+        bytes.extend_from_slice(b"_samples");
+        let name = CString::new(bytes).unwrap();
+
+        let mut query = 0;
+        unsafe {
+            gl::GenQueries(1, &mut query);
+            gl_debug_check!();
+        }
+
+        Self {
+            name,
+            query,
+            pending: false,
+            samples: 0,
+        }
+    }
+
+    /// Fetches the result of the last query, if it is ready.
+    pub fn poll(&mut self) {
+        if !self.pending {
+            return;
+        }
+
+        unsafe {
+            let mut available = 0;
+            gl::GetQueryObjectiv(self.query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+            if available != 0 {
+                let mut samples = 0;
+                gl::GetQueryObjectuiv(self.query, gl::QUERY_RESULT, &mut samples);
+                self.samples = samples;
+                self.pending = false;
+            }
+            gl_debug_check!();
+        }
+    }
+
+    /// Starts counting, unless the previous result is still outstanding.
+    pub fn begin(&mut self) -> bool {
+        if self.pending {
+            return false;
+        }
+
+        unsafe {
+            gl::BeginQuery(gl::SAMPLES_PASSED, self.query);
+            gl_debug_check!();
+        }
+
+        self.pending = true;
+        true
+    }
+
+    pub fn end(&self) {
+        unsafe {
+            gl::EndQuery(gl::SAMPLES_PASSED);
+            gl_debug_check!();
+        }
+    }
+}
+
+impl Drop for SampleCounter {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteQueries(1, &self.query);
+        }
+    }
+}
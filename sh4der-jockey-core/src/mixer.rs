@@ -0,0 +1,117 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Number of stages which can be faded in the mixer
+pub const MIXER_CHANNELS: usize = 64;
+
+lazy_static! {
+    static ref MAIN_RE: Regex = Regex::new(r"\bvoid\s+main\s*\(\s*(void)?\s*\)").unwrap();
+}
+
+/// Channel faders for the output of each stage.
+///
+/// The values are uploaded as the `stage_opacity` and `stage_tint` uniform
+/// arrays, which an epilogue injected into every fragment shader multiplies
+/// into `out_color`.
+#[derive(Debug)]
+pub struct Mixer {
+    pub opacity: [f32; MIXER_CHANNELS],
+    pub tint: [[f32; 3]; MIXER_CHANNELS],
+    pub sliders: [Option<usize>; MIXER_CHANNELS],
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self {
+            opacity: [1.0; MIXER_CHANNELS],
+            tint: [[1.0; 3]; MIXER_CHANNELS],
+            sliders: [None; MIXER_CHANNELS],
+        }
+    }
+}
+
+impl Mixer {
+    /// Opacity of all channels after applying the linked sliders.
+    pub fn effective_opacity(&self, sliders: &[f32]) -> [f32; MIXER_CHANNELS] {
+        let mut out = self.opacity;
+        for (value, slider) in out.iter_mut().zip(self.sliders.iter()) {
+            if let Some(x) = slider.and_then(|k| sliders.get(k)) {
+                *value *= x;
+            }
+        }
+
+        out
+    }
+
+    /// Moves all faders back to full opacity and white tint.
+    pub fn reset(&mut self) {
+        *self = Self {
+            sliders: self.sliders,
+            ..Default::default()
+        };
+    }
+}
+
+/// Wraps the `main` function of a fragment shader, so the stage output is
+/// multiplied by its mixer channel.
+///
+/// Shaders which don't write to `out_color` are returned unchanged.
+pub fn inject_mixer_epilogue(code: &str) -> String {
+    if !code.contains("out_color") {
+        return code.to_owned();
+    }
+
+    let main = match MAIN_RE.find(code) {
+        Some(m) => m,
+        None => return code.to_owned(),
+    };
+
+    let mut out = String::with_capacity(code.len() + 512);
+    out.push_str(&code[..main.start()]);
+    out.push_str("void sj_stage_main()");
+    out.push_str(&code[main.end()..]);
+    out.push('\n');
+
+    // the shader may already use some of these uniforms itself
+    if !code.contains("stage_opacity") {
+        out.push_str(&format!(
+            "uniform float stage_opacity[{}];\n",
+            MIXER_CHANNELS
+        ));
+    }
+    if !code.contains("stage_tint") {
+        out.push_str(&format!("uniform vec3 stage_tint[{}];\n", MIXER_CHANNELS));
+    }
+    if !code.contains("pass_index") {
+        out.push_str("uniform int pass_index;\n");
+    }
+
+    out.push_str(&format!(
+        "void main() {{\n    \
+            sj_stage_main();\n    \
+            int sj_channel = min(pass_index, {});\n    \
+            out_color.rgb *= stage_opacity[sj_channel] * stage_tint[sj_channel];\n\
+        }}\n",
+        MIXER_CHANNELS - 1
+    ));
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wraps_main() {
+        let code = "#version 140\nuniform int pass_index;\nout vec4 out_color;\nvoid main() {\n    out_color = vec4(1);\n}\n";
+        let out = inject_mixer_epilogue(code);
+        assert!(out.contains("void sj_stage_main() {"));
+        assert!(out.contains("uniform float stage_opacity["));
+        assert_eq!(out.matches("uniform int pass_index").count(), 1);
+        assert_eq!(out.matches("void main()").count(), 1);
+
+        let code = "#version 140\nvoid main() { gl_FragColor = vec4(1); }";
+        assert_eq!(inject_mixer_epilogue(code), code);
+    }
+}
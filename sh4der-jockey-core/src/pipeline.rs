@@ -0,0 +1,1164 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    future::Future,
+    mem::discriminant,
+    path::Path,
+    rc::Rc,
+};
+
+use async_std::task::yield_now;
+use image::RgbaImage;
+use serde_yaml::Value;
+
+use crate::{util::*, *};
+
+/// Type alias for box containing a partially build pipeline
+pub type PipelinePartial = Box<dyn Future<Output = Result<(Pipeline, UpdateRequest), String>>>;
+
+#[derive(Debug, Clone)]
+pub struct UpdateRequest {
+    pub audio_samples: usize,
+    pub smoothing_attack: f32,
+    pub smoothing_decay: f32,
+}
+
+impl Default for UpdateRequest {
+    fn default() -> Self {
+        Self {
+            audio_samples: AUDIO_SAMPLES,
+            smoothing_attack: FFT_ATTACK,
+            smoothing_decay: FFT_DECAY,
+        }
+    }
+}
+
+/// The rendering pipeline struct
+///
+/// This struct holds the structure of the rendering pipeline. Note that it
+/// does not render anything itself, it merely holds the information and takes
+/// care of resource management.
+#[derive(Debug)]
+pub struct Pipeline {
+    pub stages: Vec<Stage>,
+    pub buffers: HashMap<CString, Rc<dyn Texture>>,
+    pub requested_ndi_sources: HashMap<CString, NdiInput>,
+    pub blending: bool,
+    /// Targets whose mip levels are regenerated after rendering into them.
+    pub mipmapped: HashSet<CString>,
+    pub quality: Option<QualityController>,
+    pub blocks: Vec<UniformBlock>,
+    pub vertex_buffers: HashMap<CString, VertexBuffer>,
+    pub exposure: Option<AutoExposure>,
+    pub warnings: Vec<String>,
+    pub readbacks: Vec<Readback>,
+    pub screenshot: ScreenshotConfig,
+    pub profiles: Vec<String>,
+    pub layers: Option<Layers>,
+    pub macros: Vec<Macro>,
+    pub variations: Option<Variations>,
+    /// Cue notes for the performer, shown while the pipeline is active.
+    pub notes: Vec<String>,
+    /// Whether any stage reads `prev_frame_luma`, which is only measured
+    /// if it is used.
+    pub uses_frame_luma: bool,
+}
+
+impl Pipeline {
+    pub fn splash_screen() -> Self {
+        const SPLASH_FRAG: &str = include_str!("shaders/splash.frag");
+        Self::from_fragment_shader(SPLASH_FRAG).unwrap()
+    }
+
+    /// The splash screen of the project in the working directory.
+    ///
+    /// A `splash.frag` or `splash.png` in the project folder replaces the
+    /// built-in splash screen.
+    pub fn project_splash_screen() -> Self {
+        match Self::load_project_splash() {
+            Ok(Some(pipeline)) => pipeline,
+            Ok(None) => Self::splash_screen(),
+            Err(err) => {
+                log::error!("Failed to load custom splash screen: {}", err);
+                Self::splash_screen()
+            }
+        }
+    }
+
+    fn load_project_splash() -> Result<Option<Self>, String> {
+        const SPLASH_IMAGE_FRAG: &str = include_str!("shaders/splash_image.frag");
+
+        if let Ok(code) = std::fs::read_to_string("splash.frag") {
+            let code = preprocess(&code, "splash.frag", &mut Vec::new())?;
+            return Self::from_fragment_shader(&code).map(Some);
+        }
+
+        if !Path::new("splash.png").is_file() {
+            return Ok(None);
+        }
+
+        let image = image::open("splash.png")
+            .map_err(|e| format!("Failed to open splash.png: {}", e))?
+            .flipv()
+            .to_rgba8();
+
+        let mut builder = TextureBuilder::new();
+        builder.resolution = vec![image.width(), image.height()];
+        let tex = builder.build_texture_with_data(image.as_raw().as_ptr() as _);
+
+        let name = CString::new("splash").unwrap();
+        let mut pipeline = Self::from_fragment_shader(SPLASH_IMAGE_FRAG)?;
+        pipeline.stages[0].deps.push(name.clone());
+        pipeline.buffers.insert(name, tex);
+        Ok(Some(pipeline))
+    }
+
+    /// The pipeline shown when the project can't be built at all.
+    pub fn fallback() -> Self {
+        const FALLBACK_FRAG: &str = include_str!("shaders/fallback.frag");
+        Self::from_fragment_shader(FALLBACK_FRAG).unwrap()
+    }
+
+    /// Builds a pipeline with a single stage drawing to the screen.
+    fn from_fragment_shader(code: &str) -> Result<Self, String> {
+        let sh_ids = vec![
+            compile_shader(PASS_VERT, gl::VERTEX_SHADER)?,
+            compile_shader(code, gl::FRAGMENT_SHADER)?,
+        ];
+
+        let prog_id = link_program(&sh_ids)?;
+
+        let stages = vec![Stage {
+            prog_id,
+            target: None,
+            kind: StageKind::Frag {},
+            sh_ids,
+            deps: Vec::new(),
+            unis: HashMap::new(),
+            samplers: HashMap::new(),
+            blend: None,
+            perf: RunningAverage::new(),
+            builder: TextureBuilder::new(),
+            disabled: false,
+            samples: None,
+            multisample: None,
+            sources: Vec::new(),
+            variants: Vec::new(),
+            source_hash: 0,
+            previous: None,
+            comparing: false,
+            uploaded: UniformCache::default(),
+        }];
+
+        Ok(Self {
+            stages,
+            buffers: HashMap::new(),
+            requested_ndi_sources: HashMap::new(),
+            blending: false,
+            mipmapped: HashSet::new(),
+            quality: None,
+            blocks: Vec::new(),
+            vertex_buffers: HashMap::new(),
+            exposure: None,
+            warnings: Vec::new(),
+            readbacks: Vec::new(),
+            screenshot: ScreenshotConfig::default(),
+            profiles: Vec::new(),
+            layers: None,
+            macros: Vec::new(),
+            variations: None,
+            notes: Vec::new(),
+            uses_frame_luma: false,
+        })
+    }
+
+    pub async fn load(
+        path: impl AsRef<Path>,
+        screen_size: (u32, u32),
+        profile: Option<String>,
+        aliases: HashMap<String, String>,
+    ) -> Result<(Self, UpdateRequest), String> {
+        Cache::init();
+        let cached = Cache::entries().into_iter().map(|e| e.path).collect();
+        let path = path.as_ref().to_owned();
+        let source = PipelineSource::load(path, profile, aliases, cached).await?;
+
+        let empty_cache = HashMap::new();
+        Pipeline::from_source_with_cache(source, screen_size, &empty_cache).await
+    }
+
+    async fn from_source_with_cache(
+        source: PipelineSource,
+        screen_size: (u32, u32),
+        cache: &HashMap<CString, Rc<dyn Texture>>,
+    ) -> Result<(Self, UpdateRequest), String> {
+        let (mut pipeline, update) = Pipeline::from_yaml_with_cache(
+            source.object,
+            source.stages,
+            source.images,
+            screen_size,
+            cache,
+        )
+        .await?;
+
+        pipeline.warnings.extend(source.warnings);
+        if !source.layers.is_empty() {
+            pipeline.layers = Some(Layers::new(source.layers));
+        }
+
+        pipeline.profiles = source.profiles;
+        Ok((pipeline, update))
+    }
+
+    async fn from_yaml_with_cache(
+        object: Value,
+        stage_sources: Vec<Result<StageSources, String>>,
+        mut decoded: HashMap<String, Result<RgbaImage, String>>,
+        screen_size: (u32, u32),
+        cache: &HashMap<CString, Rc<dyn Texture>>,
+    ) -> Result<(Self, UpdateRequest), String> {
+        let mut buffers = HashMap::<CString, Rc<dyn Texture>>::new();
+        yield_now().await;
+
+        // init global texture cache
+        Cache::init();
+
+        // get fft texture size
+        let (
+            mut samples_opts,
+            mut raw_spectrum_opts,
+            mut spectrum_opts,
+            mut smooth_spectrum_opts,
+            mut spectrum_integrated_opts,
+            mut spectrum_smooth_integrated_opts,
+            audio_samples,
+            smoothing_attack,
+            smoothing_decay,
+        ) = match object.get("audio") {
+            None => (
+                TextureBuilder::new(),
+                TextureBuilder::new(),
+                TextureBuilder::new(),
+                TextureBuilder::new(),
+                TextureBuilder::new(),
+                TextureBuilder::new(),
+                AUDIO_SAMPLES,
+                FFT_ATTACK,
+                FFT_DECAY,
+            ),
+            Some(object) => {
+                let audio_samples = match object.get("audio_samples") {
+                    None => AUDIO_SAMPLES,
+                    Some(Value::Number(n)) => match n.as_u64() {
+                        Some(n) => n as _,
+                        _ => {
+                            return Err(format!(
+                                "Expected \"audio_samples\" to be a number, got: {:?}",
+                                n
+                            ))
+                        }
+                    },
+                    s => {
+                        return Err(format!(
+                            "Expected \"audio_samples\" to be number, got: {:?}",
+                            s
+                        ))
+                    }
+                };
+
+                let attack = match object.get("attack") {
+                    None => FFT_ATTACK,
+                    Some(s) => match s.as_f64() {
+                        Some(s) => s as _,
+                        _ => {
+                            return Err(format!(
+                                "Expected \"smoothing\" to be a float, got {:?}",
+                                s
+                            ))
+                        }
+                    },
+                };
+                let decay = match object.get("decay") {
+                    None => FFT_DECAY,
+                    Some(s) => match s.as_f64() {
+                        Some(s) => s as _,
+                        _ => {
+                            return Err(format!(
+                                "Expected \"smoothing\" to be a float, got {:?}",
+                                s
+                            ))
+                        }
+                    },
+                };
+
+                let samples_opts = match object.get("samples") {
+                    Some(s) => TextureBuilder::parse(s, false, true)?,
+                    None => TextureBuilder::new(),
+                };
+                let raw_spectrum_opts = match object.get("spectrum_raw") {
+                    Some(s) => TextureBuilder::parse(s, false, true)?,
+                    None => TextureBuilder::new(),
+                };
+                let spectrum_opts = match object.get("spectrum") {
+                    Some(s) => TextureBuilder::parse(s, false, true)?,
+                    None => TextureBuilder::new(),
+                };
+                let smooth_spectrum_opts = match object.get("spectrum_smooth") {
+                    Some(s) => TextureBuilder::parse(s, false, true)?,
+                    None => TextureBuilder::new(),
+                };
+                let spectrum_integrated_opts = match object.get("spectrum_integrated") {
+                    Some(s) => TextureBuilder::parse(s, false, true)?,
+                    None => TextureBuilder::new(),
+                };
+                let spectrum_smooth_integrated_opts = match object.get("spectrum_smooth_integrated")
+                {
+                    Some(s) => TextureBuilder::parse(s, false, true)?,
+                    None => TextureBuilder::new(),
+                };
+
+                (
+                    samples_opts,
+                    raw_spectrum_opts,
+                    spectrum_opts,
+                    smooth_spectrum_opts,
+                    spectrum_integrated_opts,
+                    spectrum_smooth_integrated_opts,
+                    audio_samples,
+                    attack,
+                    decay,
+                )
+            }
+        };
+
+        samples_opts
+            .set_resolution(vec![audio_samples as _; 1])
+            .set_channels(2)
+            .set_float(true);
+
+        raw_spectrum_opts
+            .set_resolution(vec![(audio_samples / 2) as _; 1])
+            .set_channels(2)
+            .set_float(true);
+
+        spectrum_opts
+            .set_resolution(vec![100 as _; 1])
+            .set_channels(2)
+            .set_float(true);
+
+        smooth_spectrum_opts
+            .set_resolution(vec![100 as _; 1])
+            .set_channels(2)
+            .set_float(true);
+
+        spectrum_integrated_opts
+            .set_resolution(vec![100 as _; 1])
+            .set_channels(2)
+            .set_float(true);
+
+        spectrum_smooth_integrated_opts
+            .set_resolution(vec![100 as _; 1])
+            .set_channels(2)
+            .set_float(true);
+
+        // add audio samples to buffers
+        buffers.insert(SAMPLES_NAME.clone(), samples_opts.build_texture());
+
+        buffers.insert(SPECTRUM_RAW_NAME.clone(), raw_spectrum_opts.build_texture());
+
+        buffers.insert(SPECTRUM_NAME.clone(), spectrum_opts.build_texture());
+
+        buffers.insert(
+            SPECTRUM_SMOOTH_NAME.clone(),
+            smooth_spectrum_opts.build_texture(),
+        );
+
+        buffers.insert(
+            SPECTRUM_INTEGRATED_NAME.clone(),
+            spectrum_integrated_opts.build_texture(),
+        );
+
+        buffers.insert(
+            SPECTRUM_SMOOTH_INTEGRATED_NAME.clone(),
+            spectrum_smooth_integrated_opts.build_texture(),
+        );
+
+        // add histogram of the previous frame
+        buffers.insert(
+            HISTOGRAM_NAME.clone(),
+            Rc::new(Texture1D::with_params(
+                [HISTOGRAM_BINS as _],
+                gl::LINEAR,
+                gl::LINEAR,
+                gl::CLAMP_TO_EDGE,
+                TextureFormat::RGBA32F,
+                false,
+                std::ptr::null(),
+            )),
+        );
+
+        // add band energies of the last beats
+        buffers.insert(
+            BEAT_HISTORY_NAME.clone(),
+            Rc::new(Texture2D::with_params(
+                [HISTORY_STEPS as _, HISTORY_BEATS as _],
+                gl::NEAREST,
+                gl::NEAREST,
+                gl::REPEAT,
+                TextureFormat::RGBA32F,
+                false,
+                std::ptr::null(),
+            )),
+        );
+
+        {
+            // add noise texture
+            let noise_name = NOISE_NAME.clone();
+            let noise = match cache.get(&noise_name) {
+                Some(old) => Rc::clone(old),
+                None => Rc::new(make_noise()),
+            };
+            buffers.insert(noise_name, noise);
+        }
+
+        yield_now().await;
+
+        // parse images section
+        let images = match object.get("images") {
+            Some(Value::Sequence(s)) => s.clone(),
+            None => Vec::new(),
+            s => return Err(format!("Expected \"images\" to be an array, got {:?}", s)),
+        };
+
+        // parse images
+        let mut declared_textures = Vec::new();
+        for object in images {
+            // a list of paths is loaded as the layers of an array texture
+            let paths = match object.get("path") {
+                Some(Value::String(s)) => vec![s.clone()],
+                Some(Value::Sequence(seq)) if !seq.is_empty() => seq
+                    .iter()
+                    .map(|s| s.as_str().map(String::from))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| {
+                        format!("Expected \"path\" to be a list of strings, got {:?}", seq)
+                    })?,
+                s => {
+                    return Err(format!(
+                        "Expected \"path\" to be a string or a list of strings, got {:?}",
+                        s
+                    ));
+                }
+            };
+            let name = match object.get("name") {
+                Some(Value::String(s)) => CString::new(s.as_str()).unwrap(),
+                s => return Err(format!("Expected \"name\" to be a string, got {:?}", s)),
+            };
+
+            // checked on the paths, the cache key has the region appended
+            let is_lut = CubeLut::is_lut_path(&paths);
+            let region = InputRegion::from_yaml(&object)?;
+            let path = &region.cache_key(&paths.join(";"));
+
+            // check if name is already in use
+            if buffers.get(&name).is_some() {
+                return Err(format!(
+                    "Texture {:?} already exists, please try a different name",
+                    name
+                ));
+            }
+
+            if is_lut && !region.is_full() {
+                return Err(format!(
+                    "Lookup table {:?} can't be cropped or scaled",
+                    name
+                ));
+            }
+
+            // fetch texture from global cache
+            let tex = match Cache::fetch(path) {
+                Some(cached_tex) => cached_tex,
+                None if is_lut => {
+                    let lut = CubeLut::open(path)?;
+                    async_std::task::yield_now().await;
+
+                    let tex: Rc<dyn Texture> = Rc::new(lut.to_texture());
+                    let bytes = lut.data.len() * std::mem::size_of::<f32>();
+                    Cache::store(path.clone(), Rc::clone(&tex), bytes);
+                    tex
+                }
+                None => {
+                    let mut size = None;
+                    let mut data = Vec::new();
+                    for layer_path in paths.iter() {
+                        // images evicted from the cache in the meantime are
+                        // decoded here
+                        let layer = match decoded.remove(layer_path) {
+                            Some(s) => s?,
+                            None => decode_image(&name.to_string_lossy(), layer_path)?,
+                        };
+                        let layer = region.apply(layer);
+                        async_std::task::yield_now().await;
+
+                        // all layers of an array texture share the same size
+                        let layer_size = (layer.width(), layer.height());
+                        if *size.get_or_insert(layer_size) != layer_size {
+                            return Err(format!(
+                                "Image {:?} at {:?} doesn't have the same size as the other layers",
+                                name, layer_path
+                            ));
+                        }
+
+                        data.extend_from_slice(layer.as_raw());
+                    }
+
+                    let (width, height) = size.unwrap();
+                    let mut builder = TextureBuilder::parse(&object, false, false)?;
+                    builder.resolution = vec![width, height];
+                    builder.layers = match paths.len() {
+                        1 => None,
+                        n => Some(n as _),
+                    };
+                    let tex = builder.build_texture_with_data(data.as_ptr() as _);
+                    async_std::task::yield_now().await;
+
+                    Cache::store(path.clone(), Rc::clone(&tex), data.len());
+                    tex
+                }
+            };
+
+            declared_textures.push(name.clone());
+            buffers.insert(name, tex);
+            yield_now().await;
+        }
+
+        //parse ndi section
+        let ndi_sources = match object.get("ndi") {
+            Some(Value::Sequence(s)) => s.clone(),
+            None => Vec::new(),
+            Some(s) => {
+                return Err(format!(
+                    "Expected \"ndi\" to be an array, got {:?} instead.",
+                    s
+                ));
+            }
+        };
+
+        let mut requested_ndi_sources = HashMap::new();
+        for src in ndi_sources {
+            let input = NdiInput::from_yaml(&src)?;
+            let name = match src.get("name") {
+                Some(Value::String(s)) => CString::new(s.clone()).unwrap(),
+                s => {
+                    return Err(format!(
+                        "Expected ndi.name to be a string, got {:?} instead",
+                        s
+                    ))
+                }
+            };
+
+            if buffers.get(&name).is_some() {
+                return Err(format!(
+                    "Texture {:?} already exists, please try a different name",
+                    name
+                ));
+            }
+
+            let tex = TextureBuilder::parse(&src, false, true)?
+                .set_float(false)
+                .set_resolution(vec![1, 1])
+                .build_texture();
+
+            requested_ndi_sources.insert(name.clone(), input);
+            declared_textures.push(name.clone());
+            buffers.insert(name, tex);
+        }
+
+        // parse texture buffers
+        let texture_buffers = match object.get("tbo") {
+            Some(Value::Sequence(s)) => s.clone(),
+            None => Vec::new(),
+            s => return Err(format!("Expected \"tbo\" to be an array, got {:?}", s)),
+        };
+
+        for object in texture_buffers {
+            let name = match object.get("name") {
+                Some(Value::String(s)) => CString::new(s.as_str()).unwrap(),
+                s => return Err(format!("Expected \"name\" to be a string, got {:?}", s)),
+            };
+
+            if buffers.get(&name).is_some() {
+                return Err(format!(
+                    "Texture {:?} already exists, please try a different name",
+                    name
+                ));
+            }
+
+            let tex = TextureBuffer::from_yaml(&object)?;
+            declared_textures.push(name.clone());
+            buffers.insert(name, Rc::new(tex));
+            yield_now().await;
+        }
+
+        // parse quality controller
+        let quality = match object.get("quality") {
+            Some(s) => Some(QualityController::from_yaml(s)?),
+            None => None,
+        };
+
+        // parse auto exposure
+        let exposure = match object.get("exposure") {
+            Some(s) => Some(AutoExposure::from_yaml(s)?),
+            None => None,
+        };
+
+        // parse screenshot settings
+        let screenshot = match object.get("screenshot") {
+            Some(s) => ScreenshotConfig::from_yaml(s)?,
+            None => ScreenshotConfig::default(),
+        };
+
+        // parse macros
+        let macros = match object.get("macros") {
+            Some(Value::Sequence(seq)) => seq
+                .iter()
+                .map(Macro::from_yaml)
+                .collect::<Result<Vec<_>, _>>()?,
+            Some(s) => return Err(format!("Expected \"macros\" to be an array, got {:?}", s)),
+            None => Vec::new(),
+        };
+
+        // parse randomized parameters
+        let variations = match object.get("variations") {
+            Some(s) => Some(Variations::from_yaml(s)?),
+            None => None,
+        };
+
+        // parse cue notes
+        let notes = match object.get("notes") {
+            Some(Value::String(s)) => vec![s.trim_end().to_owned()],
+            Some(Value::Sequence(seq)) => seq
+                .iter()
+                .map(|v| match v {
+                    Value::String(s) => Ok(s.trim_end().to_owned()),
+                    s => Err(format!("Expected note to be a string, got {:?}", s)),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            Some(s) => {
+                return Err(format!(
+                    "Expected \"notes\" to be a string or a list of strings, got {:?}",
+                    s
+                ))
+            }
+            None => Vec::new(),
+        };
+
+        // parse uniform blocks
+        let mut blocks = Vec::new();
+        match object.get("ubo") {
+            Some(Value::Mapping(m)) => {
+                for (name, value) in m {
+                    blocks.push(UniformBlock::from_yaml(name, value)?);
+                }
+            }
+            Some(s) => return Err(format!("Expected \"ubo\" to be a mapping, got {:?}", s)),
+            None => (),
+        }
+
+        // parse stages section
+        let passes = match object.get("stages") {
+            Some(Value::Sequence(s)) => s.clone(),
+            None => return Err("Required field \"stages\" not found".to_string()),
+            s => return Err(format!("Expected \"stages\" to be an array, got {:?}", s)),
+        };
+
+        // replace broken stages instead of failing the whole build
+        let partial_build = match object.get("partial_build") {
+            Some(Value::Bool(b)) => *b,
+            None => false,
+            s => {
+                return Err(format!(
+                    "Expected \"partial_build\" to be a bool, got {:?}",
+                    s
+                ))
+            }
+        };
+
+        // parse stages
+        let mut stages = Vec::with_capacity(passes.len());
+        let mut stage_errors = Vec::new();
+        let mut stage_sources = stage_sources.into_iter();
+        for (k, pass) in passes.into_iter().enumerate() {
+            let sources = match stage_sources.next() {
+                Some(s) => s,
+                None => StageSources::load(&pass),
+            };
+
+            let stage = match sources.and_then(|s| Stage::from_yaml(pass.clone(), s)) {
+                Ok(stage) => stage,
+                Err(err) if partial_build => {
+                    let stage = Stage::error_stage(&pass).map_err(|_| err.clone())?;
+                    stage_errors.push(format!("Stage {} failed to build:\n{}", k, err));
+                    stage
+                }
+                Err(err) => return Err(err),
+            };
+
+            stages.push(stage);
+            yield_now().await;
+        }
+
+        // create vertex buffers for feedback stages
+        let mut vertex_buffers = HashMap::new();
+        for stage in stages.iter() {
+            if let StageKind::Feedback {
+                name,
+                count,
+                varyings,
+            } = &stage.kind
+            {
+                if vertex_buffers.contains_key(name) {
+                    return Err(format!("Vertex buffer {:?} is captured twice", name));
+                }
+
+                vertex_buffers.insert(name.clone(), VertexBuffer::new(*count, varyings));
+            }
+        }
+
+        // draw all vertices of the captured buffers
+        for stage in stages.iter_mut() {
+            if let StageKind::Vert {
+                count,
+                vertices: Some(name),
+                ..
+            } = &mut stage.kind
+            {
+                match vertex_buffers.get(name) {
+                    Some(buffer) => *count = buffer.count,
+                    None => return Err(format!("Vertex buffer {:?} not found", name)),
+                }
+            }
+        }
+
+        // create render targets for stages
+        let mut res_map = HashMap::new();
+        for stage in stages.iter() {
+            let target = match &stage.target {
+                Some(s) => s,
+                None => continue,
+            };
+
+            // check if target exists already
+            let stage_res = (stage.resolution(), stage.builder.relative.clone());
+            if buffers.contains_key(target) {
+                if let Some(buffer_res) = res_map.get(target.as_c_str()) {
+                    // compare against previous stages
+                    if *buffer_res != stage_res {
+                        return Err(format!(
+                            "Texture {:?} already has a different resolution",
+                            target
+                        ));
+                    }
+
+                    // don't create the same target twice
+                    continue;
+                } else {
+                    return Err(format!(
+                        "Target {:?} is already loaded as an image or build-in texture",
+                        target
+                    ));
+                }
+            }
+
+            // record specified stage resolution
+            res_map.insert(target.as_c_str(), stage_res);
+
+            // create textures, reusing the targets of the previous build
+            let texture = match stage.kind {
+                StageKind::Frag { .. } | StageKind::Vert { .. } => {
+                    let size = stage
+                        .builder
+                        .framebuffer_size(screen_size)
+                        .map_err(|e| format!("Target {:?}: {}", target, e))?;
+                    let key = stage.builder.target_key(size.to_vec(), false);
+                    TargetPool::fetch_or_build(&key, || {
+                        stage.builder.build_double_framebuffer(size)
+                    })
+                }
+                StageKind::Comp { .. } => {
+                    let key = stage
+                        .builder
+                        .target_key(stage.builder.resolution.clone(), true);
+                    TargetPool::fetch_or_build(&key, || stage.builder.build_image())
+                }
+                StageKind::Feedback { .. } => unreachable!("Feedback stages have no target"),
+            };
+
+            // insert texture into hashmap
+            buffers.insert(target.clone(), texture);
+            yield_now().await;
+        }
+
+        // bind every sampler used by the shaders to the texture of the same name
+        let mut used_buffers = HashSet::new();
+        for (k, stage) in stages.iter_mut().enumerate() {
+            let has_sprite = matches!(
+                stage.kind,
+                StageKind::Vert {
+                    sprite: Some(_),
+                    ..
+                }
+            );
+
+            let mut unresolved = Vec::new();
+            for (name, ty) in stage.programs().into_iter().flat_map(active_uniforms) {
+                if !is_sampler_type(ty) {
+                    continue;
+                }
+
+                // the sprite texture is bound separately
+                let name = CString::new(name).unwrap();
+                if has_sprite && name == *SPRITE_NAME {
+                    continue;
+                }
+
+                if !buffers.contains_key(&name) {
+                    unresolved.push(name);
+                    continue;
+                }
+
+                if !stage.deps.contains(&name) {
+                    stage.deps.push(name.clone());
+                    used_buffers.insert(name);
+                }
+            }
+
+            if let Some(name) = stage
+                .samplers
+                .keys()
+                .find(|name| !stage.deps.contains(name))
+            {
+                return Err(format!(
+                    "Stage {}: Texture {:?} in \"sample\" is not used by the shader",
+                    k, name
+                ));
+            }
+
+            if !unresolved.is_empty() {
+                unresolved.sort();
+                unresolved.dedup();
+                return Err(format!(
+                    "Stage {}: No texture found for the samplers {:?}",
+                    k, unresolved
+                ));
+            }
+
+            yield_now().await;
+        }
+
+        // connect uniform blocks to their binding points
+        for stage in stages.iter() {
+            for prog_id in stage.programs() {
+                for (binding, block) in blocks.iter().enumerate() {
+                    block.attach(prog_id, binding as _);
+                }
+            }
+        }
+
+        // keep the buffer measured for auto exposure
+        if let Some(exposure) = &exposure {
+            if !buffers.contains_key(&exposure.buffer) {
+                return Err(format!(
+                    "Buffer {:?} used for auto exposure not found",
+                    exposure.buffer
+                ));
+            }
+
+            used_buffers.insert(exposure.buffer.clone());
+        }
+
+        // keep the textures drawn onto points
+        for stage in stages.iter() {
+            if let StageKind::Vert {
+                sprite: Some(name), ..
+            } = &stage.kind
+            {
+                if !buffers.contains_key(name) {
+                    return Err(format!("Sprite texture {:?} not found", name));
+                }
+
+                used_buffers.insert(name.clone());
+            }
+        }
+
+        // keep the textures written to disk
+        let readbacks = match object.get("readback") {
+            Some(Value::Sequence(s)) => s
+                .iter()
+                .map(Readback::from_yaml)
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+            s => return Err(format!("Expected \"readback\" to be an array, got {:?}", s)),
+        };
+
+        for readback in readbacks.iter() {
+            if !buffers.contains_key(&readback.name) {
+                return Err(format!(
+                    "Texture {:?} to read back not found",
+                    readback.name
+                ));
+            }
+
+            used_buffers.insert(readback.name.clone());
+        }
+
+        // keep the texture captured in screenshots
+        if let Some(name) = &screenshot.source {
+            match buffers.get(name) {
+                Some(tex) if tex.target() == gl::TEXTURE_2D => (),
+                Some(_) => {
+                    return Err(format!("Screenshot source {:?} must be a 2D texture", name))
+                }
+                None => return Err(format!("Screenshot source {:?} not found", name)),
+            }
+
+            used_buffers.insert(name.clone());
+        }
+
+        // report uniforms and textures that have no effect
+        let mut warnings = stage_errors;
+        let mut macro_targets: HashSet<_> = macros
+            .iter()
+            .flat_map(|m| m.targets.iter().map(|t| &t.uniform))
+            .collect();
+        let mut variation_params: HashSet<_> = variations
+            .iter()
+            .flat_map(|v| v.groups.iter())
+            .flat_map(|g| g.params.iter().map(|p| &p.uniform))
+            .collect();
+        let mut uses_frame_luma = false;
+        for (k, stage) in stages.iter().enumerate() {
+            let active: Vec<_> = stage
+                .programs()
+                .into_iter()
+                .flat_map(active_uniforms)
+                .collect();
+            macro_targets
+                .retain(|name| !active.iter().any(|(n, _)| n.as_bytes() == name.as_bytes()));
+            variation_params
+                .retain(|name| !active.iter().any(|(n, _)| n.as_bytes() == name.as_bytes()));
+            let is_active =
+                |name: &CString| active.iter().any(|(n, _)| n.as_bytes() == name.as_bytes());
+            uses_frame_luma |= is_active(&PREV_FRAME_LUMA_NAME);
+
+            let mut unused: Vec<_> = stage.unis.keys().filter(|name| !is_active(name)).collect();
+            unused.sort();
+            for name in unused {
+                warnings.push(format!(
+                    "Stage {}: uniform {:?} is not used by the shader",
+                    k, name
+                ));
+            }
+        }
+
+        let mut unused_targets: Vec<_> = macro_targets.into_iter().collect();
+        unused_targets.sort();
+        for name in unused_targets {
+            warnings.push(format!("Macro target {:?} is not used by any stage", name));
+        }
+
+        let mut unused_params: Vec<_> = variation_params.into_iter().collect();
+        unused_params.sort();
+        for name in unused_params {
+            warnings.push(format!(
+                "Variation parameter {:?} is not used by any stage",
+                name
+            ));
+        }
+
+        for name in declared_textures.iter() {
+            if !used_buffers.contains(name) {
+                warnings.push(format!("Texture {:?} is not used by any stage", name));
+            }
+        }
+
+        // check for blend modes
+        let blending = stages.iter().any(|s| s.blend.is_some());
+
+        // only generate mip levels for targets which are sampled with them
+        let mipmapped = stages
+            .iter()
+            .filter(|s| s.builder.mipmap)
+            .filter_map(|s| s.target.clone())
+            .chain(stages.iter().flat_map(|s| {
+                s.samplers
+                    .iter()
+                    .filter(|(_, sampler)| sampler.mipmap)
+                    .map(|(name, _)| name.clone())
+            }))
+            .collect();
+
+        // a mipmapped filter makes textures without mip levels incomplete
+        let targets: HashSet<_> = stages.iter().filter_map(|s| s.target.clone()).collect();
+        for (k, stage) in stages.iter_mut().enumerate() {
+            for (name, sampler) in stage.samplers.iter_mut() {
+                let has_mipmaps = targets.contains(name)
+                    || buffers.get(name).is_some_and(|tex| tex.has_mipmaps());
+
+                if sampler.mipmap && !has_mipmaps {
+                    warnings.push(format!(
+                        "Stage {}: Texture {:?} has no mip levels, sampling it without them",
+                        k, name
+                    ));
+                    sampler.disable_mipmap();
+                }
+            }
+        }
+
+        // remove unnecessary buffers
+        buffers.retain(|name, _| {
+            let needed = used_buffers.contains(name);
+            if !needed {
+                requested_ndi_sources.remove(name);
+            }
+            needed
+        });
+
+        Ok((
+            Self {
+                stages,
+                buffers,
+                requested_ndi_sources,
+                blending,
+                mipmapped,
+                quality,
+                blocks,
+                vertex_buffers,
+                exposure,
+                warnings,
+                readbacks,
+                screenshot,
+                profiles: Vec::new(),
+                layers: None,
+                macros,
+                variations,
+                notes,
+                uses_frame_luma,
+            },
+            UpdateRequest {
+                audio_samples,
+                smoothing_attack,
+                smoothing_decay,
+            },
+        ))
+    }
+
+    /// Returns all files the shaders of this pipeline were assembled from,
+    /// including the files pulled in by `#pragma include`.
+    pub fn source_files(&self) -> HashSet<&str> {
+        self.stages
+            .iter()
+            .flat_map(|stage| stage.sources.iter())
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Keeps the programs of an older build, so every stage can be compared
+    /// against its previous version.
+    ///
+    /// Stages are matched by their index. Stages with unchanged shaders keep
+    /// the program they were compared against before.
+    pub fn keep_previous_programs(&mut self, old: &mut Pipeline) {
+        for (stage, old_stage) in self.stages.iter_mut().zip(old.stages.iter_mut()) {
+            // the splash screen has no source files
+            if old_stage.sources.is_empty()
+                || stage.target != old_stage.target
+                || discriminant(&stage.kind) != discriminant(&old_stage.kind)
+            {
+                continue;
+            }
+
+            let previous = match stage.source_hash == old_stage.source_hash {
+                true => {
+                    old_stage.set_comparing(false);
+                    old_stage.previous.take()
+                }
+                false => Some(old_stage.take_program()),
+            };
+
+            // uniform blocks may be bound differently in the new build
+            if let Some((prog_id, _)) = &previous {
+                for (binding, block) in self.blocks.iter().enumerate() {
+                    block.attach(*prog_id, binding as _);
+                }
+            }
+
+            stage.previous = previous;
+        }
+    }
+
+    pub fn resize_buffers(&mut self, width: u32, height: u32) {
+        for stage in self.stages.iter() {
+            if !stage.builder.resolution.is_empty() {
+                continue;
+            }
+
+            // get name of stage render target
+            let name = match &stage.target {
+                Some(s) => s.clone(),
+                _ => continue,
+            };
+
+            if !matches!(stage.kind, StageKind::Frag { .. } | StageKind::Vert { .. }) {
+                panic!("なに the fuck?")
+            }
+
+            // keep the old target if the new size is invalid
+            let size = match stage.builder.framebuffer_size((width, height)) {
+                Ok(s) => s,
+                Err(err) => {
+                    log::error!("Target {:?}: {}", name, err);
+                    continue;
+                }
+            };
+
+            // the pool may hold targets lent by this very pipeline
+            self.buffers
+                .insert(name, stage.builder.build_double_framebuffer(size));
+        }
+    }
+
+    /// Lends the render targets of this pipeline to the [`TargetPool`], so
+    /// the pipeline built next can reuse them.
+    pub fn lend_targets(&self) {
+        let mut lent = HashSet::new();
+        for stage in self.stages.iter() {
+            let image = match stage.kind {
+                StageKind::Frag { .. } | StageKind::Vert { .. } => false,
+                StageKind::Comp { .. } => true,
+                StageKind::Feedback { .. } => continue,
+            };
+
+            // several stages may render into the same target
+            let (name, tex) = match stage
+                .target
+                .as_ref()
+                .and_then(|t| self.buffers.get_key_value(t))
+            {
+                Some(s) => s,
+                None => continue,
+            };
+
+            if !lent.insert(name) {
+                continue;
+            }
+
+            let resolution = match image {
+                true => stage.builder.resolution.clone(),
+                false => tex.resolution()[..2].to_vec(),
+            };
+
+            TargetPool::lend(stage.builder.target_key(resolution, image), Rc::clone(tex));
+        }
+    }
+}
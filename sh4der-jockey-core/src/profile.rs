@@ -0,0 +1,163 @@
+use serde_yaml::{Mapping, Value};
+
+/// Applies a profile from the `profiles` section of a pipeline file.
+///
+/// Top-level options of the profile replace those of the pipeline, with
+/// nested sections merged key by key. Stages are matched by their `target`,
+/// so a profile can lower the resolution or the uniforms of single stages.
+///
+/// Returns the names of all profiles defined in the file.
+pub fn apply_profile(object: &mut Value, name: Option<&str>) -> Result<Vec<String>, String> {
+    let root = match object {
+        Value::Mapping(m) => m,
+        _ => return Ok(Vec::new()),
+    };
+
+    let profiles = match root.remove("profiles") {
+        Some(Value::Mapping(m)) => m,
+        Some(s) => {
+            return Err(format!(
+                "Expected \"profiles\" to be a mapping, got {:?}",
+                s
+            ))
+        }
+        None => return Ok(Vec::new()),
+    };
+
+    let mut names = Vec::new();
+    for key in profiles.keys() {
+        match key.as_str() {
+            Some(s) => names.push(s.to_owned()),
+            None => {
+                return Err(format!(
+                    "Expected profile name to be a string, got {:?}",
+                    key
+                ))
+            }
+        }
+    }
+
+    let mut profile = match name.and_then(|name| profiles.get(name)) {
+        Some(Value::Mapping(m)) => m.clone(),
+        Some(Value::Null) | None => return Ok(names),
+        Some(s) => {
+            return Err(format!(
+                "Expected profile {:?} to be a mapping, got {:?}",
+                name.unwrap(),
+                s
+            ))
+        }
+    };
+
+    if let Some(overrides) = profile.remove("stages") {
+        apply_stage_overrides(root, overrides)?;
+    }
+
+    merge_layer(root, profile);
+    Ok(names)
+}
+
+/// Overlays the options of `layer` onto `object`.
+///
+/// Nested sections are merged key by key, so a project can change the
+/// watchdog threshold without losing the global `disable_stage` setting.
+pub fn merge_layer(object: &mut Mapping, layer: Mapping) {
+    for (key, value) in layer {
+        match (object.get_mut(&key), value) {
+            (Some(Value::Mapping(base)), Value::Mapping(nested)) => merge_layer(base, nested),
+            (_, value) => {
+                object.insert(key, value);
+            }
+        }
+    }
+}
+
+fn apply_stage_overrides(root: &mut Mapping, overrides: Value) -> Result<(), String> {
+    let overrides = match overrides {
+        Value::Mapping(m) => m,
+        s => {
+            return Err(format!(
+            "Expected \"stages\" of a profile to be a mapping from targets to options, got {:?}",
+            s
+        ))
+        }
+    };
+
+    let stages = match root.get_mut("stages") {
+        Some(Value::Sequence(s)) => s,
+        _ => return Err("Profile changes stages, but the pipeline has none".into()),
+    };
+
+    for (target, options) in overrides {
+        let options = match options {
+            Value::Mapping(m) => m,
+            s => {
+                return Err(format!(
+                    "Expected options for stage {:?} to be a mapping, got {:?}",
+                    target, s
+                ))
+            }
+        };
+
+        let stage = stages
+            .iter_mut()
+            .filter_map(Value::as_mapping_mut)
+            .find(|stage| stage.get("target") == Some(&target));
+
+        match stage {
+            Some(stage) => merge_layer(stage, options),
+            None => {
+                return Err(format!(
+                    "Profile changes stage {:?}, but no stage has this target",
+                    target
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PIPELINE: &str = "
+stages:
+  - fs: scene.frag
+    target: scene
+    resolution: [1920, 1080]
+    uniforms: {samples: 64, bounces: 4}
+  - fs: post.frag
+quality: {target_fps: 60}
+profiles:
+  rehearsal:
+    quality: {target_fps: 30}
+    stages:
+      scene: {resolution: [960, 540], uniforms: {samples: 8}}
+  show:
+";
+
+    #[test]
+    fn applies_profile() {
+        let mut object: Value = serde_yaml::from_str(PIPELINE).unwrap();
+        let names = apply_profile(&mut object, Some("rehearsal")).unwrap();
+        assert_eq!(names, vec!["rehearsal", "show"]);
+        assert!(object.get("profiles").is_none());
+
+        let stage = &object["stages"][0];
+        assert_eq!(stage["resolution"][0].as_u64(), Some(960));
+        assert_eq!(stage["uniforms"]["samples"].as_u64(), Some(8));
+        assert_eq!(stage["uniforms"]["bounces"].as_u64(), Some(4));
+        assert_eq!(object["quality"]["target_fps"].as_u64(), Some(30));
+    }
+
+    #[test]
+    fn rejects_unknown_target() {
+        let mut object: Value = serde_yaml::from_str(
+            "stages: [{fs: a.frag}]\nprofiles: {rehearsal: {stages: {bloom: {resolution: [1, 1]}}}}",
+        )
+        .unwrap();
+        assert!(apply_profile(&mut object, Some("rehearsal")).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use serde_yaml::Value;
+
+/// Number of frames to wait after adjusting the quality
+const COOLDOWN_FRAMES: u32 = 30;
+
+/// Adjusts a quality value to hold a target frame rate.
+///
+/// The controller tracks a smoothed frame time and steps the quality value
+/// down if the frame time exceeds the target and back up once there is
+/// enough headroom. After each change it waits for the frame time to settle
+/// before adjusting the value again.
+#[derive(Debug, Clone)]
+pub struct QualityController {
+    pub target_ms: f32,
+    pub min: f32,
+    pub step: f32,
+    pub scale_resolution: bool,
+    pub value: f32,
+    avg_ms: Option<f32>,
+    cooldown: u32,
+}
+
+impl QualityController {
+    pub fn new(target_fps: f32) -> Self {
+        Self {
+            target_ms: 1000.0 / target_fps,
+            min: 0.25,
+            step: 0.05,
+            scale_resolution: false,
+            value: 1.0,
+            avg_ms: None,
+            cooldown: COOLDOWN_FRAMES,
+        }
+    }
+
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let target_fps = match object.get("target_fps") {
+            Some(s) => match s.as_f64() {
+                Some(f) if f > 0.0 => f as f32,
+                _ => {
+                    return Err(format!(
+                        "Expected \"target_fps\" to be a positive number, got {:?}",
+                        s
+                    ))
+                }
+            },
+            None => return Err("Required field \"target_fps\" not found".to_string()),
+        };
+
+        let mut this = Self::new(target_fps);
+
+        if let Some(s) = object.get("min") {
+            this.min = match s.as_f64() {
+                Some(f) if (0.0..=1.0).contains(&f) => f as _,
+                _ => {
+                    return Err(format!(
+                        "Expected \"min\" to be a number between 0 and 1, got {:?}",
+                        s
+                    ))
+                }
+            };
+        }
+
+        if let Some(s) = object.get("step") {
+            this.step = match s.as_f64() {
+                Some(f) if f > 0.0 => f as _,
+                _ => {
+                    return Err(format!(
+                        "Expected \"step\" to be a positive number, got {:?}",
+                        s
+                    ))
+                }
+            };
+        }
+
+        this.scale_resolution = match object.get("scale_resolution") {
+            Some(Value::Bool(b)) => *b,
+            None => false,
+            s => {
+                return Err(format!(
+                    "Expected \"scale_resolution\" to be a bool, got {:?}",
+                    s
+                ))
+            }
+        };
+
+        Ok(this)
+    }
+
+    /// Records the time spent rendering the last frame.
+    ///
+    /// This must not include the wait for vsync, as the frame time would
+    /// never drop far enough below the target for the quality to recover.
+    ///
+    /// Returns `true` if the quality value has changed.
+    pub fn update(&mut self, frame_ms: f32) -> bool {
+        let avg_ms = match self.avg_ms {
+            Some(avg) => avg + 0.1 * (frame_ms - avg),
+            None => frame_ms,
+        };
+        self.avg_ms = Some(avg_ms);
+
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+            return false;
+        }
+
+        let old = self.value;
+        if avg_ms > 1.1 * self.target_ms {
+            self.value = (self.value - self.step).max(self.min);
+        } else if avg_ms < 0.8 * self.target_ms {
+            self.value = (self.value + self.step).min(1.0);
+        }
+
+        if self.value == old {
+            return false;
+        }
+
+        self.cooldown = COOLDOWN_FRAMES;
+        true
+    }
+
+    /// Scale factor for the size of screen sized render targets
+    pub fn resolution_scale(&self) -> f32 {
+        match self.scale_resolution {
+            true => self.value,
+            false => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(ctrl: &mut QualityController, frame_ms: f32, frames: usize) {
+        for _ in 0..frames {
+            ctrl.update(frame_ms);
+        }
+    }
+
+    #[test]
+    fn steps_down_when_slow() {
+        let mut ctrl = QualityController::new(60.0);
+        run(&mut ctrl, 50.0, 10 * COOLDOWN_FRAMES as usize);
+        assert!(ctrl.value < 1.0);
+        assert!(ctrl.value >= ctrl.min);
+    }
+
+    #[test]
+    fn never_below_min() {
+        let mut ctrl = QualityController::new(60.0);
+        run(&mut ctrl, 1000.0, 1000 * COOLDOWN_FRAMES as usize);
+        assert_eq!(ctrl.value, ctrl.min);
+    }
+
+    #[test]
+    fn recovers_when_fast() {
+        let mut ctrl = QualityController::new(60.0);
+        ctrl.value = 0.5;
+        run(&mut ctrl, 5.0, 100 * COOLDOWN_FRAMES as usize);
+        assert_eq!(ctrl.value, 1.0);
+    }
+
+    #[test]
+    fn drops_and_recovers() {
+        let mut ctrl = QualityController::new(60.0);
+        run(&mut ctrl, 40.0, 100 * COOLDOWN_FRAMES as usize);
+        assert_eq!(ctrl.value, ctrl.min);
+
+        // the render time falls once the quality is lower
+        run(&mut ctrl, 8.0, 100 * COOLDOWN_FRAMES as usize);
+        assert_eq!(ctrl.value, 1.0);
+    }
+
+    #[test]
+    fn holds_near_target() {
+        let mut ctrl = QualityController::new(60.0);
+        ctrl.value = 0.7;
+        run(&mut ctrl, 16.0, 100 * COOLDOWN_FRAMES as usize);
+        assert_eq!(ctrl.value, 0.7);
+    }
+
+    #[test]
+    fn parse_yaml() {
+        let object =
+            serde_yaml::from_str("target_fps: 30\nmin: 0.5\nscale_resolution: true").unwrap();
+        let ctrl = QualityController::from_yaml(&object).unwrap();
+        assert_eq!(ctrl.min, 0.5);
+        assert!(ctrl.scale_resolution);
+        assert!((ctrl.target_ms - 1000.0 / 30.0).abs() < 1e-4);
+
+        let object = serde_yaml::from_str("min: 0.5").unwrap();
+        assert!(QualityController::from_yaml(&object).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use std::{ffi::CString, path::Path};
+
+use serde_yaml::Value;
+
+use crate::util::*;
+
+/// Writes the contents of a texture to disk after the pipeline has been
+/// running for a number of frames.
+///
+/// Used to bake distance fields, lookup tables or simulation states for
+/// other projects.
+#[derive(Debug)]
+pub struct Readback {
+    pub name: CString,
+    pub path: String,
+    pub remaining: u32,
+}
+
+impl Readback {
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let name = match object.get("name") {
+            Some(Value::String(s)) => CString::new(s.as_str()).unwrap(),
+            s => return Err(format!("Expected \"name\" to be a string, got {:?}", s)),
+        };
+
+        let path = match object.get("path") {
+            Some(Value::String(s)) => s.clone(),
+            s => return Err(format!("Expected \"path\" to be a string, got {:?}", s)),
+        };
+
+        if !matches!(extension(&path).as_str(), "bin" | "hdr" | "png") {
+            return Err(format!(
+                "Expected \"path\" to end in .bin, .hdr or .png, got {:?}",
+                path
+            ));
+        }
+
+        let frame = match object.get("frame") {
+            Some(s) => match s.as_u64() {
+                Some(n) if n > 0 && n <= u32::MAX as u64 => n as u32,
+                _ => {
+                    return Err(format!(
+                        "Expected \"frame\" to be a positive number, got {:?}",
+                        s
+                    ))
+                }
+            },
+            None => 1,
+        };
+
+        Ok(Self {
+            name,
+            path,
+            remaining: frame,
+        })
+    }
+
+    /// Counts down one frame, returns `true` once the texture should be saved.
+    pub fn tick(&mut self) -> bool {
+        match self.remaining {
+            0 => false,
+            n => {
+                self.remaining = n - 1;
+                n == 1
+            }
+        }
+    }
+
+    /// Reads the texture back and writes it to the file.
+    ///
+    /// `.bin` files hold the raw texels as little endian floats, while `.hdr`
+    /// and `.png` files only support 2D textures.
+    pub fn save(&self, tex: &dyn Texture) -> Result<(), String> {
+        let (data, channels) = read_texture(tex);
+        let res = tex.resolution();
+        let (width, height) = (res[0] as usize, res[1].max(1) as usize);
+
+        let flat = res[2] == 0;
+        let rows: Vec<&[f32]> = data.chunks(width * channels).rev().collect();
+
+        let result = match extension(&self.path).as_str() {
+            "bin" => {
+                let bytes: Vec<u8> = data.iter().flat_map(|x| x.to_le_bytes()).collect();
+                std::fs::write(&self.path, bytes).map_err(|e| e.to_string())
+            }
+            "hdr" if flat => {
+                let pixels: Vec<_> = rows
+                    .iter()
+                    .flat_map(|row| row.chunks(channels))
+                    .map(|px| image::Rgb([px[0], px[1 % channels], px[2 % channels]]))
+                    .collect();
+
+                std::fs::File::create(&self.path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|file| {
+                        image::codecs::hdr::HdrEncoder::new(file)
+                            .encode(&pixels, width, height)
+                            .map_err(|e| e.to_string())
+                    })
+            }
+            "png" if flat => {
+                let pixels: Vec<u8> = rows
+                    .iter()
+                    .flat_map(|row| row.chunks(channels))
+                    .flat_map(|px| (0..4).map(move |k| px.get(k).copied().unwrap_or(1.0)))
+                    .map(|x| (255.0 * x.clamp(0.0, 1.0)).round() as u8)
+                    .collect();
+
+                image::RgbaImage::from_raw(width as _, height as _, pixels)
+                    .unwrap()
+                    .save(&self.path)
+                    .map_err(|e| e.to_string())
+            }
+            _ => Err("Only 2D textures can be saved as images, use .bin instead".into()),
+        };
+
+        result.map_err(|e| format!("Failed to save {:?} to {:?}: {}", self.name, self.path, e))
+    }
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .map_or(String::new(), |ext| ext.to_string_lossy().to_lowercase())
+}
+
+/// Reads all texels of a texture as floats.
+///
+/// Returns the data along with the number of channels per texel.
+pub fn read_texture(tex: &dyn Texture) -> (Vec<f32>, usize) {
+    unsafe {
+        // make sure all writes from compute shaders have landed
+        if supports_images() {
+            gl::MemoryBarrier(gl::ALL_BARRIER_BITS);
+        }
+
+        if let Some(buffer) = tex.as_any().downcast_ref::<TextureBuffer>() {
+            let channels = match buffer.format {
+                gl::R32F => 1,
+                gl::RG32F => 2,
+                gl::RGB32F => 3,
+                _ => 4,
+            };
+
+            let mut data = vec![0.0_f32; buffer.len as usize * channels];
+            gl::BindBuffer(gl::TEXTURE_BUFFER, buffer.buf_id);
+            gl::GetBufferSubData(
+                gl::TEXTURE_BUFFER,
+                0,
+                std::mem::size_of_val(data.as_slice()) as _,
+                data.as_mut_ptr() as _,
+            );
+            gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+            gl_debug_check!();
+
+            return (data, channels);
+        }
+
+        let res = tex.resolution();
+        let len = res.iter().map(|&n| n.max(1) as usize).product::<usize>();
+        let mut data = vec![0.0_f32; 4 * len];
+
+        gl::ActiveTexture(gl::TEXTURE0);
+        tex.bind(0);
+        gl::GetTexImage(tex.target(), 0, gl::RGBA, gl::FLOAT, data.as_mut_ptr() as _);
+        gl_debug_check!();
+
+        (data, 4)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(yaml: &str) -> Result<Readback, String> {
+        Readback::from_yaml(&serde_yaml::from_str(yaml).unwrap())
+    }
+
+    #[test]
+    fn saves_once_after_frames() {
+        let mut readback = parse("{name: sdf, path: sdf.bin, frame: 3}").unwrap();
+        let saved: Vec<_> = (0..5).map(|_| readback.tick()).collect();
+        assert_eq!(saved, vec![false, false, true, false, false]);
+    }
+
+    #[test]
+    fn rejects_unknown_format() {
+        assert!(parse("{name: sdf, path: sdf.exr}").is_err());
+        assert!(parse("{name: sdf, path: sdf.png, frame: 0}").is_err());
+    }
+}
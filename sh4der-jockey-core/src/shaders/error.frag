@@ -0,0 +1,13 @@
+#version 140
+
+out vec4 out_color;
+
+uniform vec4 resolution;
+uniform float time;
+
+// shown in place of a stage which failed to build
+void main() {
+    vec2 p = gl_FragCoord.xy / resolution.y;
+    float stripe = step(0.5, fract(8.0 * (p.x + p.y) - 0.5 * time));
+    out_color = vec4(mix(vec3(1.0, 0.0, 1.0), vec3(0.1, 0.0, 0.1), stripe), 1.0);
+}
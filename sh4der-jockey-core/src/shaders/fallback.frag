@@ -0,0 +1,23 @@
+#version 140
+
+out vec4 out_color;
+
+uniform vec4 resolution;
+uniform float time;
+uniform vec3 bass_smooth;
+uniform vec3 mid_smooth;
+uniform vec3 high_smooth;
+uniform vec3 volume;
+
+// shown when no pipeline of the project can be built
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution.xy;
+    vec3 bands = vec3(bass_smooth.x, mid_smooth.x, high_smooth.x);
+
+    float wave = sin(6.0 * uv.x + time) * 0.5 + 0.5;
+    vec3 col = 0.5 + 0.5 * cos(time * 0.2 + uv.y * 3.0 + vec3(0.0, 2.0, 4.0));
+    col *= 0.4 + 0.6 * wave + 2.0 * bands;
+    col += volume.x * smoothstep(0.02, 0.0, abs(uv.y - wave));
+
+    out_color = vec4(col, 1.0);
+}
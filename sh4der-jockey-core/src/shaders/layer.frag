@@ -0,0 +1,28 @@
+#version 140
+
+uniform sampler2D layer;
+uniform vec2 resolution;
+uniform float opacity;
+uniform int mode;
+
+out vec4 out_color;
+
+// keep in sync with LayerBlend
+#define NORMAL 0
+#define ADD 1
+#define MULTIPLY 2
+#define SCREEN 3
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution;
+    vec4 color = texture(layer, uv);
+    float alpha = opacity * color.a;
+
+    if (mode == MULTIPLY) {
+        out_color = vec4(mix(vec3(1.0), color.rgb, alpha), 1.0);
+    } else if (mode == SCREEN) {
+        out_color = vec4(alpha * color.rgb, 1.0);
+    } else {
+        out_color = vec4(color.rgb, alpha);
+    }
+}
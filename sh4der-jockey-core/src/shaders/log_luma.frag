@@ -0,0 +1,13 @@
+#version 140
+
+uniform sampler2D image;
+uniform vec2 resolution;
+
+out vec4 out_color;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution;
+    vec3 color = texture(image, uv).rgb;
+    float luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    out_color = vec4(log(max(luma, 1e-4)));
+}
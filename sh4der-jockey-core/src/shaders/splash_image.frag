@@ -0,0 +1,18 @@
+#version 140
+
+out vec4 out_color;
+
+uniform vec4 resolution;
+uniform sampler2D splash;
+uniform vec4 splash_res;
+
+// fits the splash image onto the screen, keeping its aspect ratio
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution.xy - 0.5;
+    float ratio = resolution.z / splash_res.w;
+    uv *= ratio > 1.0 ? vec2(ratio, 1.0) : vec2(1.0, 1.0 / ratio);
+    uv += 0.5;
+
+    bool inside = all(greaterThanEqual(uv, vec2(0.0))) && all(lessThanEqual(uv, vec2(1.0)));
+    out_color = inside ? texture(splash, uv) : vec4(0.0, 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,10 @@
+#version 140
+
+uniform sampler2D sprite;
+
+in vec4 v_color;
+out vec4 color;
+
+void main() {
+    color = v_color * texture(sprite, gl_PointCoord);
+}
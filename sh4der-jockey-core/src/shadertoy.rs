@@ -0,0 +1,171 @@
+use serde_yaml::Value;
+
+/// Number of input channels of a Shadertoy shader.
+pub const SHADERTOY_CHANNELS: usize = 4;
+
+/// Shadertoy uniforms, declared as globals which are set by [`SHADERTOY_MAIN`].
+const SHADERTOY_PRELUDE: &str = "\
+out vec4 out_color;
+uniform vec4 resolution;
+uniform float time;
+uniform float time_delta;
+uniform int frame_count;
+vec3 iResolution;
+float iTime;
+float iGlobalTime;
+float iTimeDelta;
+float iFrameRate;
+int iFrame;
+vec4 iMouse;
+vec4 iDate;
+float iSampleRate;
+float iChannelTime[4];
+vec3 iChannelResolution[4];
+";
+
+/// Start of the `main` function, setting the Shadertoy uniforms from the
+/// uniforms of this tool.
+///
+/// There is no mouse and no audio channel, so `iMouse` is zero, `iSampleRate`
+/// is fixed and `iDate` only holds the time.
+const SHADERTOY_MAIN: &str = "
+void main() {
+    iResolution = vec3(resolution.xy, 1.0);
+    iTime = time;
+    iGlobalTime = time;
+    iTimeDelta = time_delta;
+    iFrameRate = 1.0 / time_delta;
+    iFrame = frame_count;
+    iMouse = vec4(0.0);
+    iDate = vec4(0.0, 0.0, 0.0, time);
+    iSampleRate = 44100.0;
+    iChannelTime = float[4](time, time, time, time);
+";
+
+/// Settings of a stage running a shader copied from shadertoy.com.
+///
+/// The shader only has to define `mainImage`, the `main` function calling it
+/// is added by the wrapper.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Shadertoy {
+    /// Textures read as `iChannel0` to `iChannel3`.
+    pub channels: [Option<String>; SHADERTOY_CHANNELS],
+}
+
+impl Shadertoy {
+    /// Reads the `shadertoy` and `channels` fields of a stage, returns `None`
+    /// if the stage is a regular one.
+    pub fn from_yaml(object: &Value) -> Result<Option<Self>, String> {
+        match object.get("shadertoy") {
+            Some(Value::Bool(true)) => (),
+            Some(Value::Bool(false)) | None => return Ok(None),
+            Some(s) => {
+                return Err(format!(
+                    "Expected field \"shadertoy\" to be a bool, got {:?}",
+                    s
+                ))
+            }
+        }
+
+        let mut channels: [Option<String>; SHADERTOY_CHANNELS] = Default::default();
+        match object.get("channels") {
+            Some(Value::Sequence(seq)) if seq.len() <= SHADERTOY_CHANNELS => {
+                for (channel, value) in channels.iter_mut().zip(seq) {
+                    *channel = match value {
+                        Value::String(s) => Some(s.clone()),
+                        Value::Null => None,
+                        s => {
+                            return Err(format!(
+                                "Expected channel to be a texture name, got {:?}",
+                                s
+                            ))
+                        }
+                    };
+                }
+            }
+            None => (),
+            s => {
+                return Err(format!(
+                    "Expected field \"channels\" to be a list of up to {} texture names, got {:?}",
+                    SHADERTOY_CHANNELS, s
+                ))
+            }
+        }
+
+        Ok(Some(Self { channels }))
+    }
+
+    /// Turns a preprocessed Shadertoy shader into a regular fragment shader.
+    pub fn wrap(&self, code: &str) -> String {
+        let (version, body) = match code.strip_prefix("#version") {
+            Some(_) => code.split_at(code.find('\n').map_or(code.len(), |k| k + 1)),
+            None => ("#version 330\n", code),
+        };
+
+        let mut out = String::with_capacity(code.len() + 2048);
+        out.push_str(version);
+        out.push_str(SHADERTOY_PRELUDE);
+
+        // unused channels keep their name, so using them gives a clear error
+        let mut declared = Vec::new();
+        for (k, channel) in self.channels.iter().enumerate() {
+            let name = channel.clone().unwrap_or_else(|| format!("iChannel{}", k));
+            if !declared.contains(&name) {
+                out.push_str(&format!("uniform sampler2D {};\n", name));
+                declared.push(name.clone());
+            }
+            if channel.is_some() {
+                out.push_str(&format!("#define iChannel{} {}\n", k, name));
+            }
+        }
+
+        out.push_str(body);
+        out.push_str(SHADERTOY_MAIN);
+        for (k, channel) in self.channels.iter().enumerate() {
+            if let Some(name) = channel {
+                out.push_str(&format!(
+                    "    iChannelResolution[{}] = vec3(textureSize({}, 0), 1.0);\n",
+                    k, name
+                ));
+            }
+        }
+        out.push_str("    mainImage(out_color, gl_FragCoord.xy);\n}\n");
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wraps_main_image() {
+        let object: Value =
+            serde_yaml::from_str("{shadertoy: true, channels: [feedback, ~, noise]}").unwrap();
+        let shadertoy = Shadertoy::from_yaml(&object).unwrap().unwrap();
+        assert_eq!(shadertoy.channels[0].as_deref(), Some("feedback"));
+        assert_eq!(shadertoy.channels[1], None);
+
+        let code = "void mainImage(out vec4 c, in vec2 p) { c = texture(iChannel0, p); }";
+        let wrapped = shadertoy.wrap(code);
+        assert!(wrapped.starts_with("#version 330\n"));
+        assert!(wrapped.contains("uniform sampler2D feedback;\n#define iChannel0 feedback\n"));
+        assert!(wrapped.contains("uniform sampler2D iChannel1;\n"));
+        assert!(wrapped.contains("iChannelResolution[2] = vec3(textureSize(noise, 0), 1.0);"));
+        assert!(!wrapped.contains("textureSize(iChannel1"));
+        assert!(!wrapped.contains("#define iTime"));
+        assert!(wrapped.contains("float iTime;\n"));
+        assert!(wrapped.contains("    iTime = time;\n"));
+        assert!(wrapped.ends_with("mainImage(out_color, gl_FragCoord.xy);\n}\n"));
+
+        // an existing version directive is kept
+        let wrapped = shadertoy.wrap("#version 450\nvoid mainImage(out vec4 c, in vec2 p) {}");
+        assert!(wrapped.starts_with("#version 450\nout vec4 out_color;"));
+
+        let object: Value = serde_yaml::from_str("{fs: a.frag}").unwrap();
+        assert_eq!(Shadertoy::from_yaml(&object).unwrap(), None);
+        let object: Value = serde_yaml::from_str("{shadertoy: true, channels: a}").unwrap();
+        assert!(Shadertoy::from_yaml(&object).is_err());
+    }
+}
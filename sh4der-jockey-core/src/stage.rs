@@ -0,0 +1,902 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    ffi::CString,
+    hash::{Hash, Hasher},
+};
+
+use gl::types::*;
+use serde_yaml::Value;
+
+use super::{
+    inject_mixer_epilogue, thick_line_shaders, IndexBuffer, SampleCounter, Shadertoy, Uniform,
+    UniformCache,
+};
+use crate::util::*;
+
+pub const PASS_VERT: &str = include_str!("shaders/pass.vert");
+pub const PASS_FRAG: &str = include_str!("shaders/pass.frag");
+pub const SPRITE_FRAG: &str = include_str!("shaders/sprite.frag");
+pub const ERROR_FRAG: &str = include_str!("shaders/error.frag");
+
+#[derive(Debug)]
+pub enum StageKind {
+    Comp {
+        dispatch: [GLuint; 3],
+    },
+    Vert {
+        count: GLsizei,
+        mode: GLenum,
+        thickness: f32,
+        vertices: Option<CString>,
+        msaa: GLsizei,
+        polygon_mode: GLenum,
+        cull: Option<GLenum>,
+        sprite: Option<CString>,
+        program_point_size: bool,
+        indices: Option<IndexBuffer>,
+    },
+    Feedback {
+        name: CString,
+        count: GLsizei,
+        varyings: Vec<CString>,
+    },
+    Frag {},
+}
+
+/// The stage struct
+///
+/// This struct holds all data associated to a stage in the render pipeline.
+///
+/// Note that it does not render anything itself, it merely holds the
+/// information and takes care of resource management, i.e. it compiles
+/// all shaders and links all programs on initialization and makes sure all
+/// shaders and programs are deleted once they're no longer needed.
+#[derive(Debug)]
+pub struct Stage {
+    pub prog_id: GLuint,
+    pub target: Option<CString>,
+    pub kind: StageKind,
+    pub sh_ids: Vec<GLuint>,
+    pub deps: Vec<CString>,
+    pub unis: HashMap<CString, Uniform>,
+    pub samplers: HashMap<CString, Sampler>,
+    pub blend: Option<(GLenum, GLenum)>,
+    pub perf: RunningAverage<f32, 128>,
+    pub builder: TextureBuilder,
+    pub disabled: bool,
+    pub samples: Option<SampleCounter>,
+    pub multisample: Option<MultisampleBuffer>,
+    pub sources: Vec<String>,
+    pub variants: Vec<(GLuint, Vec<GLuint>)>,
+    pub source_hash: u64,
+    /// Program of the previous build, swapped in while comparing.
+    pub previous: Option<(GLuint, Vec<GLuint>)>,
+    pub comparing: bool,
+    /// Uniform values uploaded to the programs of this stage.
+    pub uploaded: UniformCache,
+}
+
+/// Preprocessed shader sources of a stage.
+#[derive(Debug, Clone, Default)]
+pub struct StageSources {
+    pub vs: Option<String>,
+    pub fs: Option<String>,
+    pub cs: Option<String>,
+    /// Files the sources were assembled from.
+    pub lut: Vec<String>,
+}
+
+impl StageSources {
+    /// Reads and preprocesses the shader files of a stage.
+    ///
+    /// This doesn't touch any GL objects, so it may run on any thread.
+    pub fn load(object: &Value) -> Result<Self, String> {
+        let mut out = Self::default();
+        for &name in ["vs", "fs", "cs"].iter() {
+            let file = match object.get(name) {
+                Some(Value::String(f)) => f,
+                Some(s) => {
+                    return Err(format!(
+                        "Expected shader field to be a filename, got {:?}",
+                        s
+                    ))
+                }
+                None => continue,
+            };
+
+            let code = match std::fs::read_to_string(file) {
+                Ok(s) => s,
+                Err(e) => return Err(format!("{}, {}", e, file)),
+            };
+
+            let code = preprocess(&code, file, &mut out.lut)?;
+            match name {
+                "vs" => out.vs = Some(code),
+                "fs" => out.fs = Some(code),
+                _ => out.cs = Some(code),
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+impl Stage {
+    pub fn from_yaml(object: Value, sources: StageSources) -> Result<Self, String> {
+        let perf = RunningAverage::new();
+        let deps = Vec::new();
+
+        // get render target name
+        let target = match object.get("target") {
+            Some(Value::String(s)) => Some(CString::new(s.as_str()).unwrap()),
+            Some(s) => {
+                return Err(format!(
+                    "Expected field \"target\" to be a string, got {:?}",
+                    s
+                ))
+            }
+            None => None,
+        };
+
+        // count samples written by the stage
+        let samples = match object.get("occlusion_query") {
+            Some(Value::Bool(true)) => match &target {
+                Some(name) => Some(SampleCounter::new(name)),
+                None => return Err("Field \"occlusion_query\" requires a \"target\"".into()),
+            },
+            Some(Value::Bool(false)) | None => None,
+            Some(s) => {
+                return Err(format!(
+                    "Expected field \"occlusion_query\" to be a bool, got {:?}",
+                    s
+                ))
+            }
+        };
+
+        // parse uniforms
+        let mut unis = HashMap::new();
+        match object.get("uniforms") {
+            Some(Value::Mapping(m)) => {
+                for (key, value) in m {
+                    let mut transpose = false;
+
+                    // get uniform name
+                    let name = match key.as_str() {
+                        Some(s) => {
+                            let mut name = s;
+
+                            // check for transpose suffix
+                            if s.len() > 2 {
+                                let (prefix, suffix) = s.split_at(s.len() - 2);
+                                let suffix = suffix.as_bytes();
+                                if matches!(suffix[0], b'-' | b'^')
+                                    && matches!(suffix[1], b't' | b'T')
+                                {
+                                    name = prefix;
+                                    transpose = true;
+                                }
+                            }
+
+                            CString::new(name).unwrap()
+                        }
+                        None => {
+                            return Err(format!(
+                                "Expected uniform name to be a string, got \"{:?}\"",
+                                key
+                            ))
+                        }
+                    };
+
+                    // parse uniform value
+                    let mut uniform = Uniform::from_yaml(value).map_err(|e| e.to_string())?;
+                    if transpose && uniform.transpose().is_err() {
+                        return Err(format!("Failed to transpose value \"{:?}\"", uniform));
+                    }
+
+                    unis.insert(name, uniform);
+                }
+            }
+            Some(s) => {
+                return Err(format!(
+                    "Expected field \"uniforms\" to be a mapping, got {:?}",
+                    s
+                ))
+            }
+            None => (),
+        }
+
+        // parse filtering overrides for textures read by this stage
+        let mut samplers = HashMap::new();
+        match object.get("sample") {
+            Some(Value::Mapping(m)) => {
+                for (key, value) in m {
+                    let name = match key.as_str() {
+                        Some(s) => CString::new(s).unwrap(),
+                        None => {
+                            return Err(format!(
+                                "Expected texture name to be a string, got \"{:?}\"",
+                                key
+                            ))
+                        }
+                    };
+
+                    samplers.insert(name, Sampler::from_yaml(value)?);
+                }
+            }
+            Some(s) => {
+                return Err(format!(
+                    "Expected field \"sample\" to be a mapping, got {:?}",
+                    s
+                ))
+            }
+            None => (),
+        }
+
+        // parse blend mode
+        let blend = match object.get("blend_mode").or(object.get("blend")) {
+            Some(Value::Sequence(s)) => {
+                fn parse_blend_mode(name: &str) -> Result<GLenum, String> {
+                    match name {
+                        "ZERO" => Ok(gl::ZERO),
+                        "ONE" => Ok(gl::ONE),
+                        "SRC_COLOR" => Ok(gl::SRC_COLOR),
+                        "DST_COLOR" => Ok(gl::DST_COLOR),
+                        "SRC_ALPHA" => Ok(gl::SRC_ALPHA),
+                        "DST_ALPHA" => Ok(gl::DST_ALPHA),
+                        "SRC1_COLOR" => Ok(gl::SRC1_COLOR),
+                        "SRC1_ALPHA" => Ok(gl::SRC1_ALPHA),
+                        "CONSTANT_COLOR" => Ok(gl::CONSTANT_COLOR),
+                        "CONSTANT_ALPHA" => Ok(gl::CONSTANT_ALPHA),
+                        "SRC_ALPHA_SATURATE" => Ok(gl::SRC_ALPHA_SATURATE),
+                        "ONE_MINUS_SRC_COLOR" => Ok(gl::ONE_MINUS_SRC_COLOR),
+                        "ONE_MINUS_DST_COLOR" => Ok(gl::ONE_MINUS_DST_COLOR),
+                        "ONE_MINUS_SRC_ALPHA" => Ok(gl::ONE_MINUS_SRC_ALPHA),
+                        "ONE_MINUS_DST_ALPHA" => Ok(gl::ONE_MINUS_DST_ALPHA),
+                        "ONE_MINUS_SRC1_COLOR" => Ok(gl::ONE_MINUS_SRC1_COLOR),
+                        "ONE_MINUS_SRC1_ALPHA" => Ok(gl::ONE_MINUS_SRC1_ALPHA),
+                        "ONE_MINUS_CONSTANT_COLOR" => Ok(gl::ONE_MINUS_CONSTANT_COLOR),
+                        "ONE_MINUS_CONSTANT_ALPHA" => Ok(gl::ONE_MINUS_CONSTANT_ALPHA),
+                        s => Err(format!("Expected blend mode, got \"{:?}\"", s)),
+                    }
+                }
+
+                match s.as_slice() {
+                    &[Value::String(ref src), Value::String(ref dst)] => {
+                        Some((parse_blend_mode(src)?, parse_blend_mode(dst)?))
+                    }
+                    s => {
+                        return Err(format!(
+                        "Expected field \"blend_mode\" to be a list of two strings, got \"{:?}\"",
+                        s
+                    ))
+                    }
+                }
+            }
+            Some(Value::String(_)) => {
+                // TODO: Fix this
+                return Err("Aliases for common blend modes are currently unimplemented".into());
+            }
+            Some(s) => return Err(format!("Invalid blend mode value, got \"{:?}\"", s)),
+            None => None,
+        };
+
+        // wrapper for shaders copied from shadertoy.com
+        let shadertoy = Shadertoy::from_yaml(&object)?;
+
+        // preprocessed shader sources
+        let lut = sources.lut;
+        let shaders = [sources.vs, sources.fs, sources.cs];
+
+        if shadertoy.is_some() && !matches!(shaders, [None, Some(_), None]) {
+            return Err("Field \"shadertoy\" requires a fragment shader stage".into());
+        }
+
+        match shaders {
+            // handle full screen fragment shader stages
+            [None, Some(fs), None] => {
+                let vs = PASS_VERT.to_string();
+                let fs = match &shadertoy {
+                    Some(shadertoy) => inject_mixer_epilogue(&shadertoy.wrap(&fs)),
+                    None => inject_mixer_epilogue(&fs),
+                };
+
+                let sources = [(vs, gl::VERTEX_SHADER), (fs, gl::FRAGMENT_SHADER)];
+                let source_hash = hash_sources(&sources);
+                let (prog_id, sh_ids, variants) = build_variants(&sources, &[], &lut)?;
+
+                let builder = TextureBuilder::parse(&object, true, true)?;
+
+                if !matches!(builder.resolution.as_slice(), &[] | &[_, _]) {
+                    return Err("Expected \"resolution\" to be 2D".into());
+                }
+
+                if builder.layers.is_some() {
+                    return Err("Field \"layers\" is only supported by compute shaders".into());
+                }
+
+                let kind = StageKind::Frag {};
+
+                Ok(Stage {
+                    prog_id,
+                    target,
+                    kind,
+                    sh_ids,
+                    deps,
+                    unis,
+                    samplers,
+                    blend,
+                    perf,
+                    builder,
+                    disabled: false,
+                    samples,
+                    multisample: None,
+                    sources: lut,
+                    variants,
+                    source_hash,
+                    previous: None,
+                    comparing: false,
+                    uploaded: UniformCache::default(),
+                })
+            }
+
+            // handle vertex shader stages
+            [Some(vs), fs_opt, None] => {
+                // get texture drawn onto points
+                let sprite = match object.get("sprite") {
+                    Some(Value::String(s)) => Some(CString::new(s.as_str()).unwrap()),
+                    Some(s) => {
+                        return Err(format!(
+                            "Expected field \"sprite\" to be a string, got {:?}",
+                            s
+                        ))
+                    }
+                    None => None,
+                };
+
+                let fs = match (fs_opt, &sprite) {
+                    (Some(fs), _) => inject_mixer_epilogue(&fs),
+                    (None, Some(_)) => SPRITE_FRAG.into(),
+                    (None, None) => PASS_FRAG.into(),
+                };
+
+                // let the vertex shader choose the size of points
+                let program_point_size = vs.contains("gl_PointSize");
+
+                // get captured vertex outputs
+                let feedback = match object.get("feedback") {
+                    Some(Value::String(s)) => Some(CString::new(s.as_str()).unwrap()),
+                    Some(s) => {
+                        return Err(format!(
+                            "Expected field \"feedback\" to be a string, got {:?}",
+                            s
+                        ))
+                    }
+                    None => None,
+                };
+
+                let varyings = match (&feedback, object.get("varyings")) {
+                    (Some(_), Some(Value::Sequence(s))) if !s.is_empty() => s
+                        .iter()
+                        .map(|v| match v.as_str() {
+                            Some(name) => Ok(CString::new(name).unwrap()),
+                            None => {
+                                Err(format!("Expected varying name to be a string, got {:?}", v))
+                            }
+                        })
+                        .collect::<Result<Vec<_>, _>>()?,
+                    (Some(_), Some(s)) => {
+                        return Err(format!(
+                            "Expected field \"varyings\" to be a list of names, got {:?}",
+                            s
+                        ))
+                    }
+                    (Some(_), None) => {
+                        return Err("Field \"varyings\" is mandatory for feedback stages".into())
+                    }
+                    (None, _) => Vec::new(),
+                };
+
+                let vertices = match object.get("vertices") {
+                    Some(Value::String(s)) => Some(CString::new(s.as_str()).unwrap()),
+                    Some(s) => {
+                        return Err(format!(
+                            "Expected field \"vertices\" to be a string, got {:?}",
+                            s
+                        ))
+                    }
+                    None => None,
+                };
+
+                let count = match object.get("count") {
+                    Some(s) => match s.as_u64() {
+                        Some(n) => n as _,
+                        _ => {
+                            return Err(format!(
+                                "Expected vertex count to be an unsigned int, got {:?}",
+                                s
+                            ))
+                        }
+                    },
+                    _ => 1024,
+                };
+
+                let mode = match object.get("mode") {
+                    Some(s) => match s.as_str() {
+                        Some("LINE_LOOP") => gl::LINE_LOOP,
+                        Some("LINE_STRIP") => gl::LINE_STRIP,
+                        Some("LINES") => gl::LINES,
+                        Some("POINTS") => gl::POINTS,
+                        Some("TRIANGLE_FAN") => gl::TRIANGLE_FAN,
+                        Some("TRIANGLE_STRIP") => gl::TRIANGLE_STRIP,
+                        Some("TRIANGLES") => gl::TRIANGLES,
+                        _ => return Err(format!("Invalid vertex mode: {:?}", s)),
+                    },
+                    _ => gl::TRIANGLES,
+                };
+
+                let thickness = match object
+                    .get("thickness")
+                    .or(object.get("stroke_weight"))
+                    .or(object.get("point_size"))
+                    .or(object.get("line_width"))
+                    .map(Value::as_f64)
+                {
+                    Some(Some(t)) if t > 0.0 => t as f32,
+                    None => 1.0,
+                    Some(s) => {
+                        return Err(format!(
+                            "Expected \"thickness\" to be positive float, got {:?}",
+                            s
+                        ))
+                    }
+                };
+
+                let indices = match object.get("indices") {
+                    Some(_) if feedback.is_some() || vertices.is_some() => {
+                        return Err(
+                            "Field \"indices\" can't be combined with captured vertices".into()
+                        )
+                    }
+                    Some(s) => Some(IndexBuffer::from_yaml(s, mode)?),
+                    None => None,
+                };
+
+                // draw all indexed vertices by default
+                let count = match (&indices, object.get("count")) {
+                    (Some(buffer), None) => buffer.vertex_count,
+                    _ => count,
+                };
+
+                if sprite.is_some() && mode != gl::POINTS {
+                    return Err("Field \"sprite\" requires \"mode\" to be POINTS".into());
+                }
+
+                // most drivers don't support wide lines, so expand them into quads
+                let thick_lines = feedback.is_none()
+                    && thickness > 1.0
+                    && matches!(mode, gl::LINES | gl::LINE_STRIP | gl::LINE_LOOP);
+
+                let (vs, gs) = match thick_lines {
+                    true => {
+                        let (vs, gs) = thick_line_shaders(&vs);
+                        (vs, Some(gs))
+                    }
+                    false => (vs, None),
+                };
+
+                let mut sources = vec![(vs, gl::VERTEX_SHADER), (fs, gl::FRAGMENT_SHADER)];
+                if let Some(gs) = gs {
+                    sources.push((gs, gl::GEOMETRY_SHADER));
+                }
+
+                let source_hash = hash_sources(&sources);
+                let (prog_id, sh_ids, variants) = build_variants(&sources, &varyings, &lut)?;
+
+                let polygon_mode = match object.get("polygon_mode") {
+                    Some(s) => match s.as_str() {
+                        Some("fill") => gl::FILL,
+                        Some("wireframe") | Some("line") => gl::LINE,
+                        Some("points") => gl::POINT,
+                        _ => {
+                            return Err(format!(
+                                "Expected \"polygon_mode\" to be one of \"fill\", \"wireframe\" or \"points\", got {:?}",
+                                s
+                            ))
+                        }
+                    },
+                    None => gl::FILL,
+                };
+
+                let cull = match object.get("cull") {
+                    Some(s) => match s.as_str() {
+                        Some("off") => None,
+                        Some("front") => Some(gl::FRONT),
+                        Some("back") => Some(gl::BACK),
+                        _ => {
+                            return Err(format!(
+                                "Expected \"cull\" to be one of \"front\", \"back\" or \"off\", got {:?}",
+                                s
+                            ))
+                        }
+                    },
+                    None => None,
+                };
+
+                let msaa = match object.get("msaa") {
+                    Some(s) => match s.as_u64() {
+                        Some(n) if n.is_power_of_two() && n <= 32 => n as _,
+                        _ => {
+                            return Err(format!(
+                                "Expected \"msaa\" to be a sample count like 2, 4 or 8, got {:?}",
+                                s
+                            ))
+                        }
+                    },
+                    None => 1,
+                };
+
+                if msaa > 1 && target.is_none() {
+                    return Err("Field \"msaa\" requires a \"target\"".into());
+                }
+
+                let builder = TextureBuilder::parse(&object, true, true)?;
+
+                if !matches!(builder.resolution.as_slice(), &[] | &[_, _]) {
+                    return Err("Expected \"resolution\" to be 2D".into());
+                }
+
+                if builder.layers.is_some() {
+                    return Err("Field \"layers\" is only supported by compute shaders".into());
+                }
+
+                let kind = match feedback {
+                    Some(name) => {
+                        if target.is_some() {
+                            return Err("Feedback stages can't have a \"target\"".into());
+                        }
+
+                        StageKind::Feedback {
+                            name,
+                            count,
+                            varyings,
+                        }
+                    }
+                    None => StageKind::Vert {
+                        count,
+                        mode,
+                        thickness,
+                        vertices,
+                        msaa,
+                        polygon_mode,
+                        cull,
+                        sprite,
+                        program_point_size,
+                        indices,
+                    },
+                };
+
+                Ok(Stage {
+                    prog_id,
+                    target,
+                    kind,
+                    sh_ids,
+                    deps,
+                    unis,
+                    samplers,
+                    blend,
+                    perf,
+                    builder,
+                    disabled: false,
+                    samples,
+                    multisample: None,
+                    sources: lut,
+                    variants,
+                    source_hash,
+                    previous: None,
+                    comparing: false,
+                    uploaded: UniformCache::default(),
+                })
+            }
+
+            // handle compute shader stages
+            [None, None, Some(cs)] => {
+                if !supports_compute() {
+                    let (major, minor) = gl_version();
+                    return Err(format!(
+                        "Compute shaders require OpenGL 4.3, but this context only supports {}.{}",
+                        major, minor
+                    ));
+                }
+
+                let sources = [(cs, gl::COMPUTE_SHADER)];
+                let source_hash = hash_sources(&sources);
+                let (prog_id, sh_ids, variants) = build_variants(&sources, &[], &lut)?;
+
+                // get target resolution
+                let dispatch = match object
+                    .get("dispatch_size")
+                    .or_else(|| object.get("dispatch"))
+                {
+                    Some(Value::Sequence(dims)) => {
+                        if dims.is_empty() || dims.len() > 3 {
+                            return Err(format!(
+                                "Field \"dispatch_size\" must be a list of 1 to 3 numbers, got {} elements",
+                                dims.len()
+                            ));
+                        }
+
+                        let mut out = [1; 3];
+                        for (k, dim) in dims.iter().enumerate() {
+                            match dim.as_i64() {
+                                Some(n) if n > 0 => {
+                                    // OpenGL must allow 65535 in each dimension
+                                    // https://www.khronos.org/opengl/wiki/Compute_Shader#Limitations
+                                    if n > 65535 {
+                                        return Err(format!(
+                                            "Values of \"dispatch_size\" may not exceed 65535 in any dimension, got {:?}",
+                                            n
+                                        ))
+                                    }
+
+                                    out[k] = n as _
+                                },
+                                _ => return Err(format!(
+                                    "Expected \"dispatch_size\" to be a list of positive numbers, got {:?}",
+                                    dims
+                                )),
+                            };
+                        }
+
+                        out
+                    }
+                    Some(s) => {
+                        return Err(format!(
+                        "Expected \"dispatch_size\" to be a list of unsigned integers, got {:?}",
+                        s
+                    ))
+                    }
+                    None => {
+                        return Err(
+                            "Field \"dispatch_size\" is mandatory for compute shaders".into()
+                        )
+                    }
+                };
+
+                let builder = TextureBuilder::parse(&object, true, false)?;
+
+                if !builder.relative.is_empty() {
+                    return Err(
+                        "Compute shaders don't support resolutions relative to the screen".into(),
+                    );
+                }
+
+                if builder.resolution.as_slice().is_empty() {
+                    return Err("Field \"resolution\" is mandatory for compute shaders".into());
+                }
+
+                if target.is_none() {
+                    return Err("Field \"target\" is mandatory for compute shaders".into());
+                }
+
+                let kind = StageKind::Comp { dispatch };
+
+                Ok(Stage {
+                    prog_id,
+                    target,
+                    kind,
+                    sh_ids,
+                    deps,
+                    unis,
+                    samplers,
+                    blend,
+                    perf,
+                    builder,
+                    disabled: false,
+                    samples,
+                    multisample: None,
+                    sources: lut,
+                    variants,
+                    source_hash,
+                    previous: None,
+                    comparing: false,
+                    uploaded: UniformCache::default(),
+                })
+            }
+
+            // Handle everything else
+            _ => Err("Invalid shader configuration".to_string()),
+        }
+    }
+
+    /// Builds a stage showing a magenta pattern in place of a stage which
+    /// failed to build, rendering into the same target.
+    pub fn error_stage(object: &Value) -> Result<Self, String> {
+        let target = match object.get("target") {
+            Some(Value::String(s)) => Some(CString::new(s.as_str()).unwrap()),
+            _ => None,
+        };
+
+        let builder = TextureBuilder::parse(object, true, true)?;
+        if !matches!(builder.resolution.as_slice(), &[] | &[_, _]) {
+            return Err("Can't replace a stage with a 3D target".into());
+        }
+
+        // keep watching the files of the broken stage
+        let sources = ["vs", "fs", "cs", "isf"]
+            .iter()
+            .filter_map(|key| object.get(key)?.as_str())
+            .map(str::to_owned)
+            .collect();
+
+        let sh_ids = vec![
+            compile_shader(PASS_VERT, gl::VERTEX_SHADER)?,
+            compile_shader(ERROR_FRAG, gl::FRAGMENT_SHADER)?,
+        ];
+        let prog_id = link_program(&sh_ids)?;
+
+        Ok(Stage {
+            prog_id,
+            target,
+            kind: StageKind::Frag {},
+            sh_ids,
+            deps: Vec::new(),
+            unis: HashMap::new(),
+            samplers: HashMap::new(),
+            blend: None,
+            perf: RunningAverage::new(),
+            builder,
+            disabled: false,
+            samples: None,
+            multisample: None,
+            sources,
+            variants: Vec::new(),
+            source_hash: 0,
+            previous: None,
+            comparing: false,
+            uploaded: UniformCache::default(),
+        })
+    }
+
+    pub fn resolution(&self) -> Option<[u32; 3]> {
+        match self.builder.resolution.as_slice() {
+            &[w] => Some([w, 0, 0]),
+            &[w, h] => Some([w, h, 0]),
+            &[w, h, d] => Some([w, h, d]),
+            _ => None,
+        }
+    }
+
+    /// Returns all programs of the stage, one for every quality level.
+    pub fn programs(&self) -> Vec<GLuint> {
+        match self.variants.is_empty() {
+            true => vec![self.prog_id],
+            false => self.variants.iter().map(|(prog_id, _)| *prog_id).collect(),
+        }
+    }
+
+    /// Switches to the program compiled for the given quality value.
+    pub fn select_variant(&mut self, quality: f32) {
+        if self.variants.is_empty() {
+            return;
+        }
+
+        let last = self.variants.len() - 1;
+        let index = (quality.clamp(0.0, 1.0) * last as f32).round() as usize;
+        let comparing = self.comparing;
+        self.set_comparing(false);
+
+        let (prog_id, sh_ids) = &self.variants[index.min(last)];
+        self.prog_id = *prog_id;
+        self.sh_ids = sh_ids.clone();
+
+        self.set_comparing(comparing);
+    }
+
+    /// Removes the current program from the stage, so it outlives the stage.
+    ///
+    /// The stage can't be drawn anymore afterwards.
+    pub fn take_program(&mut self) -> (GLuint, Vec<GLuint>) {
+        self.set_comparing(false);
+
+        let prog_id = std::mem::replace(&mut self.prog_id, 0);
+        self.variants.retain(|(id, _)| *id != prog_id);
+        let sh_ids = std::mem::take(&mut self.sh_ids);
+        (prog_id, sh_ids)
+    }
+
+    /// Swaps the program of the previous build in or out.
+    pub fn set_comparing(&mut self, comparing: bool) {
+        if comparing == self.comparing {
+            return;
+        }
+
+        if let Some((prog_id, sh_ids)) = &mut self.previous {
+            std::mem::swap(&mut self.prog_id, prog_id);
+            std::mem::swap(&mut self.sh_ids, sh_ids);
+            self.comparing = comparing;
+        }
+    }
+}
+
+/// Hashes shader sources to tell whether a stage has changed between builds.
+fn hash_sources(sources: &[(String, GLenum)]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    sources.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A linked program and the shaders it was linked from.
+type Program = (GLuint, Vec<GLuint>);
+
+/// Compiles and links the given shaders.
+///
+/// If the sources declare quality levels with `#pragma sj quality(...)`,
+/// one program is built for each level and all of them are returned as
+/// variants. The returned program is the one with the highest quality.
+fn build_variants(
+    sources: &[(String, GLenum)],
+    varyings: &[CString],
+    lut: &[String],
+) -> Result<(GLuint, Vec<GLuint>, Vec<Program>), String> {
+    let mut levels = Vec::new();
+    for (src, _) in sources.iter() {
+        let found = quality_levels(src).map_err(|e| process_error(e, lut))?;
+        if !levels.is_empty() && !found.is_empty() && levels != found {
+            return Err(format!(
+                "Conflicting quality levels {:?} and {:?}",
+                levels, found
+            ));
+        }
+
+        if !found.is_empty() {
+            levels = found;
+        }
+    }
+
+    let build = |defines: &str| -> Result<Program, String> {
+        let mut sh_ids = Vec::new();
+        for (src, ty) in sources.iter() {
+            let src = insert_defines(src, defines);
+            let id = compile_shader(&src, *ty).map_err(|e| process_error(e, lut))?;
+            sh_ids.push(id);
+        }
+
+        let prog_id = link_program_with_varyings(&sh_ids, varyings)?;
+        Ok((prog_id, sh_ids))
+    };
+
+    if levels.is_empty() {
+        let (prog_id, sh_ids) = build("")?;
+        return Ok((prog_id, sh_ids, Vec::new()));
+    }
+
+    let variants = (0..levels.len())
+        .map(|k| build(&quality_defines(&levels, k)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (prog_id, sh_ids) = variants.last().cloned().unwrap();
+    Ok((prog_id, sh_ids, variants))
+}
+
+impl Drop for Stage {
+    fn drop(&mut self) {
+        self.set_comparing(false);
+
+        let mut programs = std::mem::take(&mut self.variants);
+        if programs.is_empty() {
+            programs.push((self.prog_id, std::mem::take(&mut self.sh_ids)));
+        }
+        programs.extend(self.previous.take());
+
+        unsafe {
+            for (prog_id, sh_ids) in programs.iter() {
+                for &id in sh_ids.iter() {
+                    gl::DetachShader(*prog_id, id);
+                    gl::DeleteShader(id);
+                }
+
+                gl::DeleteProgram(*prog_id);
+            }
+        }
+    }
+}
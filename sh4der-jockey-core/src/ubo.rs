@@ -0,0 +1,223 @@
+use std::ffi::CString;
+
+use gl::types::*;
+use serde_yaml::Value;
+
+use super::Uniform;
+
+/// A named block of uniforms shared by all stages.
+///
+/// The members of a block are packed according to the std140 layout rules
+/// in the order they appear in the pipeline file, so the block has to be
+/// declared with `layout(std140)` and the same member order in the shader.
+/// The data is uploaded once when the pipeline is built and the buffer is
+/// bound once per frame, instead of setting every value for every stage.
+#[derive(Debug)]
+pub struct UniformBlock {
+    pub name: CString,
+    buffer_id: GLuint,
+}
+
+impl UniformBlock {
+    pub fn from_yaml(name: &Value, object: &Value) -> Result<Self, String> {
+        let name = match name.as_str() {
+            Some(s) => CString::new(s).unwrap(),
+            None => {
+                return Err(format!(
+                    "Expected uniform block name to be a string, got {:?}",
+                    name
+                ))
+            }
+        };
+
+        let members = match object {
+            Value::Mapping(m) => m,
+            s => {
+                return Err(format!(
+                    "Expected uniform block {:?} to be a mapping, got {:?}",
+                    name, s
+                ))
+            }
+        };
+
+        let mut unis = Vec::with_capacity(members.len());
+        for (key, value) in members {
+            if !key.is_string() {
+                return Err(format!(
+                    "Expected uniform name to be a string, got \"{:?}\"",
+                    key
+                ));
+            }
+
+            let uniform = Uniform::from_yaml(value).map_err(|e| e.to_string())?;
+            unis.push(uniform);
+        }
+
+        let data = pack_std140(&unis);
+
+        let mut buffer_id = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut buffer_id);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, buffer_id);
+            gl::BufferData(
+                gl::UNIFORM_BUFFER,
+                data.len() as _,
+                data.as_ptr() as _,
+                gl::STATIC_DRAW,
+            );
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+            gl_debug_check!();
+        }
+
+        Ok(Self { name, buffer_id })
+    }
+
+    /// Connects the block in the given program to a binding point, if the
+    /// program uses the block at all.
+    pub fn attach(&self, prog_id: GLuint, binding: GLuint) {
+        unsafe {
+            let index = gl::GetUniformBlockIndex(prog_id, self.name.as_ptr());
+            if index == gl::INVALID_INDEX {
+                return;
+            }
+
+            gl::UniformBlockBinding(prog_id, index, binding);
+            gl_debug_check!();
+        }
+    }
+
+    pub fn bind(&self, binding: GLuint) {
+        unsafe {
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, self.buffer_id);
+            gl_debug_check!();
+        }
+    }
+}
+
+impl Drop for UniformBlock {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.buffer_id);
+        }
+    }
+}
+
+/// Packs the given values into a buffer following the std140 layout rules.
+///
+/// Scalars are aligned to 4 bytes, `vec2` to 8 bytes and `vec3` and `vec4`
+/// to 16 bytes. Matrices are stored as arrays of column vectors, where each
+/// column is padded to the size of a `vec4`. The total size is rounded up
+/// to a multiple of 16 bytes.
+pub fn pack_std140(unis: &[Uniform]) -> Vec<u8> {
+    fn align(data: &mut Vec<u8>, alignment: usize) {
+        let len = data.len().div_ceil(alignment) * alignment;
+        data.resize(len, 0);
+    }
+
+    fn push(data: &mut Vec<u8>, values: &[GLfloat]) {
+        for v in values {
+            data.extend_from_slice(&v.to_ne_bytes());
+        }
+    }
+
+    fn push_matrix(data: &mut Vec<u8>, values: &[GLfloat], rows: usize) {
+        for column in values.chunks(rows) {
+            align(data, 16);
+            push(data, column);
+        }
+        align(data, 16);
+    }
+
+    let mut data = Vec::new();
+    for uniform in unis {
+        match uniform {
+            Uniform::Float(x) => {
+                align(&mut data, 4);
+                push(&mut data, &[*x]);
+            }
+            Uniform::Vec2(x, y) => {
+                align(&mut data, 8);
+                push(&mut data, &[*x, *y]);
+            }
+            Uniform::Vec3(x, y, z) => {
+                align(&mut data, 16);
+                push(&mut data, &[*x, *y, *z]);
+            }
+            Uniform::Vec4(x, y, z, w) => {
+                align(&mut data, 16);
+                push(&mut data, &[*x, *y, *z, *w]);
+            }
+            Uniform::Mat2(vs) => push_matrix(&mut data, vs, 2),
+            Uniform::Mat3(vs) => push_matrix(&mut data, vs, 3),
+            Uniform::Mat4(vs) => push_matrix(&mut data, vs, 4),
+            Uniform::Mat2x3(vs) => push_matrix(&mut data, vs, 3),
+            Uniform::Mat3x2(vs) => push_matrix(&mut data, vs, 2),
+            Uniform::Mat2x4(vs) => push_matrix(&mut data, vs, 4),
+            Uniform::Mat4x2(vs) => push_matrix(&mut data, vs, 2),
+            Uniform::Mat3x4(vs) => push_matrix(&mut data, vs, 4),
+            Uniform::Mat4x3(vs) => push_matrix(&mut data, vs, 3),
+        }
+    }
+
+    align(&mut data, 16);
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn floats(data: &[u8]) -> Vec<f32> {
+        data.chunks(4)
+            .map(|c| f32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+            .collect()
+    }
+
+    #[test]
+    fn scalars_are_packed_tightly() {
+        let data = pack_std140(&[Uniform::Float(1.0), Uniform::Float(2.0)]);
+        assert_eq!(floats(&data), vec![1.0, 2.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn vec3_is_aligned_to_16_bytes() {
+        let data = pack_std140(&[Uniform::Float(0.2), Uniform::Vec3(1.0, 0.9, 0.7)]);
+        assert_eq!(data.len(), 32);
+        assert_eq!(floats(&data), vec![0.2, 0.0, 0.0, 0.0, 1.0, 0.9, 0.7, 0.0]);
+    }
+
+    #[test]
+    fn float_fills_vec3_padding() {
+        let data = pack_std140(&[Uniform::Vec3(1.0, 2.0, 3.0), Uniform::Float(4.0)]);
+        assert_eq!(floats(&data), vec![1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn vec2_is_aligned_to_8_bytes() {
+        let data = pack_std140(&[Uniform::Float(1.0), Uniform::Vec2(2.0, 3.0)]);
+        assert_eq!(floats(&data), vec![1.0, 0.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn matrix_columns_are_padded() {
+        let data = pack_std140(&[
+            Uniform::Float(9.0),
+            Uniform::Mat2([1.0, 2.0, 3.0, 4.0]),
+            Uniform::Float(5.0),
+        ]);
+        assert_eq!(
+            floats(&data),
+            vec![
+                9.0, 0.0, 0.0, 0.0, //
+                1.0, 2.0, 0.0, 0.0, //
+                3.0, 4.0, 0.0, 0.0, //
+                5.0, 0.0, 0.0, 0.0,
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_block() {
+        assert!(pack_std140(&[]).is_empty());
+    }
+}
@@ -1,4 +1,8 @@
-use std::{ffi::CString, mem::MaybeUninit};
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    mem::MaybeUninit,
+};
 
 use anyhow::{bail, Result};
 use gl::types::*;
@@ -14,9 +18,20 @@ lazy_static! {
     pub static ref RESOLUTION_NAME: CString = CString::new("resolution").unwrap();
     pub static ref PASS_INDEX_NAME: CString = CString::new("pass_index").unwrap();
     pub static ref OUT_COLOR_NAME: CString = CString::new("out_color").unwrap();
+    pub static ref STAGE_OPACITY_NAME: CString = CString::new("stage_opacity").unwrap();
+    pub static ref STAGE_TINT_NAME: CString = CString::new("stage_tint").unwrap();
     pub static ref POSITION_NAME: CString = CString::new("position").unwrap();
     pub static ref VERTEX_COUNT_NAME: CString = CString::new("vertex_count").unwrap();
     pub static ref NOISE_NAME: CString = CString::new("noise").unwrap();
+    pub static ref QUALITY_NAME: CString = CString::new("quality").unwrap();
+    pub static ref EYE_NAME: CString = CString::new("eye").unwrap();
+    pub static ref PROJECTION_MODE_NAME: CString = CString::new("projection_mode").unwrap();
+    pub static ref PREV_FRAME_LUMA_NAME: CString = CString::new("prev_frame_luma").unwrap();
+    pub static ref EXPOSURE_NAME: CString = CString::new("exposure").unwrap();
+    pub static ref HISTOGRAM_NAME: CString = CString::new("histogram").unwrap();
+    pub static ref BEAT_HISTORY_NAME: CString = CString::new("beat_history").unwrap();
+    pub static ref SPRITE_NAME: CString = CString::new("sprite").unwrap();
+    pub static ref THICK_LINE_NAME: CString = CString::new("thick_line_params").unwrap();
 
     // time tracking
     pub static ref TIME_NAME: CString = CString::new("time").unwrap();
@@ -28,6 +43,7 @@ lazy_static! {
     pub static ref BEAT_NAME: CString = CString::new("beat").unwrap();
     pub static ref SLIDERS_NAME: CString = CString::new("sliders").unwrap();
     pub static ref BUTTONS_NAME: CString = CString::new("buttons").unwrap();
+    pub static ref KICK_NAME: CString = CString::new("kick").unwrap();
 
     // volume input
     pub static ref VOLUME_NAME: CString = CString::new("volume").unwrap();
@@ -41,6 +57,13 @@ lazy_static! {
     pub static ref SPECTRUM_INTEGRATED_NAME: CString = CString::new("spectrum_integrated").unwrap();
     pub static ref SPECTRUM_SMOOTH_INTEGRATED_NAME: CString = CString::new("spectrum_smooth_integrated").unwrap();
 
+    // spectral features
+    pub static ref SPECTRAL_CENTROID_NAME: CString = CString::new("spectral_centroid").unwrap();
+    pub static ref SPECTRAL_FLATNESS_NAME: CString = CString::new("spectral_flatness").unwrap();
+    pub static ref SPECTRAL_ROLLOFF_NAME: CString = CString::new("spectral_rolloff").unwrap();
+    pub static ref CHROMA_NAME: CString = CString::new("chroma").unwrap();
+    pub static ref MFCC_NAME: CString = CString::new("mfcc").unwrap();
+
     // bass
     pub static ref BASS_NAME: CString = CString::new("bass").unwrap();
     pub static ref BASS_SMOOTH_NAME: CString = CString::new("bass_smooth").unwrap();
@@ -60,6 +83,9 @@ lazy_static! {
     pub static ref HIGH_SMOOTH_INTEGRATED_NAME: CString = CString::new("high_smooth_integrated").unwrap();
 }
 
+/// Number of bins per histogram channel
+pub const HISTOGRAM_BINS: usize = 256;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Uniform {
     Float(GLfloat),
@@ -167,22 +193,24 @@ impl Uniform {
         Ok(this)
     }
 
-    pub fn bind(&self, location: GLint) {
+    /// Uploads the value to a program without binding it first.
+    pub fn upload(&self, prog_id: GLuint, location: GLint) {
+        let (p, l) = (prog_id, location);
         unsafe {
             match self {
-                Uniform::Float(v0) => gl::Uniform1f(location, *v0),
-                Uniform::Vec2(v0, v1) => gl::Uniform2f(location, *v0, *v1),
-                Uniform::Vec3(v0, v1, v2) => gl::Uniform3f(location, *v0, *v1, *v2),
-                Uniform::Vec4(v0, v1, v2, v3) => gl::Uniform4f(location, *v0, *v1, *v2, *v3),
-                Uniform::Mat2(vs) => gl::UniformMatrix2fv(location, 1, gl::FALSE, vs as _),
-                Uniform::Mat3(vs) => gl::UniformMatrix3fv(location, 1, gl::FALSE, vs as _),
-                Uniform::Mat4(vs) => gl::UniformMatrix4fv(location, 1, gl::FALSE, vs as _),
-                Uniform::Mat2x3(vs) => gl::UniformMatrix2x3fv(location, 1, gl::FALSE, vs as _),
-                Uniform::Mat3x2(vs) => gl::UniformMatrix3x2fv(location, 1, gl::FALSE, vs as _),
-                Uniform::Mat2x4(vs) => gl::UniformMatrix2x4fv(location, 1, gl::FALSE, vs as _),
-                Uniform::Mat4x2(vs) => gl::UniformMatrix4x2fv(location, 1, gl::FALSE, vs as _),
-                Uniform::Mat3x4(vs) => gl::UniformMatrix3x4fv(location, 1, gl::FALSE, vs as _),
-                Uniform::Mat4x3(vs) => gl::UniformMatrix4x3fv(location, 1, gl::FALSE, vs as _),
+                Uniform::Float(v0) => gl::ProgramUniform1f(p, l, *v0),
+                Uniform::Vec2(v0, v1) => gl::ProgramUniform2f(p, l, *v0, *v1),
+                Uniform::Vec3(v0, v1, v2) => gl::ProgramUniform3f(p, l, *v0, *v1, *v2),
+                Uniform::Vec4(v0, v1, v2, v3) => gl::ProgramUniform4f(p, l, *v0, *v1, *v2, *v3),
+                Uniform::Mat2(vs) => gl::ProgramUniformMatrix2fv(p, l, 1, gl::FALSE, vs as _),
+                Uniform::Mat3(vs) => gl::ProgramUniformMatrix3fv(p, l, 1, gl::FALSE, vs as _),
+                Uniform::Mat4(vs) => gl::ProgramUniformMatrix4fv(p, l, 1, gl::FALSE, vs as _),
+                Uniform::Mat2x3(vs) => gl::ProgramUniformMatrix2x3fv(p, l, 1, gl::FALSE, vs as _),
+                Uniform::Mat3x2(vs) => gl::ProgramUniformMatrix3x2fv(p, l, 1, gl::FALSE, vs as _),
+                Uniform::Mat2x4(vs) => gl::ProgramUniformMatrix2x4fv(p, l, 1, gl::FALSE, vs as _),
+                Uniform::Mat4x2(vs) => gl::ProgramUniformMatrix4x2fv(p, l, 1, gl::FALSE, vs as _),
+                Uniform::Mat3x4(vs) => gl::ProgramUniformMatrix3x4fv(p, l, 1, gl::FALSE, vs as _),
+                Uniform::Mat4x3(vs) => gl::ProgramUniformMatrix4x3fv(p, l, 1, gl::FALSE, vs as _),
             }
         }
     }
@@ -236,6 +264,106 @@ impl Uniform {
     }
 }
 
+/// The values last uploaded to the uniforms of some programs.
+///
+/// Uniforms keep their value between draw calls, so values which didn't
+/// change since the last frame don't have to be uploaded again. The
+/// locations of the uniforms are kept as well, so they are only looked up
+/// once per program.
+#[derive(Debug, Default)]
+pub struct UniformCache {
+    values: HashMap<(GLuint, GLint), Uniform>,
+    raw: HashMap<(GLuint, GLint), Vec<u32>>,
+    locations: HashMap<GLuint, HashMap<CString, GLint>>,
+}
+
+impl UniformCache {
+    /// Returns the location of a uniform, only asking OpenGL for it the
+    /// first time, or -1 if the program doesn't use it.
+    pub fn location(&mut self, prog_id: GLuint, name: &CStr) -> GLint {
+        let locations = self.locations.entry(prog_id).or_default();
+        if let Some(&loc) = locations.get(name) {
+            return loc;
+        }
+
+        let loc = unsafe { gl::GetUniformLocation(prog_id, name.as_ptr()) };
+        locations.insert(name.to_owned(), loc);
+        loc
+    }
+
+    /// Remembers the bit pattern of a value, returns true if it changed.
+    fn update_raw(
+        &mut self,
+        key: (GLuint, GLint),
+        bits: impl Iterator<Item = u32> + Clone,
+    ) -> bool {
+        match self.raw.get_mut(&key) {
+            Some(old) if old.iter().copied().eq(bits.clone()) => false,
+            Some(old) => {
+                old.clear();
+                old.extend(bits);
+                true
+            }
+            None => {
+                self.raw.insert(key, bits.collect());
+                true
+            }
+        }
+    }
+
+    pub fn set(&mut self, prog_id: GLuint, location: GLint, value: Uniform) {
+        if location == -1 {
+            return;
+        }
+
+        match self.values.insert((prog_id, location), value) {
+            Some(old) if old == value => (),
+            _ => value.upload(prog_id, location),
+        }
+    }
+
+    /// Sets an `int` or `ivec2` uniform.
+    pub fn set_ints(&mut self, prog_id: GLuint, location: GLint, values: &[GLint]) {
+        let bits = values.iter().map(|&v| v as u32);
+        if location == -1 || !self.update_raw((prog_id, location), bits) {
+            return;
+        }
+
+        unsafe {
+            match *values {
+                [v0] => gl::ProgramUniform1i(prog_id, location, v0),
+                [v0, v1] => gl::ProgramUniform2i(prog_id, location, v0, v1),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Sets an array of `float`, `vec3` or `vec4` values.
+    pub fn set_floats(
+        &mut self,
+        prog_id: GLuint,
+        location: GLint,
+        width: usize,
+        values: &[GLfloat],
+    ) {
+        let bits = values.iter().map(|v| v.to_bits());
+        if location == -1 || !self.update_raw((prog_id, location), bits) {
+            return;
+        }
+
+        let (p, l, count) = (prog_id, location, (values.len() / width) as _);
+        unsafe {
+            match width {
+                1 => gl::ProgramUniform1fv(p, l, count, values.as_ptr()),
+                2 => gl::ProgramUniform2fv(p, l, count, values.as_ptr()),
+                3 => gl::ProgramUniform3fv(p, l, count, values.as_ptr()),
+                4 => gl::ProgramUniform4fv(p, l, count, values.as_ptr()),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -298,4 +426,15 @@ mod test {
             0.0, 0.0, -4.0
         ]));
     }
+
+    #[test]
+    fn cache_skips_unchanged() {
+        let mut cache = UniformCache::default();
+        let bits = [1_u32, 2, 3];
+
+        assert!(cache.update_raw((1, 0), bits.iter().copied()));
+        assert!(!cache.update_raw((1, 0), bits.iter().copied()));
+        assert!(cache.update_raw((2, 0), bits.iter().copied()));
+        assert!(cache.update_raw((1, 0), bits.iter().rev().copied()));
+    }
 }
@@ -37,6 +37,11 @@ where
         self.index = (self.index + 1) % SIZE;
     }
 
+    /// Returns the element which was pushed last.
+    pub fn latest(&self) -> T {
+        self.buffer[(self.index + SIZE - 1) % SIZE]
+    }
+
     /// Computes the average of all elements in the ring buffer.
     ///
     /// This is done recursively to ensure high precision even for floating
@@ -90,5 +95,6 @@ mod test {
             ra.push(4.0);
         }
         assert_eq!(ra.get(), 3.0);
+        assert_eq!(ra.latest(), 4.0);
     }
 }
@@ -0,0 +1,144 @@
+use std::{collections::HashMap, rc::Rc};
+
+use super::Texture;
+
+static mut CACHE_INTERNAL: Option<CacheInternal> = None;
+
+#[derive(Debug)]
+struct CacheEntry {
+    tex: Rc<dyn Texture>,
+    bytes: usize,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheInternal {
+    entries: HashMap<String, CacheEntry>,
+    budget: Option<usize>,
+    clock: u64,
+}
+
+/// Information about a cached texture, as shown in the UI.
+#[derive(Debug, Clone)]
+pub struct CacheInfo {
+    pub path: String,
+    pub bytes: usize,
+    pub in_use: bool,
+}
+
+pub struct Cache;
+
+impl Cache {
+    pub fn init() {
+        unsafe {
+            if CACHE_INTERNAL.is_none() {
+                CACHE_INTERNAL = Some(CacheInternal::default());
+            }
+        }
+    }
+
+    fn internal() -> &'static CacheInternal {
+        Self::internal_mut()
+    }
+
+    fn internal_mut() -> &'static mut CacheInternal {
+        #[cfg(debug_assertions)]
+        if unsafe { CACHE_INTERNAL.is_none() } {
+            panic!("Cache has not been initialized. Please call `Cache::init` first.")
+        }
+
+        unsafe { CACHE_INTERNAL.as_mut().unwrap() }
+    }
+
+    /// Sets the number of bytes cached textures may take up, `None` keeps
+    /// every texture around.
+    pub fn set_budget(budget: Option<usize>) {
+        Self::init();
+        Self::internal_mut().budget = budget;
+        Self::evict();
+    }
+
+    /// Stores a texture taking up roughly `bytes` of video memory.
+    pub fn store(path: String, tex: Rc<dyn Texture>, bytes: usize) {
+        let cache = Self::internal_mut();
+        cache.clock += 1;
+
+        let entry = CacheEntry {
+            tex,
+            bytes,
+            last_used: cache.clock,
+        };
+
+        cache.entries.insert(path, entry);
+        Self::evict();
+    }
+
+    pub fn fetch(path: &str) -> Option<Rc<dyn Texture>> {
+        let cache = Self::internal_mut();
+        cache.clock += 1;
+
+        let entry = cache.entries.get_mut(path)?;
+        entry.last_used = cache.clock;
+        Some(Rc::clone(&entry.tex))
+    }
+
+    /// Total size of all cached textures in bytes.
+    pub fn size() -> usize {
+        Self::internal().entries.values().map(|e| e.bytes).sum()
+    }
+
+    pub fn budget() -> Option<usize> {
+        Self::internal().budget
+    }
+
+    /// Lists all cached textures, most recently used first.
+    pub fn entries() -> Vec<CacheInfo> {
+        let mut entries: Vec<_> = Self::internal().entries.iter().collect();
+        entries.sort_by_key(|(_, e)| std::cmp::Reverse(e.last_used));
+        entries
+            .into_iter()
+            .map(|(path, e)| CacheInfo {
+                path: path.clone(),
+                bytes: e.bytes,
+                in_use: Rc::strong_count(&e.tex) > 1,
+            })
+            .collect()
+    }
+
+    /// Drops the least recently used textures until the cache fits into its
+    /// budget.
+    ///
+    /// Textures which are still used by a pipeline are kept, as dropping
+    /// them wouldn't free any memory.
+    pub fn evict() {
+        let cache = Self::internal_mut();
+        let budget = match cache.budget {
+            Some(s) => s,
+            None => return,
+        };
+
+        let mut size: usize = cache.entries.values().map(|e| e.bytes).sum();
+        let mut unused: Vec<_> = cache
+            .entries
+            .iter()
+            .filter(|(_, e)| Rc::strong_count(&e.tex) == 1)
+            .map(|(path, e)| (e.last_used, e.bytes, path.clone()))
+            .collect();
+        unused.sort();
+
+        for (_, bytes, path) in unused {
+            if size <= budget {
+                break;
+            }
+
+            log::info!("Evicting {:?} from the texture cache", path);
+            cache.entries.remove(&path);
+            size -= bytes;
+        }
+    }
+
+    /// Drops all cached textures, so they are loaded from disk again.
+    pub fn clear() {
+        Self::internal_mut().entries.clear();
+    }
+}
@@ -0,0 +1,273 @@
+/// A single dimension of a texture resolution.
+///
+/// Besides fixed sizes, a dimension can be an expression of the screen size,
+/// like `"screen.x / 2"`, or `"auto"` to follow the aspect ratio of the
+/// screen based on the other dimension.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dimension {
+    Fixed(u32),
+    Expr(SizeExpr),
+    Auto,
+}
+
+impl Dimension {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "auto" => Ok(Self::Auto),
+            s => SizeExpr::parse(s).map(Self::Expr),
+        }
+    }
+
+    fn eval(&self, screen_size: (u32, u32)) -> Option<f32> {
+        match self {
+            Self::Fixed(n) => Some(*n as f32),
+            Self::Expr(expr) => Some(expr.eval(screen_size)),
+            Self::Auto => None,
+        }
+    }
+}
+
+/// Evaluates a pair of dimensions for the given screen size.
+///
+/// Results which aren't finite or positive are an error, anything larger than
+/// `max_size` is clamped to it.
+pub fn resolve_dimensions(
+    x: &Dimension,
+    y: &Dimension,
+    screen_size: (u32, u32),
+    max_size: u32,
+) -> Result<[u32; 2], String> {
+    let (sw, sh) = (screen_size.0.max(1) as f32, screen_size.1.max(1) as f32);
+    let (width, height) = match (x.eval(screen_size), y.eval(screen_size)) {
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, w * sh / sw),
+        (None, Some(h)) => (h * sw / sh, h),
+        (None, None) => (sw, sh),
+    };
+
+    for &(name, value) in [("width", width), ("height", height)].iter() {
+        if !value.is_finite() || value <= 0.0 {
+            return Err(format!(
+                "Expected the {} of the resolution to be positive, got {} for a {}x{} screen",
+                name, value, screen_size.0, screen_size.1
+            ));
+        }
+    }
+
+    let max_size = max_size.max(1) as f32;
+    Ok([
+        width.round().clamp(1.0, max_size) as u32,
+        height.round().clamp(1.0, max_size) as u32,
+    ])
+}
+
+/// An arithmetic expression of the screen size
+#[derive(Debug, Clone, PartialEq)]
+pub enum SizeExpr {
+    Number(f32),
+    ScreenX,
+    ScreenY,
+    Add(Box<SizeExpr>, Box<SizeExpr>),
+    Sub(Box<SizeExpr>, Box<SizeExpr>),
+    Mul(Box<SizeExpr>, Box<SizeExpr>),
+    Div(Box<SizeExpr>, Box<SizeExpr>),
+}
+
+impl SizeExpr {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.expr()?;
+
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(t) => Err(format!("Unexpected {:?} in expression {:?}", t, s)),
+        }
+    }
+
+    pub fn eval(&self, screen_size: (u32, u32)) -> f32 {
+        match self {
+            Self::Number(x) => *x,
+            Self::ScreenX => screen_size.0 as _,
+            Self::ScreenY => screen_size.1 as _,
+            Self::Add(a, b) => a.eval(screen_size) + b.eval(screen_size),
+            Self::Sub(a, b) => a.eval(screen_size) - b.eval(screen_size),
+            Self::Mul(a, b) => a.eval(screen_size) * b.eval(screen_size),
+            Self::Div(a, b) => a.eval(screen_size) / b.eval(screen_size),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f32),
+    ScreenX,
+    ScreenY,
+    Op(char),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if "+-*/()".contains(c) {
+            tokens.push(Token::Op(c));
+            rest = &rest[1..];
+        } else if c.is_ascii_digit() || c == '.' {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = rest[..end]
+                .parse()
+                .map_err(|_| format!("Invalid number {:?} in expression {:?}", &rest[..end], s))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if let Some(r) = rest.strip_prefix("screen.x") {
+            tokens.push(Token::ScreenX);
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("screen.y") {
+            tokens.push(Token::ScreenY);
+            rest = r;
+        } else {
+            return Err(format!("Unexpected {:?} in expression {:?}", c, s));
+        }
+
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next_op(&mut self, ops: &str) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(c)) if ops.contains(*c) => {
+                self.pos += 1;
+                Some(*c)
+            }
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self) -> Result<SizeExpr, String> {
+        let mut lhs = self.term()?;
+        while let Some(op) = self.next_op("+-") {
+            let rhs = Box::new(self.term()?);
+            lhs = match op {
+                '+' => SizeExpr::Add(Box::new(lhs), rhs),
+                _ => SizeExpr::Sub(Box::new(lhs), rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<SizeExpr, String> {
+        let mut lhs = self.factor()?;
+        while let Some(op) = self.next_op("*/") {
+            let rhs = Box::new(self.factor()?);
+            lhs = match op {
+                '*' => SizeExpr::Mul(Box::new(lhs), rhs),
+                _ => SizeExpr::Div(Box::new(lhs), rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn factor(&mut self) -> Result<SizeExpr, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+
+        match token {
+            Some(Token::Number(x)) => Ok(SizeExpr::Number(x)),
+            Some(Token::ScreenX) => Ok(SizeExpr::ScreenX),
+            Some(Token::ScreenY) => Ok(SizeExpr::ScreenY),
+            Some(Token::Op('(')) => {
+                let expr = self.expr()?;
+                match self.next_op(")") {
+                    Some(_) => Ok(expr),
+                    None => Err("Expected closing parenthesis".into()),
+                }
+            }
+            Some(t) => Err(format!("Unexpected {:?} in expression", t)),
+            None => Err("Unexpected end of expression".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn eval(s: &str) -> f32 {
+        SizeExpr::parse(s).unwrap().eval((1920, 1080))
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(eval("screen.x"), 1920.0);
+        assert_eq!(eval("screen.y/2"), 540.0);
+        assert_eq!(eval("screen.x * 3 / 4"), 1440.0);
+        assert_eq!(eval("(screen.x + screen.y) / 2"), 1500.0);
+        assert_eq!(eval("screen.x - 2 * 10"), 1900.0);
+        assert_eq!(eval("0.5 * screen.y"), 540.0);
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!(SizeExpr::parse("").is_err());
+        assert!(SizeExpr::parse("screen.z").is_err());
+        assert!(SizeExpr::parse("screen.x /").is_err());
+        assert!(SizeExpr::parse("(screen.x").is_err());
+        assert!(SizeExpr::parse("screen.x screen.y").is_err());
+    }
+
+    #[test]
+    fn auto_keeps_aspect() {
+        let size = (1920, 1080);
+        let (auto, x, y) = (
+            Dimension::Auto,
+            Dimension::Fixed(960),
+            Dimension::Fixed(540),
+        );
+        assert_eq!(resolve_dimensions(&x, &auto, size, 8192), Ok([960, 540]));
+        assert_eq!(resolve_dimensions(&auto, &y, size, 8192), Ok([960, 540]));
+        assert_eq!(
+            resolve_dimensions(&auto, &auto, size, 8192),
+            Ok([1920, 1080])
+        );
+    }
+
+    #[test]
+    fn never_zero() {
+        let x = Dimension::parse("screen.x / 4096").unwrap();
+        let y = Dimension::parse("screen.y / 4096").unwrap();
+        assert_eq!(resolve_dimensions(&x, &y, (1920, 1080), 8192), Ok([1, 1]));
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        let size = (1920, 1080);
+        let one = Dimension::Fixed(1);
+        for s in &["screen.y - screen.y", "screen.x - 4096", "1 / 0", "0 / 0"] {
+            let dim = Dimension::parse(s).unwrap();
+            assert!(resolve_dimensions(&dim, &one, size, 8192).is_err(), "{}", s);
+            assert!(resolve_dimensions(&one, &dim, size, 8192).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn clamps_to_max_size() {
+        let x = Dimension::parse("screen.x * 100").unwrap();
+        let auto = Dimension::Auto;
+        assert_eq!(
+            resolve_dimensions(&x, &auto, (1920, 1080), 16384),
+            Ok([16384, 16384])
+        );
+    }
+}
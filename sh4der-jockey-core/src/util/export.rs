@@ -0,0 +1,426 @@
+use std::collections::{HashMap, HashSet};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{insert_defines, preprocess, quality_defines, quality_levels};
+
+/// Operators made up of more than one character
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "++", "--", "+=", "-=", "*=", "/=", "%=", "<<", ">>", "<=", ">=", "==", "!=",
+    "&&", "||", "^^", "&=", "|=", "^=",
+];
+
+/// Character pairs that can't be written next to each other without
+/// changing the meaning of the code
+const SPACED_PAIRS: &[&str] = &[
+    "++", "--", "+=", "-=", "*=", "/=", "%=", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "^^",
+    "&=", "|=", "^=", "//", "/*",
+];
+
+/// Builtin types, used to find declarations
+const TYPES: &[&str] = &[
+    "void",
+    "bool",
+    "int",
+    "uint",
+    "float",
+    "double",
+    "vec2",
+    "vec3",
+    "vec4",
+    "dvec2",
+    "dvec3",
+    "dvec4",
+    "bvec2",
+    "bvec3",
+    "bvec4",
+    "ivec2",
+    "ivec3",
+    "ivec4",
+    "uvec2",
+    "uvec3",
+    "uvec4",
+    "mat2",
+    "mat3",
+    "mat4",
+    "mat2x2",
+    "mat2x3",
+    "mat2x4",
+    "mat3x2",
+    "mat3x3",
+    "mat3x4",
+    "mat4x2",
+    "mat4x3",
+    "mat4x4",
+    "sampler1D",
+    "sampler2D",
+    "sampler3D",
+    "samplerCube",
+    "image1D",
+    "image2D",
+    "image3D",
+];
+
+/// Qualifiers of global variables whose names are visible outside the shader
+const INTERFACE_QUALIFIERS: &[&str] = &["uniform", "in", "out", "buffer", "attribute", "varying"];
+
+/// Short names that are keywords or builtin functions
+const RESERVED: &[&str] = &[
+    "do", "if", "in", "for", "int", "out", "abs", "all", "any", "cos", "dot", "exp", "log", "max",
+    "min", "mix", "mod", "not", "pow", "sin", "tan",
+];
+
+/// Turns a shader into a single self-contained file.
+///
+/// Includes are inlined, comments are removed and, if requested, identifiers
+/// are shortened and all unnecessary whitespace is stripped. If the shader
+/// declares quality levels, the highest level is selected.
+pub fn export_shader(path: &str, minify_code: bool) -> Result<String, String> {
+    let code = std::fs::read_to_string(path).map_err(|e| format!("{}, {}", e, path))?;
+    let mut code = preprocess(&code, path, &mut Vec::new())?;
+
+    let levels = quality_levels(&code)?;
+    if !levels.is_empty() {
+        code = insert_defines(&code, &quality_defines(&levels, levels.len() - 1));
+    }
+
+    let code: Vec<&str> = code
+        .lines()
+        .filter(|line| !line.starts_with("#line ") && *line != "#define SH4DERJOCKEY 1")
+        .collect();
+
+    let code = strip_comments(&code.join("\n"));
+
+    if minify_code {
+        return Ok(minify(&code));
+    }
+
+    // remove trailing whitespace and repeated empty lines
+    let mut out = String::new();
+    for line in code.lines().map(str::trim_end) {
+        if line.is_empty() && (out.is_empty() || out.ends_with("\n\n")) {
+            continue;
+        }
+
+        out += line;
+        out += "\n";
+    }
+
+    Ok(out)
+}
+
+/// Removes all line and block comments.
+pub fn strip_comments(code: &str) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut rest = code;
+
+    while let Some(pos) = rest.find('/') {
+        out += &rest[..pos];
+        rest = &rest[pos..];
+
+        if rest.starts_with("//") {
+            rest = &rest[rest.find('\n').unwrap_or(rest.len())..];
+        } else if rest.starts_with("/*") {
+            rest = rest[2..].find("*/").map_or("", |end| &rest[end + 4..]);
+            out.push(' ');
+        } else {
+            out.push('/');
+            rest = &rest[1..];
+        }
+    }
+
+    out + rest
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn tokenize(code: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = code.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let next = rest[c.len_utf8()..].chars().next();
+        let len = if c.is_ascii_digit() || (c == '.' && next.is_some_and(|d| d.is_ascii_digit())) {
+            // numbers, including exponents like 1e-3
+            let hex = rest.starts_with("0x") || rest.starts_with("0X");
+            let mut prev = ' ';
+            rest.find(|d: char| {
+                let sign = (d == '+' || d == '-') && (prev == 'e' || prev == 'E') && !hex;
+                prev = d;
+                !(is_word_char(d) || d == '.' || sign)
+            })
+            .unwrap_or(rest.len())
+        } else if is_word_char(c) {
+            rest.find(|d: char| !is_word_char(d)).unwrap_or(rest.len())
+        } else {
+            OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .map_or(c.len_utf8(), |op| op.len())
+        };
+
+        tokens.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+
+    tokens
+}
+
+/// Finds the names declared in the code, except for those that are
+/// visible outside the shader, like uniforms and inputs.
+fn declared_names(tokens: &[&str]) -> HashSet<String> {
+    let mut types: HashSet<&str> = TYPES.iter().copied().collect();
+    let mut declared = HashSet::new();
+    let mut keep = HashSet::new();
+
+    let mut depth = 0;
+    let mut parens = 0;
+    let mut opaque_blocks = Vec::new();
+    let mut next_is_struct = false;
+    let mut interface = false;
+    let mut decl_parens = None;
+
+    for (k, &token) in tokens.iter().enumerate() {
+        let prev = match k {
+            0 => "",
+            _ => tokens[k - 1],
+        };
+
+        match token {
+            "{" => {
+                if next_is_struct || interface {
+                    opaque_blocks.push(depth);
+                }
+                next_is_struct = false;
+                decl_parens = None;
+                depth += 1;
+            }
+            "}" => {
+                depth -= 1;
+                if opaque_blocks.last() == Some(&depth) {
+                    opaque_blocks.pop();
+                }
+            }
+            "(" => parens += 1,
+            ")" => {
+                parens -= 1;
+                if decl_parens > Some(parens) {
+                    decl_parens = None;
+                }
+            }
+            ";" => {
+                interface = false;
+                decl_parens = None;
+            }
+            "struct" => next_is_struct = true,
+            _ if depth == 0 && parens == 0 && INTERFACE_QUALIFIERS.contains(&token) => {
+                interface = true
+            }
+            _ if is_word_char(token.chars().next().unwrap()) => {
+                if next_is_struct {
+                    types.insert(token);
+                    continue;
+                }
+
+                if types.contains(token) || !opaque_blocks.is_empty() {
+                    continue;
+                }
+
+                let is_decl = types.contains(prev) || (prev == "," && decl_parens == Some(parens));
+
+                if !is_decl || token.starts_with(|c: char| c.is_ascii_digit()) {
+                    continue;
+                }
+
+                if types.contains(prev) {
+                    decl_parens = Some(parens);
+                }
+
+                match interface && depth == 0 {
+                    true => keep.insert(token.to_string()),
+                    false => declared.insert(token.to_string()),
+                };
+            }
+            _ => (),
+        }
+    }
+
+    declared
+        .into_iter()
+        .filter(|name| !keep.contains(name) && name != "main" && !name.starts_with("gl_"))
+        .collect()
+}
+
+/// Generates the `n`-th short identifier.
+fn short_name(mut n: usize) -> String {
+    const FIRST: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    const REST: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+    let mut name = vec![FIRST[n % FIRST.len()]];
+    n /= FIRST.len();
+    while n > 0 {
+        n -= 1;
+        name.push(REST[n % REST.len()]);
+        n /= REST.len();
+    }
+
+    String::from_utf8(name).unwrap()
+}
+
+/// Shortens identifiers and removes unnecessary whitespace.
+///
+/// Uniforms, inputs, outputs and the members of structs and blocks keep
+/// their names, so the shader can still be used in the same way. Comments
+/// need to be removed beforehand.
+pub fn minify(code: &str) -> String {
+    lazy_static! {
+        static ref WORD_RE: Regex = Regex::new(r"[A-Za-z_]\w*").expect("failed to compile regex");
+    }
+
+    let is_directive = |line: &&str| line.trim_start().starts_with('#');
+
+    let body: Vec<&str> = code.lines().filter(|line| !is_directive(line)).collect();
+    let body = body.join("\n");
+    let tokens = tokenize(&body);
+
+    // give the most frequent names the shortest replacements
+    let declared = declared_names(&tokens);
+    let mut counts = HashMap::new();
+    for (k, token) in tokens.iter().enumerate() {
+        if declared.contains(*token) && (k == 0 || tokens[k - 1] != ".") {
+            *counts.entry(*token).or_insert(0) += 1;
+        }
+    }
+
+    let mut order: Vec<_> = counts.into_iter().collect();
+    order.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let taken: HashSet<&str> = WORD_RE.find_iter(code).map(|m| m.as_str()).collect();
+    let mut names = (0..)
+        .map(short_name)
+        .filter(|name| !taken.contains(name.as_str()) && !RESERVED.contains(&name.as_str()));
+
+    let renames: HashMap<&str, String> = order
+        .into_iter()
+        .map(|(name, _)| (name, names.next().unwrap()))
+        .collect();
+
+    let rename = |word: &str, prev: Option<char>| match (renames.get(word), prev) {
+        (Some(new), p) if p != Some('.') => new.clone(),
+        _ => word.to_string(),
+    };
+
+    let mut out = String::new();
+    let mut pending = Vec::new();
+
+    let flush = |out: &mut String, pending: &mut Vec<&str>| {
+        let text = pending.join("\n");
+        pending.clear();
+
+        for token in tokenize(&text) {
+            let first = token.chars().next().unwrap();
+            let prev = out.chars().last();
+            let token = match is_word_char(first) && !first.is_ascii_digit() {
+                true => rename(token, prev),
+                false => token.to_string(),
+            };
+
+            if let Some(p) = prev {
+                let number = first == '.' && token.len() > 1;
+                let words = is_word_char(p) && (is_word_char(first) || number);
+                if words || SPACED_PAIRS.contains(&format!("{}{}", p, first).as_str()) {
+                    out.push(' ');
+                }
+            }
+
+            *out += &token;
+        }
+    };
+
+    for line in code.lines() {
+        if !is_directive(&line) {
+            pending.push(line);
+            continue;
+        }
+
+        flush(&mut out, &mut pending);
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+
+        // keep the whitespace of directives, as it matters for macros
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        let line = WORD_RE.replace_all(&line, |cap: &regex::Captures| {
+            let m = cap.get(0).unwrap();
+            rename(m.as_str(), line[..m.start()].chars().last())
+        });
+
+        out += &line;
+        out.push('\n');
+    }
+
+    flush(&mut out, &mut pending);
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn strip_line_and_block_comments() {
+        let code = "float a; // one\nfloat b /* two\nthree */ = 1.0 / 2.0;";
+        assert_eq!(strip_comments(code), "float a; \nfloat b   = 1.0 / 2.0;");
+    }
+
+    #[test]
+    fn tokenize_numbers_and_operators() {
+        let tokens = tokenize("x+=1e-3*.5;i++");
+        assert_eq!(tokens, vec!["x", "+=", "1e-3", "*", ".5", ";", "i", "++"]);
+    }
+
+    #[test]
+    fn short_names_are_unique() {
+        let names: HashSet<_> = (0..5000).map(short_name).collect();
+        assert_eq!(names.len(), 5000);
+        assert_eq!(short_name(0), "a");
+        assert_eq!(short_name(52), "aa");
+    }
+
+    #[test]
+    fn minify_keeps_interface() {
+        let code = "#version 330\n\
+            uniform float time;\n\
+            out vec4 out_color;\n\
+            struct Ray { vec3 origin; };\n\
+            float scene(vec3 position) { return length(position) - 1.0; }\n\
+            void main() {\n    \
+                Ray ray = Ray(vec3(time));\n    \
+                float dist = scene(ray.origin), other = -dist;\n    \
+                out_color = vec4(dist - -other);\n\
+            }\n";
+
+        let expected = "#version 330\n\
+            uniform float time;out vec4 out_color;struct Ray{vec3 origin;};\
+            float e(vec3 c){return length(c)-1.0;}\
+            void main(){Ray d=Ray(vec3(time));float a=e(d.origin),b=-a;out_color=vec4(a- -b);}\n";
+
+        assert_eq!(minify(code), expected);
+    }
+
+    #[test]
+    fn minify_renames_in_macros() {
+        let code = "float scale = 2.0;\n#define SCALED(x) (x * scale)\nfloat y = SCALED(1.0);";
+        assert_eq!(
+            minify(code),
+            "float a=2.0;\n#define SCALED(x) (x * a)\nfloat b=SCALED(1.0);\n"
+        );
+    }
+}
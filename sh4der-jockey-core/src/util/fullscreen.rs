@@ -0,0 +1,94 @@
+use std::ffi::CStr;
+
+use gl::types::*;
+
+use crate::{util::*, *};
+
+/// A built-in pass drawing a single fragment shader over the whole target.
+///
+/// Used for the final passes of the tool, like presenting, stereo output or
+/// color grading, which all share the pass-through vertex shader.
+#[derive(Debug)]
+pub struct FullscreenPass {
+    pub prog_id: GLuint,
+    sh_ids: Vec<GLuint>,
+}
+
+impl FullscreenPass {
+    pub fn new(frag: &str) -> Result<Self, String> {
+        let vs_id = compile_shader(PASS_VERT, gl::VERTEX_SHADER)?;
+        let fs_id = match compile_shader(frag, gl::FRAGMENT_SHADER) {
+            Ok(s) => s,
+            Err(err) => {
+                unsafe { gl::DeleteShader(vs_id) };
+                return Err(err);
+            }
+        };
+
+        let sh_ids = vec![vs_id, fs_id];
+        let prog_id = match link_program(&sh_ids) {
+            Ok(s) => s,
+            Err(err) => {
+                for &id in sh_ids.iter() {
+                    unsafe { gl::DeleteShader(id) };
+                }
+                return Err(err);
+            }
+        };
+
+        Ok(Self { prog_id, sh_ids })
+    }
+
+    pub fn location(&self, name: &CStr) -> GLint {
+        unsafe { gl::GetUniformLocation(self.prog_id, name.as_ptr()) }
+    }
+
+    /// Draws the pass into the given framebuffer with blending disabled.
+    ///
+    /// The `resolution` uniform is set to the size of the target, all other
+    /// uniforms are set by `set_uniforms` while the program is bound.
+    pub fn draw(
+        &self,
+        vao: GLuint,
+        fb_id: GLuint,
+        width: u32,
+        height: u32,
+        set_uniforms: impl FnOnce(&Self),
+    ) {
+        unsafe {
+            let blending = gl::IsEnabled(gl::BLEND) != 0;
+            gl::Disable(gl::BLEND);
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fb_id);
+            gl::Viewport(0, 0, width as _, height as _);
+            gl::UseProgram(self.prog_id);
+            gl_debug_check!();
+
+            let res_loc = self.location(&RESOLUTION_NAME);
+            gl::Uniform2f(res_loc, width as _, height as _);
+            set_uniforms(self);
+            gl_debug_check!();
+
+            draw_fullscreen_pass(self.prog_id, vao);
+
+            gl::ActiveTexture(gl::TEXTURE0);
+            if blending {
+                gl::Enable(gl::BLEND);
+            }
+            gl_debug_check!();
+        }
+    }
+}
+
+impl Drop for FullscreenPass {
+    fn drop(&mut self) {
+        unsafe {
+            for &id in self.sh_ids.iter() {
+                gl::DetachShader(self.prog_id, id);
+                gl::DeleteShader(id);
+            }
+
+            gl::DeleteProgram(self.prog_id);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::path::Path;
+
+use super::{Texture3D, TextureFormat};
+
+/// A 3D color lookup table in the `.cube` format.
+///
+/// The table is stored with red changing fastest, then green, then blue,
+/// which is the same order OpenGL expects the texels of a 3D texture in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub size: u32,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub data: Vec<f32>,
+}
+
+impl CubeLut {
+    /// Tells if the paths of an image input name a lookup table, which is
+    /// a single file with the `.cube` extension.
+    pub fn is_lut_path<S: AsRef<str>>(paths: &[S]) -> bool {
+        match paths {
+            [path] => Path::new(path.as_ref())
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("cube")),
+            _ => false,
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read LUT at {:?}: {}", path, e))?;
+
+        Self::parse(&source).map_err(|e| format!("Failed to parse LUT at {:?}: {}", path, e))
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        fn parse_triple(line: &str, words: &[&str]) -> Result<[f32; 3], String> {
+            match words {
+                [r, g, b] => {
+                    let mut out = [0.0; 3];
+                    for (k, word) in [r, g, b].iter().enumerate() {
+                        out[k] = word
+                            .parse()
+                            .map_err(|_| format!("Expected a number, got {:?}", word))?;
+                    }
+                    Ok(out)
+                }
+                _ => Err(format!("Expected three numbers, got {:?}", line)),
+            }
+        }
+
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut data = Vec::new();
+
+        for line in source.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let words: Vec<_> = line.split_whitespace().collect();
+            match words[0] {
+                "TITLE" => {}
+                "LUT_3D_SIZE" => {
+                    let n = match words.get(1).and_then(|s| s.parse::<u32>().ok()) {
+                        Some(n) if (2..=256).contains(&n) => n,
+                        _ => return Err(format!("Invalid LUT size {:?}", line)),
+                    };
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => return Err("1D LUTs are not supported".into()),
+                "DOMAIN_MIN" => domain_min = parse_triple(line, &words[1..])?,
+                "DOMAIN_MAX" => domain_max = parse_triple(line, &words[1..])?,
+                _ => data.extend_from_slice(&parse_triple(line, &words)?),
+            }
+        }
+
+        let size = match size {
+            Some(n) => n,
+            None => return Err("Required keyword \"LUT_3D_SIZE\" not found".into()),
+        };
+
+        let expected = 3 * (size * size * size) as usize;
+        if data.len() != expected {
+            return Err(format!(
+                "Expected {} entries for a LUT of size {}, got {}",
+                expected / 3,
+                size,
+                data.len() / 3
+            ));
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            data,
+        })
+    }
+
+    /// Uploads the table into a 3D texture set up for trilinear filtering.
+    ///
+    /// The table only samples the domain at texel centers, so the input color
+    /// has to be scaled by `(size - 1) / size` and offset by half a texel to
+    /// get exact results at the corners of the cube.
+    pub fn to_texture(&self) -> Texture3D {
+        Texture3D::with_params(
+            [self.size; 3],
+            gl::LINEAR,
+            gl::LINEAR,
+            gl::CLAMP_TO_EDGE,
+            TextureFormat::RGB32F,
+            false,
+            self.data.as_ptr() as _,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const IDENTITY: &str = "
+        TITLE \"identity\"
+        # a comment
+        LUT_3D_SIZE 2
+
+        0 0 0
+        1 0 0
+        0 1 0
+        1 1 0
+        0 0 1
+        1 0 1
+        0 1 1
+        1 1 1
+    ";
+
+    #[test]
+    fn detects_lut_paths() {
+        assert!(CubeLut::is_lut_path(&["grade.CUBE"]));
+        assert!(!CubeLut::is_lut_path(&["grade.png"]));
+        assert!(!CubeLut::is_lut_path(&["a.cube", "b.cube"]));
+    }
+
+    #[test]
+    fn parse_identity() {
+        let lut = CubeLut::parse(IDENTITY).unwrap();
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.domain_min, [0.0; 3]);
+        assert_eq!(lut.domain_max, [1.0; 3]);
+        assert_eq!(lut.data.len(), 24);
+        assert_eq!(&lut.data[3..6], &[1.0, 0.0, 0.0]);
+        assert_eq!(&lut.data[21..24], &[1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn parse_domain() {
+        let source = IDENTITY.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nDOMAIN_MAX 2.0 2.0 4.0");
+        let lut = CubeLut::parse(&source).unwrap();
+        assert_eq!(lut.domain_max, [2.0, 2.0, 4.0]);
+    }
+
+    #[test]
+    fn missing_entries() {
+        let source = IDENTITY.replace("1 1 1", "");
+        assert!(CubeLut::parse(&source).is_err());
+    }
+
+    #[test]
+    fn missing_size() {
+        let source = IDENTITY.replace("LUT_3D_SIZE 2", "");
+        assert!(CubeLut::parse(&source).is_err());
+    }
+
+    #[test]
+    fn reject_1d() {
+        let source = IDENTITY.replace("LUT_3D_SIZE", "LUT_1D_SIZE");
+        assert!(CubeLut::parse(&source).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     collections::HashSet,
     ffi::{c_void, CString},
+    sync::atomic::{AtomicU32, Ordering},
 };
 
 use gl::types::*;
@@ -9,13 +10,27 @@ use regex::Regex;
 
 mod average;
 mod cache;
+mod dimension;
+mod export;
+mod fullscreen;
+mod lut;
+mod pixel_buffer;
+mod pool;
 mod ringbuffer;
 mod texture;
+mod timer;
 
 pub use average::*;
 pub use cache::*;
+pub use dimension::*;
+pub use export::*;
+pub use fullscreen::*;
+pub use lut::*;
+pub use pixel_buffer::*;
+pub use pool::*;
 pub use ringbuffer::*;
 pub use texture::*;
+pub use timer::*;
 
 pub fn gcd(mut x: u32, mut y: u32) -> u32 {
     if x == 0 {
@@ -119,6 +134,27 @@ pub fn draw_fullscreen(vao: GLuint) {
     }
 }
 
+/// Draws a fullscreen rect with a program using the default pass vertex shader.
+pub fn draw_fullscreen_pass(prog_id: GLuint, vao: GLuint) {
+    unsafe {
+        let pos_attr = gl::GetAttribLocation(prog_id, "position\0".as_ptr() as _);
+        if pos_attr != -1 {
+            gl::EnableVertexAttribArray(pos_attr as GLuint);
+            gl::VertexAttribPointer(
+                pos_attr as GLuint,
+                2,
+                gl::FLOAT,
+                gl::FALSE as GLboolean,
+                0,
+                std::ptr::null(),
+            );
+        }
+        gl_debug_check!();
+    }
+
+    draw_fullscreen(vao);
+}
+
 pub fn draw_vertices(vao: GLuint, count: GLsizei, mode: GLenum) {
     unsafe {
         gl::BindVertexArray(vao);
@@ -168,9 +204,25 @@ pub fn compile_shader(src: &str, ty: GLenum) -> Result<GLuint, String> {
 ///
 /// Creates a new program and attaches the given shaders to that program.
 pub fn link_program(sh: &[GLuint]) -> Result<GLuint, String> {
+    link_program_with_varyings(sh, &[])
+}
+
+/// Links a program and records the given vertex outputs with transform feedback.
+pub fn link_program_with_varyings(sh: &[GLuint], varyings: &[CString]) -> Result<GLuint, String> {
     unsafe {
         let program = gl::CreateProgram();
 
+        // Declare captured outputs
+        if !varyings.is_empty() {
+            let ptrs: Vec<_> = varyings.iter().map(|s| s.as_ptr()).collect();
+            gl::TransformFeedbackVaryings(
+                program,
+                ptrs.len() as _,
+                ptrs.as_ptr(),
+                gl::INTERLEAVED_ATTRIBS,
+            );
+        }
+
         // Link program
         sh.iter().for_each(|&s| gl::AttachShader(program, s));
         gl::LinkProgram(program);
@@ -197,6 +249,176 @@ pub fn link_program(sh: &[GLuint]) -> Result<GLuint, String> {
     }
 }
 
+/// Lists the names and types of all uniforms used by a linked program.
+///
+/// Uniforms that are declared but optimized away by the compiler are not
+/// included. Arrays are listed under their name, without the `[0]` suffix.
+pub fn active_uniforms(prog_id: GLuint) -> Vec<(String, GLenum)> {
+    unsafe {
+        let mut count = 0;
+        let mut max_len = 0;
+        gl::GetProgramiv(prog_id, gl::ACTIVE_UNIFORMS, &mut count);
+        gl::GetProgramiv(prog_id, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_len);
+
+        let mut out = Vec::with_capacity(count.max(0) as _);
+        for k in 0..count.max(0) as GLuint {
+            let mut buf = vec![0_u8; max_len.max(1) as usize];
+            let (mut len, mut size, mut ty) = (0, 0, 0);
+            gl::GetActiveUniform(
+                prog_id,
+                k,
+                buf.len() as _,
+                &mut len,
+                &mut size,
+                &mut ty,
+                buf.as_mut_ptr() as _,
+            );
+
+            buf.truncate(len.max(0) as _);
+            let name = String::from_utf8_lossy(&buf);
+            let name = name.strip_suffix("[0]").unwrap_or(&name).to_string();
+            out.push((name, ty));
+        }
+
+        out
+    }
+}
+
+/// OpenGL version of the output context as `major * 10 + minor`
+static GL_VERSION: AtomicU32 = AtomicU32::new(0);
+
+/// Reads the version of the current context, so later calls to
+/// [`gl_version`] don't have to query it.
+pub fn detect_gl_version() -> (u32, u32) {
+    let (mut major, mut minor) = (0, 0);
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    }
+
+    let version = 10 * major.max(0) as u32 + minor.clamp(0, 9) as u32;
+    GL_VERSION.store(version, Ordering::Relaxed);
+    gl_version()
+}
+
+/// Returns the OpenGL version found by [`detect_gl_version`].
+pub fn gl_version() -> (u32, u32) {
+    let version = GL_VERSION.load(Ordering::Relaxed);
+    (version / 10, version % 10)
+}
+
+/// Largest width or height of a texture the current context supports
+pub fn max_texture_size() -> u32 {
+    let mut size = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut size);
+    }
+    size.max(1) as u32
+}
+
+/// Image load/store and memory barriers were added in OpenGL 4.2.
+pub fn supports_images() -> bool {
+    gl_version() >= (4, 2)
+}
+
+/// Compute shaders were added in OpenGL 4.3, which macOS never got.
+pub fn supports_compute() -> bool {
+    gl_version() >= (4, 3)
+}
+
+/// Returns `true` for uniform types that read from a texture.
+///
+/// Covers every sampler and image type of OpenGL 4.6, including the
+/// integer, shadow and multisample variants.
+pub fn is_sampler_type(ty: GLenum) -> bool {
+    is_texture_sampler(ty) || is_image_type(ty)
+}
+
+fn is_texture_sampler(ty: GLenum) -> bool {
+    matches!(
+        ty,
+        gl::SAMPLER_1D
+            | gl::SAMPLER_2D
+            | gl::SAMPLER_3D
+            | gl::SAMPLER_CUBE
+            | gl::SAMPLER_1D_ARRAY
+            | gl::SAMPLER_2D_ARRAY
+            | gl::SAMPLER_2D_RECT
+            | gl::SAMPLER_2D_MULTISAMPLE
+            | gl::SAMPLER_2D_MULTISAMPLE_ARRAY
+            | gl::SAMPLER_CUBE_MAP_ARRAY
+            | gl::SAMPLER_BUFFER
+            | gl::INT_SAMPLER_1D
+            | gl::INT_SAMPLER_2D
+            | gl::INT_SAMPLER_3D
+            | gl::INT_SAMPLER_CUBE
+            | gl::INT_SAMPLER_1D_ARRAY
+            | gl::INT_SAMPLER_2D_ARRAY
+            | gl::INT_SAMPLER_2D_RECT
+            | gl::INT_SAMPLER_2D_MULTISAMPLE
+            | gl::INT_SAMPLER_2D_MULTISAMPLE_ARRAY
+            | gl::INT_SAMPLER_CUBE_MAP_ARRAY
+            | gl::INT_SAMPLER_BUFFER
+            | gl::UNSIGNED_INT_SAMPLER_1D
+            | gl::UNSIGNED_INT_SAMPLER_2D
+            | gl::UNSIGNED_INT_SAMPLER_3D
+            | gl::UNSIGNED_INT_SAMPLER_CUBE
+            | gl::UNSIGNED_INT_SAMPLER_1D_ARRAY
+            | gl::UNSIGNED_INT_SAMPLER_2D_ARRAY
+            | gl::UNSIGNED_INT_SAMPLER_2D_RECT
+            | gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE
+            | gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE_ARRAY
+            | gl::UNSIGNED_INT_SAMPLER_CUBE_MAP_ARRAY
+            | gl::UNSIGNED_INT_SAMPLER_BUFFER
+            | gl::SAMPLER_1D_SHADOW
+            | gl::SAMPLER_2D_SHADOW
+            | gl::SAMPLER_CUBE_SHADOW
+            | gl::SAMPLER_1D_ARRAY_SHADOW
+            | gl::SAMPLER_2D_ARRAY_SHADOW
+            | gl::SAMPLER_2D_RECT_SHADOW
+            | gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW
+    )
+}
+
+fn is_image_type(ty: GLenum) -> bool {
+    matches!(
+        ty,
+        gl::IMAGE_1D
+            | gl::IMAGE_2D
+            | gl::IMAGE_3D
+            | gl::IMAGE_CUBE
+            | gl::IMAGE_1D_ARRAY
+            | gl::IMAGE_2D_ARRAY
+            | gl::IMAGE_2D_RECT
+            | gl::IMAGE_2D_MULTISAMPLE
+            | gl::IMAGE_2D_MULTISAMPLE_ARRAY
+            | gl::IMAGE_CUBE_MAP_ARRAY
+            | gl::IMAGE_BUFFER
+            | gl::INT_IMAGE_1D
+            | gl::INT_IMAGE_2D
+            | gl::INT_IMAGE_3D
+            | gl::INT_IMAGE_CUBE
+            | gl::INT_IMAGE_1D_ARRAY
+            | gl::INT_IMAGE_2D_ARRAY
+            | gl::INT_IMAGE_2D_RECT
+            | gl::INT_IMAGE_2D_MULTISAMPLE
+            | gl::INT_IMAGE_2D_MULTISAMPLE_ARRAY
+            | gl::INT_IMAGE_CUBE_MAP_ARRAY
+            | gl::INT_IMAGE_BUFFER
+            | gl::UNSIGNED_INT_IMAGE_1D
+            | gl::UNSIGNED_INT_IMAGE_2D
+            | gl::UNSIGNED_INT_IMAGE_3D
+            | gl::UNSIGNED_INT_IMAGE_CUBE
+            | gl::UNSIGNED_INT_IMAGE_1D_ARRAY
+            | gl::UNSIGNED_INT_IMAGE_2D_ARRAY
+            | gl::UNSIGNED_INT_IMAGE_2D_RECT
+            | gl::UNSIGNED_INT_IMAGE_2D_MULTISAMPLE
+            | gl::UNSIGNED_INT_IMAGE_2D_MULTISAMPLE_ARRAY
+            | gl::UNSIGNED_INT_IMAGE_CUBE_MAP_ARRAY
+            | gl::UNSIGNED_INT_IMAGE_BUFFER
+    )
+}
+
 #[allow(non_snake_case)]
 pub unsafe fn gl_TexImageND(
     target: GLenum,
@@ -231,7 +453,7 @@ pub unsafe fn gl_TexImageND(
                 type_,
                 pixels,
             ),
-            gl::TEXTURE_3D => gl::TexImage3D(
+            gl::TEXTURE_3D | gl::TEXTURE_2D_ARRAY => gl::TexImage3D(
                 target,
                 level,
                 internalformat,
@@ -248,6 +470,56 @@ pub unsafe fn gl_TexImageND(
     }
 }
 
+/// Replaces the contents of a whole texture without reallocating its storage.
+///
+/// # Safety
+///
+/// The texture must be bound to `target` and `pixels` must point to the
+/// texels of the whole `resolution`, laid out as `format` and `type_`
+/// describe, with every row padded to the current `GL_UNPACK_ALIGNMENT`.
+#[allow(non_snake_case)]
+pub unsafe fn gl_TexSubImageND(
+    target: GLenum,
+    level: GLint,
+    resolution: &[u32],
+    format: GLenum,
+    type_: GLenum,
+    pixels: *const c_void,
+) {
+    unsafe {
+        match target {
+            gl::TEXTURE_1D => {
+                gl::TexSubImage1D(target, level, 0, resolution[0] as _, format, type_, pixels)
+            }
+            gl::TEXTURE_2D => gl::TexSubImage2D(
+                target,
+                level,
+                0,
+                0,
+                resolution[0] as _,
+                resolution[1] as _,
+                format,
+                type_,
+                pixels,
+            ),
+            gl::TEXTURE_3D | gl::TEXTURE_2D_ARRAY => gl::TexSubImage3D(
+                target,
+                level,
+                0,
+                0,
+                0,
+                resolution[0] as _,
+                resolution[1] as _,
+                resolution[2] as _,
+                format,
+                type_,
+                pixels,
+            ),
+            _ => unreachable!(),
+        }
+    }
+}
+
 fn in_block(prefix: &str, start: &str, end: &str) -> bool {
     debug_assert_ne!(start, end);
 
@@ -415,6 +687,82 @@ pub fn preprocess(
     Ok(lines.join("\n"))
 }
 
+/// Inserts defines into preprocessed code.
+///
+/// The defines are placed right above the first line directive, so they
+/// don't shift the line numbers reported by the shader compiler.
+pub fn insert_defines(code: &str, defines: &str) -> String {
+    match code.find("#define SH4DERJOCKEY") {
+        Some(pos) => format!("{}{}{}", &code[..pos], defines, &code[pos..]),
+        None => match code.find('\n').filter(|_| code.starts_with("#version")) {
+            Some(pos) => format!("{}\n{}{}", &code[..pos], defines, &code[pos + 1..]),
+            None => format!("{}{}", defines, code),
+        },
+    }
+}
+
+/// Finds the quality levels declared with `#pragma sj quality(low, high)`.
+///
+/// Returns an empty list if the code declares no quality levels.
+pub fn quality_levels(code: &str) -> Result<Vec<String>, String> {
+    lazy_static! {
+        static ref QUALITY_RE: Regex =
+            Regex::new(r#"#\s*pragma\s+sj\s+quality\s*\((?P<levels>[^)]*)\)"#)
+                .expect("failed to compile regex");
+    }
+
+    let mut out = Vec::new();
+    for cap in QUALITY_RE.captures_iter(code) {
+        let prefix = &code[..cap.get(0).unwrap().start()];
+        if in_block(prefix, "//", "\n") || in_block(prefix, "/*", "*/") {
+            continue;
+        }
+
+        let levels: Vec<String> = cap["levels"]
+            .split(',')
+            .map(|s| s.trim().to_uppercase())
+            .collect();
+
+        for level in levels.iter() {
+            let valid = level.chars().next().is_some_and(|c| !c.is_ascii_digit())
+                && level.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+            if !valid {
+                return Err(format!(
+                    "Expected quality levels to be a list of names, got {:?}",
+                    &cap["levels"]
+                ));
+            }
+        }
+
+        if !out.is_empty() && out != levels {
+            return Err(format!(
+                "Conflicting quality levels {:?} and {:?}",
+                out, levels
+            ));
+        }
+
+        out = levels;
+    }
+
+    Ok(out)
+}
+
+/// Defines the macros selecting the quality level with the given index.
+///
+/// Every level gets a macro holding its index and `SJ_QUALITY` is set to
+/// the selected one, so shaders can check `#if SJ_QUALITY >= SJ_QUALITY_HIGH`.
+pub fn quality_defines(levels: &[String], index: usize) -> String {
+    let mut out: String = levels
+        .iter()
+        .enumerate()
+        .map(|(k, level)| format!("#define SJ_QUALITY_{} {}\n", level, k))
+        .collect();
+
+    out += &format!("#define SJ_QUALITY {}\n", index);
+    out
+}
+
 pub fn interlace<T: Clone>(first: &[T], second: &[T], out: &mut [T]) {
     debug_assert_eq!(first.len() + second.len(), out.len());
 
@@ -550,6 +898,57 @@ mod test {
         let result = preprocess(original, "test", &mut lut).unwrap();
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn insert_defines_before_line_directive() {
+        let code = "#version 123\n#define SH4DERJOCKEY 1\n#line 2 0\nmain(){}";
+        let expected = "#version 123\n#define FOO 1\n#define SH4DERJOCKEY 1\n#line 2 0\nmain(){}";
+        assert_eq!(insert_defines(code, "#define FOO 1\n"), expected);
+    }
+
+    #[test]
+    fn quality_levels_simple() {
+        let code = "#version 123\n#pragma sj quality(low, high)\nmain(){}";
+        assert_eq!(quality_levels(code).unwrap(), vec!["LOW", "HIGH"]);
+        assert!(quality_levels("main(){}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn quality_levels_in_comment() {
+        let code = "// #pragma sj quality(low, high)\n/* #pragma sj quality(a, b) */";
+        assert!(quality_levels(code).unwrap().is_empty());
+    }
+
+    #[test]
+    fn quality_levels_invalid() {
+        assert!(quality_levels("#pragma sj quality(low, 2x)").is_err());
+        assert!(quality_levels("#pragma sj quality()").is_err());
+        assert!(quality_levels("#pragma sj quality(a, b)\n#pragma sj quality(a, c)").is_err());
+        assert!(quality_levels("#pragma sj quality(a, b)\n#pragma sj quality(a, b)").is_ok());
+    }
+
+    #[test]
+    fn quality_defines_simple() {
+        let levels = vec!["LOW".to_string(), "HIGH".to_string()];
+        let expected =
+            "#define SJ_QUALITY_LOW 0\n#define SJ_QUALITY_HIGH 1\n#define SJ_QUALITY 0\n";
+        assert_eq!(quality_defines(&levels, 0), expected);
+    }
+
+    #[test]
+    fn sampler_types() {
+        assert!(is_sampler_type(gl::SAMPLER_2D));
+        assert!(is_sampler_type(gl::SAMPLER_2D_MULTISAMPLE));
+        assert!(is_sampler_type(
+            gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE_ARRAY
+        ));
+        assert!(is_sampler_type(gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW));
+        assert!(is_sampler_type(gl::INT_SAMPLER_CUBE));
+        assert!(is_sampler_type(gl::IMAGE_CUBE));
+        assert!(is_sampler_type(gl::UNSIGNED_INT_IMAGE_2D_MULTISAMPLE_ARRAY));
+        assert!(!is_sampler_type(gl::FLOAT_VEC2));
+        assert!(!is_sampler_type(gl::INT));
+    }
 }
 
 #[allow(dead_code)]
@@ -0,0 +1,132 @@
+use gl::types::*;
+
+use crate::*;
+
+/// Reads data back from the GPU without stalling the render loop.
+///
+/// A read back is started with [`PixelBuffer::begin`], followed by calls to
+/// `ReadPixels` or `GetTexImage` with a null pointer, and finished with
+/// [`PixelBuffer::end`]. The data can be fetched once [`PixelBuffer::poll`]
+/// returns `true`, which is usually a frame later.
+#[derive(Debug)]
+pub struct PixelBuffer {
+    pbo: GLuint,
+    capacity: usize,
+    fence: Option<GLsync>,
+}
+
+impl Default for PixelBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PixelBuffer {
+    pub fn new() -> Self {
+        let mut pbo = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut pbo);
+            gl_debug_check!();
+        }
+
+        Self {
+            pbo,
+            capacity: 0,
+            fence: None,
+        }
+    }
+
+    /// Returns `true` if no read back is in flight.
+    pub fn is_idle(&self) -> bool {
+        self.fence.is_none()
+    }
+
+    /// Binds the buffer as the pixel pack buffer, growing it to `size` bytes
+    /// if needed.
+    pub fn begin(&mut self, size: usize) {
+        unsafe {
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.pbo);
+            if self.capacity < size {
+                gl::BufferData(
+                    gl::PIXEL_PACK_BUFFER,
+                    size as _,
+                    std::ptr::null(),
+                    gl::STREAM_READ,
+                );
+                self.capacity = size;
+            }
+            gl_debug_check!();
+        }
+    }
+
+    /// Unbinds the buffer and marks the read back as in flight.
+    pub fn end(&mut self) {
+        unsafe {
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            self.fence = Some(gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+            gl_debug_check!();
+        }
+    }
+
+    /// Returns `true` once the data has arrived, blocking until then if
+    /// `wait` is set.
+    ///
+    /// A failed read back is logged and leaves the buffer idle.
+    pub fn poll(&mut self, wait: bool) -> bool {
+        let fence = match self.fence {
+            Some(s) => s,
+            None => return false,
+        };
+
+        unsafe {
+            let (flags, timeout) = match wait {
+                true => (gl::SYNC_FLUSH_COMMANDS_BIT, u64::MAX),
+                false => (0, 0),
+            };
+
+            let status = gl::ClientWaitSync(fence, flags, timeout);
+            if status == gl::TIMEOUT_EXPIRED {
+                return false;
+            }
+
+            gl::DeleteSync(fence);
+            self.fence = None;
+
+            if status == gl::WAIT_FAILED {
+                log::error!("Failed to read back data from the GPU");
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Copies the start of the buffer into `out`.
+    pub fn read(&self, out: &mut [u8]) {
+        debug_assert!(out.len() <= self.capacity);
+
+        unsafe {
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.pbo);
+            gl::GetBufferSubData(
+                gl::PIXEL_PACK_BUFFER,
+                0,
+                out.len() as _,
+                out.as_mut_ptr() as _,
+            );
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            gl_debug_check!();
+        }
+    }
+}
+
+impl Drop for PixelBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(fence) = self.fence {
+                gl::DeleteSync(fence);
+            }
+
+            gl::DeleteBuffers(1, &self.pbo);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use gl::types::*;
+
+use super::{gl_version, Texture};
+
+thread_local! {
+    static POOL_INTERNAL: RefCell<PoolInternal> = RefCell::new(PoolInternal::default());
+}
+
+/// Everything that has to match for a render target to be reused.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TargetKey {
+    pub resolution: Vec<u32>,
+    pub layers: Option<u32>,
+    pub min_filter: GLenum,
+    pub mag_filter: GLenum,
+    pub wrap_mode: GLenum,
+    pub float: bool,
+    pub mipmap: bool,
+    /// Images written by compute shaders, as opposed to framebuffers.
+    pub image: bool,
+}
+
+#[derive(Debug, Default)]
+struct PoolInternal {
+    entries: HashMap<TargetKey, Vec<Rc<dyn Texture>>>,
+    /// Targets handed to the build, cleared once it has been swapped in.
+    taken: Vec<Rc<dyn Texture>>,
+}
+
+/// Render targets of the running pipeline, lent to the pipeline being built.
+///
+/// Handing targets from one build to the next avoids freeing and allocating
+/// lots of GPU memory at once, which makes the frames after a rebuild stutter.
+/// Both pipelines share the targets until the new one is swapped in, which is
+/// fine as a pipeline doesn't render anything while it is being built. The
+/// pool is emptied after every swap, so it never keeps targets alive on its
+/// own.
+pub struct TargetPool;
+
+impl TargetPool {
+    /// Offers a target of the running pipeline to the next build.
+    pub fn lend(key: TargetKey, tex: Rc<dyn Texture>) {
+        POOL_INTERNAL.with(|pool| {
+            let mut pool = pool.borrow_mut();
+            pool.entries.entry(key).or_default().push(tex);
+        });
+    }
+
+    /// Returns a lent target matching the key, or builds a new one.
+    pub fn fetch_or_build(
+        key: &TargetKey,
+        build: impl FnOnce() -> Rc<dyn Texture>,
+    ) -> Rc<dyn Texture> {
+        let lent = POOL_INTERNAL.with(|pool| {
+            let mut pool = pool.borrow_mut();
+            let tex = pool.entries.get_mut(key).and_then(Vec::pop)?;
+            pool.taken.push(Rc::clone(&tex));
+            Some(tex)
+        });
+
+        lent.unwrap_or_else(build)
+    }
+
+    /// Empties the pool after the pipeline which lent its targets has been
+    /// replaced.
+    ///
+    /// Reused targets are cleared, so they look like freshly allocated ones,
+    /// while the ones that weren't taken are freed with the old pipeline.
+    pub fn collect() {
+        let taken = POOL_INTERNAL.with(|pool| {
+            let mut pool = pool.borrow_mut();
+            pool.entries.clear();
+            std::mem::take(&mut pool.taken)
+        });
+
+        for tex in taken {
+            clear_target(tex.as_ref());
+        }
+    }
+
+    /// Empties the pool without touching the targets, as they are still in
+    /// use by the running pipeline, e.g. after a failed build.
+    pub fn reset() {
+        POOL_INTERNAL.with(|pool| {
+            let mut pool = pool.borrow_mut();
+            pool.entries.clear();
+            pool.taken.clear();
+        });
+    }
+
+    /// Number of targets lent to the build.
+    pub fn len() -> usize {
+        POOL_INTERNAL.with(|pool| pool.borrow().entries.values().map(Vec::len).sum())
+    }
+}
+
+/// Clears the contents of a target, including the back buffer of double
+/// buffered framebuffers.
+fn clear_target(tex: &dyn Texture) {
+    unsafe {
+        if tex.framebuffer_id().is_some() {
+            let mut prev_fb = 0;
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut prev_fb);
+
+            for _ in 0..2 {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, tex.framebuffer_id().unwrap());
+                gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+                tex.swap();
+            }
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, prev_fb as _);
+        } else if gl_version() >= (4, 4) {
+            gl::ClearTexImage(tex.texture_id(), 0, gl::RGBA, gl::FLOAT, std::ptr::null());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Dummy;
+
+    impl Texture for Dummy {
+        fn bind(&self, _binding_unit: u32) {}
+        fn resolution(&self) -> [u32; 3] {
+            [1, 1, 0]
+        }
+        fn texture_id(&self) -> GLuint {
+            0
+        }
+        fn framebuffer_id(&self) -> Option<GLuint> {
+            None
+        }
+        fn target(&self) -> GLenum {
+            gl::TEXTURE_2D
+        }
+    }
+
+    #[test]
+    fn reuses_lent_targets() {
+        let key = TargetKey {
+            resolution: vec![1, 1],
+            layers: None,
+            min_filter: gl::LINEAR,
+            mag_filter: gl::LINEAR,
+            wrap_mode: gl::CLAMP_TO_EDGE,
+            float: false,
+            mipmap: false,
+            image: true,
+        };
+
+        let tex: Rc<dyn Texture> = Rc::new(Dummy);
+        TargetPool::lend(key.clone(), Rc::clone(&tex));
+        TargetPool::lend(key.clone(), Rc::new(Dummy));
+        assert_eq!(TargetPool::len(), 2);
+
+        let mut built = false;
+        let reused = TargetPool::fetch_or_build(&key, || {
+            built = true;
+            Rc::new(Dummy)
+        });
+        assert!(!built);
+        assert_eq!(TargetPool::len(), 1);
+
+        // the pool doesn't hold on to anything after the swap
+        TargetPool::collect();
+        assert_eq!(TargetPool::len(), 0);
+        drop(reused);
+
+        let mut built = false;
+        TargetPool::fetch_or_build(&key, || {
+            built = true;
+            Rc::new(Dummy)
+        });
+        assert!(built);
+
+        TargetPool::lend(key, tex);
+        TargetPool::reset();
+        assert_eq!(TargetPool::len(), 0);
+    }
+}
@@ -33,7 +33,6 @@ where
         self.index = (self.index + 1) % self.size;
     }
 
-    #[allow(dead_code)]
     pub fn push_slice(&mut self, val: &[T]) {
         for x in val.iter() {
             self.push(x);
@@ -52,6 +51,28 @@ where
             *slot = self.get(k);
         }
     }
+
+    /// Copies the values which came before the `delay` freshest ones,
+    /// ending with the freshest of those.
+    pub fn copy_delayed(&self, vec: &mut [T], delay: usize) {
+        let start = self.size.saturating_sub(vec.len() + delay);
+        for (k, slot) in vec.iter_mut().enumerate() {
+            *slot = self.get(start + k);
+        }
+    }
+
+    /// Changes the size of the buffer, keeping the freshest values.
+    pub fn resize(&mut self, size: usize) {
+        let keep = size.min(self.size);
+        let mut buffer = vec![T::default(); size];
+        for (k, slot) in buffer[size - keep..].iter_mut().enumerate() {
+            *slot = self.get(self.size - keep + k);
+        }
+
+        self.buffer = buffer;
+        self.index = 0;
+        self.size = size;
+    }
 }
 
 #[cfg(test)]
@@ -94,4 +115,23 @@ mod test {
             assert_eq!(pre_slice[i], ret_slice[i]);
         }
     }
+
+    #[test]
+    fn delay_and_resize() {
+        let mut rb = RingBuffer::<f32>::new(6);
+        rb.push_slice(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+
+        let mut ret_slice = [0.0; 3];
+        rb.copy_delayed(&mut ret_slice, 2);
+        assert_eq!(ret_slice, [3.0, 4.0, 5.0]);
+
+        rb.resize(4);
+        rb.copy_to_slice(&mut ret_slice);
+        assert_eq!(ret_slice, [4.0, 5.0, 6.0]);
+
+        rb.resize(6);
+        rb.copy_delayed(&mut ret_slice, 0);
+        assert_eq!(ret_slice, [5.0, 6.0, 7.0]);
+        assert_eq!(rb.get(0), 0.0);
+    }
 }
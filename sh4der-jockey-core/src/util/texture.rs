@@ -14,7 +14,16 @@ pub trait Texture: Debug + AsAny {
     fn resolution(&self) -> [u32; 3];
     fn texture_id(&self) -> GLuint;
     fn framebuffer_id(&self) -> Option<GLuint>;
+    fn target(&self) -> GLenum;
     fn swap(&self) {}
+
+    /// Returns `true` if the mip levels of the texture are filled in.
+    ///
+    /// Render targets are left out, as their levels are only generated
+    /// while rendering when the pipeline asks for them.
+    fn has_mipmaps(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Debug)]
@@ -43,6 +52,10 @@ impl Texture for FrameBuffer {
     fn framebuffer_id(&self) -> Option<GLuint> {
         Some(self.fb_id)
     }
+
+    fn target(&self) -> GLenum {
+        gl::TEXTURE_2D
+    }
 }
 
 impl FrameBuffer {
@@ -168,6 +181,10 @@ impl Texture for DoubleFrameBuffer {
         self.back.borrow().framebuffer_id()
     }
 
+    fn target(&self) -> GLenum {
+        gl::TEXTURE_2D
+    }
+
     fn swap(&self) {
         self.front.swap(&self.back)
     }
@@ -206,33 +223,413 @@ impl DoubleFrameBuffer {
     }
 }
 
+/// A multisampled render target.
+///
+/// Multisampled buffers can't be sampled in shaders directly, so they have
+/// to be resolved into a regular framebuffer after drawing.
+#[derive(Debug)]
+pub struct MultisampleBuffer {
+    pub rb_id: GLuint,
+    pub fb_id: GLuint,
+    pub res: [u32; 2],
+}
+
+impl MultisampleBuffer {
+    pub fn new(width: u32, height: u32, samples: GLsizei, float: bool) -> Self {
+        let width = width.max(1);
+        let height = height.max(1);
+
+        unsafe {
+            let mut max_samples = 0;
+            gl::GetIntegerv(gl::MAX_SAMPLES, &mut max_samples);
+            let samples = samples.min(max_samples.max(1));
+
+            let internal_format = match float {
+                true => gl::RGBA32F,
+                false => gl::RGBA8,
+            };
+
+            let mut rb_id = 0;
+            gl::GenRenderbuffers(1, &mut rb_id);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, rb_id);
+            gl::RenderbufferStorageMultisample(
+                gl::RENDERBUFFER,
+                samples,
+                internal_format,
+                width as _,
+                height as _,
+            );
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+            gl_debug_check!();
+
+            let mut fb_id = 0;
+            gl::GenFramebuffers(1, &mut fb_id);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fb_id);
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::RENDERBUFFER,
+                rb_id,
+            );
+            gl_debug_check!();
+
+            debug_assert_eq!(
+                gl::CheckFramebufferStatus(gl::FRAMEBUFFER),
+                gl::FRAMEBUFFER_COMPLETE
+            );
+
+            Self {
+                rb_id,
+                fb_id,
+                res: [width, height],
+            }
+        }
+    }
+
+    /// Copies the averaged samples into the given framebuffer.
+    pub fn resolve(&self, target_fb: GLuint) {
+        let [width, height] = self.res;
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fb_id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target_fb);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                width as _,
+                height as _,
+                0,
+                0,
+                width as _,
+                height as _,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, target_fb);
+            gl_debug_check!();
+        }
+    }
+}
+
+impl Drop for MultisampleBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fb_id);
+            gl::DeleteRenderbuffers(1, &self.rb_id);
+        }
+    }
+}
+
+/// A texture buffer object, which exposes a large array of floats to the
+/// shaders as a `samplerBuffer`, or an `imageBuffer` in compute shaders.
+#[derive(Debug)]
+pub struct TextureBuffer {
+    pub buf_id: GLuint,
+    pub tex_id: GLuint,
+    pub format: GLenum,
+    pub len: u32,
+}
+
+impl Texture for TextureBuffer {
+    fn resolution(&self) -> [u32; 3] {
+        [self.len, 0, 0]
+    }
+
+    fn bind(&self, binding_unit: u32) {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_BUFFER, self.tex_id);
+            gl_debug_check!();
+
+            // three channel formats can't be used as images
+            if self.format != gl::RGB32F && supports_images() {
+                gl::BindImageTexture(
+                    binding_unit,
+                    self.tex_id,
+                    0,
+                    gl::FALSE,
+                    0,
+                    gl::READ_WRITE,
+                    self.format,
+                );
+                gl_debug_check!();
+            }
+        }
+    }
+
+    fn texture_id(&self) -> GLuint {
+        self.tex_id
+    }
+
+    fn framebuffer_id(&self) -> Option<GLuint> {
+        None
+    }
+
+    fn target(&self) -> GLenum {
+        gl::TEXTURE_BUFFER
+    }
+}
+
+impl TextureBuffer {
+    /// Creates a buffer of `len` elements with 1 to 4 float channels each.
+    pub fn new(len: u32, channels: u8, data: &[f32]) -> Result<Self, String> {
+        debug_assert_eq!(data.len(), len as usize * channels as usize);
+
+        let format = match channels {
+            1 => gl::R32F,
+            2 => gl::RG32F,
+            3 => gl::RGB32F,
+            4 => gl::RGBA32F,
+            _ => return Err(format!("Expected 1 to 4 channels, got {}", channels)),
+        };
+
+        unsafe {
+            let mut max_size = 0;
+            gl::GetIntegerv(gl::MAX_TEXTURE_BUFFER_SIZE, &mut max_size);
+            if len as i64 > max_size as i64 {
+                return Err(format!(
+                    "Texture buffer of size {} exceeds the limit of {} elements",
+                    len, max_size
+                ));
+            }
+
+            let mut buf_id = 0;
+            gl::GenBuffers(1, &mut buf_id);
+            gl::BindBuffer(gl::TEXTURE_BUFFER, buf_id);
+            gl::BufferData(
+                gl::TEXTURE_BUFFER,
+                std::mem::size_of_val(data) as _,
+                data.as_ptr() as _,
+                gl::DYNAMIC_COPY,
+            );
+            gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+            gl_debug_check!();
+
+            let mut tex_id = 0;
+            gl::GenTextures(1, &mut tex_id);
+            gl::BindTexture(gl::TEXTURE_BUFFER, tex_id);
+            gl::TexBuffer(gl::TEXTURE_BUFFER, format, buf_id);
+            gl::BindTexture(gl::TEXTURE_BUFFER, 0);
+            gl_debug_check!();
+
+            Ok(Self {
+                buf_id,
+                tex_id,
+                format,
+                len,
+            })
+        }
+    }
+
+    /// Creates a buffer from an object with a `size`, `channels` and either
+    /// a `fill` mode or the `path` of a file with raw 32 bit floats.
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let channels = match object.get("channels") {
+            Some(s) => match s.as_u64() {
+                Some(n @ 1..=4) => n as u8,
+                _ => {
+                    return Err(format!(
+                        "Expected \"channels\" to be a number from 1 to 4, got {:?}",
+                        s
+                    ))
+                }
+            },
+            None => 4,
+        };
+
+        let size = match object.get("size") {
+            Some(s) => match s.as_u64() {
+                Some(n) if n > 0 && n <= u32::MAX as u64 => Some(n as u32),
+                _ => {
+                    return Err(format!(
+                        "Expected \"size\" to be a positive number, got {:?}",
+                        s
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        let data: Vec<f32> = match (object.get("path"), object.get("fill")) {
+            (Some(_), Some(_)) => {
+                return Err("Fields \"path\" and \"fill\" can't be used together".into())
+            }
+            (Some(Value::String(path)), None) => {
+                let bytes = std::fs::read(path)
+                    .map_err(|e| format!("Failed to read texture buffer at {:?}: {}", path, e))?;
+
+                let stride = 4 * channels as usize;
+                if bytes.len() % stride != 0 {
+                    return Err(format!(
+                        "Expected the size of {:?} to be a multiple of {} bytes, got {}",
+                        path,
+                        stride,
+                        bytes.len()
+                    ));
+                }
+
+                let data: Vec<f32> = bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+
+                match size {
+                    Some(n) if n as usize * channels as usize != data.len() => {
+                        return Err(format!(
+                            "Expected {:?} to contain {} elements, got {}",
+                            path,
+                            n,
+                            data.len() / channels as usize
+                        ))
+                    }
+                    _ => data,
+                }
+            }
+            (Some(s), None) => {
+                return Err(format!("Expected \"path\" to be a string, got {:?}", s))
+            }
+            (None, fill) => {
+                let len =
+                    match size {
+                        Some(n) => n as usize * channels as usize,
+                        None => return Err(
+                            "Field \"size\" is mandatory for texture buffers without a \"path\""
+                                .into(),
+                        ),
+                    };
+
+                match fill.map(Value::as_str) {
+                    Some(Some("zero")) | None => vec![0.0; len],
+                    Some(Some("random")) => (0..len).map(|_| rand::random()).collect(),
+                    Some(s) => {
+                        return Err(format!(
+                            "Expected \"fill\" to be either \"zero\" or \"random\", got {:?}",
+                            s
+                        ))
+                    }
+                }
+            }
+        };
+
+        let len = (data.len() / channels as usize) as u32;
+        Self::new(len, channels, &data)
+    }
+}
+
+impl Drop for TextureBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.tex_id);
+            gl::DeleteBuffers(1, &self.buf_id);
+        }
+    }
+}
+
+/// A sampler object, which overrides the filtering and wrapping of the
+/// texture bound to the same texture unit.
+#[derive(Debug)]
+pub struct Sampler {
+    pub id: GLuint,
+    pub min_filter: GLenum,
+    /// Whether the sampler reads from the mip levels of the texture.
+    pub mipmap: bool,
+}
+
+impl Sampler {
+    pub fn new(min_filter: GLenum, mag_filter: GLenum, wrap_mode: GLenum) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::GenSamplers(1, &mut id);
+            gl::SamplerParameteri(id, gl::TEXTURE_MIN_FILTER, min_filter as _);
+            gl::SamplerParameteri(id, gl::TEXTURE_MAG_FILTER, mag_filter as _);
+            gl::SamplerParameteri(id, gl::TEXTURE_WRAP_S, wrap_mode as _);
+            gl::SamplerParameteri(id, gl::TEXTURE_WRAP_T, wrap_mode as _);
+            gl::SamplerParameteri(id, gl::TEXTURE_WRAP_R, wrap_mode as _);
+            gl_debug_check!();
+        }
+
+        let mipmap = !matches!(min_filter, gl::NEAREST | gl::LINEAR);
+        Self {
+            id,
+            min_filter,
+            mipmap,
+        }
+    }
+
+    /// Switches to the filter without mip levels, for textures that don't
+    /// have any and would be incomplete otherwise.
+    pub fn disable_mipmap(&mut self) {
+        let min_filter = match self.min_filter {
+            gl::NEAREST_MIPMAP_NEAREST | gl::NEAREST_MIPMAP_LINEAR => gl::NEAREST,
+            gl::LINEAR_MIPMAP_NEAREST | gl::LINEAR_MIPMAP_LINEAR => gl::LINEAR,
+            filter => filter,
+        };
+
+        unsafe {
+            gl::SamplerParameteri(self.id, gl::TEXTURE_MIN_FILTER, min_filter as _);
+            gl_debug_check!();
+        }
+
+        self.min_filter = min_filter;
+        self.mipmap = false;
+    }
+
+    /// Reads the `filter`, `wrap` and `mipmap` fields of an object.
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let builder = TextureBuilder::parse(object, false, true)?;
+        Ok(Self::new(
+            builder.min_filter,
+            builder.mag_filter,
+            builder.wrap_mode,
+        ))
+    }
+
+    pub fn bind(&self, unit: GLuint) {
+        unsafe {
+            gl::BindSampler(unit, self.id);
+            gl_debug_check!();
+        }
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteSamplers(1, &self.id);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TextureBuilder {
     pub resolution: Vec<u32>,
+    pub relative: Vec<Dimension>,
     pub min_filter: GLenum,
     pub mag_filter: GLenum,
     pub wrap_mode: GLenum,
     pub channels: u8,
     pub float: bool,
     pub mipmap: bool,
+    pub layers: Option<u32>,
 }
 
 impl TextureBuilder {
     pub fn new() -> Self {
         Self {
             resolution: Vec::new(),
+            relative: Vec::new(),
             min_filter: gl::NEAREST,
             mag_filter: gl::NEAREST,
             wrap_mode: gl::CLAMP_TO_EDGE,
             channels: 4,
             float: false,
             mipmap: false,
+            layers: None,
         }
     }
 
     pub fn parse(object: &Value, support_res: bool, support_mipmap: bool) -> Result<Self, String> {
         // get target resolution
-        let resolution = match object
+        let dims = match object
             .get("size")
             .or_else(|| object.get("res"))
             .or_else(|| object.get("resolution"))
@@ -248,30 +645,50 @@ impl TextureBuilder {
 
                 let mut out = Vec::with_capacity(3);
                 for dim in dims {
-                    match dim.as_u64() {
-                        None => {
+                    match (dim, dim.as_u64()) {
+                        (Value::String(s), _) => out.push(Dimension::parse(s)?),
+
+                        (_, None) => {
                             return Err(format!(
                             "Expected \"resolution\" to be a list of positive numbers, got {:?}",
                             dims
                         ))
                         }
 
-                        Some(0) => {
+                        (_, Some(0)) => {
                             return Err(format!(
                                 "Expected all numbers in \"resolution\" to be positive, got {:?}",
                                 dims
                             ))
                         }
 
-                        Some(n) => out.push(n as _),
+                        (_, Some(n)) => out.push(Dimension::Fixed(n as _)),
                     };
                 }
 
-                Some(out)
+                out
             }
-            _ => None,
-        }
-        .unwrap_or_else(Vec::new);
+            _ => Vec::new(),
+        };
+
+        // dimensions depending on the screen are evaluated whenever the screen is resized
+        let (resolution, relative) = if dims.iter().all(|d| matches!(d, Dimension::Fixed(_))) {
+            let resolution = dims
+                .iter()
+                .map(|d| match d {
+                    Dimension::Fixed(n) => *n,
+                    _ => unreachable!(),
+                })
+                .collect();
+            (resolution, Vec::new())
+        } else if dims.len() == 2 {
+            (Vec::new(), dims)
+        } else {
+            return Err(format!(
+                "Expected \"resolution\" to be 2D when it depends on the screen, got {:?}",
+                dims
+            ));
+        };
 
         // get mipmap flag
         let mipmap = match object
@@ -327,14 +744,34 @@ impl TextureBuilder {
             Some(s) => return Err(format!("Expected \"float\" to be a bool, got {:?}", s)),
         };
 
+        // get number of layers of an array texture
+        let layers = match object.get("layers") {
+            Some(s) => match s.as_u64() {
+                Some(n) if n > 0 => Some(n as u32),
+                _ => {
+                    return Err(format!(
+                        "Expected \"layers\" to be a positive number, got {:?}",
+                        s
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        if layers.is_some() && !matches!(resolution.as_slice(), &[] | &[_, _]) {
+            return Err("Expected \"resolution\" to be 2D when using \"layers\"".into());
+        }
+
         Ok(Self {
             resolution,
+            relative,
             min_filter,
             mag_filter,
             wrap_mode,
             channels: 4,
             float,
             mipmap,
+            layers,
         })
     }
 
@@ -353,12 +790,34 @@ impl TextureBuilder {
         self
     }
 
-    pub fn build_framebuffer(&self, screen_size: (u32, u32)) -> Rc<FrameBuffer> {
-        let [width, height] = match self.resolution.as_slice() {
-            &[w, h] => [w, h],
-            &[] => [screen_size.0, screen_size.1],
+    /// Returns the size of a framebuffer built for the given screen size.
+    pub fn framebuffer_size(&self, screen_size: (u32, u32)) -> Result<[u32; 2], String> {
+        match (self.resolution.as_slice(), self.relative.as_slice()) {
+            (&[w, h], _) => Ok([w, h]),
+            (&[], [x, y]) => resolve_dimensions(x, y, screen_size, max_texture_size()),
+            (&[], &[]) => Ok([screen_size.0, screen_size.1]),
             _ => unreachable!(),
-        };
+        }
+    }
+
+    /// Returns the key under which a render target of the given resolution
+    /// is pooled.
+    pub fn target_key(&self, resolution: Vec<u32>, image: bool) -> TargetKey {
+        TargetKey {
+            resolution,
+            layers: self.layers,
+            min_filter: self.min_filter,
+            mag_filter: self.mag_filter,
+            wrap_mode: self.wrap_mode,
+            float: self.float,
+            mipmap: self.mipmap,
+            image,
+        }
+    }
+
+    /// Builds a framebuffer of a size returned by [`Self::framebuffer_size`].
+    pub fn build_framebuffer(&self, size: [u32; 2]) -> Rc<FrameBuffer> {
+        let [width, height] = size;
 
         Rc::new(FrameBuffer::with_params(
             width,
@@ -371,12 +830,8 @@ impl TextureBuilder {
         ))
     }
 
-    pub fn build_double_framebuffer(&self, screen_size: (u32, u32)) -> Rc<DoubleFrameBuffer> {
-        let [width, height] = match self.resolution.as_slice() {
-            &[w, h] => [w, h],
-            &[] => [screen_size.0, screen_size.1],
-            _ => unreachable!(),
-        };
+    pub fn build_double_framebuffer(&self, size: [u32; 2]) -> Rc<DoubleFrameBuffer> {
+        let [width, height] = size;
 
         Rc::new(DoubleFrameBuffer::with_params(
             width,
@@ -413,6 +868,18 @@ impl TextureBuilder {
 
     pub fn build_texture_with_data(&self, data: *const c_void) -> Rc<dyn Texture> {
         let format = self.texture_format();
+        if let (&[w, h], Some(layers)) = (self.resolution.as_slice(), self.layers) {
+            return Rc::new(Texture2DArray::with_params(
+                [w, h, layers],
+                self.min_filter,
+                self.mag_filter,
+                self.wrap_mode,
+                format,
+                self.mipmap,
+                data,
+            ));
+        }
+
         match self.resolution.as_slice() {
             &[w] => Rc::new(Texture1D::with_params(
                 [w],
@@ -447,6 +914,18 @@ impl TextureBuilder {
 
     pub fn build_image_with_data(&self, data: *const c_void) -> Rc<dyn Texture> {
         let format = self.texture_format();
+        if let (&[w, h], Some(layers)) = (self.resolution.as_slice(), self.layers) {
+            return Rc::new(Image2DArray::with_params(
+                [w, h, layers],
+                self.min_filter,
+                self.mag_filter,
+                self.wrap_mode,
+                format,
+                false,
+                data,
+            ));
+        }
+
         match self.resolution.as_slice() {
             &[w] => Rc::new(Image1D::with_params(
                 [w],
@@ -485,9 +964,11 @@ pub enum TextureKind {
     Image1D { res: [u32; 1] },
     Image2D { res: [u32; 2] },
     Image3D { res: [u32; 3] },
+    Image2DArray { res: [u32; 3] },
     Texture1D { res: [u32; 1] },
     Texture2D { res: [u32; 2] },
     Texture3D { res: [u32; 3] },
+    Texture2DArray { res: [u32; 3] },
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -522,17 +1003,23 @@ macro_rules! impl_texture {
                 out
             }
 
+            fn has_mipmaps(&self) -> bool {
+                self.mipmap
+            }
+
             fn bind(&self, binding_unit: u32) {
                 unsafe {
                     gl::BindTexture($enum_type, self.id);
                     gl_debug_check!();
 
-                    if $is_image {
+                    if $is_image && supports_images() {
+                        // bind all layers of 3D and array textures
+                        let layered = if $dim > 2 { gl::TRUE } else { gl::FALSE };
                         gl::BindImageTexture(
                             binding_unit,
                             self.id,
                             0,
-                            gl::FALSE,
+                            layered,
                             0,
                             gl::READ_WRITE,
                             self.format as _,
@@ -549,6 +1036,10 @@ macro_rules! impl_texture {
             fn framebuffer_id(&self) -> Option<GLuint> {
                 None
             }
+
+            fn target(&self) -> GLenum {
+                $enum_type
+            }
         }
 
         impl $name {
@@ -632,7 +1123,7 @@ macro_rules! impl_texture {
                     );
                     gl_debug_check!();
 
-                    if $is_image {
+                    if $is_image && supports_images() {
                         gl::BindImageTexture(
                             0,
                             tex_id,
@@ -657,22 +1148,20 @@ macro_rules! impl_texture {
                 }
             }
 
-            pub fn write(&mut self, data: *const c_void) {
+            /// Overwrites the contents of the texture, keeping its storage.
+            ///
+            /// # Safety
+            ///
+            /// `data` must point to the texels of the whole texture, in the
+            /// color format and type of its [`TextureFormat`], with every row
+            /// padded to the current `GL_UNPACK_ALIGNMENT`.
+            pub unsafe fn write(&mut self, data: *const c_void) {
                 unsafe {
                     gl::BindTexture($enum_type, self.id);
                     gl_debug_check!();
 
-                    let (internal_format, color_format, type_) = Self::get_formats(self.format);
-                    gl_TexImageND(
-                        $enum_type,
-                        0,
-                        internal_format,
-                        &self.res,
-                        0,
-                        color_format,
-                        type_,
-                        data,
-                    );
+                    let (_, color_format, type_) = Self::get_formats(self.format);
+                    gl_TexSubImageND($enum_type, 0, &self.res, color_format, type_, data);
                     gl_debug_check!();
                     if self.mipmap {
                         gl::GenerateMipmap($enum_type);
@@ -695,9 +1184,11 @@ macro_rules! impl_texture {
 impl_texture!(Image1D, gl::TEXTURE_1D, 1, true);
 impl_texture!(Image2D, gl::TEXTURE_2D, 2, true);
 impl_texture!(Image3D, gl::TEXTURE_3D, 3, true);
+impl_texture!(Image2DArray, gl::TEXTURE_2D_ARRAY, 3, true);
 impl_texture!(Texture1D, gl::TEXTURE_1D, 1, false);
 impl_texture!(Texture2D, gl::TEXTURE_2D, 2, false);
 impl_texture!(Texture3D, gl::TEXTURE_3D, 3, false);
+impl_texture!(Texture2DArray, gl::TEXTURE_2D_ARRAY, 3, false);
 
 #[deprecated]
 pub fn make_image(resolution: &[u32]) -> Rc<dyn Texture> {
@@ -0,0 +1,98 @@
+use gl::types::*;
+
+use crate::*;
+
+/// Measures how long the GPU takes for a span of commands.
+///
+/// Timestamps are used rather than a `TIME_ELAPSED` query, so the timed span
+/// may contain other timer queries. The result only becomes available a
+/// frame or two later, no new span is timed until the last one has been
+/// read back, so the render loop never waits for the GPU.
+#[derive(Debug)]
+pub struct GpuTimer {
+    queries: [GLuint; 2],
+    pending: bool,
+    active: bool,
+}
+
+impl GpuTimer {
+    pub fn new() -> Self {
+        let mut queries = [0; 2];
+        unsafe {
+            gl::GenQueries(2, queries.as_mut_ptr());
+            gl_debug_check!();
+        }
+
+        Self {
+            queries,
+            pending: false,
+            active: false,
+        }
+    }
+
+    /// Marks the start of the timed span, unless a result is still pending.
+    pub fn begin(&mut self) {
+        if self.pending {
+            return;
+        }
+
+        unsafe {
+            gl::QueryCounter(self.queries[0], gl::TIMESTAMP);
+            gl_debug_check!();
+        }
+
+        self.active = true;
+    }
+
+    pub fn end(&mut self) {
+        if !self.active {
+            return;
+        }
+
+        unsafe {
+            gl::QueryCounter(self.queries[1], gl::TIMESTAMP);
+            gl_debug_check!();
+        }
+
+        self.active = false;
+        self.pending = true;
+    }
+
+    /// Returns the duration of the last span in milliseconds, once the GPU
+    /// has finished it.
+    pub fn poll(&mut self) -> Option<f32> {
+        if !self.pending {
+            return None;
+        }
+
+        unsafe {
+            let mut available = 0;
+            gl::GetQueryObjectiv(self.queries[1], gl::QUERY_RESULT_AVAILABLE, &mut available);
+            if available == 0 {
+                return None;
+            }
+
+            let (mut start, mut end) = (0, 0);
+            gl::GetQueryObjectui64v(self.queries[0], gl::QUERY_RESULT, &mut start);
+            gl::GetQueryObjectui64v(self.queries[1], gl::QUERY_RESULT, &mut end);
+            gl_debug_check!();
+
+            self.pending = false;
+            Some(end.saturating_sub(start) as f32 / 1_000_000.0)
+        }
+    }
+}
+
+impl Default for GpuTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteQueries(2, self.queries.as_ptr());
+        }
+    }
+}
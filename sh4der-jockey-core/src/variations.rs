@@ -0,0 +1,262 @@
+use std::ffi::CString;
+
+use serde_yaml::Value;
+
+use crate::Uniform;
+
+/// Largest seed handed out by a reroll, small enough to be read out loud.
+pub const MAX_SEED: u32 = 999_999;
+
+/// A uniform whose value is picked at random between `min` and `max`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariationParam {
+    pub uniform: CString,
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+/// Parameters which are randomized together.
+///
+/// Every group draws from its own stream of random numbers, so adding or
+/// removing a group doesn't change the values of the others.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariationGroup {
+    pub name: String,
+    pub params: Vec<VariationParam>,
+}
+
+/// Randomized parameters of a generative piece.
+///
+/// The values are derived from a single seed, so a variation which turned
+/// out well can be brought back by entering its seed again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variations {
+    pub groups: Vec<VariationGroup>,
+    /// Seed set in the pipeline file, which replaces the random one.
+    pub fixed_seed: Option<u32>,
+    seed: u32,
+    values: Vec<(CString, Uniform)>,
+}
+
+impl VariationParam {
+    fn from_yaml(name: &Value, range: &Value) -> Result<Self, String> {
+        let uniform = match name.as_str() {
+            Some(s) => CString::new(s).unwrap(),
+            None => {
+                return Err(format!(
+                    "Expected variation parameter name to be a string, got {:?}",
+                    name
+                ))
+            }
+        };
+
+        let components = |value: &Value| match value {
+            Value::Number(n) => n.as_f64().map(|f| vec![f as f32]),
+            Value::Sequence(seq) => seq
+                .iter()
+                .map(|v| v.as_f64().map(|f| f as f32))
+                .collect::<Option<Vec<_>>>()
+                .filter(|v| (1..=4).contains(&v.len())),
+            _ => None,
+        };
+
+        let bounds = match range {
+            Value::Sequence(seq) if seq.len() == 2 => components(&seq[0]).zip(components(&seq[1])),
+            _ => None,
+        };
+
+        match bounds {
+            Some((min, max)) if min.len() == max.len() => Ok(Self { uniform, min, max }),
+            _ => Err(format!(
+                "Expected range of variation parameter {:?} to be a pair [min, max] of numbers or vectors of the same size, got {:?}",
+                uniform, range
+            )),
+        }
+    }
+}
+
+impl Variations {
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let fixed_seed = match object.get("seed") {
+            None => None,
+            Some(s) => match s.as_u64() {
+                Some(n) if n <= MAX_SEED as u64 => Some(n as u32),
+                _ => {
+                    return Err(format!(
+                        "Expected \"seed\" to be a number from 0 to {}, got {:?}",
+                        MAX_SEED, s
+                    ))
+                }
+            },
+        };
+
+        let groups = match object.get("groups") {
+            Some(Value::Mapping(m)) => m,
+            s => {
+                return Err(format!(
+                    "Expected \"groups\" of variations to be a mapping, got {:?}",
+                    s
+                ))
+            }
+        };
+
+        let mut out = Vec::with_capacity(groups.len());
+        for (name, params) in groups {
+            let name = match name.as_str() {
+                Some(s) => s.to_owned(),
+                None => {
+                    return Err(format!(
+                        "Expected variation group name to be a string, got {:?}",
+                        name
+                    ))
+                }
+            };
+
+            let params = match params {
+                Value::Mapping(m) => m
+                    .iter()
+                    .map(|(k, v)| VariationParam::from_yaml(k, v))
+                    .collect::<Result<Vec<_>, _>>()?,
+                s => {
+                    return Err(format!(
+                        "Expected variation group {:?} to be a mapping, got {:?}",
+                        name, s
+                    ))
+                }
+            };
+
+            out.push(VariationGroup { name, params });
+        }
+
+        let mut this = Self {
+            groups: out,
+            fixed_seed,
+            seed: 0,
+            values: Vec::new(),
+        };
+
+        match fixed_seed {
+            Some(seed) => this.set_seed(seed),
+            None => this.reroll(),
+        }
+
+        Ok(this)
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Picks the values of all parameters for the given seed.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed.min(MAX_SEED);
+        self.values.clear();
+
+        for group in self.groups.iter() {
+            let mut state = (self.seed as u64) ^ fnv1a(group.name.as_bytes());
+            for param in group.params.iter() {
+                let v: Vec<f32> = param
+                    .min
+                    .iter()
+                    .zip(&param.max)
+                    .map(|(lo, hi)| lo + (hi - lo) * next_f32(&mut state))
+                    .collect();
+
+                let uniform = match *v.as_slice() {
+                    [x] => Uniform::Float(x),
+                    [x, y] => Uniform::Vec2(x, y),
+                    [x, y, z] => Uniform::Vec3(x, y, z),
+                    [x, y, z, w] => Uniform::Vec4(x, y, z, w),
+                    _ => unreachable!(),
+                };
+
+                self.values.push((param.uniform.clone(), uniform));
+            }
+        }
+    }
+
+    /// Picks a new random seed.
+    pub fn reroll(&mut self) {
+        self.set_seed(rand::random::<u32>() % (MAX_SEED + 1));
+    }
+
+    /// Values of all parameters, in the order of the groups.
+    pub fn uniforms(&self) -> &[(CString, Uniform)] {
+        &self.values
+    }
+
+    /// Whether both declare the same parameters, ignoring their ranges.
+    pub fn same_params(&self, other: &Self) -> bool {
+        let names = |v: &Self| -> Vec<(String, Vec<CString>)> {
+            v.groups
+                .iter()
+                .map(|g| {
+                    let params = g.params.iter().map(|p| p.uniform.clone()).collect();
+                    (g.name.clone(), params)
+                })
+                .collect()
+        };
+
+        names(self) == names(other)
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// SplitMix64, which unlike the generators of the `rand` crate is
+/// guaranteed to give the same numbers in every version.
+fn next_f32(state: &mut u64) -> f32 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 24) as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reproducible_variations() {
+        let yaml = "
+            seed: 1234
+            groups:
+              palette:
+                hue: [0.2, 0.4]
+                tint: [[0, 0, 0], [1, 1, 1]]
+              shape:
+                sides: [3, 8]
+        ";
+        let mut variations = Variations::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        assert_eq!(variations.seed(), 1234);
+
+        let first = variations.uniforms().to_vec();
+        assert_eq!(first.len(), 3);
+        assert_eq!(first[0].0.to_str().unwrap(), "hue");
+        match first[0].1 {
+            Uniform::Float(x) => assert!((0.2..0.4).contains(&x)),
+            u => panic!("unexpected uniform {:?}", u),
+        }
+        assert!(matches!(first[1].1, Uniform::Vec3(..)));
+
+        variations.set_seed(99);
+        assert_ne!(variations.uniforms(), first.as_slice());
+        variations.set_seed(1234);
+        assert_eq!(variations.uniforms(), first.as_slice());
+
+        // groups don't affect each other
+        let yaml = "{seed: 1234, groups: {shape: {sides: [3, 8]}}}";
+        let other = Variations::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        assert_eq!(other.uniforms()[0], first[2]);
+        assert!(!other.same_params(&variations));
+
+        let bad = "{groups: {a: {x: [0, [1, 2]]}}}";
+        assert!(Variations::from_yaml(&serde_yaml::from_str(bad).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+/*
+ * C interface of the Sh4derJockey pipeline engine.
+ *
+ * Every function taking a context must be called on the thread which owns
+ * the OpenGL context the engine was created in, with that context current.
+ *
+ * The functions restore the OpenGL state of the host before returning:
+ * the draw and read framebuffers, viewport, program, vertex array, array
+ * buffer, active texture unit, the 2D textures bound to units 0 to 15,
+ * blending (enable, function and equation) and the clear color. Other
+ * state, like uniform buffer bindings, image units and textures of other
+ * targets or higher units, is left as the pipeline set it.
+ */
+
+#ifndef SH4DER_JOCKEY_H
+#define SH4DER_JOCKEY_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SjContext SjContext;
+
+/* Resolves OpenGL functions by name, e.g. a wrapper around
+ * wglGetProcAddress or glXGetProcAddress. */
+typedef const void *(*SjGlLoader)(void *user_data, const char *name);
+
+/* Creates an engine in the current OpenGL context, which must support at
+ * least OpenGL 4.1. Returns NULL if the engine could not be created or the
+ * context is too old. */
+SjContext *sj_create(SjGlLoader loader, void *user_data);
+
+/* Destroys an engine and all OpenGL objects it created. */
+void sj_destroy(SjContext *ctx);
+
+/* Builds the pipeline file at path for an output of the given size. Files
+ * referenced by the pipeline are relative to the working directory.
+ * Returns 0 on success and -1 on failure, in which case the previous
+ * pipeline keeps running. */
+int sj_load_pipeline(SjContext *ctx, const char *path, unsigned int width, unsigned int height);
+
+/* Sets a float uniform with 1 to 4 components on every stage. The value
+ * stays set until it is changed. Returns 0 on success and -1 on failure. */
+int sj_set_uniform(SjContext *ctx, const char *name, const float *values, int count);
+
+/* Passes count stereo audio samples to the engine.
+ * Returns 0 on success and -1 on failure. */
+int sj_feed_audio(SjContext *ctx, const float *left, const float *right, unsigned int count);
+
+/* Renders a frame into the framebuffer fbo, with 0 being the default
+ * framebuffer, and advances the time by delta seconds.
+ * Returns 0 on success and -1 on failure. */
+int sj_render(SjContext *ctx, unsigned int fbo, unsigned int width, unsigned int height, float delta);
+
+/* Describes the last error which happened in a context, or returns an
+ * empty string. The string stays valid until the next call which fails. */
+const char *sj_last_error(const SjContext *ctx);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SH4DER_JOCKEY_H */
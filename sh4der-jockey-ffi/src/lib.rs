@@ -0,0 +1,256 @@
+//! C interface of the Sh4derJockey pipeline engine.
+//!
+//! This lets wrappers written in C or C++, like FFGL or OBS plugins, host
+//! the engine inside their own OpenGL context. The functions are declared
+//! in `include/sh4der_jockey.h`.
+//!
+//! Every function taking a context must be called on the thread which owns
+//! the OpenGL context the engine was created in, with that context current.
+
+#![warn(unsafe_op_in_unsafe_fn)]
+
+use std::{
+    ffi::{c_void, CStr, CString},
+    os::raw::{c_char, c_int, c_uint},
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use sh4der_jockey_core::{util::detect_gl_version, Engine, Uniform};
+
+/// Oldest OpenGL version the engine runs on.
+const MIN_GL_VERSION: (u32, u32) = (4, 1);
+
+/// Resolves OpenGL functions by name, e.g. a wrapper around
+/// `wglGetProcAddress` or `glXGetProcAddress`.
+pub type SjGlLoader = extern "C" fn(user_data: *mut c_void, name: *const c_char) -> *const c_void;
+
+/// An engine and the last error which happened in it.
+pub struct SjContext {
+    engine: Engine,
+    error: CString,
+}
+
+impl std::fmt::Debug for SjContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(SjContext))
+            .field("engine", &self.engine)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl SjContext {
+    fn fail(&mut self, err: impl Into<String>) -> c_int {
+        self.error = to_c_string(err.into());
+        -1
+    }
+
+    /// Runs `f` on the engine and returns 0, or -1 if it fails or panics.
+    ///
+    /// Panics must not unwind into the host, which is written in C.
+    fn run(&mut self, f: impl FnOnce(&mut Engine) -> Result<(), String>) -> c_int {
+        let engine = &mut self.engine;
+        match catch_unwind(AssertUnwindSafe(|| f(engine))) {
+            Ok(Ok(())) => 0,
+            Ok(Err(err)) => self.fail(err),
+            Err(err) => self.fail(panic_message(err)),
+        }
+    }
+}
+
+/// Converts an error message, dropping nul bytes which C can't represent.
+fn to_c_string(mut s: String) -> CString {
+    s.retain(|c| c != '\0');
+    CString::new(s).unwrap()
+}
+
+fn panic_message(err: Box<dyn std::any::Any + Send>) -> String {
+    match (err.downcast_ref::<&str>(), err.downcast_ref::<String>()) {
+        (Some(s), _) => s.to_string(),
+        (_, Some(s)) => s.clone(),
+        _ => "Engine panicked".into(),
+    }
+}
+
+/// Creates an engine in the current OpenGL context, which must support at
+/// least OpenGL 4.1, as uniforms are uploaded with `glProgramUniform`.
+///
+/// Returns null if the engine could not be created or the context is too
+/// old.
+#[no_mangle]
+pub extern "C" fn sj_create(loader: SjGlLoader, user_data: *mut c_void) -> *mut SjContext {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        gl::load_with(|name| {
+            let name = CString::new(name).unwrap();
+            loader(user_data, name.as_ptr())
+        });
+
+        match detect_gl_version() >= MIN_GL_VERSION {
+            true => Some(Engine::new()),
+            false => None,
+        }
+    }));
+
+    match result {
+        Ok(Some(engine)) => Box::into_raw(Box::new(SjContext {
+            engine,
+            error: CString::default(),
+        })),
+        Ok(None) | Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Destroys an engine and all OpenGL objects it created.
+///
+/// A panic while cleaning up is caught and ignored, as there is no context
+/// left to report it in.
+///
+/// # Safety
+///
+/// `ctx` must be null or a pointer returned by [`sj_create`], which is not
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn sj_destroy(ctx: *mut SjContext) {
+    if !ctx.is_null() {
+        let ctx = unsafe { Box::from_raw(ctx) };
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(ctx)));
+    }
+}
+
+/// Builds the pipeline file at `path` for an output of the given size.
+///
+/// Files referenced by the pipeline are relative to the working directory.
+/// Returns 0 on success and -1 on failure, in which case the previous
+/// pipeline keeps running and [`sj_last_error`] tells what went wrong.
+///
+/// # Safety
+///
+/// `ctx` must be a valid context and `path` a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sj_load_pipeline(
+    ctx: *mut SjContext,
+    path: *const c_char,
+    width: c_uint,
+    height: c_uint,
+) -> c_int {
+    let ctx = unsafe { &mut *ctx };
+    let path = match unsafe { CStr::from_ptr(path) }.to_str() {
+        Ok(s) => s,
+        Err(err) => return ctx.fail(err.to_string()),
+    };
+
+    ctx.run(|engine| engine.load(path, width, height))
+}
+
+/// Sets a float uniform with 1 to 4 components on every stage, e.g. a
+/// parameter of the host plugin. The value stays set until it is changed.
+///
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+///
+/// `ctx` must be a valid context, `name` a null terminated string and
+/// `values` must point to `count` floats.
+#[no_mangle]
+pub unsafe extern "C" fn sj_set_uniform(
+    ctx: *mut SjContext,
+    name: *const c_char,
+    values: *const f32,
+    count: c_int,
+) -> c_int {
+    let ctx = unsafe { &mut *ctx };
+    let name = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(s) => s,
+        Err(err) => return ctx.fail(err.to_string()),
+    };
+
+    let values = match count {
+        1..=4 => unsafe { slice::from_raw_parts(values, count as _) },
+        _ => return ctx.fail(format!("Expected 1 to 4 values, got {}", count)),
+    };
+
+    let uniform = match *values {
+        [x] => Uniform::Float(x),
+        [x, y] => Uniform::Vec2(x, y),
+        [x, y, z] => Uniform::Vec3(x, y, z),
+        [x, y, z, w] => Uniform::Vec4(x, y, z, w),
+        _ => unreachable!(),
+    };
+
+    ctx.run(|engine| engine.set_uniform(name, uniform))
+}
+
+/// Passes stereo audio samples to the engine, which are analyzed in the
+/// next frames like the input of an audio device.
+///
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+///
+/// `ctx` must be a valid context and `left` and `right` must each point to
+/// `count` floats.
+#[no_mangle]
+pub unsafe extern "C" fn sj_feed_audio(
+    ctx: *mut SjContext,
+    left: *const f32,
+    right: *const f32,
+    count: c_uint,
+) -> c_int {
+    let ctx = unsafe { &mut *ctx };
+    let left = unsafe { slice::from_raw_parts(left, count as _) };
+    let right = unsafe { slice::from_raw_parts(right, count as _) };
+    ctx.run(|engine| {
+        engine.audio.feed(left, right);
+        Ok(())
+    })
+}
+
+/// Renders a frame into the framebuffer `fbo`, with 0 being the default
+/// framebuffer, and advances the time by `delta` seconds.
+///
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+///
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn sj_render(
+    ctx: *mut SjContext,
+    fbo: c_uint,
+    width: c_uint,
+    height: c_uint,
+    delta: f32,
+) -> c_int {
+    let ctx = unsafe { &mut *ctx };
+    ctx.run(|engine| {
+        engine.render(fbo, width, height, delta);
+        Ok(())
+    })
+}
+
+/// Describes the last error which happened in a context, or returns an
+/// empty string. The string stays valid until the next call which fails.
+///
+/// # Safety
+///
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn sj_last_error(ctx: *const SjContext) -> *const c_char {
+    unsafe { (*ctx).error.as_ptr() }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn error_messages() {
+        assert_eq!(panic_message(Box::new("oops")), "oops");
+        assert_eq!(panic_message(Box::new(String::from("oops"))), "oops");
+        assert_eq!(panic_message(Box::new(42)), "Engine panicked");
+
+        let error = to_c_string("bad\0 shader".into());
+        assert_eq!(error.to_str(), Ok("bad shader"));
+    }
+}
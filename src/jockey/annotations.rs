@@ -0,0 +1,147 @@
+/// Things which happen now and then and may make a frame take longer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameEvent {
+    PipelineRebuild,
+    NdiReconnect,
+    TextureUpload,
+    Screenshot,
+}
+
+impl FrameEvent {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PipelineRebuild => "pipeline rebuild",
+            Self::NdiReconnect => "NDI reconnect",
+            Self::TextureUpload => "texture upload",
+            Self::Screenshot => "screenshot",
+        }
+    }
+
+    pub fn color(self) -> [f32; 4] {
+        match self {
+            Self::PipelineRebuild => [1.0, 0.4, 0.2, 1.0],
+            Self::NdiReconnect => [0.3, 0.6, 1.0, 1.0],
+            Self::TextureUpload => [1.0, 0.9, 0.2, 1.0],
+            Self::Screenshot => [0.8, 0.4, 1.0, 1.0],
+        }
+    }
+}
+
+/// Events of the last frames, laid out like the ring buffer of frame times,
+/// so they can be drawn on top of the frame time plot.
+#[derive(Debug)]
+pub struct FrameAnnotations {
+    slots: Vec<Vec<FrameEvent>>,
+    pending: Vec<FrameEvent>,
+    latest: usize,
+}
+
+impl FrameAnnotations {
+    pub fn new(size: usize) -> Self {
+        Self {
+            slots: vec![Vec::new(); size],
+            pending: Vec::new(),
+            latest: 0,
+        }
+    }
+
+    /// Remembers an event for the frame which is currently being rendered.
+    pub fn record(&mut self, event: FrameEvent) {
+        if !self.pending.contains(&event) {
+            self.pending.push(event);
+        }
+    }
+
+    /// Attaches the events recorded since the last call to the frame whose
+    /// time was written into `slot`.
+    pub fn end_frame(&mut self, slot: usize) {
+        self.slots[slot] = std::mem::take(&mut self.pending);
+        self.latest = slot;
+    }
+
+    /// Events of the frame which ended last.
+    pub fn latest(&self) -> &[FrameEvent] {
+        &self.slots[self.latest]
+    }
+
+    /// Lists the annotated frames, newest first, as the slot of the frame,
+    /// the number of frames since and its events.
+    pub fn recent(&self) -> impl Iterator<Item = (usize, usize, &[FrameEvent])> + '_ {
+        let size = self.slots.len();
+        (0..size).filter_map(move |age| {
+            let slot = (self.latest + size - age) % size;
+            let events = self.slots[slot].as_slice();
+            (!events.is_empty()).then_some((slot, age, events))
+        })
+    }
+}
+
+/// Draws the frame time plot with a marker for every annotated frame,
+/// followed by a list of the latest events and the frame times they caused.
+pub fn draw_frame_plot(
+    ui: &imgui::Ui,
+    label: &imgui::ImStr,
+    times: &[f32],
+    notes: &FrameAnnotations,
+) {
+    let size = [ui.content_region_avail()[0], 48.0];
+    let origin = ui.cursor_screen_pos();
+    ui.plot_lines(label, times).graph_size(size).build();
+
+    // the plot spreads its points over the frame minus the padding
+    let padding = ui.clone_style().frame_padding;
+    let left = origin[0] + padding[0];
+    let width = size[0] - 2.0 * padding[0];
+    let step = width / times.len().saturating_sub(1).max(1) as f32;
+
+    let draw_list = ui.get_window_draw_list();
+    for (slot, _, events) in notes.recent() {
+        let x = left + slot as f32 * step;
+        let top = [x, origin[1]];
+        let bottom = [x, origin[1] + size[1]];
+        draw_list.add_line(top, bottom, events[0].color()).build();
+    }
+
+    for (slot, age, events) in notes.recent().take(4) {
+        let names: Vec<_> = events.iter().map(|e| e.name()).collect();
+        ui.text_colored(
+            events[0].color(),
+            format!(
+                "{} frames ago: {} ({:.2} ms)",
+                age,
+                names.join(", "),
+                times.get(slot).copied().unwrap_or_default()
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lists_newest_first() {
+        let mut notes = FrameAnnotations::new(4);
+        notes.record(FrameEvent::Screenshot);
+        notes.record(FrameEvent::Screenshot);
+        notes.end_frame(3);
+        notes.end_frame(0);
+        notes.record(FrameEvent::PipelineRebuild);
+        notes.end_frame(1);
+
+        let recent: Vec<_> = notes.recent().collect();
+        assert_eq!(
+            recent,
+            vec![
+                (1, 0, &[FrameEvent::PipelineRebuild][..]),
+                (3, 2, &[FrameEvent::Screenshot][..]),
+            ]
+        );
+
+        // slots are overwritten once the ring buffer wraps around
+        notes.end_frame(2);
+        notes.end_frame(3);
+        assert_eq!(notes.recent().count(), 1);
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Folder inside the project which holds the shader backups
+pub const BACKUP_FOLDER: &str = ".sj-backups";
+
+/// Returns true if the path lies inside the backup folder.
+pub fn is_backup_path(path: &Path) -> bool {
+    path.components()
+        .any(|c| c == Component::Normal(BACKUP_FOLDER.as_ref()))
+}
+
+/// Turns a shader path into a file name for its backups.
+fn backup_name(path: &str) -> String {
+    path.trim_start_matches("./")
+        .replace(|c| matches!(c, '/' | '\\' | ':'), "_")
+}
+
+/// Writes a backup of a file and removes all but the `keep` newest ones.
+fn write_backup(folder: &Path, path: &str, content: &str, keep: usize) -> std::io::Result<()> {
+    std::fs::create_dir_all(folder)?;
+
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+
+    let name = backup_name(path);
+    std::fs::write(folder.join(format!("{}.{:020}", name, stamp)), content)?;
+
+    let prefix = format!("{}.", name);
+    let mut versions = Vec::new();
+    for entry in std::fs::read_dir(folder)? {
+        let file_name = entry?.file_name().to_string_lossy().into_owned();
+        let is_version = file_name.strip_prefix(&prefix).map_or(false, |s| {
+            !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+        });
+
+        if is_version {
+            versions.push(file_name);
+        }
+    }
+
+    versions.sort();
+    let excess = versions.len().saturating_sub(keep);
+    for file_name in versions.iter().take(excess) {
+        std::fs::remove_file(folder.join(file_name))?;
+    }
+
+    Ok(())
+}
+
+/// Shader files of the last successful build, used to back up the previous
+/// version of every file which changed since.
+#[derive(Debug, Default)]
+pub struct Backups {
+    contents: HashMap<String, String>,
+}
+
+impl Backups {
+    /// Remembers the files of a successful build and backs up the previous
+    /// version of those which changed, keeping the `keep` newest backups of
+    /// each file.
+    pub fn update<'a>(
+        &mut self,
+        folder: &Path,
+        files: impl IntoIterator<Item = &'a str>,
+        keep: usize,
+    ) {
+        for path in files {
+            let content = match std::fs::read_to_string(path) {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+
+            match self.contents.get(path) {
+                Some(old) if *old != content => {
+                    if let Err(err) = write_backup(folder, path, old, keep) {
+                        log::error!("Failed to back up {:?}: {}", path, err);
+                    }
+                }
+                _ => (),
+            }
+
+            self.contents.insert(path.to_owned(), content);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.contents.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_newest_versions() {
+        let dir = std::env::temp_dir().join("sh4der-jockey-backup-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let folder = dir.join(BACKUP_FOLDER);
+        assert!(is_backup_path(&folder.join("scene.frag.1")));
+
+        let file = dir.join("scene.frag");
+        let path = file.to_str().unwrap();
+        let mut backups = Backups::default();
+        for k in 0..5 {
+            std::fs::write(&file, k.to_string()).unwrap();
+            backups.update(&folder, [path], 3);
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        let mut versions: Vec<_> = std::fs::read_dir(&folder)
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        versions.sort();
+
+        assert_eq!(versions, vec!["1", "2", "3"]);
+    }
+}
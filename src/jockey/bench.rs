@@ -0,0 +1,151 @@
+use std::{fmt::Write, time::Instant};
+
+use anyhow::{bail, Result};
+
+use super::*;
+
+/// Settings for benchmarking a pipeline.
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub pipeline: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub frames: u32,
+    /// Frames rendered before measuring, so caches and drivers settle.
+    pub warmup: u32,
+}
+
+/// Summary of the timings of a stage over all measured frames, in
+/// milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchStats {
+    pub min: f32,
+    pub avg: f32,
+    pub p99: f32,
+}
+
+impl BenchStats {
+    pub fn from_samples(samples: &[f32]) -> Self {
+        if samples.is_empty() {
+            return Self {
+                min: 0.0,
+                avg: 0.0,
+                p99: 0.0,
+            };
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let rank = (0.99 * sorted.len() as f32).ceil() as usize;
+        Self {
+            min: sorted[0],
+            avg: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            p99: sorted[rank.max(1) - 1],
+        }
+    }
+}
+
+impl Jockey {
+    /// Renders the selected pipeline offscreen for a fixed number of frames
+    /// and reports the min, average and 99th percentile time of each stage.
+    ///
+    /// Time advances in fixed steps of 1/60s without audio input, and every
+    /// stage is waited for on the GPU, so results are comparable between
+    /// runs on the same machine.
+    pub fn run_bench(&mut self, opts: &BenchOptions) -> Result<String> {
+        if opts.frames == 0 {
+            bail!("Number of frames must be greater than zero");
+        }
+
+        self.time_step = Some(1.0 / 60.0);
+        self.time = 0.0;
+        self.time_since_build = 0.0;
+        self.frame = 0;
+        self.audio = Audio::offline(self.audio.size, 44100);
+        self.build_offscreen(opts.pipeline.as_deref(), opts.width, opts.height)?;
+        self.sync_stages = true;
+
+        for _ in 0..opts.warmup {
+            self.draw();
+        }
+
+        let stage_count = self.pipeline.stages.len();
+        let mut stage_samples = vec![Vec::with_capacity(opts.frames as _); stage_count];
+        let mut frame_samples = Vec::with_capacity(opts.frames as _);
+
+        log::info!(
+            "Benchmarking {} frames at {}x{}",
+            opts.frames,
+            opts.width,
+            opts.height
+        );
+
+        for _ in 0..opts.frames {
+            let start = Instant::now();
+            self.draw();
+            frame_samples.push(1000.0 * start.elapsed().as_secs_f32());
+
+            for (samples, stage) in stage_samples.iter_mut().zip(self.pipeline.stages.iter()) {
+                samples.push(stage.perf.latest());
+            }
+        }
+
+        let pipeline = self
+            .pipeline_files
+            .get(self.pipeline_index)
+            .map_or("<unknown>", String::as_str);
+
+        let mut report = String::new();
+        writeln!(
+            report,
+            "{} at {}x{}, {} frames ({} warmup)",
+            pipeline, opts.width, opts.height, opts.frames, opts.warmup
+        )?;
+        writeln!(
+            report,
+            "{:<24} {:>10} {:>10} {:>10}",
+            "stage", "min [ms]", "avg [ms]", "p99 [ms]"
+        )?;
+
+        let mut write_row = |name: &str, samples: &[f32]| {
+            let stats = BenchStats::from_samples(samples);
+            writeln!(
+                report,
+                "{:<24} {:>10.4} {:>10.4} {:>10.4}",
+                name, stats.min, stats.avg, stats.p99
+            )
+        };
+
+        for (k, (samples, stage)) in stage_samples
+            .iter()
+            .zip(self.pipeline.stages.iter())
+            .enumerate()
+        {
+            let name = match &stage.target {
+                Some(target) => format!("{} -> {:?}", k, target),
+                None => k.to_string(),
+            };
+            write_row(&name, samples)?;
+        }
+
+        write_row("frame", &frame_samples)?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bench_stats() {
+        let samples: Vec<f32> = (1..=200).map(|k| k as f32).collect();
+        let stats = BenchStats::from_samples(&samples);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.avg, 100.5);
+        assert_eq!(stats.p99, 198.0);
+
+        assert_eq!(BenchStats::from_samples(&[3.0]).p99, 3.0);
+    }
+}
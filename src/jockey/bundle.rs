@@ -0,0 +1,248 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, format_err, Result};
+use serde_yaml::{Mapping, Value};
+use zip::{write::FileOptions, ZipWriter};
+
+use super::{config_folder_path, expand_aliases, read_layer, Config};
+use crate::util::preprocess;
+
+/// Fields of a pipeline file which hold shader paths
+const SHADER_FIELDS: &[&str] = &["vs", "fs", "cs", "isf"];
+
+/// Fields of a pipeline file which hold paths of other files
+const FILE_FIELDS: &[&str] = &["path", "indices"];
+
+/// Files referenced by a project, keyed by their name inside the bundle.
+#[derive(Debug, Default)]
+struct Manifest {
+    files: BTreeMap<String, PathBuf>,
+    aliases: BTreeSet<String>,
+    outside: BTreeSet<String>,
+}
+
+impl Manifest {
+    /// Adds a file referenced as `reference`, which may contain path aliases.
+    ///
+    /// Returns the path of the file on disk.
+    fn add(&mut self, reference: &str, config: &Config) -> Result<PathBuf> {
+        let disk_path =
+            PathBuf::from(expand_aliases(reference, &config.paths).map_err(|e| format_err!(e))?);
+        if !disk_path.is_file() {
+            bail!("Failed to find file {:?}", reference);
+        }
+
+        // files behind an alias are stored in a folder named after it
+        let mut name = reference.to_owned();
+        for alias in config.paths.keys() {
+            let pattern = format!("${{{}}}", alias);
+            if name.contains(&pattern) {
+                name = name.replace(&pattern, alias);
+                self.aliases.insert(alias.clone());
+            }
+        }
+
+        let inside = Path::new(&name)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+
+        match inside {
+            true => {
+                let name = name.trim_start_matches("./").replace('\\', "/");
+                self.files.insert(name, disk_path.clone());
+            }
+            false => {
+                self.outside.insert(reference.to_owned());
+            }
+        }
+
+        Ok(disk_path)
+    }
+
+    /// Adds a shader along with all files it includes.
+    fn add_shader(&mut self, reference: &str, config: &Config) -> Result<()> {
+        let disk_path = self.add(reference, config)?;
+        let code = std::fs::read_to_string(&disk_path)?;
+
+        let mut lut = Vec::new();
+        preprocess(&code, reference, &mut lut).map_err(|e| format_err!(e))?;
+        for include in lut.iter().skip(1) {
+            self.add(include, config)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds a pipeline listed under `layers` along with its files.
+    fn add_layer(&mut self, reference: &str, config: &Config) -> Result<()> {
+        let disk_path = self.add(reference, config)?;
+        let object: Value = serde_yaml::from_reader(std::fs::File::open(disk_path)?)?;
+        self.add_pipeline(&object, config)
+    }
+
+    /// Walks a pipeline file and adds every file it refers to.
+    ///
+    /// Read backs are skipped, as their paths are written rather than read.
+    fn add_pipeline(&mut self, value: &Value, config: &Config) -> Result<()> {
+        match value {
+            Value::Mapping(map) => {
+                for (key, item) in map {
+                    match key.as_str() {
+                        Some("readback") => (),
+                        Some("pipeline") => {
+                            if let Some(path) = item.as_str() {
+                                self.add_layer(path, config)?;
+                            }
+                        }
+                        Some(k) if SHADER_FIELDS.contains(&k) => {
+                            if let Some(path) = item.as_str() {
+                                self.add_shader(path, config)?;
+                            }
+                        }
+                        Some(k) if FILE_FIELDS.contains(&k) => {
+                            let paths = match item {
+                                Value::Sequence(seq) => {
+                                    seq.iter().filter_map(Value::as_str).collect()
+                                }
+                                _ => item.as_str().into_iter().collect::<Vec<_>>(),
+                            };
+
+                            for path in paths {
+                                if !path.trim().starts_with("grid ") {
+                                    self.add(path, config)?;
+                                }
+                            }
+                        }
+                        _ => self.add_pipeline(item, config)?,
+                    }
+                }
+            }
+            Value::Sequence(seq) => {
+                for item in seq {
+                    self.add_pipeline(item, config)?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// Packs a pipeline file and every file it refers to into a zip archive.
+///
+/// This includes shaders and their includes, images, index and texture buffer
+/// files, the pipelines of all layers, a custom splash screen as well as the
+/// project config and the files it names. Files behind a path alias are
+/// stored in a folder named after the alias, and the bundled config points
+/// the alias there. Files outside of the project folder can't be bundled and
+/// are reported instead.
+pub fn write_project_bundle(pipeline_path: &str, out: impl AsRef<Path>) -> Result<()> {
+    let config_path = Path::new("config.yaml");
+    let config = Config::load(config_folder_path().as_deref())?;
+
+    let text = std::fs::read_to_string(pipeline_path)
+        .map_err(|e| format_err!("Failed to read {}: {}", pipeline_path, e))?;
+    let object: Value = serde_yaml::from_str(&text)?;
+
+    let mut manifest = Manifest::default();
+    manifest.add(pipeline_path, &config)?;
+    manifest.add_pipeline(&object, &config)?;
+
+    // custom splash screens
+    if Path::new("splash.frag").is_file() {
+        manifest.add_shader("splash.frag", &config)?;
+    }
+    if Path::new("splash.png").is_file() {
+        manifest.add("splash.png", &config)?;
+    }
+
+    let mut project_config = read_layer(config_path)?;
+    if let Some(map) = &project_config {
+        for field in [["grade", "lut"], ["window", "icon"]] {
+            if let Some(path) = map.get(field[0]).and_then(|s| s.get(field[1])) {
+                if let Some(path) = path.as_str() {
+                    manifest.add(path, &config)?;
+                }
+            }
+        }
+    }
+
+    // point all used aliases to their folder inside the bundle
+    if !manifest.aliases.is_empty() {
+        let map = project_config.get_or_insert_with(Mapping::new);
+        let paths: Mapping = manifest
+            .aliases
+            .iter()
+            .map(|name| (Value::from(name.as_str()), Value::from(name.as_str())))
+            .collect();
+        map.insert("paths".into(), Value::Mapping(paths));
+    }
+
+    let file = std::fs::File::create(out)?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default();
+
+    for (name, path) in manifest.files.iter() {
+        log::info!("Adding {}", name);
+        zip.start_file(name, options)?;
+        zip.write_all(&std::fs::read(path)?)?;
+    }
+
+    match (&project_config, manifest.aliases.is_empty()) {
+        (Some(_), true) => {
+            zip.start_file("config.yaml", options)?;
+            zip.write_all(&std::fs::read(config_path)?)?;
+        }
+        (Some(map), false) => {
+            zip.start_file("config.yaml", options)?;
+            zip.write_all(serde_yaml::to_string(map)?.as_bytes())?;
+        }
+        (None, _) => (),
+    }
+
+    zip.finish()?;
+
+    for reference in manifest.outside.iter() {
+        log::warn!(
+            "File {:?} lies outside of the project folder and has not been bundled",
+            reference
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn maps_aliases_into_bundle() {
+        let dir = std::env::temp_dir().join("sh4der-jockey-bundle-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("tex.png"), b"").unwrap();
+
+        let mut config = Config::default();
+        let assets = dir.to_string_lossy().into_owned();
+        config.paths.insert("ASSETS".into(), assets.clone());
+
+        let mut manifest = Manifest::default();
+        manifest.add("${ASSETS}/tex.png", &config).unwrap();
+        manifest
+            .add(&format!("{}/tex.png", assets), &config)
+            .unwrap();
+        assert!(manifest.add("${ASSETS}/missing.png", &config).is_err());
+
+        assert_eq!(
+            manifest.files.keys().collect::<Vec<_>>(),
+            vec!["ASSETS/tex.png"]
+        );
+        assert!(manifest.aliases.contains("ASSETS"));
+        assert_eq!(manifest.outside.len(), 1);
+    }
+}
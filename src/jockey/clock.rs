@@ -0,0 +1,180 @@
+use std::{
+    sync::atomic::{AtomicI32, Ordering},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Offset of the local time zone from UTC in seconds.
+static LOCAL_OFFSET: AtomicI32 = AtomicI32::new(0);
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Looks up the offset of the local time zone.
+///
+/// This has to run before any other threads are started, as the time zone
+/// can't be read safely from a multithreaded process on some platforms.
+/// Returns false if the offset is unknown, in which case UTC is shown.
+pub fn init_local_offset() -> bool {
+    match time::UtcOffset::current_local_offset() {
+        Ok(offset) => {
+            LOCAL_OFFSET.store(offset.whole_seconds(), Ordering::Relaxed);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Settings of the show clock.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockConfig {
+    /// Local time the set ends at, in seconds since midnight.
+    pub end: Option<u32>,
+    /// Minutes before the end at which the countdown turns yellow.
+    pub warn: f32,
+    /// Minutes before the end at which the countdown turns red.
+    pub alert: f32,
+    pub colors: bool,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            end: None,
+            warn: 10.0,
+            alert: 2.0,
+            colors: true,
+        }
+    }
+}
+
+/// Parses a time of day like `"23:30"` into seconds since midnight.
+pub fn parse_time_of_day(s: &str) -> Option<u32> {
+    let mut parts = s.trim().splitn(3, ':').map(str::parse::<u32>);
+    let hours = parts.next()?.ok()?;
+    let minutes = parts.next()?.ok()?;
+    let seconds = parts.next().unwrap_or(Ok(0)).ok()?;
+
+    (hours < 24 && minutes < 60 && seconds < 60).then(|| hours * 3600 + minutes * 60 + seconds)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockLevel {
+    Normal,
+    Warn,
+    Alert,
+    Over,
+}
+
+impl ClockLevel {
+    pub fn color(&self) -> [f32; 4] {
+        match self {
+            Self::Normal => [1.0, 1.0, 1.0, 1.0],
+            Self::Warn => [1.0, 0.8, 0.1, 1.0],
+            Self::Alert | Self::Over => [1.0, 0.2, 0.2, 1.0],
+        }
+    }
+}
+
+/// Keeps track of the time a set has been running and the time left until
+/// it has to end, for the performer to read at a glance.
+#[derive(Debug, Clone)]
+pub struct ShowClock {
+    pub config: ClockConfig,
+    start: Instant,
+}
+
+impl ShowClock {
+    pub fn new(config: ClockConfig) -> Self {
+        Self {
+            config,
+            start: Instant::now(),
+        }
+    }
+
+    /// Starts counting the set time from zero.
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+    }
+
+    /// Seconds since the set started.
+    pub fn elapsed(&self, now: Instant) -> i64 {
+        now.duration_since(self.start).as_secs() as _
+    }
+
+    /// Local time in seconds since midnight.
+    pub fn wall_time(now: SystemTime) -> i64 {
+        let secs = now
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+
+        (secs + LOCAL_OFFSET.load(Ordering::Relaxed) as i64).rem_euclid(DAY)
+    }
+
+    /// Seconds until the end of the set, negative once it is over.
+    ///
+    /// The end time refers to whichever day is closest, so a set can run
+    /// past midnight and an overrun shows up as negative time.
+    pub fn remaining(&self, wall_time: i64) -> Option<i64> {
+        let end = self.config.end? as i64;
+        let left = (end - wall_time).rem_euclid(DAY);
+        Some(match left > DAY / 2 {
+            true => left - DAY,
+            false => left,
+        })
+    }
+
+    pub fn level(&self, remaining: i64) -> ClockLevel {
+        match remaining as f32 / 60.0 {
+            _ if !self.config.colors => ClockLevel::Normal,
+            m if m < 0.0 => ClockLevel::Over,
+            m if m < self.config.alert => ClockLevel::Alert,
+            m if m < self.config.warn => ClockLevel::Warn,
+            _ => ClockLevel::Normal,
+        }
+    }
+}
+
+/// Formats seconds as `h:mm:ss`, with a sign for negative durations.
+pub fn format_hms(secs: i64) -> String {
+    let sign = if secs < 0 { "-" } else { "" };
+    let secs = secs.abs();
+    format!(
+        "{}{}:{:02}:{:02}",
+        sign,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_down_to_the_end() {
+        assert_eq!(parse_time_of_day("23:30"), Some(84600));
+        assert_eq!(parse_time_of_day("0:05:30"), Some(330));
+        assert_eq!(parse_time_of_day("24:00"), None);
+        assert_eq!(parse_time_of_day("late"), None);
+
+        let config = ClockConfig {
+            end: parse_time_of_day("01:00"),
+            ..ClockConfig::default()
+        };
+        let clock = ShowClock::new(config);
+
+        // the set runs past midnight
+        let at = |s: &str| parse_time_of_day(s).unwrap() as i64;
+        assert_eq!(clock.remaining(at("23:00")), Some(2 * 3600));
+        assert_eq!(clock.remaining(at("00:55")), Some(300));
+        assert_eq!(clock.remaining(at("01:10")), Some(-600));
+
+        assert_eq!(clock.level(20 * 60), ClockLevel::Normal);
+        assert_eq!(clock.level(5 * 60), ClockLevel::Warn);
+        assert_eq!(clock.level(60), ClockLevel::Alert);
+        assert_eq!(clock.level(-1), ClockLevel::Over);
+
+        assert_eq!(format_hms(3723), "1:02:03");
+        assert_eq!(format_hms(-600), "-0:10:00");
+    }
+}
@@ -1,93 +1,748 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
 use anyhow::{format_err, Result};
-use serde_yaml::Value;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_yaml::{Mapping, Value};
+
+use super::{
+    expand_yaml_aliases, merge_layer, parse_aspect, parse_time_of_day, ClockConfig, GradeConfig,
+    JackConfig, KickConfig, LineCheckConfig, ProjectionConfig, ProjectionMode, StereoMode,
+    LINE_CHECK_DELAY, MAX_AUDIO_LATENCY,
+};
+
+/// Prefix of environment variables overriding options of the config file,
+/// nested options are separated by two underscores, e.g.
+/// `SH4DER_CONFIG_WATCHDOG__THRESHOLD=5`
+const ENV_PREFIX: &str = "SH4DER_CONFIG_";
 
 #[derive(Debug, Default, Clone)]
 pub struct Config {
     pub midi_devices: Vec<String>,
+    /// MIDI note driving the `kick` uniform.
+    pub kick: Option<KickConfig>,
     pub audio_device: Option<String>,
+    /// Milliseconds the audio analysis is delayed by.
+    pub audio_latency: Option<f32>,
+    /// Records audio from JACK or PipeWire instead of `audio_device`.
+    pub jack: Option<JackConfig>,
+    pub line_check: LineCheckConfig,
+    pub clock: ClockConfig,
+    pub watchdog_threshold: Option<f32>,
+    pub watchdog_disable_stage: bool,
+    pub frame_interpolation: bool,
+    pub stereo: Option<StereoMode>,
+    pub projection: Option<ProjectionConfig>,
+    pub grade: Option<GradeConfig>,
+    /// Name the output is shared under with Spout receivers like OBS.
+    pub spout_sender: Option<String>,
+    pub output_aspect: Option<f32>,
+    pub window: WindowConfig,
+    pub watch: WatchConfig,
+    pub ui_fps: Option<f32>,
+    pub update_rate: Option<f32>,
+    /// Number of old versions kept of each shader, no backups if `None`.
+    pub backups: Option<usize>,
+    /// Size limit of the image cache in megabytes, unlimited if `None`.
+    pub cache_budget: Option<usize>,
+    /// Names for folders which differ between machines, used as `${NAME}`.
+    pub paths: HashMap<String, String>,
+    /// All options after merging the config layers, as shown in the UI.
+    pub effective: Mapping,
+    /// Config files which were found, from lowest to highest priority.
+    pub sources: Vec<PathBuf>,
 }
 
-impl Config {
-    pub fn load_or_default() -> Self {
-        match Self::load() {
-            Ok(config) => config,
+/// Settings for the output window.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub title: Option<String>,
+    pub icon: Option<String>,
+    pub borderless: bool,
+    pub always_on_top: bool,
+    pub hide_cursor: Option<f32>,
+    pub click_through: bool,
+}
+
+/// How changed files trigger rebuilds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    /// Milliseconds a file has to stay unchanged before rebuilding.
+    pub debounce: u64,
+    /// Ignore changes to files the pipeline wasn't built from.
+    pub only_dependencies: bool,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            debounce: 100,
+            only_dependencies: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct WatchdogSection {
+    threshold: Option<f32>,
+    disable_stage: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProjectionSection {
+    mode: String,
+    #[serde(default = "default_fov")]
+    fov: f32,
+    #[serde(default)]
+    native: bool,
+}
+
+fn default_fov() -> f32 {
+    180.0
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JackSection {
+    client_name: Option<String>,
+    #[serde(default)]
+    ports: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KickSection {
+    note: u8,
+    channel: Option<u8>,
+    attack: Option<f32>,
+    decay: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LineCheckSection {
+    enabled: bool,
+    delay: f32,
+    osc: Option<String>,
+}
+
+impl Default for LineCheckSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            delay: LINE_CHECK_DELAY,
+            osc: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ClockSection {
+    end: Option<String>,
+    warn: Option<f32>,
+    alert: Option<f32>,
+    colors: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GradeSection {
+    lut: String,
+    #[serde(default = "default_amount")]
+    amount: f32,
+}
+
+fn default_amount() -> f32 {
+    1.0
+}
+
+/// Collects all problems of a config file, instead of stopping at the first.
+struct Validator {
+    object: Mapping,
+    errors: Vec<String>,
+}
+
+impl Validator {
+    /// Removes an option from the file and deserializes it.
+    fn take<T: DeserializeOwned>(&mut self, key: &str) -> Option<T> {
+        let value = self.object.remove(key)?;
+        match serde_yaml::from_value(value) {
+            Ok(val) => Some(val),
             Err(err) => {
-                log::warn!("Failed to load config.yaml: {err}");
-                Default::default()
+                self.errors.push(format!("{}: {}", key, err));
+                None
             }
         }
     }
 
-    pub fn load() -> Result<Self> {
-        let mut file_path = std::env::current_dir()?;
-        file_path.push("config.yaml");
+    /// Checks an option after it has been deserialized.
+    fn check<T, U>(
+        &mut self,
+        key: &str,
+        value: Option<T>,
+        f: impl FnOnce(T) -> Result<U, String>,
+    ) -> Option<U> {
+        match f(value?) {
+            Ok(val) => Some(val),
+            Err(err) => {
+                self.errors.push(format!("{}: {}", key, err));
+                None
+            }
+        }
+    }
+}
 
-        let reader = std::fs::File::open(file_path)?;
-        let object: Value = serde_yaml::from_reader(reader)?;
+fn positive(x: f32) -> Result<f32, String> {
+    match x > 0.0 && x.is_finite() {
+        true => Ok(x),
+        false => Err(format!("expected a positive number, got {}", x)),
+    }
+}
 
-        let mut midi_devices = Vec::new();
+/// Frame and update rates are turned into intervals, which have to stay
+/// representable and reasonably long.
+fn rate(x: f32) -> Result<f32, String> {
+    match (1.0..=1000.0).contains(&x) {
+        true => Ok(x),
+        false => Err(format!(
+            "expected a rate between 1 and 1000 per second, got {}",
+            x
+        )),
+    }
+}
 
-        match object.get("midi_devices") {
-            Some(Value::Sequence(xs)) => {
-                for val in xs {
-                    match val.as_str() {
-                        Some(s) => midi_devices.push(s.to_owned()),
-                        None => {
-                            return Err(format_err!(
-                                "Expected midi_device name {:?} to be a string",
-                                val
-                            ));
-                        }
-                    }
+impl Config {
+    /// Loads the config like [`Config::load`], but only drops the options
+    /// and files which are invalid, after warning about each of them.
+    pub fn load_or_default(global_dir: Option<&Path>) -> Self {
+        let (object, sources, errors) = read_layers(global_dir);
+        for err in errors {
+            log::warn!("Skipping config file: {err}");
+        }
+
+        let (mut config, errors) = Self::validate(object);
+        for err in errors {
+            log::warn!("Ignoring config option {err}");
+        }
+
+        config.sources = sources;
+        config
+    }
+
+    /// Loads the global `config.yaml` from the config folder, followed by the
+    /// one in the project folder, which overrides options of the former.
+    pub fn load(global_dir: Option<&Path>) -> Result<Self> {
+        let (object, sources, errors) = read_layers(global_dir);
+        if let Some(err) = errors.into_iter().next() {
+            return Err(err);
+        }
+
+        let mut config = Self::from_mapping(object)?;
+        config.sources = sources;
+        Ok(config)
+    }
+
+    /// Validates all options, reporting every invalid or unknown one.
+    pub fn from_mapping(object: Mapping) -> Result<Self> {
+        match Self::validate(object) {
+            (config, errors) if errors.is_empty() => Ok(config),
+            (_, errors) => Err(format_err!("{}", errors.join("\n"))),
+        }
+    }
+
+    /// Reads all valid options, invalid and unknown ones are left at their
+    /// defaults and returned as errors.
+    pub fn validate(object: Mapping) -> (Self, Vec<String>) {
+        let effective = object.clone();
+        let mut v = Validator {
+            object,
+            errors: Vec::new(),
+        };
+
+        let paths = v.take::<HashMap<String, String>>("paths");
+        let paths = v
+            .check("paths", paths, |paths| {
+                match paths.keys().find(|name| !is_alias_name(name)) {
+                    Some(name) => Err(format!(
+                        "expected alias names to only contain letters, digits and underscores, got {:?}",
+                        name
+                    )),
+                    None => Ok(paths),
                 }
+            })
+            .unwrap_or_default();
+
+        // the remaining options may use the aliases as well
+        for (key, value) in v.object.iter_mut() {
+            if let Err(err) = expand_yaml_aliases(value, &paths) {
+                v.errors
+                    .push(format!("{}: {}", key.as_str().unwrap_or("?"), err));
             }
-            None => {}
-            s => {
-                return Err(format_err!(
-                    "Expected midi_devices to be a list of strings, got: {:?}",
-                    s
-                ))
+        }
+
+        let midi_devices = v.take::<Vec<String>>("midi_devices").unwrap_or_default();
+
+        let kick = v.take::<KickSection>("kick");
+        let kick = v.check("kick", kick, |k| {
+            let default = KickConfig::default();
+            let config = KickConfig {
+                note: k.note,
+                channel: k.channel,
+                attack: k.attack.unwrap_or(default.attack),
+                decay: k.decay.unwrap_or(default.decay),
+            };
+
+            if config.note > 127 {
+                return Err(format!(
+                    "expected note to be at most 127, got {}",
+                    config.note
+                ));
+            }
+
+            if let Some(c) = config.channel.filter(|c| !(1..=16).contains(c)) {
+                return Err(format!(
+                    "expected channel to be between 1 and 16, got {}",
+                    c
+                ));
             }
-        };
 
-        let audio_device = match object.get("audio_device") {
-            Some(Value::String(s)) => Some(s.clone()),
-            None => None,
-            s => {
-                return Err(format_err!(
-                    "Expected audio_device name to be a string, got: {:?}",
-                    s
-                ))
+            if config.attack < 0.0 || config.decay <= 0.0 {
+                return Err(format!(
+                    "expected a positive attack and decay, got {} and {}",
+                    config.attack, config.decay
+                ));
             }
+
+            Ok(config)
+        });
+        let audio_device = v.take::<String>("audio_device");
+
+        let audio_latency = v.take::<f32>("audio_latency");
+        let audio_latency = v.check("audio_latency", audio_latency, |ms| {
+            match (0.0..=MAX_AUDIO_LATENCY).contains(&ms) {
+                true => Ok(ms),
+                false => Err(format!(
+                    "expected a delay between 0 and {} ms, got {}",
+                    MAX_AUDIO_LATENCY, ms
+                )),
+            }
+        });
+
+        let jack = v.take::<JackSection>("jack").map(|j| JackConfig {
+            client_name: j
+                .client_name
+                .unwrap_or_else(|| JackConfig::default().client_name),
+            ports: j.ports,
+        });
+        let jack = v.check("jack", jack, |j| match j.ports.len() {
+            0..=2 => Ok(j),
+            n => Err(format!("expected at most two ports, got {}", n)),
+        });
+
+        let line_check = v.take::<LineCheckSection>("line_check").unwrap_or_default();
+        let line_check_delay = v.check("line_check", Some(line_check.delay), positive);
+        let line_check = LineCheckConfig {
+            enabled: line_check.enabled,
+            delay: line_check_delay.unwrap_or(LINE_CHECK_DELAY),
+            osc: line_check.osc,
         };
 
-        let mut ndi_sources = Vec::new();
-        match object.get("ndi_sources") {
-            Some(Value::Sequence(xs)) => {
-                for val in xs {
-                    match val.as_str() {
-                        Some(s) => ndi_sources.push(s.to_owned()),
+        let clock = v.take::<ClockSection>("clock").unwrap_or_default();
+        let clock = v
+            .check("clock", Some(clock), |c| {
+                let default = ClockConfig::default();
+                let end = match c.end {
+                    Some(s) => match parse_time_of_day(&s) {
+                        Some(t) => Some(t),
                         None => {
-                            return Err(format_err!(
-                                "Expected NDI source name {:?} to be a string",
-                                val
+                            return Err(format!(
+                                "expected end to be a time like \"23:30\", got {:?}",
+                                s
                             ))
                         }
-                    }
+                    },
+                    None => None,
+                };
+
+                let config = ClockConfig {
+                    end,
+                    warn: c.warn.unwrap_or(default.warn),
+                    alert: c.alert.unwrap_or(default.alert),
+                    colors: c.colors.unwrap_or(default.colors),
+                };
+
+                if config.alert < 0.0 || config.warn < config.alert {
+                    return Err(format!(
+                        "expected 0 <= alert <= warn, got {} and {}",
+                        config.alert, config.warn
+                    ));
                 }
+
+                Ok(config)
+            })
+            .unwrap_or_default();
+
+        if v.object.remove("ndi_sources").is_some() {
+            v.errors.push(
+                "ndi_sources: NDI sources are picked in the ndi section of the pipeline instead"
+                    .to_string(),
+            );
+        }
+
+        let watchdog = v.take::<WatchdogSection>("watchdog").unwrap_or_default();
+        let frame_interpolation = v.take::<bool>("frame_interpolation").unwrap_or(false);
+
+        let stereo = v.take::<String>("stereo");
+        let stereo = v
+            .check("stereo", stereo, |s| match s.as_str() {
+                "off" => Ok(None),
+                s => StereoMode::parse(s).map(Some).ok_or_else(|| {
+                    format!(
+                        "expected one of side_by_side, top_bottom, anaglyph or off, got {:?}",
+                        s
+                    )
+                }),
+            })
+            .flatten();
+
+        let projection = v.take::<ProjectionSection>("projection");
+        let projection = v.check("projection", projection, |p| {
+            let mode = ProjectionMode::parse(&p.mode).ok_or_else(|| {
+                format!(
+                    "expected mode to be one of flat, equirect or fisheye, got {:?}",
+                    p.mode
+                )
+            })?;
+
+            Ok(ProjectionConfig {
+                mode,
+                fov: p.fov,
+                native: p.native,
+            })
+        });
+
+        let grade = v.take::<GradeSection>("grade").map(|g| GradeConfig {
+            lut: g.lut,
+            amount: g.amount,
+        });
+
+        let spout_sender = v.take::<String>("spout_sender");
+
+        let output_aspect = v.take::<Value>("output_aspect");
+        let output_aspect = v.check("output_aspect", output_aspect, |val| {
+            parse_aspect(&val).ok_or_else(|| {
+                format!(
+                    "expected a ratio like \"16:9\" or a positive number, got {:?}",
+                    val
+                )
+            })
+        });
+
+        let window = v.take::<WindowConfig>("window").unwrap_or_default();
+        v.check("window", window.hide_cursor, |x| {
+            match x >= 0.0 && x.is_finite() {
+                true => Ok(()),
+                false => Err(format!("expected hide_cursor to be at least 0, got {}", x)),
             }
-            None => {}
-            Some(s) => {
-                return Err(format_err!(
-                    "Expected ndi_sources to be a list of strings, got: {:?}",
-                    s
-                ))
+        });
+
+        let watch = v.take::<WatchConfig>("watch").unwrap_or_default();
+
+        let ui_fps = v.take::<f32>("ui_fps");
+        let ui_fps = v.check("ui_fps", ui_fps, rate);
+
+        let update_rate = v.take::<f32>("update_rate");
+        let update_rate = v.check("update_rate", update_rate, rate);
+
+        let backups = v.take::<usize>("backups");
+        let backups = v.check("backups", backups, |n| match n > 0 {
+            true => Ok(n),
+            false => Err(format!("expected a positive number, got {}", n)),
+        });
+
+        let cache_budget = v.take::<usize>("cache_budget");
+
+        let watchdog_threshold = v.check("watchdog", watchdog.threshold, |x| {
+            match x > 0.0 && x.is_finite() {
+                true => Ok(x),
+                false => Err(format!(
+                    "expected a positive threshold in seconds, got {}",
+                    x
+                )),
             }
-        };
+        });
 
-        Ok(Self {
+        for (key, _) in v.object.iter() {
+            v.errors.push(format!("unknown option {:?}", key));
+        }
+
+        let config = Self {
             midi_devices,
+            kick,
             audio_device,
-        })
+            audio_latency,
+            jack,
+            line_check,
+            clock,
+            watchdog_threshold,
+            watchdog_disable_stage: watchdog.disable_stage,
+            frame_interpolation,
+            stereo,
+            projection,
+            grade,
+            spout_sender,
+            output_aspect,
+            window,
+            watch,
+            ui_fps,
+            update_rate,
+            backups,
+            cache_budget,
+            paths,
+            effective,
+            sources: Vec::new(),
+        };
+
+        (config, v.errors)
+    }
+}
+
+fn is_alias_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Merges the global and the project config file, files which can't be read
+/// are skipped and returned as errors.
+fn read_layers(global_dir: Option<&Path>) -> (Mapping, Vec<PathBuf>, Vec<anyhow::Error>) {
+    let mut object = Mapping::new();
+    let mut sources = Vec::new();
+    let mut errors = Vec::new();
+
+    let project_path = match std::env::current_dir() {
+        Ok(dir) => Some(dir.join("config.yaml")),
+        Err(err) => {
+            errors.push(format_err!("Failed to find the project folder: {}", err));
+            None
+        }
+    };
+
+    let global_path = global_dir
+        .map(|dir| dir.join("config.yaml"))
+        .filter(|global| !matches!(&project_path, Some(p) if same_file(global, p)));
+
+    for path in global_path.into_iter().chain(project_path) {
+        match read_layer(&path) {
+            Ok(Some(layer)) => {
+                merge_layer(&mut object, layer);
+                sources.push(path);
+            }
+            Ok(None) => (),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    apply_env_overrides(&mut object, std::env::vars());
+    (object, sources, errors)
+}
+
+/// Reads a config file, returns `None` if it doesn't exist.
+pub fn read_layer(path: &Path) -> Result<Option<Mapping>> {
+    let reader = match std::fs::File::open(path) {
+        Ok(s) => s,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format_err!("Failed to open {}: {}", path.display(), err)),
+    };
+
+    let value = serde_yaml::from_reader(reader)
+        .map_err(|err| format_err!("Failed to parse {}: {}", path.display(), err))?;
+
+    match value {
+        Value::Mapping(map) => Ok(Some(map)),
+        Value::Null => Ok(Some(Mapping::new())),
+        s => Err(format_err!(
+            "Expected {} to be a mapping, got: {:?}",
+            path.display(),
+            s
+        )),
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Replaces options with the values of matching environment variables.
+///
+/// Values are parsed as yaml, so `SH4DER_CONFIG_STEREO=off` and
+/// `SH4DER_CONFIG_MIDI_DEVICES=[a, b]` both work.
+fn apply_env_overrides(object: &mut Mapping, vars: impl Iterator<Item = (String, String)>) {
+    for (name, text) in vars {
+        let path = match name.strip_prefix(ENV_PREFIX) {
+            Some(s) => s.to_lowercase(),
+            None => continue,
+        };
+
+        let value = serde_yaml::from_str(&text).unwrap_or(Value::String(text));
+        let keys: Vec<_> = path.split("__").collect();
+        let (last, parents) = keys.split_last().unwrap();
+
+        let mut map = &mut *object;
+        for key in parents {
+            let entry = map
+                .entry(Value::String(key.to_string()))
+                .or_insert_with(|| Value::Mapping(Mapping::new()));
+            if !entry.is_mapping() {
+                *entry = Value::Mapping(Mapping::new());
+            }
+            map = entry.as_mapping_mut().unwrap();
+        }
+
+        log::info!("Config option {} set by {}", path, name);
+        map.insert(Value::String(last.to_string()), value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(yaml: &str) -> Result<Config> {
+        Config::from_mapping(serde_yaml::from_str(yaml).unwrap())
+    }
+
+    #[test]
+    fn parse_config() {
+        let config = parse(
+            "midi_devices: [a, b]\nstereo: anaglyph\nwatchdog: {threshold: 5}\noutput_aspect: \"4:3\"",
+        )
+        .unwrap();
+        assert_eq!(config.midi_devices, vec!["a", "b"]);
+        assert_eq!(config.stereo, Some(StereoMode::Anaglyph));
+        assert_eq!(config.watchdog_threshold, Some(5.0));
+        assert_eq!(config.output_aspect, Some(4.0 / 3.0));
+        assert!(parse("watchdog: {threshold: -2}").is_err());
+        assert!(parse("watchdog: {threshold: .inf}").is_err());
+
+        let config =
+            parse("jack: {ports: [\"Master/audio_out 1\", \"Master/audio_out 2\"]}").unwrap();
+        let jack = config.jack.unwrap();
+        assert_eq!(jack.client_name, "Sh4derJockey");
+        assert_eq!(jack.ports, ["Master/audio_out 1", "Master/audio_out 2"]);
+        assert!(parse("jack: {ports: [a, b, c]}").is_err());
+
+        let config = parse("line_check: {delay: 5, osc: \"127.0.0.1:9000\"}").unwrap();
+        assert!(config.line_check.enabled);
+        assert_eq!(config.line_check.delay, 5.0);
+        assert_eq!(config.line_check.osc.as_deref(), Some("127.0.0.1:9000"));
+        assert!(parse("line_check: {delay: 0}").is_err());
+        assert!(parse("line_check: {delay: .inf}").is_err());
+
+        assert_eq!(parse("ui_fps: 30").unwrap().ui_fps, Some(30.0));
+        assert!(parse("ui_fps: .inf").is_err());
+        assert!(parse("ui_fps: .nan").is_err());
+        assert!(parse("ui_fps: 1.0e-40").is_err());
+        assert!(parse("ui_fps: 5000").is_err());
+        assert!(parse("update_rate: 0.001").is_err());
+
+        assert_eq!(
+            parse("audio_latency: 120").unwrap().audio_latency,
+            Some(120.0)
+        );
+        assert!(parse("audio_latency: -5").is_err());
+
+        let kick = parse("kick: {note: 36, channel: 10}")
+            .unwrap()
+            .kick
+            .unwrap();
+        assert_eq!(kick.channel, Some(10));
+        assert_eq!(kick.decay, KickConfig::default().decay);
+        assert!(parse("kick: {note: 36, channel: 0}").is_err());
+        assert!(parse("kick: {note: 36, decay: 0}").is_err());
+
+        let clock = parse("clock: {end: \"23:30\", warn: 15}").unwrap().clock;
+        assert_eq!(clock.end, Some(84600));
+        assert_eq!(clock.warn, 15.0);
+        assert!(parse("clock: {end: \"25:00\"}").is_err());
+        assert!(parse("clock: {warn: 1, alert: 5}").is_err());
+    }
+
+    #[test]
+    fn reports_all_errors() {
+        let err = parse("stereo: sideways\nui_fps: -1\nwindow: {title: 3, colour: red}\nfoo: 1")
+            .unwrap_err()
+            .to_string();
+        let lines: Vec<_> = err.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", err);
+        assert!(lines[0].starts_with("stereo:"));
+        assert!(lines[3].contains("\"foo\""));
+    }
+
+    #[test]
+    fn keeps_valid_options() {
+        let object = serde_yaml::from_str(
+            "stereo: sideways\nui_fps: 30\nndi_sources: [cam]\nclock: {end: \"23:30\"}",
+        )
+        .unwrap();
+        let (config, errors) = Config::validate(object);
+        assert_eq!(config.stereo, None);
+        assert_eq!(config.ui_fps, Some(30.0));
+        assert_eq!(config.clock.end, Some(84600));
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors.iter().any(|e| e.starts_with("ndi_sources:")));
+    }
+
+    #[test]
+    fn project_overrides_global() {
+        let mut object: Mapping = serde_yaml::from_str(
+            "audio_device: Loopback\nwatchdog: {threshold: 2, disable_stage: true}",
+        )
+        .unwrap();
+        let layer = serde_yaml::from_str("watchdog: {threshold: 5}\nstereo: off").unwrap();
+
+        merge_layer(&mut object, layer);
+        let config = Config::from_mapping(object).unwrap();
+        assert_eq!(config.audio_device.as_deref(), Some("Loopback"));
+        assert_eq!(config.watchdog_threshold, Some(5.0));
+        assert!(config.watchdog_disable_stage);
+        assert_eq!(config.stereo, None);
+    }
+
+    #[test]
+    fn path_aliases() {
+        let config =
+            parse("paths: {LOOKS: /mnt/looks}\ngrade: {lut: \"${LOOKS}/film.cube\"}").unwrap();
+        assert_eq!(config.grade.unwrap().lut, "/mnt/looks/film.cube");
+        assert!(parse("paths: {MY-LOOKS: /mnt/looks}").is_err());
+    }
+
+    #[test]
+    fn env_overrides() {
+        let mut object: Mapping = serde_yaml::from_str("watchdog: {threshold: 2}").unwrap();
+        let vars = vec![
+            (
+                "SH4DER_CONFIG_WATCHDOG__THRESHOLD".to_string(),
+                "5".to_string(),
+            ),
+            (
+                "SH4DER_CONFIG_AUDIO_DEVICE".to_string(),
+                "Loopback".to_string(),
+            ),
+            ("SH4DER_DIR".to_string(), "/tmp".to_string()),
+        ];
+
+        apply_env_overrides(&mut object, vars.into_iter());
+        let config = Config::from_mapping(object).unwrap();
+        assert_eq!(config.watchdog_threshold, Some(5.0));
+        assert_eq!(config.audio_device.as_deref(), Some("Loopback"));
     }
 }
@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+use glutin::window::Window;
+
+/// Hides the mouse cursor over the output window once it stops moving.
+#[derive(Debug)]
+pub struct CursorHider {
+    pub timeout: Option<Duration>,
+    last_move: Instant,
+    hidden: bool,
+}
+
+impl CursorHider {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            last_move: Instant::now(),
+            hidden: false,
+        }
+    }
+
+    /// Shows the cursor again and restarts the timeout.
+    pub fn moved(&mut self, window: &Window) {
+        self.last_move = Instant::now();
+        if self.hidden {
+            window.set_cursor_visible(true);
+            self.hidden = false;
+        }
+    }
+
+    /// Hides the cursor if it hasn't moved for long enough.
+    pub fn update(&mut self, window: &Window) {
+        let expired = match self.timeout {
+            Some(timeout) => self.last_move.elapsed() > timeout,
+            None => false,
+        };
+
+        if expired != self.hidden {
+            window.set_cursor_visible(!expired);
+            self.hidden = expired;
+        }
+    }
+}
+
+/// Lets mouse input pass through the window to whatever is behind it.
+#[cfg(windows)]
+pub fn set_click_through(window: &Window, enabled: bool) {
+    use glutin::platform::windows::WindowExtWindows;
+    use winapi::{shared::windef::HWND, um::winuser};
+
+    let hwnd = window.hwnd() as HWND;
+    let flags = winuser::WS_EX_TRANSPARENT | winuser::WS_EX_LAYERED;
+
+    unsafe {
+        let style = winuser::GetWindowLongW(hwnd, winuser::GWL_EXSTYLE) as u32;
+        let style = match enabled {
+            true => style | flags,
+            false => style & !flags,
+        };
+
+        winuser::SetWindowLongW(hwnd, winuser::GWL_EXSTYLE, style as _);
+
+        // layered windows stay invisible until their opacity is set
+        if enabled {
+            winuser::SetLayeredWindowAttributes(hwnd, 0, 255, winuser::LWA_ALPHA);
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub fn set_click_through(_window: &Window, enabled: bool) {
+    if enabled {
+        log::warn!("Click-through windows are only supported on Windows");
+    }
+}
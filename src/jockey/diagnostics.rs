@@ -0,0 +1,191 @@
+use std::{
+    collections::VecDeque,
+    ffi::CStr,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, TryLockError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use zip::{write::FileOptions, ZipWriter};
+
+/// Number of log lines kept around for the diagnostics bundle
+pub const LOG_TAIL_LINES: usize = 1000;
+
+lazy_static! {
+    static ref DIAGNOSTICS: Mutex<Diagnostics> = Mutex::new(Diagnostics::default());
+}
+
+#[derive(Debug, Default)]
+struct Diagnostics {
+    log: VecDeque<String>,
+    gl_info: Option<String>,
+    pipeline_path: Option<PathBuf>,
+    last_error: Option<String>,
+}
+
+/// Locks the global diagnostics state.
+///
+/// This may be called from within the panic hook, so a poisoned lock is
+/// recovered instead of propagated. Returns `None` if the lock is held by
+/// the current thread already.
+fn lock() -> Option<MutexGuard<'static, Diagnostics>> {
+    match DIAGNOSTICS.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// A log target which keeps the last few lines in memory.
+#[derive(Debug)]
+pub struct LogTail;
+
+impl Write for LogTail {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(mut diag) = lock() {
+            for line in String::from_utf8_lossy(buf).lines() {
+                if diag.log.len() >= LOG_TAIL_LINES {
+                    diag.log.pop_front();
+                }
+                diag.log.push_back(line.to_owned());
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Records information about the current OpenGL context.
+///
+/// Must be called with a current OpenGL context.
+pub fn record_gl_info() {
+    fn get_string(name: gl::types::GLenum) -> String {
+        unsafe {
+            let ptr = gl::GetString(name);
+            if ptr.is_null() {
+                return "<unknown>".into();
+            }
+
+            CStr::from_ptr(ptr as _).to_string_lossy().into_owned()
+        }
+    }
+
+    let info = format!(
+        "GL vendor: {}\nGL renderer: {}\nGL version: {}\nGLSL version: {}\n",
+        get_string(gl::VENDOR),
+        get_string(gl::RENDERER),
+        get_string(gl::VERSION),
+        get_string(gl::SHADING_LANGUAGE_VERSION),
+    );
+
+    log::info!("{}", info.trim_end());
+    if let Some(mut diag) = lock() {
+        diag.gl_info = Some(info);
+    }
+}
+
+pub fn record_pipeline_path(path: impl AsRef<Path>) {
+    if let Some(mut diag) = lock() {
+        diag.pipeline_path = Some(path.as_ref().to_owned());
+    }
+}
+
+pub fn record_error(error: Option<&str>) {
+    if let Some(mut diag) = lock() {
+        diag.last_error = error.map(str::to_owned);
+    }
+}
+
+/// Generates a unique file name for a new diagnostics bundle.
+pub fn diagnostics_file_name() -> String {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    format!("sh4der-jockey-diagnostics-{}.zip", stamp)
+}
+
+/// Writes all collected diagnostics into a zip file for bug reports.
+///
+/// The bundle contains the tail of the log, information about the system
+/// and the OpenGL driver, the last build error as well as the project
+/// config file and the active pipeline file, if present.
+pub fn write_diagnostics_bundle(path: impl AsRef<Path>, panic_message: Option<&str>) -> Result<()> {
+    let diag = match lock() {
+        Some(s) => s,
+        None => anyhow::bail!("Diagnostics are currently locked"),
+    };
+
+    let file = std::fs::File::create(path)?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default();
+
+    let mut system = format!(
+        "Version: {} (commit {})\nOS: {} ({})\n",
+        env!("CARGO_PKG_VERSION"),
+        env!("VERGEN_GIT_SHA"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+    );
+    if let Some(info) = &diag.gl_info {
+        system.push_str(info);
+    }
+
+    zip.start_file("system.txt", options)?;
+    zip.write_all(system.as_bytes())?;
+
+    zip.start_file("log.txt", options)?;
+    for line in diag.log.iter() {
+        writeln!(zip, "{}", line)?;
+    }
+
+    if let Some(msg) = panic_message {
+        zip.start_file("panic.txt", options)?;
+        zip.write_all(msg.as_bytes())?;
+    }
+
+    if let Some(err) = &diag.last_error {
+        zip.start_file("last_error.txt", options)?;
+        zip.write_all(err.as_bytes())?;
+    }
+
+    // the pipeline may live anywhere, so only its file name is kept
+    let mut files = vec![(PathBuf::from("config.yaml"), "config.yaml".to_string())];
+    if let Some(path) = &diag.pipeline_path {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        files.push((path.clone(), format!("pipeline/{}", name)));
+    }
+
+    for (path, name) in files {
+        if let Ok(data) = std::fs::read(&path) {
+            zip.start_file(name, options)?;
+            zip.write_all(&data)?;
+        }
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// Installs a panic hook which writes a diagnostics bundle before
+/// handing over to the default hook.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let path = diagnostics_file_name();
+        match write_diagnostics_bundle(&path, Some(&info.to_string())) {
+            Ok(()) => eprintln!("Diagnostics have been written to {}", path),
+            Err(err) => eprintln!("Failed to write diagnostics: {}", err),
+        }
+
+        default_hook(info);
+    }));
+}
@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+use glutin::{
+    dpi::PhysicalPosition,
+    monitor::MonitorHandle,
+    window::{Fullscreen, Window},
+};
+
+/// Time between two checks for connected monitors
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Moves the output window back onto its monitor when it gets reconnected.
+///
+/// Winit doesn't report monitors being plugged in or out, so the list of
+/// connected monitors is polled instead. As long as the monitor the window
+/// was last seen on is connected, its name, position and fullscreen state
+/// are remembered. Once that monitor disappears, the window usually ends up
+/// on another screen, and it is put back as soon as the monitor returns.
+#[derive(Debug)]
+pub struct DisplayWatch {
+    monitor: Option<String>,
+    position: Option<PhysicalPosition<i32>>,
+    fullscreen: bool,
+    connected: Option<Vec<String>>,
+    last_check: Instant,
+}
+
+impl DisplayWatch {
+    pub fn new() -> Self {
+        Self {
+            monitor: None,
+            position: None,
+            fullscreen: false,
+            connected: None,
+            last_check: Instant::now(),
+        }
+    }
+
+    pub fn update(&mut self, window: &Window) {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return;
+        }
+
+        self.last_check = Instant::now();
+
+        let monitors: Vec<MonitorHandle> = window.available_monitors().collect();
+        let connected: Vec<String> = monitors.iter().filter_map(|m| m.name()).collect();
+
+        let previous = self
+            .connected
+            .replace(connected.clone())
+            .unwrap_or_default();
+        if connected != previous && !previous.is_empty() {
+            for name in connected.iter().filter(|n| !previous.contains(n)) {
+                log::info!("Monitor {:?} connected", name);
+            }
+            for name in previous.iter().filter(|n| !connected.contains(n)) {
+                log::warn!("Monitor {:?} disconnected", name);
+            }
+
+            self.restore(window, &monitors);
+            return;
+        }
+
+        // only remember the window state while its monitor is still around
+        let remembered_is_connected = match &self.monitor {
+            Some(name) => connected.contains(name),
+            None => true,
+        };
+
+        if remembered_is_connected {
+            self.monitor = window.current_monitor().and_then(|m| m.name());
+            self.position = window.outer_position().ok();
+            self.fullscreen = window.fullscreen().is_some();
+        }
+    }
+
+    fn restore(&self, window: &Window, monitors: &[MonitorHandle]) {
+        let name = match &self.monitor {
+            Some(s) => s,
+            None => return,
+        };
+
+        let current = window.current_monitor().and_then(|m| m.name());
+        if current.as_ref() == Some(name) {
+            return;
+        }
+
+        let monitor = match monitors.iter().find(|m| m.name().as_ref() == Some(name)) {
+            Some(m) => m.clone(),
+            None => return,
+        };
+
+        log::info!("Moving output window back to monitor {:?}", name);
+        if self.fullscreen {
+            window.set_fullscreen(Some(Fullscreen::Borderless(Some(monitor))));
+        } else if is_wayland(window) {
+            // windows can't place themselves on wayland
+            log::warn!("Can't move a window on Wayland, use fullscreen instead");
+        } else if let Some(position) = self.position {
+            window.set_fullscreen(None);
+            window.set_outer_position(position);
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn is_wayland(window: &Window) -> bool {
+    use glutin::platform::unix::WindowExtUnix;
+    window.wayland_surface().is_some()
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn is_wayland(_window: &Window) -> bool {
+    false
+}
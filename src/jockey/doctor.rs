@@ -0,0 +1,234 @@
+use std::{
+    ffi::CStr,
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use glutin::ContextBuilder;
+use midir::MidiInput;
+
+use super::{config_folder_path, create_event_loop, Config, JackInput, Ndi, WinitBackend};
+use crate::util::{detect_gl_version, supports_compute, supports_images, RingBuffer};
+
+/// Extensions which some features of the tool rely on
+const GL_EXTENSIONS: &[&str] = &[
+    "GL_ARB_compute_shader",
+    "GL_ARB_shader_image_load_store",
+    "GL_ARB_shader_storage_buffer_object",
+    "GL_KHR_debug",
+];
+
+/// Results of the self test, one line per check.
+#[derive(Debug, Default)]
+struct Report {
+    text: String,
+    problems: usize,
+}
+
+impl Report {
+    fn section(&mut self, name: &str) {
+        writeln!(self.text, "\n== {} ==", name).unwrap();
+    }
+
+    fn ok(&mut self, message: impl AsRef<str>) {
+        writeln!(self.text, "[ok]   {}", message.as_ref()).unwrap();
+    }
+
+    fn warn(&mut self, message: impl AsRef<str>) {
+        self.problems += 1;
+        writeln!(self.text, "[warn] {}", message.as_ref()).unwrap();
+    }
+
+    fn fail(&mut self, message: impl AsRef<str>) {
+        self.problems += 1;
+        writeln!(self.text, "[fail] {}", message.as_ref()).unwrap();
+    }
+}
+
+fn check_gl(report: &mut Report, backend: Option<WinitBackend>) {
+    report.section("OpenGL");
+
+    let events_loop = create_event_loop(backend);
+    let window_builder = glutin::window::WindowBuilder::new().with_visible(false);
+
+    // request the same context as the tool does
+    #[cfg(target_os = "macos")]
+    let context_builder = ContextBuilder::new()
+        .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (4, 1)))
+        .with_gl_profile(glutin::GlProfile::Core);
+    #[cfg(not(target_os = "macos"))]
+    let context_builder = ContextBuilder::new().with_gl(glutin::GlRequest::Latest);
+
+    let context = match context_builder.build_windowed(window_builder, &events_loop) {
+        Ok(s) => s,
+        Err(err) => return report.fail(format!("Failed to create a context: {}", err)),
+    };
+
+    let context = match unsafe { context.make_current() } {
+        Ok(s) => s,
+        Err((_, err)) => return report.fail(format!("Failed to activate the context: {}", err)),
+    };
+
+    gl::load_with(|s| context.get_proc_address(s) as _);
+
+    let get_string = |name| unsafe {
+        let ptr = gl::GetString(name);
+        match ptr.is_null() {
+            true => "<unknown>".to_owned(),
+            false => CStr::from_ptr(ptr as _).to_string_lossy().into_owned(),
+        }
+    };
+
+    report.ok(format!("Vendor: {}", get_string(gl::VENDOR)));
+    report.ok(format!("Renderer: {}", get_string(gl::RENDERER)));
+
+    let (major, minor) = detect_gl_version();
+    let version = format!("Version: {}.{} ({})", major, minor, get_string(gl::VERSION));
+    match (supports_compute(), supports_images()) {
+        (true, _) => report.ok(version),
+        (false, true) => report.warn(format!("{}, compute shaders require OpenGL 4.3", version)),
+        (false, false) => report.warn(format!(
+            "{}, compute shaders and images require OpenGL 4.3",
+            version
+        )),
+    }
+
+    let mut count = 0;
+    unsafe { gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count) };
+    let extensions: Vec<String> = (0..count.max(0) as u32)
+        .filter_map(|k| unsafe {
+            let ptr = gl::GetStringi(gl::EXTENSIONS, k);
+            (!ptr.is_null()).then(|| CStr::from_ptr(ptr as _).to_string_lossy().into_owned())
+        })
+        .collect();
+
+    report.ok(format!("{} extensions available", extensions.len()));
+    for name in GL_EXTENSIONS {
+        match extensions.iter().any(|ext| ext == name) {
+            true => report.ok(format!("Extension {}", name)),
+            false => report.warn(format!("Extension {} is missing", name)),
+        }
+    }
+}
+
+fn check_audio(report: &mut Report, config: &Config) {
+    report.section("Audio");
+
+    let host = cpal::default_host();
+    report.ok(format!("Host: {:?}", host.id()));
+
+    match host.default_input_device().and_then(|dev| dev.name().ok()) {
+        Some(name) => report.ok(format!("Default input: {}", name)),
+        None => report.warn("No default input device"),
+    }
+
+    let names: Vec<String> = match host.input_devices() {
+        Ok(devices) => devices.filter_map(|dev| dev.name().ok()).collect(),
+        Err(err) => return report.fail(format!("Failed to list input devices: {}", err)),
+    };
+
+    for name in names.iter() {
+        report.ok(format!("Input device: {}", name));
+    }
+
+    if let Some(wanted) = &config.audio_device {
+        match names.iter().any(|name| name.contains(wanted.as_str())) {
+            true => report.ok(format!("Configured device {:?} found", wanted)),
+            false => report.fail(format!("Configured device {:?} not found", wanted)),
+        }
+    }
+
+    if let Some(jack) = &config.jack {
+        let buffer = || Arc::new(Mutex::new(RingBuffer::new(1)));
+        match JackInput::connect(jack, buffer(), buffer()) {
+            Ok(input) => report.ok(format!(
+                "JACK ports {:?} at {} Hz",
+                input.sources, input.sample_rate
+            )),
+            Err(err) => report.fail(err),
+        }
+    }
+}
+
+fn check_midi(report: &mut Report, config: &Config) {
+    report.section("MIDI");
+
+    let midi_in = match MidiInput::new("Sh4derJockey") {
+        Ok(s) => s,
+        Err(err) => return report.fail(format!("Failed to create MIDI input: {}", err)),
+    };
+
+    let names: Vec<String> = midi_in
+        .ports()
+        .iter()
+        .filter_map(|port| midi_in.port_name(port).ok())
+        .collect();
+
+    if names.is_empty() {
+        report.warn("No MIDI input ports found");
+    }
+
+    for name in names.iter() {
+        report.ok(format!("Input port: {}", name));
+    }
+
+    for wanted in config.midi_devices.iter() {
+        match names.iter().any(|name| name.contains(wanted.as_str())) {
+            true => report.ok(format!("Configured device {:?} found", wanted)),
+            false => report.fail(format!("Configured device {:?} not found", wanted)),
+        }
+    }
+}
+
+/// Checks graphics, audio, MIDI and NDI support of this machine.
+///
+/// Returns a human readable report.
+pub fn run_doctor(backend: Option<WinitBackend>) -> String {
+    let mut report = Report::default();
+    writeln!(report.text, "Sh4derJockey self test").unwrap();
+    writeln!(
+        report.text,
+        "OS: {} ({})",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+    .unwrap();
+
+    let config_folder = config_folder_path();
+    report.section("Config");
+    let config = match Config::load(config_folder.as_deref()) {
+        Ok(config) => {
+            match config.sources.as_slice() {
+                [] => report.ok("No config file found, using defaults"),
+                sources => {
+                    for path in sources {
+                        report.ok(format!("Loaded {}", path.to_string_lossy()));
+                    }
+                }
+            }
+            config
+        }
+        Err(err) => {
+            report.fail(format!("Invalid config: {}", err));
+            Config::default()
+        }
+    };
+
+    check_gl(&mut report, backend);
+    check_audio(&mut report, &config);
+    check_midi(&mut report, &config);
+
+    report.section("NDI");
+    match Ndi::with_config_path(config_folder).is_available() {
+        true => report.ok("NDI runtime found"),
+        false => report.warn("NDI runtime not found, NDI sources are disabled"),
+    }
+
+    match report.problems {
+        0 => writeln!(report.text, "\nNo problems found").unwrap(),
+        n => writeln!(report.text, "\n{} problem(s) found", n).unwrap(),
+    }
+
+    report.text
+}
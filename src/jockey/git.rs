@@ -0,0 +1,111 @@
+use std::{
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+
+/// State of the git repository containing the project folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitStatus {
+    pub branch: String,
+    pub changes: usize,
+}
+
+impl GitStatus {
+    pub fn is_dirty(&self) -> bool {
+        self.changes != 0
+    }
+}
+
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git").args(args).output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parses the output of `git status --porcelain --branch`.
+fn parse_status(text: &str) -> GitStatus {
+    let mut lines = text.lines();
+    let header = lines.next().unwrap_or_default();
+    let header = header.trim_start_matches("## ");
+    let header = header.trim_start_matches("No commits yet on ");
+    let branch = header.split("...").next().unwrap_or_default();
+    let branch = branch.split(' ').next().unwrap_or_default().to_owned();
+
+    let changes = lines.filter(|line| !line.is_empty()).count();
+    GitStatus { branch, changes }
+}
+
+/// Returns the state of the repository in the working directory, or `None`
+/// if the project isn't tracked by git.
+pub fn git_status() -> Option<GitStatus> {
+    git(&["status", "--porcelain", "--branch"])
+        .ok()
+        .map(|text| parse_status(&text))
+}
+
+/// Formats a unix timestamp as a UTC date and time.
+fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // convert days since 1970-01-01 to a civil date
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Commits all files of the project with a timestamp message.
+///
+/// Returns the commit message.
+pub fn commit_snapshot() -> Result<String> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let message = format!("Snapshot {} UTC", format_timestamp(secs));
+    git(&["add", "--all", "."])?;
+    git(&["commit", "--message", &message])?;
+    Ok(message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_status() {
+        let text = "## main...origin/main [ahead 1]\n M scene.frag\n?? new.frag\n";
+        let status = parse_status(text);
+        assert_eq!(status.branch, "main");
+        assert_eq!(status.changes, 2);
+
+        let status = parse_status("## No commits yet on live\n");
+        assert_eq!(status.branch, "live");
+        assert!(!status.is_dirty());
+
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(1709210096), "2024-02-29 12:34:56");
+    }
+}
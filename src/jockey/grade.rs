@@ -0,0 +1,89 @@
+use std::ffi::CString;
+
+use gl::types::*;
+use lazy_static::lazy_static;
+
+use crate::util::*;
+
+lazy_static! {
+    static ref IMAGE_NAME: CString = CString::new("image").unwrap();
+    static ref LUT_NAME: CString = CString::new("lut").unwrap();
+    static ref LUT_SIZE_NAME: CString = CString::new("lut_size").unwrap();
+    static ref DOMAIN_MIN_NAME: CString = CString::new("domain_min").unwrap();
+    static ref DOMAIN_MAX_NAME: CString = CString::new("domain_max").unwrap();
+    static ref AMOUNT_NAME: CString = CString::new("amount").unwrap();
+}
+
+/// Color grading settings from the config file
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradeConfig {
+    pub lut: String,
+    pub amount: f32,
+}
+
+/// Applies a color lookup table to the output.
+///
+/// The final image is rendered into an intermediate buffer instead, which
+/// is then mapped through the table by a built-in final pass.
+#[derive(Debug)]
+pub struct Grade {
+    pub config: GradeConfig,
+    lut: CubeLut,
+    lut_tex: Texture3D,
+    buffer: FrameBuffer,
+    pass: FullscreenPass,
+}
+
+impl Grade {
+    pub fn new(config: GradeConfig, width: u32, height: u32) -> Result<Self, String> {
+        const GRADE_FRAG: &str = include_str!("shaders/grade.frag");
+
+        let lut = CubeLut::open(&config.lut)?;
+        let lut_tex = lut.to_texture();
+
+        let pass = FullscreenPass::new(GRADE_FRAG).unwrap();
+
+        Ok(Self {
+            config,
+            lut,
+            lut_tex,
+            buffer: FrameBuffer::new(width, height),
+            pass,
+        })
+    }
+
+    /// Resizes the intermediate buffer, if the output size has changed.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let res = self.buffer.resolution();
+        if res[0] == width && res[1] == height {
+            return;
+        }
+
+        self.buffer = FrameBuffer::new(width, height);
+    }
+
+    pub fn framebuffer(&self) -> GLuint {
+        self.buffer.fb_id
+    }
+
+    /// Draws the graded intermediate buffer.
+    pub fn apply(&self, vao: GLuint, target_fb: GLuint, width: u32, height: u32) {
+        self.pass
+            .draw(vao, target_fb, width, height, |pass| unsafe {
+                gl::ActiveTexture(gl::TEXTURE0);
+                self.buffer.bind(0);
+                gl::Uniform1i(pass.location(&IMAGE_NAME), 0);
+
+                gl::ActiveTexture(gl::TEXTURE1);
+                self.lut_tex.bind(1);
+                gl::Uniform1i(pass.location(&LUT_NAME), 1);
+
+                let [r0, g0, b0] = self.lut.domain_min;
+                let [r1, g1, b1] = self.lut.domain_max;
+                gl::Uniform1f(pass.location(&LUT_SIZE_NAME), self.lut.size as _);
+                gl::Uniform3f(pass.location(&DOMAIN_MIN_NAME), r0, g0, b0);
+                gl::Uniform3f(pass.location(&DOMAIN_MAX_NAME), r1, g1, b1);
+                gl::Uniform1f(pass.location(&AMOUNT_NAME), self.config.amount);
+            });
+    }
+}
@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+
+use gl::types::*;
+use glutin::{dpi::PhysicalSize, ContextBuilder, PossiblyCurrent};
+use sh4der_jockey_core::Engine;
+
+use crate::util::*;
+
+/// Resolution all test frames are rendered at.
+const SIZE: (u32, u32) = (64, 32);
+
+/// Set for the child processes rendering the example pipelines.
+const EXAMPLE_VAR: &str = "SH4DER_JOCKEY_TEST_EXAMPLE";
+
+/// An OpenGL context without a window.
+///
+/// The event loop is only set if the context needed one to be created.
+struct Headless {
+    _context: glutin::Context<PossiblyCurrent>,
+    _events_loop: Option<glutin::event_loop::EventLoop<()>>,
+}
+
+type NotCurrentContext = (
+    glutin::Context<glutin::NotCurrent>,
+    Option<glutin::event_loop::EventLoop<()>>,
+);
+
+/// Creates a headless context, preferring one which doesn't need a display
+/// server at all.
+fn create_context() -> Result<Headless, String> {
+    let (context, events_loop) = build_context(PhysicalSize::new(SIZE.0, SIZE.1))?;
+    let context = unsafe { context.make_current() }.map_err(|(_, e)| e.to_string())?;
+    gl::load_with(|s| context.get_proc_address(s) as _);
+    detect_gl_version();
+
+    Ok(Headless {
+        _context: context,
+        _events_loop: events_loop,
+    })
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn build_context(size: PhysicalSize<u32>) -> Result<NotCurrentContext, String> {
+    use glutin::platform::unix::{EventLoopExtUnix, HeadlessContextExt};
+
+    let osmesa_err = match ContextBuilder::new().build_osmesa(size) {
+        Ok(s) => return Ok((s, None)),
+        Err(err) => err,
+    };
+
+    let events_loop = glutin::event_loop::EventLoop::new_x11_any_thread()
+        .map_err(|e| format!("no OSMesa ({}) and no X server ({})", osmesa_err, e))?;
+    let context = ContextBuilder::new()
+        .build_headless(&events_loop, size)
+        .map_err(|e| e.to_string())?;
+    Ok((context, Some(events_loop)))
+}
+
+#[cfg(target_os = "windows")]
+fn build_context(size: PhysicalSize<u32>) -> Result<NotCurrentContext, String> {
+    use glutin::platform::windows::EventLoopExtWindows;
+
+    let events_loop = glutin::event_loop::EventLoop::new_any_thread();
+    let context = ContextBuilder::new()
+        .build_headless(&events_loop, size)
+        .map_err(|e| e.to_string())?;
+    Ok((context, Some(events_loop)))
+}
+
+/// Event loops only run on the main thread on macOS, which tests don't run on.
+#[cfg(not(any(all(unix, not(target_os = "macos")), target_os = "windows")))]
+fn build_context(_size: PhysicalSize<u32>) -> Result<NotCurrentContext, String> {
+    Err("tests can't create a context on this platform".into())
+}
+
+/// Builds the pipeline at `path` and reads back its first frame, with rows
+/// from bottom to top.
+fn render_frame(path: &Path) -> Result<Vec<u8>, String> {
+    let (width, height) = SIZE;
+    let mut engine = Engine::new();
+    engine.load(path, width, height)?;
+
+    let mut pixels = vec![0; 4 * width as usize * height as usize];
+    unsafe {
+        let mut tex = 0;
+        gl::GenTextures(1, &mut tex);
+        gl::BindTexture(gl::TEXTURE_2D, tex);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as _,
+            width as _,
+            height as _,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            std::ptr::null(),
+        );
+
+        let mut fbo = 0;
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            tex,
+            0,
+        );
+        gl_debug_check!();
+
+        engine.render(fbo, width, height, 1.0 / 60.0);
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(
+            0,
+            0,
+            width as _,
+            height as _,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut GLvoid,
+        );
+        gl_debug_check!();
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl::DeleteFramebuffers(1, &fbo);
+        gl::DeleteTextures(1, &tex);
+    }
+
+    Ok(pixels)
+}
+
+fn test_data(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+/// Renders a gradient and compares it to the checked in reference image.
+///
+/// Skipped if no OpenGL context can be created, e.g. on a machine without
+/// a GPU, OSMesa or a display server.
+#[test]
+fn renders_gradient() {
+    let _headless = match create_context() {
+        Ok(s) => s,
+        Err(err) => return eprintln!("skipping GL test: {}", err),
+    };
+
+    let dir = std::env::temp_dir().join(format!("sh4der-jockey-gradient-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let pipeline = dir.join("pipeline.yaml");
+    let frag = test_data("gradient.frag");
+    std::fs::write(&pipeline, format!("stages:\n  - fs: {:?}\n", frag)).unwrap();
+
+    let pixels = render_frame(&pipeline).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let reference = image::open(test_data("gradient.png"))
+        .unwrap()
+        .flipv()
+        .to_rgba8();
+    assert_eq!(reference.dimensions(), SIZE);
+
+    let max_diff = pixels
+        .iter()
+        .zip(reference.as_raw().iter())
+        .map(|(&a, &b)| (a as i32 - b as i32).abs())
+        .max()
+        .unwrap();
+    assert!(
+        max_diff <= 2,
+        "frame differs from reference image by {}",
+        max_diff
+    );
+}
+
+/// Checks that all example pipelines build and render without errors.
+///
+/// Files in a pipeline are relative to the working directory, so every
+/// example is rendered by running this test again in a child process
+/// started in the example's folder.
+#[test]
+fn renders_examples() {
+    let _headless = match create_context() {
+        Ok(s) => s,
+        Err(err) => return eprintln!("skipping GL test: {}", err),
+    };
+
+    if let Ok(example) = std::env::var(EXAMPLE_VAR) {
+        if example.ends_with("compute") && !supports_compute() {
+            return;
+        }
+
+        if let Err(err) = render_frame(Path::new("pipeline.yaml")) {
+            panic!("Example {:?} failed: {}", example, err);
+        }
+
+        return;
+    }
+
+    let examples = [
+        "example",
+        "example/audio",
+        "example/blossom",
+        "example/bpm",
+        "example/compute",
+        "example/vertex",
+    ];
+
+    for example in examples.iter() {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(&[
+                "--exact",
+                "jockey::headless::renders_examples",
+                "--nocapture",
+            ])
+            .env(EXAMPLE_VAR, example)
+            .current_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join(example))
+            .output()
+            .unwrap();
+
+        assert!(
+            output.status.success(),
+            "Example {:?} failed:\n{}",
+            example,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::time::Instant;
+
+/// Which MIDI note drives the `kick` uniform and how it responds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KickConfig {
+    pub note: u8,
+    /// MIDI channel from 1 to 16, any channel if `None`.
+    pub channel: Option<u8>,
+    /// Seconds until the envelope reaches the velocity of the note.
+    pub attack: f32,
+    /// Seconds until the envelope falls back to zero.
+    pub decay: f32,
+}
+
+impl Default for KickConfig {
+    fn default() -> Self {
+        Self {
+            note: 36,
+            channel: None,
+            attack: 0.005,
+            decay: 0.3,
+        }
+    }
+}
+
+/// An attack/decay envelope triggered by MIDI notes, e.g. from a kick
+/// trigger in the DJ booth, for when the audio feed is not reliable.
+#[derive(Debug, Clone)]
+pub struct KickEnvelope {
+    pub config: KickConfig,
+    start: f32,
+    velocity: f32,
+    hit: Option<Instant>,
+}
+
+impl KickEnvelope {
+    pub fn new(config: KickConfig) -> Self {
+        Self {
+            config,
+            start: 0.0,
+            velocity: 0.0,
+            hit: None,
+        }
+    }
+
+    /// Whether a note on the given zero-based channel triggers the envelope.
+    pub fn matches(&self, channel: u8, key: u8) -> bool {
+        key == self.config.note && self.config.channel.map_or(true, |c| c == channel + 1)
+    }
+
+    /// Starts the envelope from its current level, so quick hits don't jump.
+    pub fn trigger(&mut self, velocity: f32, now: Instant) {
+        self.start = self.value(now);
+        self.velocity = velocity;
+        self.hit = Some(now);
+    }
+
+    pub fn value(&self, now: Instant) -> f32 {
+        let t = match self.hit {
+            Some(hit) => now.duration_since(hit).as_secs_f32(),
+            None => return 0.0,
+        };
+
+        let attack = self.config.attack;
+        if t < attack {
+            return self.start + (self.velocity - self.start) * t / attack;
+        }
+
+        let s = ((t - attack) / self.config.decay.max(1e-3)).min(1.0);
+        self.velocity * (1.0 - s) * (1.0 - s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn attack_and_decay() {
+        let config = KickConfig {
+            note: 36,
+            channel: Some(10),
+            attack: 0.01,
+            decay: 0.2,
+        };
+
+        let mut kick = KickEnvelope::new(config);
+        assert!(kick.matches(9, 36));
+        assert!(!kick.matches(0, 36));
+        assert!(!kick.matches(9, 37));
+
+        let start = Instant::now();
+        let at = |secs: f32| start + Duration::from_secs_f32(secs);
+        assert_eq!(kick.value(start), 0.0);
+
+        kick.trigger(0.8, start);
+        assert!((kick.value(at(0.005)) - 0.4).abs() < 1e-3);
+        assert!((kick.value(at(0.01)) - 0.8).abs() < 1e-3);
+        assert!((kick.value(at(0.11)) - 0.2).abs() < 1e-3);
+        assert_eq!(kick.value(at(1.0)), 0.0);
+
+        // retriggering starts from the current level
+        kick.trigger(1.0, at(0.11));
+        assert!((kick.value(at(0.11)) - 0.2).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Number of taps needed for a measurement
+pub const CALIBRATION_TAPS: usize = 8;
+
+/// Longest time between a tap and the kick it belongs to
+const MATCH_WINDOW: Duration = Duration::from_millis(400);
+
+/// Factor by which the bass has to rise above its average to count as a kick
+const ONSET_RATIO: f32 = 1.5;
+
+/// Bass level below which nothing counts as a kick
+const ONSET_FLOOR: f32 = 1e-3;
+
+/// Shortest time between two kicks
+const ONSET_GAP: Duration = Duration::from_millis(150);
+
+/// Measures how far the audio analysis is ahead of the sound in the room.
+///
+/// While the user taps along to the kicks coming out of the PA, kicks are
+/// detected in the analyzed bass. The median time between each tap and the
+/// closest kick is the delay which is missing, minus the reaction time of
+/// the user.
+#[derive(Debug, Default)]
+pub struct LatencyCalibration {
+    average: f32,
+    rising: bool,
+    onsets: VecDeque<Instant>,
+    taps: VecDeque<Instant>,
+    offsets: Vec<f32>,
+}
+
+impl LatencyCalibration {
+    /// Looks for kicks in the analyzed bass and matches them with the taps.
+    pub fn update(&mut self, bass: f32, now: Instant) {
+        let is_onset = bass > ONSET_FLOOR && bass > ONSET_RATIO * self.average;
+        let spaced = self
+            .onsets
+            .back()
+            .map_or(true, |&last| now.duration_since(last) >= ONSET_GAP);
+        if is_onset && !self.rising && spaced {
+            self.onsets.push_back(now);
+        }
+
+        self.rising = is_onset;
+        self.average += 0.1 * (bass - self.average);
+
+        // a tap can only be matched once all kicks around it are known
+        while let Some(&tap) = self.taps.front() {
+            if now.duration_since(tap) < MATCH_WINDOW {
+                break;
+            }
+
+            self.taps.pop_front();
+            let offset = self
+                .onsets
+                .iter()
+                .map(|&onset| signed_secs(tap, onset))
+                .filter(|dt| dt.abs() <= MATCH_WINDOW.as_secs_f32())
+                .min_by(|a, b| a.abs().total_cmp(&b.abs()));
+
+            if let Some(dt) = offset {
+                self.offsets.push(1000.0 * dt);
+            }
+        }
+
+        while let Some(&onset) = self.onsets.front() {
+            match now.duration_since(onset) > 2 * MATCH_WINDOW {
+                true => self.onsets.pop_front(),
+                false => break,
+            };
+        }
+    }
+
+    /// Registers a tap on a kick heard from the PA.
+    pub fn tap(&mut self, now: Instant) {
+        self.taps.push_back(now);
+    }
+
+    /// Number of taps which have been matched with a kick.
+    pub fn matched(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Milliseconds to add to the latency, once enough taps have been matched.
+    pub fn correction(&self) -> Option<f32> {
+        if self.offsets.len() < CALIBRATION_TAPS {
+            return None;
+        }
+
+        let mut sorted = self.offsets.clone();
+        sorted.sort_by(f32::total_cmp);
+        Some(sorted[sorted.len() / 2])
+    }
+}
+
+/// Seconds from `b` to `a`, negative if `a` came first.
+fn signed_secs(a: Instant, b: Instant) -> f32 {
+    match a >= b {
+        true => a.duration_since(b).as_secs_f32(),
+        false => -b.duration_since(a).as_secs_f32(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn measures_tap_offset() {
+        let mut calib = LatencyCalibration::default();
+        let start = Instant::now();
+        let ms = |t: u64| start + Duration::from_millis(t);
+
+        // kicks every 500 ms, heard and tapped 80 ms later, at 100 fps
+        for t in (0..6000).step_by(10) {
+            let bass = match t % 500 < 50 {
+                true => 1.0,
+                false => 0.1,
+            };
+
+            if t % 500 == 80 && t > 500 {
+                calib.tap(ms(t));
+            }
+
+            calib.update(bass, ms(t));
+        }
+
+        assert_eq!(calib.matched(), 11);
+        let correction = calib.correction().unwrap();
+        assert!((correction - 80.0).abs() < 1.0, "{}", correction);
+    }
+}
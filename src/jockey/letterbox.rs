@@ -0,0 +1,143 @@
+use gl::types::*;
+use serde_yaml::Value;
+
+use crate::util::*;
+
+/// Parses an aspect ratio like `"16:9"` or `2.39`.
+pub fn parse_aspect(value: &Value) -> Option<f32> {
+    let aspect = match value {
+        Value::String(s) => {
+            let (w, h) = s.split_once(':')?;
+            let w: f32 = w.trim().parse().ok()?;
+            let h: f32 = h.trim().parse().ok()?;
+            w / h
+        }
+        s => s.as_f64()? as f32,
+    };
+
+    match aspect.is_finite() && aspect > 0.0 {
+        true => Some(aspect),
+        false => None,
+    }
+}
+
+/// Returns the largest rectangle with the given aspect ratio that fits into
+/// the screen, centered, as `(x, y, width, height)`.
+pub fn fit_aspect(aspect: f32, width: u32, height: u32) -> (u32, u32, u32, u32) {
+    let (w, h) = (width as f32, height as f32);
+    let (box_width, box_height) = if w / h.max(1.0) > aspect {
+        ((h * aspect).round() as u32, height)
+    } else {
+        (width, (w / aspect).round() as u32)
+    };
+
+    let box_width = box_width.min(width).max(1);
+    let box_height = box_height.min(height).max(1);
+    let x = width.saturating_sub(box_width) / 2;
+    let y = height.saturating_sub(box_height) / 2;
+    (x, y, box_width, box_height)
+}
+
+/// Keeps the output at a fixed aspect ratio.
+///
+/// The final image is rendered into an intermediate buffer with the given
+/// aspect ratio, which is then centered on the screen with black bars
+/// filling the rest.
+#[derive(Debug)]
+pub struct Letterbox {
+    pub aspect: f32,
+    buffer: FrameBuffer,
+}
+
+impl Letterbox {
+    pub fn new(aspect: f32, width: u32, height: u32) -> Self {
+        let (_, _, box_width, box_height) = fit_aspect(aspect, width, height);
+        Self {
+            aspect,
+            buffer: FrameBuffer::new(box_width, box_height),
+        }
+    }
+
+    /// Resizes the intermediate buffer, if the screen size has changed.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let (_, _, box_width, box_height) = fit_aspect(self.aspect, width, height);
+        let res = self.buffer.resolution();
+        if res[0] == box_width && res[1] == box_height {
+            return;
+        }
+
+        self.buffer = FrameBuffer::new(box_width, box_height);
+    }
+
+    pub fn framebuffer(&self) -> GLuint {
+        self.buffer.fb_id
+    }
+
+    /// Size of the image area inside the bars
+    pub fn size(&self) -> (u32, u32) {
+        let res = self.buffer.resolution();
+        (res[0], res[1])
+    }
+
+    /// Draws the image centered on the target with black bars around it.
+    pub fn present(&self, target_fb: GLuint, width: u32, height: u32) {
+        let (x, y, box_width, box_height) = fit_aspect(self.aspect, width, height);
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, target_fb);
+            gl::Viewport(0, 0, width as _, height as _);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl_debug_check!();
+
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.buffer.fb_id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target_fb);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                box_width as _,
+                box_height as _,
+                x as _,
+                y as _,
+                (x + box_width) as _,
+                (y + box_height) as _,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl_debug_check!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_ratios() {
+        let aspect = |s: &str| parse_aspect(&serde_yaml::from_str(s).unwrap());
+        assert_eq!(aspect("\"16:9\""), Some(16.0 / 9.0));
+        assert_eq!(aspect("4:3"), Some(4.0 / 3.0));
+        assert_eq!(aspect("2.39"), Some(2.39));
+        assert_eq!(aspect("2"), Some(2.0));
+        assert_eq!(aspect("\"16:0\""), None);
+        assert_eq!(aspect("-1.0"), None);
+        assert_eq!(aspect("wide"), None);
+    }
+
+    #[test]
+    fn pillarbox() {
+        assert_eq!(fit_aspect(1.0, 1920, 1080), (420, 0, 1080, 1080));
+    }
+
+    #[test]
+    fn letterbox() {
+        assert_eq!(fit_aspect(16.0 / 9.0, 1920, 1440), (0, 180, 1920, 1080));
+    }
+
+    #[test]
+    fn exact_fit() {
+        assert_eq!(fit_aspect(16.0 / 9.0, 1920, 1080), (0, 0, 1920, 1080));
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+/// Default number of seconds a problem has to last before it is reported
+pub const LINE_CHECK_DELAY: f32 = 3.0;
+
+/// RMS volume below which the input counts as silent, about -80 dBFS
+const SILENCE_LEVEL: f32 = 1e-4;
+
+/// Peak level at which a sample counts as clipped
+const CLIP_LEVEL: f32 = 0.999;
+
+/// Time without clipped samples after which clipping is considered over
+const CLIP_HOLD: Duration = Duration::from_millis(500);
+
+/// Address of the OSC messages sent when the state changes
+const OSC_ADDRESS: &str = "/sh4der-jockey/line_check";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineState {
+    Ok,
+    Silent,
+    Clipping,
+}
+
+impl LineState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Silent => "silent",
+            Self::Clipping => "clipping",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::Ok => "Audio input ok",
+            Self::Silent => "No audio input, check the cables and the audio device",
+            Self::Clipping => "Audio input is clipping, turn down the input gain",
+        }
+    }
+}
+
+/// Settings of the audio line check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineCheckConfig {
+    pub enabled: bool,
+    /// Seconds of silence or clipping before a warning is shown.
+    pub delay: f32,
+    /// Address like `"127.0.0.1:9000"` an OSC message is sent to whenever
+    /// the state changes.
+    pub osc: Option<String>,
+}
+
+impl Default for LineCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            delay: LINE_CHECK_DELAY,
+            osc: None,
+        }
+    }
+}
+
+/// Warns when the audio input has been silent or clipping for a while,
+/// which usually means a cable has been unplugged or the gain is off.
+#[derive(Debug)]
+pub struct LineCheck {
+    delay: Duration,
+    osc: Option<(UdpSocket, String)>,
+    silent_since: Option<Instant>,
+    clipping_since: Option<Instant>,
+    last_clip: Option<Instant>,
+    pub state: LineState,
+}
+
+impl LineCheck {
+    pub fn new(config: &LineCheckConfig) -> Self {
+        let osc = config
+            .osc
+            .as_ref()
+            .and_then(|target| match UdpSocket::bind(("0.0.0.0", 0)) {
+                Ok(socket) => Some((socket, target.clone())),
+                Err(err) => {
+                    log::error!("Failed to open a socket for OSC alerts: {}", err);
+                    None
+                }
+            });
+
+        Self {
+            delay: Duration::from_secs_f32(config.delay),
+            osc,
+            silent_since: None,
+            clipping_since: None,
+            last_clip: None,
+            state: LineState::Ok,
+        }
+    }
+
+    /// Checks the latest analysis window, returns the new state if it changed.
+    pub fn update(
+        &mut self,
+        volume: f32,
+        left: &[f32],
+        right: &[f32],
+        now: Instant,
+    ) -> Option<LineState> {
+        match volume < SILENCE_LEVEL {
+            true => self.silent_since = self.silent_since.or(Some(now)),
+            false => self.silent_since = None,
+        }
+
+        let clipped = left.iter().chain(right).any(|x| x.abs() >= CLIP_LEVEL);
+        if clipped {
+            self.clipping_since = self.clipping_since.or(Some(now));
+            self.last_clip = Some(now);
+        } else if let Some(last) = self.last_clip {
+            if now.duration_since(last) > CLIP_HOLD {
+                self.clipping_since = None;
+                self.last_clip = None;
+            }
+        }
+
+        let lasted = |since: Option<Instant>| since.map_or(false, |t| now - t >= self.delay);
+        let state = match () {
+            _ if lasted(self.silent_since) => LineState::Silent,
+            _ if lasted(self.clipping_since) => LineState::Clipping,
+            _ => LineState::Ok,
+        };
+
+        if state == self.state {
+            return None;
+        }
+
+        self.state = state;
+        match state {
+            LineState::Ok => log::info!("{}", state.message()),
+            _ => log::warn!("{}", state.message()),
+        }
+
+        if let Some((socket, target)) = &self.osc {
+            let packet = osc_message(OSC_ADDRESS, state.label());
+            if let Err(err) = socket.send_to(&packet, target.as_str()) {
+                log::error!("Failed to send OSC alert to {}: {}", target, err);
+            }
+        }
+
+        Some(state)
+    }
+}
+
+/// Encodes an OSC message with a single string argument.
+fn osc_message(address: &str, arg: &str) -> Vec<u8> {
+    fn push_padded(out: &mut Vec<u8>, s: &str) {
+        out.extend_from_slice(s.as_bytes());
+        out.push(0);
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+    }
+
+    let mut out = Vec::new();
+    push_padded(&mut out, address);
+    push_padded(&mut out, ",s");
+    push_padded(&mut out, arg);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_silence_and_clipping() {
+        let mut check = LineCheck::new(&LineCheckConfig::default());
+        let start = Instant::now();
+        let at = |secs: f32| start + Duration::from_secs_f32(secs);
+        let quiet = [0.0; 4];
+        let loud = [0.5, -1.0, 0.2, 0.0];
+
+        assert_eq!(check.update(0.0, &quiet, &quiet, at(0.0)), None);
+        assert_eq!(check.update(0.0, &quiet, &quiet, at(2.0)), None);
+        assert_eq!(
+            check.update(0.0, &quiet, &quiet, at(3.0)),
+            Some(LineState::Silent)
+        );
+
+        assert_eq!(
+            check.update(0.3, &loud, &quiet, at(4.0)),
+            Some(LineState::Ok)
+        );
+
+        // short gaps between clipped windows don't reset the timer
+        assert_eq!(check.update(0.3, &quiet, &quiet, at(4.4)), None);
+        assert_eq!(check.update(0.3, &loud, &quiet, at(5.0)), None);
+        assert_eq!(
+            check.update(0.3, &quiet, &loud, at(7.0)),
+            Some(LineState::Clipping)
+        );
+
+        // but longer ones do
+        assert_eq!(
+            check.update(0.3, &quiet, &quiet, at(8.0)),
+            Some(LineState::Ok)
+        );
+    }
+
+    #[test]
+    fn encodes_osc_messages() {
+        assert_eq!(
+            osc_message("/a/bc", "ok"),
+            b"/a/bc\0\0\0,s\0\0ok\0\0".to_vec()
+        );
+    }
+}
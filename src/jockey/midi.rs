@@ -8,7 +8,7 @@ use std::{
 
 use midir::{Ignore, MidiInput, MidiInputConnection, MidiInputPort};
 
-use super::Config;
+use super::{Config, KickEnvelope};
 
 pub const MIDI_N: usize = 32;
 
@@ -21,6 +21,8 @@ pub struct Midi {
     pub buttons: [(f32, Instant, Instant, u32); MIDI_N],
     pub button_bindings: HashMap<[u8; 2], usize>,
     pub slider_bindings: HashMap<[u8; 2], usize>,
+    /// Envelope driven by a note, independent of the bindings.
+    pub kick: Option<KickEnvelope>,
     preferred_devices: Vec<String>,
     config_file: Option<PathBuf>,
     port_count: usize,
@@ -69,6 +71,7 @@ impl Midi {
             buttons,
             button_bindings,
             slider_bindings,
+            kick: config.kick.clone().map(KickEnvelope::new),
             preferred_devices,
             config_file,
             port_count: 0,
@@ -230,6 +233,12 @@ impl Midi {
                             key,
                             velocity,
                         } => {
+                            if let Some(kick) = &mut self.kick {
+                                if velocity > 0 && kick.matches(channel, key) {
+                                    kick.trigger(velocity as f32 / 127.0, Instant::now());
+                                }
+                            }
+
                             self.last_button = [channel, key];
                             if let Some(&id) = self.button_bindings.get(&self.last_button) {
                                 self.buttons[id].0 = velocity as f32 / 127.0;
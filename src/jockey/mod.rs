@@ -1,57 +1,126 @@
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
+    collections::HashMap,
     ffi::CString,
-    future::Future,
-    hash::{Hash, Hasher},
     io::Write,
-    mem::MaybeUninit,
     path::{Path, PathBuf},
     pin::Pin,
     rc::Rc,
     sync::atomic::{AtomicBool, Ordering},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 use gl::types::*;
 use glutin::{dpi::PhysicalSize, platform::run_return::EventLoopExtRunReturn};
 use imgui::im_str;
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
-use notify::Watcher;
 
 use crate::util::*;
 
-mod audio;
+mod annotations;
+mod backup;
 mod beatsync;
+mod bench;
+mod bundle;
+mod clock;
 mod config;
+mod cursor;
+mod diagnostics;
+mod display;
+mod doctor;
+mod git;
+mod grade;
+mod kick;
+mod latency;
+mod letterbox;
+mod linecheck;
 mod midi;
+mod mutate;
 mod network;
-mod pipeline;
-mod stage;
-mod uniforms;
-
-pub use audio::*;
+mod perflog;
+mod present;
+mod projection;
+mod recent;
+mod render;
+mod scopes;
+mod snapshots;
+mod spout;
+mod stereo;
+mod timestep;
+mod watchdog;
+mod watcher;
+
+pub use annotations::*;
+pub use backup::*;
 pub use beatsync::*;
+pub use bench::*;
+pub use bundle::*;
+pub use clock::*;
 pub use config::*;
+pub use cursor::*;
+pub use diagnostics::*;
+pub use display::*;
+pub use doctor::*;
+pub use git::*;
+pub use grade::*;
+pub use kick::*;
+pub use latency::*;
+pub use letterbox::*;
+pub use linecheck::*;
 pub use midi::*;
+pub use mutate::*;
 pub use network::*;
-pub use pipeline::*;
-pub use stage::*;
-pub use uniforms::*;
+pub use perflog::*;
+pub use present::*;
+pub use projection::*;
+pub use recent::*;
+pub use render::*;
+pub use scopes::*;
+pub use sh4der_jockey_core::*;
+pub use snapshots::*;
+pub use spout::*;
+pub use stereo::*;
+pub use timestep::*;
+pub use watchdog::*;
+pub use watcher::*;
+
+#[cfg(test)]
+mod headless;
+
+/// Default frame rate limit of the control panel
+pub const UI_FPS: f32 = 60.0;
+
+/// Converts megabytes to bytes
+fn megabytes(mb: usize) -> usize {
+    mb * 1024 * 1024
+}
+
+/// Time between checks whether the project folder still exists
+pub const PROJECT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 static mut PIPELINE_STALE: AtomicBool = AtomicBool::new(false);
 static mut PROJECT_STALE: AtomicBool = AtomicBool::new(false);
 
 /// A struct for all the ugly internals.
 pub struct MegaContext {
-    pub imgui: imgui::Context,
-    pub renderer: imgui_opengl_renderer::Renderer,
     pub vao: GLuint,
     pub vbo: GLuint,
-    pub watcher: Option<notify::RecommendedWatcher>,
+    pub watcher: Option<ProjectWatcher>,
     pub context: glutin::WindowedContext<glutin::PossiblyCurrent>,
-    pub ui_context: glutin::WindowedContext<glutin::PossiblyCurrent>,
+    pub ui: Option<UiContext>,
     pub events_loop: glutin::event_loop::EventLoop<()>,
+}
+
+/// Everything needed to draw the control panel.
+///
+/// The control panel is drawn without vsync and at a limited frame rate,
+/// so it doesn't block the output window or eat into its frame budget.
+pub struct UiContext {
+    pub imgui: imgui::Context,
+    pub renderer: imgui_opengl_renderer::Renderer,
+    pub context: glutin::WindowedContext<glutin::PossiblyCurrent>,
     pub platform: WinitPlatform,
+    pub last_frame: Instant,
+    pub interval: Duration,
 }
 
 /// A struct to keep the state of the tool.
@@ -63,19 +132,59 @@ pub struct Jockey {
     pub ctx: MegaContext,
     pub done: bool,
     pub frame_perf: RunningAverage<f32, 128>,
+    pub frame_events: FrameAnnotations,
+    pub perf_log: Option<PerfLog>,
     pub beat_sync: BeatSync,
     pub last_build: Instant,
     pub last_frame: Instant,
     pub last_frame_ui: Instant,
     pub config_folder_path: Option<PathBuf>,
+    pub config: Config,
     pub midi: Midi,
+    pub mixer: Mixer,
+    pub snapshots: Snapshots,
+    pub mutator: Mutator,
     pub audio: Audio,
+    pub line_check: Option<LineCheck>,
+    pub clock: ShowClock,
+    /// Cue notes of the pipeline which have been ticked off.
+    pub cues_done: Vec<bool>,
+    /// Measures the audio latency while the user taps along to the kicks.
+    pub calibration: Option<LatencyCalibration>,
     pub ndi: Ndi,
+    pub watchdog: Watchdog,
     pub pipeline_files: Vec<String>,
     pub pipeline_index: usize,
+    pub pending_pipeline: Option<String>,
+    pub recent: RecentProjects,
+    pub git: Option<GitStatus>,
+    pub backups: Backups,
+    pub profile: Option<String>,
     pub pipeline: Pipeline,
     pub pipeline_partial: Option<Pin<PipelinePartial>>,
+    pub build_error: Option<String>,
+    pub offscreen: Option<FrameBuffer>,
+    pub presenter: Option<Presenter>,
+    /// GPU time of the frames, which the quality controller adjusts to.
+    pub frame_timer: GpuTimer,
+    /// CPU time of the last frame, not counting the wait for vsync.
+    pub frame_work_ms: f32,
+    pub stereo: Option<Stereo>,
+    pub projection_mode: ProjectionMode,
+    pub warp: Option<Projection>,
+    pub grade: Option<Grade>,
+    pub spout: Option<SpoutSender>,
+    pub letterbox: Option<Letterbox>,
+    pub luma: LumaMeter,
+    pub scopes: Option<Scopes>,
+    pub capture: Capture,
+    pub cursor: CursorHider,
+    pub display: DisplayWatch,
     pub time: f32,
+    pub time_step: Option<f32>,
+    /// Wait for every stage to finish on the GPU, so stage timings are exact.
+    pub sync_stages: bool,
+    pub update_step: FixedTimestep,
     pub time_since_build: f32,
     pub speed: f32,
     pub time_range: (f32, f32),
@@ -84,6 +193,10 @@ pub struct Jockey {
     pub custom_scale: i32,
     pub frame: u32,
     pub alt_pressed: bool,
+    pub comparing: bool,
+    pub built_any: bool,
+    pub project_missing: bool,
+    pub project_check: Instant,
     pub console: String,
 }
 
@@ -99,6 +212,115 @@ impl std::fmt::Debug for Jockey {
 
 static CONFIG_ENV: &'static str = "SH4DER_DIR";
 
+/// Opens the audio input chosen in the config.
+fn open_audio(config: &Config) -> Audio {
+    let mut audio = match &config.jack {
+        Some(jack) => Audio::with_jack(AUDIO_SAMPLES, jack),
+        None => Audio::new(AUDIO_SAMPLES, config.audio_device.as_deref()),
+    };
+
+    audio.set_latency(config.audio_latency.unwrap_or(0.0));
+    audio
+}
+
+fn load_window_icon(path: &str) -> anyhow::Result<glutin::window::Icon> {
+    let img = image::open(path)?.to_rgba8();
+    let (width, height) = img.dimensions();
+    Ok(glutin::window::Icon::from_rgba(
+        img.into_raw(),
+        width,
+        height,
+    )?)
+}
+
+/// Windowing system used on Linux and BSD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum WinitBackend {
+    X11,
+    Wayland,
+}
+
+/// Creates the event loop, on the given windowing system if there is a choice.
+fn create_event_loop(backend: Option<WinitBackend>) -> glutin::event_loop::EventLoop<()> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        use glutin::platform::unix::EventLoopExtUnix;
+
+        match backend {
+            Some(WinitBackend::X11) => {
+                return glutin::event_loop::EventLoop::new_x11()
+                    .expect("Failed to connect to the X server")
+            }
+            Some(WinitBackend::Wayland) => return glutin::event_loop::EventLoop::new_wayland(),
+            None => (),
+        }
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    if backend.is_some() {
+        log::warn!("The winit backend can only be chosen on Linux and BSD");
+    }
+
+    glutin::event_loop::EventLoop::new()
+}
+
+/// Presents the back buffer, a failed swap only drops the frame.
+///
+/// Some Wayland compositors report errors while the window is hidden or
+/// being reconfigured, which shouldn't take the show down.
+fn swap_buffers(context: &glutin::WindowedContext<glutin::PossiblyCurrent>) {
+    if let Err(err) = context.swap_buffers() {
+        log::error!("Failed to swap buffers: {}", err);
+    }
+}
+
+/// Estimates the time between two refreshes of the monitor the window is on.
+fn refresh_interval_ms(window: &glutin::window::Window) -> f32 {
+    let monitor = window.current_monitor().or(window.primary_monitor());
+    let rate = monitor.and_then(|monitor| {
+        let size = monitor.size();
+        monitor
+            .video_modes()
+            .filter(|mode| mode.size() == size)
+            .map(|mode| mode.refresh_rate())
+            .max()
+    });
+
+    1000.0 / rate.unwrap_or(60).max(1) as f32
+}
+
+/// Switches the working directory to a project folder and reloads everything.
+fn open_project(path: &Path) {
+    log::info!("Setting cwd to {}", path.to_string_lossy().as_ref());
+    if let Err(err) = std::env::set_current_dir(path) {
+        log::error!("Failed setting cwd: {}", err);
+    }
+
+    unsafe {
+        PIPELINE_STALE.store(true, Ordering::Release);
+        PROJECT_STALE.store(true, Ordering::Release);
+    }
+}
+
+/// Lets the user pick a project folder without blocking the UI.
+fn browse_project() {
+    std::thread::spawn(|| {
+        if let Some(path) = rfd::FileDialog::new().pick_folder() {
+            open_project(&path);
+        }
+    });
+}
+
+/// Lists all pipeline files in the working directory.
+pub fn find_pipeline_files() -> Vec<String> {
+    std::fs::read_dir(".")
+        .unwrap()
+        .map(|s| s.unwrap().file_name().into_string().unwrap())
+        .filter(|s| s.ends_with(".yaml"))
+        .filter(|s| s != "config.yaml")
+        .collect()
+}
+
 fn config_folder_path() -> Option<PathBuf> {
     // Fetch config folder path from enviroment variable
     if let Some(path) = std::env::var_os(CONFIG_ENV) {
@@ -156,7 +378,11 @@ impl Jockey {
     ///
     /// This will spin up a Winit window, initialize Imgui,
     /// create a OpenGL context and more!
-    pub fn init() -> Self {
+    ///
+    /// Without `show_ui`, the control panel is never created, which saves
+    /// a second context and its vsync wait. `profile` selects a profile
+    /// from the pipeline files, which can be changed in the UI later on.
+    pub fn init(show_ui: bool, backend: Option<WinitBackend>, profile: Option<String>) -> Self {
         let config_folder_path = config_folder_path();
         match &config_folder_path {
             Some(path) => log::info!("Using config folder: {}", path.to_string_lossy()),
@@ -166,62 +392,57 @@ impl Jockey {
             }
         }
 
-        let config = Config::load_or_default();
-        let audio = Audio::new(AUDIO_SAMPLES, &config);
+        let config = Config::load_or_default(config_folder_path.as_deref());
+        let audio = open_audio(&config);
+        let watchdog = Watchdog::new(
+            Duration::from_secs_f32(config.watchdog_threshold.unwrap_or(WATCHDOG_THRESHOLD)),
+            config.watchdog_disable_stage,
+        );
 
-        let events_loop = glutin::event_loop::EventLoop::new();
+        let events_loop = create_event_loop(backend);
+        // macOS only hands out core profile contexts up to OpenGL 4.1
+        #[cfg(target_os = "macos")]
+        let request = glutin::GlRequest::Specific(glutin::Api::OpenGl, (4, 1));
+        #[cfg(not(target_os = "macos"))]
         let request = glutin::GlRequest::Latest;
 
-        // Setup for imgui
-        let ui_window_builder = glutin::window::WindowBuilder::new()
-            .with_inner_size(glutin::dpi::LogicalSize::new(720.0, 640.0))
-            .with_resizable(true)
-            .with_title("Control Panel");
-
-        #[cfg(target_os = "windows")]
-        let ui_window_builder =
-            glutin::platform::windows::WindowBuilderExtWindows::with_drag_and_drop(
-                ui_window_builder,
-                false,
-            );
-
-        let ui_context_builder = glutin::ContextBuilder::new().with_vsync(true);
-        let ui_built_context = ui_context_builder
-            .build_windowed(ui_window_builder, &events_loop)
-            .expect("Failed to create windowed context");
-
-        let ui_context = unsafe {
-            ui_built_context
-                .make_current()
-                .expect("Failed to activate windowed context")
+        let ui = match show_ui {
+            true => Some(Self::init_ui(
+                &events_loop,
+                config_folder_path.as_deref(),
+                config.ui_fps.unwrap_or(UI_FPS),
+            )),
+            false => None,
         };
-        let ui_prog_addr = |s| ui_context.get_proc_address(s) as _;
-        let mut imgui = imgui::Context::create();
-        imgui.io_mut().config_flags |=
-            imgui::ConfigFlags::DOCKING_ENABLE | imgui::ConfigFlags::VIEWPORTS_ENABLE;
-
-        let ini_path = config_folder_path
-            .as_ref()
-            .map(|base| base.join("imgui-layout.ini"));
-        imgui.set_ini_filename(ini_path);
-
-        let renderer = imgui_opengl_renderer::Renderer::new(&mut imgui, ui_prog_addr);
-        let mut platform = WinitPlatform::init(&mut imgui);
-        let hidpi_factor = platform.hidpi_factor();
-        imgui.io_mut().font_global_scale = (1.0 / hidpi_factor) as f32;
-        platform.attach_window(imgui.io_mut(), ui_context.window(), HiDpiMode::Rounded);
-
-        Self::init_imgui_style(imgui.style_mut());
 
         // Set up winit for OpenGL stuff
         let context_builder = glutin::ContextBuilder::new()
             .with_vsync(true)
             .with_gl(request);
 
+        #[cfg(target_os = "macos")]
+        let context_builder = context_builder.with_gl_profile(glutin::GlProfile::Core);
+
+        let title = config.window.title.as_deref().unwrap_or("Sh4derJockey");
+        let icon = config
+            .window
+            .icon
+            .as_ref()
+            .and_then(|path| match load_window_icon(path) {
+                Ok(icon) => Some(icon),
+                Err(err) => {
+                    log::warn!("Failed to load window icon {:?}: {}", path, err);
+                    None
+                }
+            });
+
         let window_builder = glutin::window::WindowBuilder::new()
             .with_inner_size(glutin::dpi::LogicalSize::new(1280.0, 720.0))
             .with_resizable(true)
-            .with_title("Sh4derJockey");
+            .with_title(title)
+            .with_window_icon(icon)
+            .with_decorations(!config.window.borderless)
+            .with_always_on_top(config.window.always_on_top);
 
         #[cfg(target_os = "windows")]
         let window_builder = glutin::platform::windows::WindowBuilderExtWindows::with_drag_and_drop(
@@ -241,6 +462,16 @@ impl Jockey {
 
         let prog_addr = |s| context.get_proc_address(s) as _;
         gl::load_with(prog_addr);
+        record_gl_info();
+
+        let (major, minor) = detect_gl_version();
+        if !supports_compute() {
+            log::warn!(
+                "OpenGL {}.{} doesn't support compute shaders, compute stages and video scopes are disabled",
+                major,
+                minor
+            );
+        }
 
         // setup OpenGL
         let mut vao = 0;
@@ -253,22 +484,29 @@ impl Jockey {
         }
 
         let frame_perf = RunningAverage::new();
+        let frame_events = FrameAnnotations::new(frame_perf.buffer.len());
 
         let ctx = MegaContext {
-            imgui,
-            renderer,
             vao,
             vbo,
             watcher: None,
             context,
-            ui_context,
+            ui,
             events_loop,
-            platform,
         };
 
-        let pipeline = Pipeline::splash_screen();
+        let pipeline = Pipeline::project_splash_screen();
+        let luma = LumaMeter::new();
+        let scopes = match Scopes::new() {
+            Ok(s) => Some(s),
+            Err(err) => {
+                log::warn!("Video scopes are not available: {}", err);
+                None
+            }
+        };
         let midi = Midi::new(&config, config_folder_path.as_deref());
         let ndi = Ndi::with_config_path(config_folder_path.clone());
+        let recent = RecentProjects::load(config_folder_path.as_deref());
 
         let console = "No pipeline has been built yet".into();
 
@@ -277,19 +515,54 @@ impl Jockey {
             ctx,
             done: false,
             frame_perf,
+            frame_events,
+            perf_log: None,
             beat_sync: BeatSync::new(),
             last_build: now,
             last_frame: now,
             last_frame_ui: now,
             config_folder_path,
+            config: config.clone(),
             midi,
+            mixer: Mixer::default(),
+            snapshots: Snapshots::default(),
+            mutator: Mutator::default(),
             audio,
+            line_check: None,
+            clock: ShowClock::new(config.clock.clone()),
+            cues_done: Vec::new(),
+            calibration: None,
             ndi,
+            watchdog,
             pipeline_files: Vec::new(),
             pipeline,
             pipeline_index: 0,
+            pending_pipeline: None,
+            recent,
+            git: None,
+            backups: Backups::default(),
+            profile,
             pipeline_partial: None,
+            build_error: None,
+            offscreen: None,
+            presenter: None,
+            frame_timer: GpuTimer::new(),
+            frame_work_ms: 0.0,
+            stereo: None,
+            projection_mode: ProjectionMode::Flat,
+            warp: None,
+            grade: None,
+            spout: None,
+            letterbox: None,
+            luma,
+            scopes,
+            capture: Capture::new(),
+            cursor: CursorHider::new(None),
+            display: DisplayWatch::new(),
             time: 0.0,
+            time_step: None,
+            sync_stages: false,
+            update_step: FixedTimestep::new(config.update_rate),
             time_since_build: 0.0,
             speed: 1.0,
             time_range: (0.0, 60.0),
@@ -298,15 +571,75 @@ impl Jockey {
             custom_scale: 512,
             frame: 0,
             alt_pressed: false,
+            comparing: false,
+            built_any: false,
+            project_missing: false,
+            project_check: Instant::now(),
             console,
         };
 
         this.ctx.context = unsafe { this.ctx.context.make_current().unwrap() };
+        this.set_frame_interpolation(config.frame_interpolation);
+        this.set_stereo_mode(config.stereo);
+        this.set_projection(config.projection);
+        this.set_grade(config.grade);
+        this.set_spout_sender(config.spout_sender);
+        this.set_line_check(&config.line_check);
+        this.set_output_aspect(config.output_aspect);
+        this.set_cursor_options(&config.window);
+        Cache::set_budget(config.cache_budget.map(megabytes));
         this.update_pipeline();
         gl_debug_check!();
         this
     }
 
+    /// Creates the control panel window and sets up imgui.
+    fn init_ui(
+        events_loop: &glutin::event_loop::EventLoop<()>,
+        config_folder_path: Option<&Path>,
+        fps: f32,
+    ) -> UiContext {
+        let ui_window_builder = glutin::window::WindowBuilder::new()
+            .with_inner_size(glutin::dpi::LogicalSize::new(720.0, 640.0))
+            .with_resizable(true)
+            .with_title("Control Panel");
+
+        let ui_context_builder = glutin::ContextBuilder::new().with_vsync(false);
+        let ui_built_context = ui_context_builder
+            .build_windowed(ui_window_builder, events_loop)
+            .expect("Failed to create windowed context");
+
+        let ui_context = unsafe {
+            ui_built_context
+                .make_current()
+                .expect("Failed to activate windowed context")
+        };
+        let ui_prog_addr = |s| ui_context.get_proc_address(s) as _;
+        let mut imgui = imgui::Context::create();
+        imgui.io_mut().config_flags |=
+            imgui::ConfigFlags::DOCKING_ENABLE | imgui::ConfigFlags::VIEWPORTS_ENABLE;
+
+        let ini_path = config_folder_path.map(|base| base.join("imgui-layout.ini"));
+        imgui.set_ini_filename(ini_path);
+
+        let renderer = imgui_opengl_renderer::Renderer::new(&mut imgui, ui_prog_addr);
+        let mut platform = WinitPlatform::init(&mut imgui);
+        let hidpi_factor = platform.hidpi_factor();
+        imgui.io_mut().font_global_scale = (1.0 / hidpi_factor) as f32;
+        platform.attach_window(imgui.io_mut(), ui_context.window(), HiDpiMode::Rounded);
+
+        Self::init_imgui_style(imgui.style_mut());
+
+        UiContext {
+            imgui,
+            renderer,
+            context: ui_context,
+            platform,
+            last_frame: Instant::now(),
+            interval: Duration::from_secs_f32(1.0 / fps),
+        }
+    }
+
     // adapted from https://www.gitmemory.com/issue/ocornut/imgui/707/512669512
     #[rustfmt::skip]
     fn init_imgui_style(style: &mut imgui::Style) {
@@ -375,12 +708,14 @@ impl Jockey {
     /// successfully, the new Pipeline struct will stomp the old one.
     pub fn update_pipeline(&mut self) {
         // find pipeline files in working directory
-        self.pipeline_files = std::fs::read_dir(".")
-            .unwrap()
-            .map(|s| s.unwrap().file_name().into_string().unwrap())
-            .filter(|s| s.ends_with(".yaml"))
-            .filter(|s| s != "config.yaml")
-            .collect();
+        self.pipeline_files = find_pipeline_files();
+
+        // select a pipeline file which has been dropped onto the control panel
+        if let Some(name) = self.pending_pipeline.take() {
+            if let Some(k) = self.pipeline_files.iter().position(|s| *s == name) {
+                self.pipeline_index = k;
+            }
+        }
 
         log::info!("Found pipeline files: {:?}", &self.pipeline_files);
 
@@ -394,15 +729,247 @@ impl Jockey {
             Some(s) => s,
             None => {
                 log::warn!("Failed to find pipeline file");
+                self.console = "No pipeline file found, please open a project folder".into();
                 return;
             }
         };
 
-        let screen_size = self.ctx.context.window().inner_size();
-        let screen_size = (screen_size.width as u32, screen_size.height as u32);
+        if let Ok(cwd) = std::env::current_dir() {
+            self.recent.push(cwd);
+        }
+
+        self.git = git_status();
+
+        let screen_size = self.screen_size();
+        record_pipeline_path(path);
+
+        // let the new pipeline reuse the render targets of the running one
+        TargetPool::reset();
+        self.pipeline.lend_targets();
 
         log::info!("Start building pipeline");
-        self.pipeline_partial = Some(Box::pin(Pipeline::load(path.to_owned(), screen_size)));
+        self.pipeline_partial = Some(Box::pin(Pipeline::load(
+            path.to_owned(),
+            screen_size,
+            self.profile.clone(),
+            self.config.paths.clone(),
+        )));
+    }
+
+    /// Turns frame interpolation on or off.
+    ///
+    /// Must be called while the main context is current.
+    pub fn set_frame_interpolation(&mut self, enabled: bool) {
+        if enabled == self.presenter.is_some() {
+            return;
+        }
+
+        self.presenter = match enabled {
+            true => {
+                let (width, height) = self.screen_size();
+                let refresh_ms = refresh_interval_ms(self.ctx.context.window());
+                Some(Presenter::new(width, height, refresh_ms))
+            }
+            false => None,
+        };
+    }
+
+    /// Sets up stereo output, or turns it off if `mode` is `None`.
+    ///
+    /// Must be called while the main context is current.
+    pub fn set_stereo_mode(&mut self, mode: Option<StereoMode>) {
+        if mode == self.stereo.as_ref().map(|s| s.mode) {
+            return;
+        }
+
+        let (width, height) = self.screen_size();
+        self.stereo = mode.map(|mode| Stereo::new(mode, width, height));
+    }
+
+    /// Sets up the output projection.
+    ///
+    /// Fisheye output is rendered as an equirectangular image and warped in
+    /// a final pass, unless the shaders take care of the projection natively.
+    /// Must be called while the main context is current.
+    pub fn set_projection(&mut self, config: Option<ProjectionConfig>) {
+        let config = match config {
+            Some(s) => s,
+            None => {
+                self.projection_mode = ProjectionMode::Flat;
+                self.warp = None;
+                return;
+            }
+        };
+
+        let needs_warp = config.mode == ProjectionMode::Fisheye && !config.native;
+        if needs_warp && self.stereo.is_some() {
+            log::warn!("Fisheye warp is not supported in stereo mode, ignoring projection");
+            self.projection_mode = ProjectionMode::Flat;
+            self.warp = None;
+            return;
+        }
+
+        if !needs_warp {
+            self.projection_mode = config.mode;
+            self.warp = None;
+            return;
+        }
+
+        self.projection_mode = ProjectionMode::Equirect;
+        match &mut self.warp {
+            Some(warp) => warp.config = config,
+            None => {
+                let (width, height) = self.screen_size();
+                self.warp = Some(Projection::new(config, width, height));
+            }
+        }
+    }
+
+    /// Sets up the color grade applied to the output, or turns it off if
+    /// `config` is `None`.
+    ///
+    /// Must be called while the main context is current.
+    pub fn set_grade(&mut self, config: Option<GradeConfig>) {
+        let config = match config {
+            Some(s) => s,
+            None => {
+                self.grade = None;
+                return;
+            }
+        };
+
+        // only reload the table if a different file has been chosen
+        if let Some(grade) = &mut self.grade {
+            if grade.config.lut == config.lut {
+                grade.config = config;
+                return;
+            }
+        }
+
+        let (width, height) = self.screen_size();
+        self.grade = match Grade::new(config, width, height) {
+            Ok(grade) => Some(grade),
+            Err(err) => {
+                log::warn!("Failed to set up color grade: {}", err);
+                None
+            }
+        };
+    }
+
+    /// Watches the audio input for silence and clipping, unless the check is
+    /// disabled in `config`.
+    pub fn set_line_check(&mut self, config: &LineCheckConfig) {
+        self.line_check = config.enabled.then(|| LineCheck::new(config));
+    }
+
+    /// Shares the output with other programs as a Spout sender called
+    /// `name`, or stops sharing it if `name` is `None`.
+    ///
+    /// Must be called while the main context is current.
+    pub fn set_spout_sender(&mut self, name: Option<String>) {
+        let name = match name {
+            Some(s) => s,
+            None => {
+                self.spout = None;
+                return;
+            }
+        };
+
+        if matches!(&self.spout, Some(spout) if spout.name() == name) {
+            return;
+        }
+
+        // drop the old sender first, so its name is free again
+        self.spout = None;
+        let context = &self.ctx.context;
+        self.spout = match SpoutSender::new(&name, |s| context.get_proc_address(s) as _) {
+            Ok(spout) => Some(spout),
+            Err(err) => {
+                log::warn!("Failed to set up Spout sender: {}", err);
+                None
+            }
+        };
+    }
+
+    /// Locks the output to a fixed aspect ratio, or lets it follow the
+    /// window if `aspect` is `None`.
+    pub fn set_output_aspect(&mut self, aspect: Option<f32>) {
+        if aspect == self.letterbox.as_ref().map(|s| s.aspect) {
+            return;
+        }
+
+        let (width, height) = self.screen_size();
+        self.letterbox = aspect.map(|aspect| Letterbox::new(aspect, width, height));
+
+        let (width, height) = self.buffer_size();
+        self.pipeline.resize_buffers(width, height);
+    }
+
+    pub fn set_cursor_options(&mut self, config: &WindowConfig) {
+        let window = self.ctx.context.window();
+        self.cursor.timeout = config.hide_cursor.map(Duration::from_secs_f32);
+        self.cursor.moved(window);
+        set_click_through(window, config.click_through);
+    }
+
+    /// Returns the size of the final render target.
+    ///
+    /// This is the size of the main window, unless an offscreen
+    /// target has been set up for rendering.
+    pub fn screen_size(&self) -> (u32, u32) {
+        if let Some(fb) = &self.offscreen {
+            let res = fb.resolution();
+            return (res[0], res[1]);
+        }
+
+        let screen_size = self.ctx.context.window().inner_size();
+        (screen_size.width as u32, screen_size.height as u32)
+    }
+
+    /// Returns the size of the image area on the screen.
+    ///
+    /// This is the screen size, unless the output is letterboxed to a fixed
+    /// aspect ratio.
+    pub fn output_size(&self) -> (u32, u32) {
+        let (width, height) = self.screen_size();
+        match &self.letterbox {
+            Some(letterbox) => {
+                let (_, _, width, height) = fit_aspect(letterbox.aspect, width, height);
+                (width, height)
+            }
+            None => (width, height),
+        }
+    }
+
+    /// Returns the size of screen sized render targets.
+    ///
+    /// This is the screen size, scaled down by the quality controller
+    /// of the current pipeline, if there is one.
+    pub fn buffer_size(&self) -> (u32, u32) {
+        let (width, height) = self.output_size();
+        let scale = match &self.pipeline.quality {
+            Some(q) => q.resolution_scale(),
+            None => 1.0,
+        };
+
+        let width = (width as f32 * scale).round() as u32;
+        let height = (height as f32 * scale).round() as u32;
+        (width.max(1), height.max(1))
+    }
+
+    /// Watches the project folder and the files the current pipeline was
+    /// built from, creating the watcher on first use.
+    fn update_watcher(&mut self) {
+        if self.ctx.watcher.is_none() {
+            match ProjectWatcher::new() {
+                Ok(s) => self.ctx.watcher = Some(s),
+                Err(err) => return log::error!("Failed to create file watcher: {}", err),
+            }
+        }
+
+        let watcher = self.ctx.watcher.as_mut().unwrap();
+        watcher.watch_project();
+        watcher.set_dependencies(self.pipeline.source_files());
     }
 
     fn update_pipeline_incremental(&mut self, timeout: Duration) {
@@ -414,36 +981,93 @@ impl Jockey {
 
             if let Some(result) = futures::FutureExt::now_or_never(part) {
                 self.pipeline_partial = None;
-
-                // set waker on current working directory
-                self.ctx.watcher = Some({
-                    let event_fn = |_| unsafe { PIPELINE_STALE.store(true, Ordering::Release) };
-                    let mut watcher = notify::recommended_watcher(event_fn).unwrap();
-                    watcher
-                        .watch(Path::new("."), notify::RecursiveMode::Recursive)
-                        .unwrap();
-
-                    watcher
-                });
+                self.frame_events.record(FrameEvent::PipelineRebuild);
 
                 // unwrap pipeline build result
                 let (new_pipeline, update) = match result {
                     Ok(t) => t,
                     Err(err) => {
+                        // keep watching the includes of the last working pipeline
+                        self.update_watcher();
+
+                        // the running pipeline keeps its render targets
+                        TargetPool::reset();
+
                         self.console = format!("Failed to build pipeline:\n{}", err);
                         log::error!("{}", &self.console);
+                        record_error(Some(&err));
+                        self.build_error = Some(err);
+
+                        // show something other than the splash screen
+                        if !self.built_any {
+                            self.show_fallback();
+                        }
                         return;
                     }
                 };
 
+                record_error(None);
+                self.built_any = true;
+                self.build_error = None;
+                self.watchdog.warning = None;
+                self.watchdog.probing = false;
+
                 // set new pipeline
+                let mut new_pipeline = new_pipeline;
+                new_pipeline.keep_previous_programs(&mut self.pipeline);
+
+                // start the run sheet over when it changes
+                if new_pipeline.notes != self.pipeline.notes {
+                    self.cues_done.clear();
+                }
+
+                // keep the opacity of the layers set in the UI
+                if let (Some(new), Some(old)) = (&mut new_pipeline.layers, &self.pipeline.layers) {
+                    for layer in new.layers.iter_mut() {
+                        layer.keep_opacity(&old.layers);
+                    }
+                }
+
+                // keep the variation while working on the same piece
+                if let (Some(new), Some(old)) =
+                    (&mut new_pipeline.variations, &self.pipeline.variations)
+                {
+                    if new.fixed_seed.is_none() && new.same_params(old) {
+                        new.set_seed(old.seed());
+                    }
+                }
                 self.pipeline = new_pipeline;
 
+                // free the render targets the new pipeline didn't take over
+                TargetPool::collect();
+
+                // textures of the old pipeline may be unused now
+                Cache::evict();
+                self.update_watcher();
+
+                // back up the previous version of every changed shader
+                match self.config.backups {
+                    Some(keep) => {
+                        let files = self.pipeline.source_files();
+                        self.backups.update(Path::new(BACKUP_FOLDER), files, keep);
+                    }
+                    None => self.backups.clear(),
+                }
+
                 // log build time
                 let build_time = self.last_build.elapsed().as_secs_f64();
                 self.console = format!("Build pipeline over a span of {}s", build_time);
                 log::info!("{}", &self.console);
 
+                // report problems that don't stop the pipeline from working
+                if !self.pipeline.warnings.is_empty() {
+                    self.console += "\n\nWarnings:";
+                    for warning in self.pipeline.warnings.iter() {
+                        self.console += &format!("\n{}", warning);
+                        log::warn!("{}", warning);
+                    }
+                }
+
                 // toggle blend modes
                 unsafe {
                     match self.pipeline.blending {
@@ -462,8 +1086,17 @@ impl Jockey {
 
                 // update ndi module
                 let requests = self.pipeline.requested_ndi_sources.values();
-                if let Err(err) = self.ndi.connect(&requests) {
-                    log::error!("Failed to connect to NDI sources: {}", err);
+                if requests.len() != 0 && !self.ndi.is_available() {
+                    let warning = "NDI runtime not found, NDI textures will stay black. \
+                        Install the NDI runtime or place the library next to the executable.";
+                    self.console += &format!("\n\n{}", warning);
+                    log::warn!("{}", warning);
+                }
+
+                match self.ndi.connect(&requests) {
+                    Ok(0) => (),
+                    Ok(_) => self.frame_events.record(FrameEvent::NdiReconnect),
+                    Err(err) => log::error!("Failed to connect to NDI sources: {}", err),
                 }
             }
         }
@@ -479,7 +1112,14 @@ impl Jockey {
         // reload all things that depend on the project-level config file
         if do_update_project {
             let base = self.config_folder_path.as_deref();
-            let config = Config::load_or_default();
+            let config = Config::load_or_default(base);
+
+            // pipeline files may refer to the path aliases
+            if config.paths != self.config.paths {
+                unsafe { PIPELINE_STALE.store(true, Ordering::Release) };
+            }
+
+            self.config = config.clone();
 
             // the old midi struct must be dropped before the new one is created,
             // because it fails to connect to any common midi controller otherwise
@@ -490,28 +1130,62 @@ impl Jockey {
 
             take_mut::take(&mut self.audio, |audio| {
                 drop(audio);
-                Audio::new(AUDIO_SAMPLES, &config)
+                open_audio(&config)
             });
+
+            self.set_frame_interpolation(config.frame_interpolation);
+            self.set_stereo_mode(config.stereo);
+            self.set_projection(config.projection);
+            self.set_grade(config.grade);
+            self.set_spout_sender(config.spout_sender);
+            self.set_line_check(&config.line_check);
+            self.clock.config = config.clock.clone();
+            self.set_output_aspect(config.output_aspect);
+            self.set_cursor_options(&config.window);
+            Cache::set_budget(config.cache_budget.map(megabytes));
+            self.update_step = FixedTimestep::new(config.update_rate);
+            if let Some(ui) = &mut self.ctx.ui {
+                let fps = config.ui_fps.unwrap_or(UI_FPS);
+                ui.interval = Duration::from_secs_f32(1.0 / fps);
+            }
+
+            let threshold = config.watchdog_threshold.unwrap_or(WATCHDOG_THRESHOLD);
+            self.watchdog
+                .set_threshold(Duration::from_secs_f32(threshold));
+            self.watchdog.disable_stage = config.watchdog_disable_stage;
         }
 
-        let platform = &mut self.ctx.platform;
         let events_loop = &mut self.ctx.events_loop;
-        let imgui = &mut self.ctx.imgui;
+        let mut ui = self.ctx.ui.as_mut();
         let window = self.ctx.context.window();
-        let ui_window = self.ctx.ui_context.window();
+        let scale = match &self.pipeline.quality {
+            Some(q) => q.resolution_scale(),
+            None => 1.0,
+        };
+        let aspect = self.letterbox.as_ref().map(|s| s.aspect);
         let pipeline = &mut self.pipeline;
+        let offscreen = self.offscreen.is_some();
         let alt_pressed = &mut self.alt_pressed;
+        let comparing = &mut self.comparing;
+        let cursor = &mut self.cursor;
         let mut done = false;
 
         self.midi.check_connections();
         self.midi.handle_input();
 
         let mut take_screenshot = false;
+        let mut dropped_file = None;
         let mut do_update_pipeline = unsafe { PIPELINE_STALE.swap(false, Ordering::AcqRel) }
             && self.last_build.elapsed().as_millis() > 300;
 
+        // rebuild once the changed files settled, any change may fix a broken build
+        if let Some(watcher) = &mut self.ctx.watcher {
+            let any_file = self.build_error.is_some() || !self.built_any;
+            do_update_pipeline |= watcher.rebuild_due(&self.config.watch, any_file);
+        }
+
         let main_id = self.ctx.context.window().id();
-        let ui_id = ui_window.id();
+        let ui_id = ui.as_ref().map(|ui| ui.context.window().id());
 
         events_loop.run_return(|e, _window_target, cf| {
             match e {
@@ -519,17 +1193,39 @@ impl Jockey {
                     window_id,
                     ref event,
                 } => {
-                    if window_id == ui_id {
-                        platform.handle_event(imgui.io_mut(), ui_window, &e);
+                    if let Some(ui) = &mut ui {
+                        if Some(window_id) == ui_id {
+                            ui.platform
+                                .handle_event(ui.imgui.io_mut(), ui.context.window(), &e);
+                        }
                     }
 
                     match event {
                         glutin::event::WindowEvent::CloseRequested => done = true,
 
-                        glutin::event::WindowEvent::Resized(size) if window_id == main_id => {
-                            let width = size.width as u32;
-                            let height = size.height as u32;
-                            pipeline.resize_buffers(width, height);
+                        glutin::event::WindowEvent::Resized(size)
+                            if window_id == main_id && !offscreen =>
+                        {
+                            let (width, height) = match aspect {
+                                Some(aspect) => {
+                                    let (_, _, w, h) = fit_aspect(aspect, size.width, size.height);
+                                    (w, h)
+                                }
+                                None => (size.width, size.height),
+                            };
+                            let width = (width as f32 * scale).round() as u32;
+                            let height = (height as f32 * scale).round() as u32;
+                            pipeline.resize_buffers(width.max(1), height.max(1));
+                        }
+
+                        glutin::event::WindowEvent::CursorMoved { .. } if window_id == main_id => {
+                            cursor.moved(window);
+                        }
+
+                        glutin::event::WindowEvent::DroppedFile(path)
+                            if Some(window_id) == ui_id =>
+                        {
+                            dropped_file = Some(path.clone());
                         }
 
                         #[allow(deprecated)]
@@ -561,6 +1257,43 @@ impl Jockey {
                                         window.set_fullscreen(handle);
                                     }
                                 }
+
+                                // move fullscreen output to the next monitor
+                                if alt && shift && !(ctrl || logo) && window.id() == window_id {
+                                    let monitors: Vec<_> = window.available_monitors().collect();
+                                    let current = window.current_monitor().and_then(|m| m.name());
+                                    let index = monitors
+                                        .iter()
+                                        .position(|m| m.name().is_some() && m.name() == current)
+                                        .map_or(0, |k| k + 1);
+
+                                    if let Some(monitor) =
+                                        monitors.get(index % monitors.len().max(1))
+                                    {
+                                        log::info!(
+                                            "Moving output to monitor {:?}",
+                                            monitor.name().unwrap_or_default()
+                                        );
+
+                                        let handle = glutin::window::Fullscreen::Borderless(Some(
+                                            monitor.clone(),
+                                        ));
+
+                                        window.set_fullscreen(Some(handle));
+                                    }
+                                }
+                            }
+
+                            // show the previous build while the key is held
+                            if Some(glutin::event::VirtualKeyCode::B) == input.virtual_keycode {
+                                match input.state {
+                                    glutin::event::ElementState::Pressed => {
+                                        if ctrl && !(shift || alt || logo) {
+                                            *comparing = true;
+                                        }
+                                    }
+                                    glutin::event::ElementState::Released => *comparing = false,
+                                }
                             }
 
                             if Some(glutin::event::VirtualKeyCode::S) == input.virtual_keycode
@@ -581,11 +1314,52 @@ impl Jockey {
         });
 
         self.done = done;
+        self.cursor.update(self.ctx.context.window());
+        if self.offscreen.is_none() {
+            self.display.update(self.ctx.context.window());
+        }
 
         if take_screenshot {
             self.save_frame();
         }
 
+        for stage in self.pipeline.stages.iter_mut() {
+            stage.set_comparing(self.comparing);
+        }
+
+        if let Some(path) = dropped_file {
+            self.open_dropped_file(&path);
+        }
+
+        // check whether the project folder is still there, e.g. on a removed SD card
+        if self.project_check.elapsed() > PROJECT_CHECK_INTERVAL {
+            self.project_check = Instant::now();
+            let missing = match self.pipeline_files.get(self.pipeline_index) {
+                Some(path) => !Path::new(path).exists(),
+                None => false,
+            };
+
+            if missing && !self.project_missing {
+                log::error!("The project folder has disappeared");
+                self.show_fallback();
+            }
+
+            if !missing && self.project_missing {
+                log::info!("The project folder is back, rebuilding");
+                do_update_pipeline = true;
+            }
+
+            self.project_missing = missing;
+        }
+
+        // bring back NDI feeds which have stopped sending frames
+        let requests = self.pipeline.requested_ndi_sources.values();
+        match self.ndi.reconnect_stalled(&requests) {
+            Ok(0) => (),
+            Ok(_) => self.frame_events.record(FrameEvent::NdiReconnect),
+            Err(err) => log::error!("Failed to reconnect NDI sources: {}", err),
+        }
+
         // live shader reloading hype
         if do_update_pipeline {
             self.update_pipeline();
@@ -594,6 +1368,38 @@ impl Jockey {
         }
     }
 
+    /// Swaps in the built-in fallback pipeline, so the output never stays black.
+    ///
+    /// Must be called while the main context is current.
+    fn show_fallback(&mut self) {
+        log::warn!("Showing the fallback pipeline");
+        self.pipeline = Pipeline::fallback();
+        unsafe {
+            gl::Disable(gl::BLEND);
+            gl_debug_check!();
+        }
+    }
+
+    /// Opens a project folder or pipeline file dropped onto the control panel.
+    fn open_dropped_file(&mut self, path: &Path) {
+        if path.is_dir() {
+            open_project(path);
+            return;
+        }
+
+        let file_name = path.file_name().map(|s| s.to_string_lossy().into_owned());
+        match (file_name, path.parent()) {
+            (Some(name), Some(folder)) if name.ends_with(".yaml") && name != "config.yaml" => {
+                self.pending_pipeline = Some(name);
+                open_project(folder);
+            }
+            _ => log::warn!(
+                "Dropped file {} is neither a folder nor a pipeline file",
+                path.to_string_lossy()
+            ),
+        }
+    }
+
     /// Does all the OpenGL magic.
     ///
     /// This function iterates over all stages in the pipeline and renders
@@ -607,15 +1413,70 @@ impl Jockey {
         // build pipeline a little
         self.update_pipeline_incremental(Duration::from_micros(50));
 
+        // re-present the last frames if the pipeline can't keep up
+        let (width, height) = self.screen_size();
+        if let Some(presenter) = &mut self.presenter {
+            presenter.resize(width, height);
+
+            if !presenter.should_render() {
+                presenter.present(self.ctx.vao, 0, width, height);
+                swap_buffers(&self.ctx.context);
+                return;
+            }
+        }
+
+        let frame_start = Instant::now();
+        let mut stalled_stage = None;
+        self.watchdog.frame_begin();
+
+        // adjust quality to the time spent on a frame, the time between
+        // frames is useless for this as it never drops below the vsync interval
+        let now = Instant::now();
+        let gpu_ms = self.frame_timer.poll();
+        if let (Some(quality), None, Some(gpu_ms)) =
+            (&mut self.pipeline.quality, self.time_step, gpu_ms)
+        {
+            if quality.update(gpu_ms.max(self.frame_work_ms)) {
+                let value = quality.value;
+                let scale_resolution = quality.scale_resolution;
+
+                // switch to the programs compiled for the new quality level
+                for stage in self.pipeline.stages.iter_mut() {
+                    stage.select_variant(value);
+                }
+
+                if scale_resolution {
+                    let (width, height) = self.buffer_size();
+                    self.pipeline.resize_buffers(width, height);
+                }
+            }
+        }
+
+        self.frame_timer.begin();
+
         // compute uniforms
-        let screen_size = self.ctx.context.window().inner_size();
-        let (width, height) = (screen_size.width as u32, screen_size.height as u32);
+        let (buffer_width, buffer_height) = self.buffer_size();
+        let quality = self.pipeline.quality.as_ref().map_or(1.0, |q| q.value);
         let beat = self.beat_sync.beat();
-        let now = Instant::now();
+        if self.mutator.triggered(&self.midi.buttons) {
+            self.snapshots.morph = None;
+            self.mutator.mutate(&mut self.midi.sliders);
+        }
+        self.snapshots.update(beat, &mut self.midi.sliders);
         let time = self.time;
         let time_since_build = self.time_since_build;
-        let delta = self.speed * now.duration_since(self.last_frame).as_secs_f32();
+        let delta = match self.time_step {
+            Some(step) => step,
+            None => self.speed * now.duration_since(self.last_frame).as_secs_f32(),
+        };
+        let real_delta = match self.time_step {
+            Some(step) => step,
+            None => now.duration_since(self.last_frame).as_secs_f32(),
+        };
         let frame = self.frame;
+        let mixer_opacity = self.mixer.effective_opacity(&self.midi.sliders);
+        let kick = self.midi.kick.as_ref().map_or(0.0, |k| k.value(now));
+        let mixer_tint = self.mixer.tint.concat();
         self.time += delta;
         self.time_since_build += delta;
         self.last_frame = now;
@@ -624,441 +1485,433 @@ impl Jockey {
         {
             // update audio samples texture
             self.audio.update_samples();
-            self.audio.update_fft();
-
-            fn audio_tex_update(
-                buffers: &mut HashMap<CString, Rc<dyn Texture>>,
-                name: &CString,
-                left: &[f32],
-                right: &[f32],
-            ) {
-                if let Some(tex) = buffers.get_mut(name) {
-                    unsafe {
-                        alloca::with_slice(left.len() + right.len(), |buffer| {
-                            let buffer = &mut *(buffer as *mut [MaybeUninit<f32>] as *mut _);
-
-                            interlace(left, right, buffer);
-                            Rc::get_mut(tex)
-                                .unwrap()
-                                .as_any_mut()
-                                .downcast_mut::<Texture1D>()
-                                .unwrap()
-                                .write(buffer.as_ptr() as _);
-                        })
-                    }
-                }
+
+            // analyze audio at a fixed rate, so smoothing doesn't depend on the fps
+            for _ in 0..self.update_step.advance(real_delta) {
+                self.audio.update_fft();
             }
+            self.audio.update_history(beat);
 
-            for (tex_name, src_name) in self.pipeline.requested_ndi_sources.iter() {
+            if let Some(calib) = &mut self.calibration {
+                calib.update(self.audio.bass[0], now);
+            }
+
+            if let Some(check) = &mut self.line_check {
+                let (left, right) = (&self.audio.l_signal, &self.audio.r_signal);
+                check.update(self.audio.volume[0], left, right, now);
+            }
+
+            for (tex_name, input) in self.pipeline.requested_ndi_sources.iter() {
                 let tex = self.pipeline.buffers.get_mut(tex_name).unwrap();
                 let tex = Rc::get_mut(tex)
                     .unwrap()
                     .as_any_mut()
                     .downcast_mut::<Texture2D>()
                     .unwrap();
-                self.ndi.update_texture(src_name, tex);
+                if self.ndi.update_texture(input, tex) {
+                    self.frame_events.record(FrameEvent::TextureUpload);
+                }
             }
 
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SAMPLES_NAME,
-                &self.audio.l_signal,
-                &self.audio.r_signal,
-            );
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SPECTRUM_RAW_NAME,
-                &self.audio.l_raw_spectrum,
-                &self.audio.r_raw_spectrum,
-            );
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SPECTRUM_NAME,
-                &self.audio.l_spectrum,
-                &self.audio.r_spectrum,
-            );
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SPECTRUM_SMOOTH_NAME,
-                &self.audio.l_spectrum_smooth,
-                &self.audio.r_spectrum_smooth,
-            );
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SPECTRUM_SMOOTH_INTEGRATED_NAME,
-                &self.audio.l_spectrum_smooth_integrated,
-                &self.audio.r_spectrum_smooth_integrated,
-            );
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SPECTRUM_INTEGRATED_NAME,
-                &self.audio.l_spectrum_integrated,
-                &self.audio.r_spectrum_integrated,
-            );
+            self.pipeline.update_audio_textures(&self.audio);
         }
 
+        // render the screen into the presenter instead
+        let present_fb = self.presenter.as_mut().map(|p| p.begin_frame());
+        let screen_fb = match (&self.offscreen, present_fb) {
+            (Some(fb), _) => fb.fb_id,
+            (None, Some(fb_id)) => fb_id,
+            (None, None) => 0, // The screen is always id=0
+        };
+
+        // letterboxed output is rendered into an intermediate buffer first
+        let (window_fb, window_width, window_height) = (screen_fb, width, height);
+        let (screen_fb, width, height) = match &mut self.letterbox {
+            Some(letterbox) => {
+                letterbox.resize(width, height);
+                let (box_width, box_height) = letterbox.size();
+                (letterbox.framebuffer(), box_width, box_height)
+            }
+            None => (screen_fb, width, height),
+        };
+
+        // everything is drawn into the grade buffer before the final pass
+        let output_fb = match &mut self.grade {
+            Some(grade) => {
+                grade.resize(width, height);
+                grade.framebuffer()
+            }
+            None => screen_fb,
+        };
+
+        // stages rendering to the screen are rendered once per eye in stereo mode
+        let (eye_width, eye_height) = match (&mut self.stereo, &mut self.warp) {
+            (Some(stereo), _) => {
+                stereo.resize(width, height);
+                stereo.eye_size(width, height)
+            }
+            (None, Some(warp)) => {
+                warp.resize(width, height);
+                warp.buffer_size(width, height)
+            }
+            (None, None) => (width, height),
+        };
+        let projection_mode = self.projection_mode as i32;
+
+        let eye_count = if self.stereo.is_some() { 2 } else { 1 };
+        let schedule: Vec<_> = (0..eye_count)
+            .flat_map(|eye| (0..self.pipeline.stages.len()).map(move |k| (eye, k)))
+            .filter(|&(eye, k)| {
+                let stage = &self.pipeline.stages[k];
+                eye == 0
+                    || (stage.target.is_none() && !matches!(stage.kind, StageKind::Feedback { .. }))
+            })
+            .collect();
+
+        // screen stages of layered pipelines render into their layer first
+        if let Some(layers) = &mut self.pipeline.layers {
+            layers.begin_frame(eye_width, eye_height, eye_count);
+        }
+
+        // bind shared uniform blocks
+        for (binding, block) in self.pipeline.blocks.iter().enumerate() {
+            block.bind(binding as _);
+        }
+
+        // fetch the results measured in previous frames
+        self.luma.poll();
+        let prev_frame_luma = self.luma.value;
+        let exposure = match &mut self.pipeline.exposure {
+            Some(auto) => {
+                let tex = self.pipeline.buffers.get(&auto.buffer).unwrap();
+                auto.update(self.ctx.vao, tex.as_ref(), delta);
+                auto.exposure()
+            }
+            None => 1.0,
+        };
+        let mut sample_counts = Vec::new();
+        for stage in self.pipeline.stages.iter_mut() {
+            if let Some(counter) = &mut stage.samples {
+                counter.poll();
+                sample_counts.push((counter.name.clone(), counter.samples));
+            }
+        }
+
+        // when the latest frame of each NDI source arrived, for custom interpolation
+        let source_times: Vec<_> = self
+            .pipeline
+            .requested_ndi_sources
+            .iter()
+            .filter_map(|(name, input)| {
+                let time = self.ndi.source_time(&input.source)?;
+                let mut uniform = name.as_bytes().to_vec();
+                uniform.extend_from_slice(b"_source_time");
+                Some((CString::new(uniform).unwrap(), time))
+            })
+            .collect();
+
+        let frame_uniforms = FrameUniforms {
+            time,
+            time_since_build,
+            delta,
+            frame,
+            beat,
+            quality,
+            eye: 0.0,
+            projection_mode,
+            prev_frame_luma,
+            exposure,
+        };
+
         // render all shader stages
-        for (pass_num, stage) in self.pipeline.stages.iter_mut().enumerate() {
+        for (eye, pass_num) in schedule {
             let stage_start = Instant::now();
+            self.watchdog.stage_begin(pass_num);
 
-            // skip stage if target is never used
-            if !matches!(stage.kind, StageKind::Comp { .. }) {
-                if let Some(name) = &stage.target {
-                    if self.pipeline.buffers.get(name).is_none() {
-                        continue;
-                    }
-                }
+            // skip stages disabled by the watchdog
+            if self.pipeline.stages[pass_num].disabled {
+                continue;
             }
 
-            // get size of the render target
-            let target_res = match stage.resolution() {
+            // get size of the render target, skip stage if target is never used
+            let buffer_size = (buffer_width, buffer_height);
+            let eye_size = (eye_width, eye_height);
+            let target_res = match self
+                .pipeline
+                .target_resolution(pass_num, buffer_size, eye_size)
+            {
                 Some(s) => s,
-                _ => [width, height, 0],
+                None => continue,
             };
 
-            unsafe {
-                // Use shader program
-                gl::UseProgram(stage.prog_id);
-                gl_debug_check!();
+            let stage = &mut self.pipeline.stages[pass_num];
+            let eye_value = match (&self.stereo, &stage.target) {
+                (Some(_), None) => [-1.0, 1.0][eye],
+                _ => 0.0,
+            };
 
-                {
-                    // Add time, beat, resolution and volume
-                    let r_loc = gl::GetUniformLocation(stage.prog_id, R_NAME.as_ptr());
-                    let k_loc = gl::GetUniformLocation(stage.prog_id, K_NAME.as_ptr());
-                    let res_loc = gl::GetUniformLocation(stage.prog_id, RESOLUTION_NAME.as_ptr());
-                    let pass_loc = gl::GetUniformLocation(stage.prog_id, PASS_INDEX_NAME.as_ptr());
-                    let time_loc = gl::GetUniformLocation(stage.prog_id, TIME_NAME.as_ptr());
-                    let time_since_build_loc =
-                        gl::GetUniformLocation(stage.prog_id, TIME_SINCE_BUILD_NAME.as_ptr());
-                    let frame_loc =
-                        gl::GetUniformLocation(stage.prog_id, FRAME_COUNT_NAME.as_ptr());
-                    let delta_loc = gl::GetUniformLocation(stage.prog_id, TIME_DELTA_NAME.as_ptr());
-                    let beat_loc = gl::GetUniformLocation(stage.prog_id, BEAT_NAME.as_ptr());
-                    let volume_loc = gl::GetUniformLocation(stage.prog_id, VOLUME_NAME.as_ptr());
-                    let volume_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, VOLUME_INTEGRATED_NAME.as_ptr());
-                    let bass_loc = gl::GetUniformLocation(stage.prog_id, BASS_NAME.as_ptr());
-                    let mid_loc = gl::GetUniformLocation(stage.prog_id, MID_NAME.as_ptr());
-                    let high_loc = gl::GetUniformLocation(stage.prog_id, HIGH_NAME.as_ptr());
-                    let smooth_bass_loc =
-                        gl::GetUniformLocation(stage.prog_id, BASS_SMOOTH_NAME.as_ptr());
-                    let smooth_mid_loc =
-                        gl::GetUniformLocation(stage.prog_id, MID_SMOOTH_NAME.as_ptr());
-                    let smooth_high_loc =
-                        gl::GetUniformLocation(stage.prog_id, HIGH_SMOOTH_NAME.as_ptr());
-
-                    let bass_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, BASS_INTEGRATED_NAME.as_ptr());
-                    let mid_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, MID_INTEGRATED_NAME.as_ptr());
-                    let high_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, HIGH_INTEGRATED_NAME.as_ptr());
-                    let smooth_bass_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, BASS_SMOOTH_INTEGRATED_NAME.as_ptr());
-                    let smooth_mid_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, MID_SMOOTH_INTEGRATED_NAME.as_ptr());
-                    let smooth_high_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, HIGH_SMOOTH_INTEGRATED_NAME.as_ptr());
-
-                    gl::Uniform4f(
-                        res_loc,
-                        target_res[0] as f32,
-                        target_res[1] as f32,
-                        target_res[0] as f32 / target_res[1] as f32, // x/y
-                        target_res[1] as f32 / target_res[0] as f32, // x/y
-                    );
-                    gl::Uniform3f(r_loc, target_res[0] as _, target_res[1] as _, time);
-                    gl::Uniform3f(
-                        volume_loc,
-                        self.audio.volume[0], // average L/R
-                        self.audio.volume[1], // L
-                        self.audio.volume[2], // R
-                    );
-                    gl::Uniform3f(
-                        bass_loc,
-                        self.audio.bass[0],
-                        self.audio.bass[1],
-                        self.audio.bass[2],
-                    );
-                    gl::Uniform3f(
-                        mid_loc,
-                        self.audio.mid[0],
-                        self.audio.mid[1],
-                        self.audio.mid[2],
-                    );
-                    gl::Uniform3f(
-                        high_loc,
-                        self.audio.high[0],
-                        self.audio.high[1],
-                        self.audio.high[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_bass_loc,
-                        self.audio.bass_smooth[0],
-                        self.audio.bass_smooth[1],
-                        self.audio.bass_smooth[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_mid_loc,
-                        self.audio.mid_smooth[0],
-                        self.audio.mid_smooth[1],
-                        self.audio.mid_smooth[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_high_loc,
-                        self.audio.high_smooth[0],
-                        self.audio.high_smooth[1],
-                        self.audio.high_smooth[2],
-                    );
-                    gl::Uniform3f(
-                        volume_integrated_loc,
-                        self.audio.volume_integrated[0], // average L/R
-                        self.audio.volume_integrated[1], // L
-                        self.audio.volume_integrated[2], // R
-                    );
-                    gl::Uniform3f(
-                        bass_integrated_loc,
-                        self.audio.bass_integrated[0],
-                        self.audio.bass_integrated[1],
-                        self.audio.bass_integrated[2],
-                    );
-                    gl::Uniform3f(
-                        mid_integrated_loc,
-                        self.audio.mid_integrated[0],
-                        self.audio.mid_integrated[1],
-                        self.audio.mid_integrated[2],
-                    );
-                    gl::Uniform3f(
-                        high_integrated_loc,
-                        self.audio.high_integrated[0],
-                        self.audio.high_integrated[1],
-                        self.audio.high_integrated[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_bass_integrated_loc,
-                        self.audio.bass_smooth_integrated[0],
-                        self.audio.bass_smooth_integrated[1],
-                        self.audio.bass_smooth_integrated[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_mid_integrated_loc,
-                        self.audio.mid_smooth_integrated[0],
-                        self.audio.mid_smooth_integrated[1],
-                        self.audio.mid_smooth_integrated[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_high_integrated_loc,
-                        self.audio.high_smooth_integrated[0],
-                        self.audio.high_smooth_integrated[1],
-                        self.audio.high_smooth_integrated[2],
-                    );
-                    gl::Uniform2i(k_loc, pass_num as _, frame as _);
-                    gl::Uniform1i(pass_loc, pass_num as _);
-                    gl::Uniform1i(frame_loc, frame as _);
-                    gl::Uniform1f(time_loc, time);
-                    gl::Uniform1f(time_since_build_loc, time_since_build);
-                    gl::Uniform1f(beat_loc, beat);
-                    gl::Uniform1f(delta_loc, delta);
-                    gl_debug_check!();
-                }
+            let uniforms = FrameUniforms {
+                eye: eye_value,
+                ..frame_uniforms
+            };
+            stage.upload_frame_uniforms(pass_num, target_res, &uniforms, &self.audio);
+
+            let prog_id = stage.prog_id;
+            // Add sliders and buttons
+            let s_loc = stage.uploaded.location(prog_id, &SLIDERS_NAME);
+            let b_loc = stage.uploaded.location(prog_id, &BUTTONS_NAME);
+
+            let mut buttons = [0.0; 4 * MIDI_N];
+            for (k, button) in self.midi.buttons.iter().enumerate() {
+                buttons[k * 4 + 0] = button.0;
+                buttons[k * 4 + 1] = button.1.elapsed().as_secs_f32();
+                buttons[k * 4 + 2] = button.2.elapsed().as_secs_f32();
+                buttons[k * 4 + 3] = button.3 as f32;
+            }
 
-                {
-                    // Add sliders and buttons
-                    let s_loc = gl::GetUniformLocation(stage.prog_id, SLIDERS_NAME.as_ptr());
-                    let b_loc = gl::GetUniformLocation(stage.prog_id, BUTTONS_NAME.as_ptr());
-
-                    let mut buttons = [0.0; 4 * MIDI_N];
-                    for (k, button) in self.midi.buttons.iter().enumerate() {
-                        buttons[k * 4 + 0] = button.0;
-                        buttons[k * 4 + 1] = button.1.elapsed().as_secs_f32();
-                        buttons[k * 4 + 2] = button.2.elapsed().as_secs_f32();
-                        buttons[k * 4 + 3] = button.3 as f32;
-                    }
+            stage
+                .uploaded
+                .set_floats(prog_id, s_loc, 1, &self.midi.sliders);
+            stage.uploaded.set_floats(prog_id, b_loc, 4, &buttons);
 
-                    gl::Uniform1fv(s_loc, self.midi.sliders.len() as _, &self.midi.sliders as _);
-                    gl::Uniform4fv(b_loc, self.midi.buttons.len() as _, &buttons as _);
-                    gl_debug_check!();
-                }
+            let kick_loc = stage.uploaded.location(prog_id, &KICK_NAME);
+            stage.uploaded.set(prog_id, kick_loc, Uniform::Float(kick));
+            gl_debug_check!();
 
-                // Add custom uniforms
-                for (name, uniform) in &stage.unis {
-                    let loc = gl::GetUniformLocation(stage.prog_id, name.as_ptr());
-                    uniform.bind(loc);
-                    gl_debug_check!();
-                }
+            // Add mixer channels
+            let opacity_loc = stage.uploaded.location(prog_id, &STAGE_OPACITY_NAME);
+            let tint_loc = stage.uploaded.location(prog_id, &STAGE_TINT_NAME);
 
-                // Add vertex count uniform
-                if let StageKind::Vert { count, .. } = stage.kind {
-                    let loc = gl::GetUniformLocation(stage.prog_id, VERTEX_COUNT_NAME.as_ptr());
-                    gl::Uniform1i(loc, count as _);
-                    gl_debug_check!();
+            stage
+                .uploaded
+                .set_floats(prog_id, opacity_loc, 1, &mixer_opacity);
+            stage.uploaded.set_floats(prog_id, tint_loc, 3, &mixer_tint);
+            gl_debug_check!();
+
+            for (name, time) in source_times.iter() {
+                let loc = stage.uploaded.location(prog_id, name);
+                stage.uploaded.set_floats(prog_id, loc, 2, time);
+            }
+            gl_debug_check!();
+
+            // Add randomized parameters
+            for (name, value) in self.pipeline.variations.iter().flat_map(|v| v.uniforms()) {
+                let loc = stage.uploaded.location(prog_id, name);
+                stage.uploaded.set(prog_id, loc, *value);
+            }
+            gl_debug_check!();
+
+            stage.bind_inputs(&self.pipeline.buffers, &sample_counts);
+
+            // Macros override the values set in the stages
+            for m in self.pipeline.macros.iter() {
+                for (name, value) in m.uniforms(&self.midi.sliders) {
+                    let loc = stage.uploaded.location(prog_id, name);
+                    stage.uploaded.set(prog_id, loc, value);
                 }
+            }
 
-                // Add and bind uniform texture dependencies
-                for (k, name) in stage.deps.iter().enumerate() {
-                    let tex = self.pipeline.buffers.get(name).unwrap();
-                    let loc = gl::GetUniformLocation(stage.prog_id, name.as_ptr());
-                    debug_assert_ne!(loc, -1);
+            let screen_fb = match (&self.stereo, &self.warp) {
+                (Some(stereo), _) => stereo.eye_framebuffer(eye),
+                (None, Some(warp)) => warp.framebuffer(),
+                (None, None) => output_fb,
+            };
+            self.pipeline
+                .render_stage(pass_num, eye, screen_fb, target_res, self.ctx.vao);
+            let stage = &mut self.pipeline.stages[pass_num];
 
-                    gl::ActiveTexture(gl::TEXTURE0 + k as GLenum);
-                    gl_debug_check!();
+            // wait for the gpu to catch up after a stall or while benchmarking
+            if self.watchdog.probing || self.sync_stages {
+                unsafe { gl::Finish() };
+            }
 
-                    tex.bind(k as _);
-                    gl_debug_check!();
+            // log render time
+            let stage_time = stage_start.elapsed();
+            stage.perf.push(1000.0 * stage_time.as_secs_f32());
 
-                    gl::Uniform1i(loc, k as _);
-                    gl_debug_check!();
+            if stage_time > self.watchdog.threshold {
+                stalled_stage = Some((pass_num, stage_time));
+            }
+        }
 
-                    let name_len = name.as_bytes().len();
-                    let res_loc = alloca::with_bytes_zeroed(name_len + 5, |res_name| {
-                        res_name[..name_len].copy_from_slice(name.as_bytes());
-                        res_name[name_len..].copy_from_slice("_res\0".as_bytes());
+        // write requested textures to disk
+        for readback in self.pipeline.readbacks.iter_mut() {
+            if !readback.tick() {
+                continue;
+            }
 
-                        gl::GetUniformLocation(stage.prog_id, res_name.as_ptr() as _)
-                    });
+            let tex = self.pipeline.buffers.get(&readback.name).unwrap();
+            match readback.save(tex.as_ref()) {
+                Ok(()) => log::info!("Saved {:?} to {:?}", readback.name, readback.path),
+                Err(err) => log::error!("{}", err),
+            }
+        }
 
-                    let res = tex.resolution();
-                    gl_debug_check!();
+        if let Some(layers) = &self.pipeline.layers {
+            for eye in 0..eye_count {
+                let target_fb = match (&self.stereo, &self.warp) {
+                    (Some(stereo), _) => stereo.eye_framebuffer(eye),
+                    (None, Some(warp)) => warp.framebuffer(),
+                    (None, None) => output_fb,
+                };
 
-                    gl::Uniform4f(
-                        res_loc,
-                        res[0] as _,
-                        res[1] as _,
-                        res[2] as _,
-                        res[0] as f32 / res[1] as f32,
-                    );
-                    gl_debug_check!();
-                }
+                let sliders = &self.midi.sliders;
+                layers.composite(self.ctx.vao, eye, sliders, target_fb, eye_width, eye_height);
             }
+        }
 
-            match &stage.kind {
-                StageKind::Comp { dispatch, .. } => unsafe {
-                    gl::DispatchCompute(dispatch[0], dispatch[1], dispatch[2]);
-                    gl::MemoryBarrier(
-                        gl::TEXTURE_UPDATE_BARRIER_BIT
-                            | gl::TEXTURE_FETCH_BARRIER_BIT
-                            | gl::SHADER_IMAGE_ACCESS_BARRIER_BIT,
-                    );
-                    gl_debug_check!();
-                },
-                _ => unsafe {
-                    debug_assert_eq!(target_res[2], 0);
-
-                    // get render target id
-                    let (target_tex, target_fb) = if let Some(name) = &stage.target {
-                        let tex = self.pipeline.buffers.get(name).unwrap();
-                        let tex_id = tex.texture_id();
-                        let fb_id = tex
-                            .framebuffer_id()
-                            .expect("Render target should be a framebuffer");
-                        (tex_id, fb_id)
-                    } else {
-                        (0, 0) // The screen is always id=0
-                    };
+        if let Some(stereo) = &self.stereo {
+            stereo.composite(self.ctx.vao, output_fb, width, height);
+        } else if let Some(warp) = &self.warp {
+            warp.warp(self.ctx.vao, output_fb, width, height);
+        }
 
-                    // Specify render target
-                    gl::BindFramebuffer(gl::FRAMEBUFFER, target_fb);
-                    gl::Viewport(0, 0, target_res[0] as _, target_res[1] as _);
-                    gl_debug_check!();
+        if let Some(grade) = &self.grade {
+            grade.apply(self.ctx.vao, screen_fb, width, height);
+        }
 
-                    // Specify fragment shader color output
-                    gl::BindFragDataLocation(stage.prog_id, 0, OUT_COLOR_NAME.as_ptr());
-                    gl_debug_check!();
+        // measure the brightness of the final image for the next frame
+        if self.pipeline.uses_frame_luma {
+            self.luma.measure(screen_fb, width, height);
+        }
 
-                    // Specify the layout of the vertex data
-                    let pos_attr = gl::GetAttribLocation(stage.prog_id, POSITION_NAME.as_ptr());
-                    if pos_attr != -1 {
-                        gl_debug_check!();
-                        gl::EnableVertexAttribArray(pos_attr as GLuint);
-                        gl_debug_check!();
-                        gl::VertexAttribPointer(
-                            pos_attr as GLuint,
-                            2,
-                            gl::FLOAT,
-                            gl::FALSE as GLboolean,
-                            0,
-                            std::ptr::null(),
-                        );
-                    }
-                    gl_debug_check!();
+        if let Some(spout) = &mut self.spout {
+            spout.send(screen_fb, width, height);
+        }
 
-                    // Set blend mode
-                    if self.pipeline.blending {
-                        let (src, dst) = stage.blend.unwrap_or((gl::ONE, gl::ZERO));
-                        gl::BlendFunc(src, dst);
-                        gl_debug_check!();
-                    }
+        if let Some(scopes) = &mut self.scopes {
+            let target = self
+                .pipeline
+                .buffers
+                .get(&*HISTOGRAM_NAME)
+                .map(|tex| tex.texture_id());
+            scopes.update(screen_fb, width, height, target);
+        }
 
-                    // Draw stuff
-                    if let StageKind::Vert {
-                        count,
-                        mode,
-                        thickness,
-                        ..
-                    } = stage.kind
-                    {
-                        gl::ClearColor(0.0, 0.0, 0.0, 0.0);
-                        gl::Clear(gl::COLOR_BUFFER_BIT);
-                        gl_debug_check!();
-
-                        gl::PointSize(thickness);
-                        gl::LineWidth(thickness);
-                        gl_debug_check!();
-
-                        draw_vertices(self.ctx.vao, count, mode);
-                        gl_debug_check!();
-                    } else {
-                        draw_fullscreen(self.ctx.vao);
-                        gl_debug_check!();
-                    }
+        self.capture.poll();
+        if self.capture.is_requested() {
+            self.frame_events.record(FrameEvent::Screenshot);
+            let config = &self.pipeline.screenshot;
+            match &config.source {
+                Some(name) => {
+                    let tex = self.pipeline.buffers.get(name).unwrap();
+                    self.capture.capture_texture(tex.as_ref(), config);
+                }
+                None => self.capture.capture(screen_fb, width, height, config),
+            }
+        }
 
-                    // Generate mip maps
-                    // don't do it for the screen buffer
-                    if target_tex != 0 {
-                        gl::BindTexture(gl::TEXTURE_2D, target_tex);
-                        gl::GenerateMipmap(gl::TEXTURE_2D);
-                        gl_debug_check!();
-                    }
+        if let Some(letterbox) = &self.letterbox {
+            letterbox.present(window_fb, window_width, window_height);
+        }
 
-                    // swap buffers
-                    if let Some(name) = &stage.target {
-                        self.pipeline.buffers.get(name).unwrap().swap();
-                    }
-                },
+        if let Some(presenter) = &mut self.presenter {
+            presenter.end_frame();
+            presenter.present(self.ctx.vao, 0, window_width, window_height);
+        }
+
+        self.frame_timer.end();
+        self.frame_work_ms = 1000.0 * frame_start.elapsed().as_secs_f32();
+
+        swap_buffers(&self.ctx.context);
+        self.watchdog.frame_end();
+
+        // check for stalled stages
+        if frame_start.elapsed() > self.watchdog.threshold || stalled_stage.is_some() {
+            self.handle_stall(stalled_stage);
+        }
+    }
+
+    fn handle_stall(&mut self, stalled_stage: Option<(usize, Duration)>) {
+        let (index, time) = match stalled_stage {
+            Some(s) => s,
+            None => {
+                // find the culprit in the next frame
+                self.watchdog.probing = true;
+                return;
             }
+        };
 
-            // log render time
-            let stage_time = stage_start.elapsed().as_secs_f32();
-            stage.perf.push(1000.0 * stage_time);
+        self.watchdog.probing = false;
+        let mut warning = format!(
+            "Stage {} stalled the frame for {:.2}s",
+            index,
+            time.as_secs_f32()
+        );
+
+        if self.watchdog.disable_stage {
+            if let Some(stage) = self.pipeline.stages.get_mut(index) {
+                stage.disabled = true;
+                warning.push_str(", it has been disabled until the next rebuild");
+            }
         }
 
-        self.ctx.context.swap_buffers().unwrap();
+        log::error!("{}", warning);
+        self.watchdog.warning = Some(warning);
     }
 
     /// Wrapper function for all the imgui stuff.
     pub fn update_ui(&mut self) {
-        take_mut::take(&mut self.ctx.ui_context, |s| unsafe {
-            s.make_current().unwrap()
-        });
-
-        let io = self.ctx.imgui.io_mut();
-        self.ctx
-            .platform
-            .prepare_frame(io, self.ctx.ui_context.window())
-            .expect("Failed to start frame");
-
-        // tell imgui what time it is
         let now = Instant::now();
         let delta_time = now.duration_since(self.last_frame_ui).as_secs_f32();
-        io.delta_time = delta_time;
         self.last_frame_ui = now;
 
         // record frame time
         self.frame_perf.push(1000.0 * delta_time);
         let frame_ms = self.frame_perf.get();
 
+        let size = self.frame_perf.buffer.len();
+        self.frame_events
+            .end_frame((self.frame_perf.index + size - 1) % size);
+
+        if let Some(perf_log) = &mut self.perf_log {
+            let record = PerfRecord {
+                frame: self.frame as _,
+                time: self.time,
+                frame_ms: 1000.0 * delta_time,
+                stage_ms: self
+                    .pipeline
+                    .stages
+                    .iter()
+                    .map(|s| s.perf.latest())
+                    .collect(),
+                events: self.frame_events.latest(),
+            };
+
+            if let Err(err) = perf_log.write(&record) {
+                log::error!("Failed to write performance log: {}", err);
+                self.perf_log = None;
+            }
+        }
+
+        let ui_ctx = match &mut self.ctx.ui {
+            Some(s) => s,
+            None => return,
+        };
+
+        // skip frames to stay below the frame rate limit
+        let ui_delta_time = now.duration_since(ui_ctx.last_frame);
+        if ui_delta_time < ui_ctx.interval {
+            return;
+        }
+
+        ui_ctx.last_frame = now;
+
+        take_mut::take(&mut ui_ctx.context, |s| unsafe {
+            s.make_current().unwrap()
+        });
+
+        let io = ui_ctx.imgui.io_mut();
+        ui_ctx
+            .platform
+            .prepare_frame(io, ui_ctx.context.window())
+            .expect("Failed to start frame");
+
+        // tell imgui what time it is
+        io.delta_time = ui_delta_time.as_secs_f32();
+
         // title section
-        let ui = self.ctx.imgui.frame();
+        let ui = ui_ctx.imgui.frame();
 
         // create docking space
         unsafe {
@@ -1068,23 +1921,87 @@ impl Jockey {
             imgui::sys::igDockSpaceOverViewport(viewport, flags, window_class);
         }
 
-        if let Some(window) = imgui::Window::new(im_str!("Pipelines")).begin(&ui) {
-            if ui.button_with_size(im_str!("Select project folder"), [0.0; 2]) {
-                std::thread::spawn(|| {
-                    let Some(path) = rfd::FileDialog::new().pick_folder() else {
-                        return;
-                    };
+        // make sure nobody misses a dead audio line
+        if let Some(check) = self
+            .line_check
+            .as_ref()
+            .filter(|c| c.state != LineState::Ok)
+        {
+            let flags = imgui::WindowFlags::NO_COLLAPSE
+                | imgui::WindowFlags::ALWAYS_AUTO_RESIZE
+                | imgui::WindowFlags::NO_SAVED_SETTINGS;
+            if let Some(window) = imgui::Window::new(im_str!("Line Check"))
+                .flags(flags)
+                .position([16.0, 16.0], imgui::Condition::Appearing)
+                .begin(&ui)
+            {
+                ui.set_window_font_scale(2.0);
+                ui.text_colored([1.0, 0.2, 0.2, 1.0], check.state.message());
+                window.end();
+            }
+        }
 
-                    log::info!("Setting cwd to {}", path.to_string_lossy().as_ref());
-                    if let Err(err) = std::env::set_current_dir(path) {
-                        log::error!("Failed setting cwd: {}", err);
-                    }
+        if !self.pipeline.notes.is_empty() {
+            if let Some(window) = imgui::Window::new(im_str!("Notes")).begin(&ui) {
+                let notes = &self.pipeline.notes;
+                self.cues_done.resize(notes.len(), false);
 
-                    unsafe {
-                        PIPELINE_STALE.store(true, Ordering::Release);
-                        PROJECT_STALE.store(true, Ordering::Release);
+                for (k, (note, done)) in notes.iter().zip(self.cues_done.iter_mut()).enumerate() {
+                    let token = ui.push_id(k as i32);
+                    ui.checkbox(im_str!("##done"), done);
+                    ui.same_line();
+
+                    let text = imgui::ImString::new(note.as_str());
+                    match *done {
+                        true => ui.text_disabled(&text),
+                        false => ui.text_wrapped(&text),
                     }
-                });
+                    token.pop();
+                }
+
+                window.end();
+            }
+        }
+
+        if let Some(window) = imgui::Window::new(im_str!("Show Clock")).begin(&ui) {
+            let wall_time = ShowClock::wall_time(SystemTime::now());
+            let elapsed = self.clock.elapsed(Instant::now());
+
+            ui.set_window_font_scale(3.0);
+            ui.text(format_hms(wall_time));
+            ui.set_window_font_scale(1.0);
+            ui.text_disabled("local time");
+
+            ui.set_window_font_scale(3.0);
+            ui.text(format_hms(elapsed));
+            ui.set_window_font_scale(1.0);
+            ui.text_disabled("set time");
+            ui.same_line();
+            if ui.small_button(im_str!("restart")) {
+                self.clock.restart();
+            }
+
+            if let Some(remaining) = self.clock.remaining(wall_time) {
+                let level = self.clock.level(remaining);
+
+                // blink once the set is over
+                let text = match level == ClockLevel::Over && elapsed % 2 == 1 {
+                    true => String::new(),
+                    false => format_hms(remaining),
+                };
+
+                ui.set_window_font_scale(3.0);
+                ui.text_colored(level.color(), text);
+                ui.set_window_font_scale(1.0);
+                ui.text_disabled("time left");
+            }
+
+            window.end();
+        }
+
+        if let Some(window) = imgui::Window::new(im_str!("Pipelines")).begin(&ui) {
+            if ui.button_with_size(im_str!("Select project folder"), [0.0; 2]) {
+                browse_project();
             }
 
             ui.separator();
@@ -1103,9 +2020,77 @@ impl Jockey {
                 }
             }
 
+            if !self.pipeline.profiles.is_empty() {
+                ui.separator();
+                let active = self.profile.as_deref().unwrap_or("default");
+                ui.text(format!("Profile: {}", active));
+
+                let names = std::iter::once(None).chain(self.pipeline.profiles.iter().map(Some));
+                for name in names {
+                    let label = name.map_or("default", String::as_str);
+                    let cst = CString::new(label.as_bytes()).unwrap();
+                    let ims = unsafe { imgui::ImStr::from_cstr_unchecked(&cst) };
+                    if ui.button_with_size(ims, [256.0, 18.0]) {
+                        self.profile = name.cloned();
+                        unsafe { PIPELINE_STALE.store(true, Ordering::Release) }
+                    }
+                }
+            }
+
+            if let Some(git) = &self.git {
+                ui.separator();
+                match git.is_dirty() {
+                    true => ui.text(format!(
+                        "Branch: {} ({} uncommitted changes)",
+                        git.branch, git.changes
+                    )),
+                    false => ui.text(format!("Branch: {} (clean)", git.branch)),
+                }
+
+                if ui.button_with_size(im_str!("Commit snapshot"), [256.0, 18.0]) {
+                    match commit_snapshot() {
+                        Ok(message) => {
+                            self.console = format!("Committed \"{}\"", message);
+                            log::info!("{}", &self.console);
+                        }
+                        Err(err) => {
+                            self.console = format!("Failed to commit snapshot:\n{}", err);
+                            log::error!("{}", &self.console);
+                        }
+                    }
+
+                    self.git = git_status();
+                }
+            }
+
             window.end();
         }
 
+        // offer to open a project as long as no pipeline has been found
+        if self.pipeline_files.is_empty() {
+            if let Some(window) = imgui::Window::new(im_str!("Open Project")).begin(&ui) {
+                if ui.button_with_size(im_str!("Browse..."), [256.0, 18.0]) {
+                    browse_project();
+                }
+
+                ui.separator();
+                match self.recent.folders.is_empty() {
+                    true => ui.text("No recent projects"),
+                    false => ui.text("Recent projects"),
+                }
+
+                for path in self.recent.folders.iter() {
+                    let cst = CString::new(path.to_string_lossy().as_bytes()).unwrap();
+                    let ims = unsafe { imgui::ImStr::from_cstr_unchecked(&cst) };
+                    if ui.button_with_size(ims, [256.0, 18.0]) {
+                        open_project(path);
+                    }
+                }
+
+                window.end();
+            }
+        }
+
         if let Some(window) = imgui::Window::new(im_str!("Resolution")).begin(&ui) {
             let mut new_size = None;
 
@@ -1314,6 +2299,247 @@ impl Jockey {
             window.end();
         }
 
+        if !self.pipeline.macros.is_empty() {
+            if let Some(window) = imgui::Window::new(im_str!("Macros")).begin(&ui) {
+                for (k, m) in self.pipeline.macros.iter_mut().enumerate() {
+                    let token = ui.push_id(k as i32);
+                    let mut slider = m.slider as i32;
+                    ui.set_next_item_width(96.0);
+                    if ui.input_int(im_str!("slider"), &mut slider).build() {
+                        m.slider = (slider.max(0) as usize).min(MIDI_N - 1);
+                    }
+                    ui.same_line();
+
+                    let label = imgui::ImString::new(m.name.as_str());
+                    if let Some(value) = self.midi.sliders.get_mut(m.slider) {
+                        imgui::Slider::new(&label)
+                            .range(0.0..=1.0)
+                            .build(&ui, value);
+                    }
+
+                    for target in m.targets.iter() {
+                        ui.text(format!(
+                            "  {}: {:?} -> {:?} ({})",
+                            target.uniform.to_string_lossy(),
+                            target.from,
+                            target.to,
+                            target.easing.name(),
+                        ));
+                    }
+                    token.pop();
+                }
+
+                window.end();
+            }
+        }
+
+        if let Some(variations) = &mut self.pipeline.variations {
+            if let Some(window) = imgui::Window::new(im_str!("Variations")).begin(&ui) {
+                ui.set_window_font_scale(2.0);
+                ui.text(format!("seed {:06}", variations.seed()));
+                ui.set_window_font_scale(1.0);
+
+                if ui.button_with_size(im_str!("Reroll"), [128.0, 32.0]) {
+                    variations.reroll();
+                    log::info!("Rerolled variation, seed {}", variations.seed());
+                }
+
+                let mut seed = variations.seed() as i32;
+                ui.same_line();
+                ui.set_next_item_width(128.0);
+                if ui.input_int(im_str!("seed"), &mut seed).build() {
+                    variations.set_seed(seed.max(0) as u32);
+                }
+
+                if let Some(seed) = variations.fixed_seed {
+                    ui.text_disabled(format!("the pipeline fixes the seed to {}", seed));
+                }
+
+                let mut values = variations.uniforms().iter();
+                for group in variations.groups.iter() {
+                    ui.separator();
+                    ui.text(&group.name);
+                    for (name, value) in values.by_ref().take(group.params.len()) {
+                        let components: Vec<_> = match *value {
+                            Uniform::Float(x) => vec![x],
+                            Uniform::Vec2(x, y) => vec![x, y],
+                            Uniform::Vec3(x, y, z) => vec![x, y, z],
+                            Uniform::Vec4(x, y, z, w) => vec![x, y, z, w],
+                            _ => Vec::new(),
+                        };
+                        let components: Vec<_> =
+                            components.iter().map(|x| format!("{:.3}", x)).collect();
+                        ui.text(format!(
+                            "  {} = {}",
+                            name.to_string_lossy(),
+                            components.join(", ")
+                        ));
+                    }
+                }
+
+                window.end();
+            }
+        }
+
+        if let Some(window) = imgui::Window::new(im_str!("Mutate")).begin(&ui) {
+            if ui.button_with_size(im_str!("Mutate"), [128.0, 32.0]) {
+                self.snapshots.morph = None;
+                self.mutator.mutate(&mut self.midi.sliders);
+            }
+            ui.same_line();
+            if ui.button_with_size(im_str!("Undo"), [64.0, 32.0])
+                && !self.mutator.undo(&mut self.midi.sliders)
+            {
+                log::warn!("Nothing to undo");
+            }
+
+            imgui::Slider::new(im_str!("amount"))
+                .range(0.0..=1.0)
+                .build(&ui, &mut self.mutator.amount);
+
+            // -1 means no button triggers a mutation
+            let mut button = self.mutator.button.map_or(-1, |n| n as i32);
+            if ui.input_int(im_str!("button"), &mut button).build() {
+                self.mutator.button = match button {
+                    n if n >= 0 && (n as usize) < MIDI_N => Some(n as usize),
+                    _ => None,
+                };
+            }
+
+            ui.separator();
+            for k in 0..MIDI_N {
+                let token = ui.push_id(k as i32);
+                ui.checkbox(im_str!("##selected"), &mut self.mutator.selected[k]);
+                ui.same_line();
+                ui.text(format!("slider{}", k));
+                let [lo, hi] = &mut self.mutator.ranges[k];
+                ui.same_line();
+                ui.set_next_item_width(96.0);
+                imgui::Slider::new(im_str!("min"))
+                    .range(0.0..=1.0)
+                    .build(&ui, lo);
+                ui.same_line();
+                ui.set_next_item_width(96.0);
+                imgui::Slider::new(im_str!("max"))
+                    .range(0.0..=1.0)
+                    .build(&ui, hi);
+                token.pop();
+            }
+
+            window.end();
+        }
+
+        if let Some(window) = imgui::Window::new(im_str!("Snapshots")).begin(&ui) {
+            for k in 0..SNAPSHOT_SLOTS {
+                let token = ui.push_id(k as i32);
+                if ui.small_button(im_str!("store")) {
+                    self.snapshots.store(k, &self.midi.sliders);
+                }
+                ui.same_line();
+
+                match self.snapshots.slots[k] {
+                    Some(values) => {
+                        if ui.small_button(im_str!("recall")) {
+                            self.snapshots.morph = None;
+                            self.midi.sliders = values;
+                        }
+                        ui.same_line();
+                        ui.text(format!("slot {}", k));
+                    }
+                    None => ui.text(format!("slot {} (empty)", k)),
+                }
+                token.pop();
+            }
+
+            ui.separator();
+            let mut from = self.snapshots.from as i32;
+            let mut to = self.snapshots.to as i32;
+            let max = SNAPSHOT_SLOTS as i32 - 1;
+            ui.set_next_item_width(96.0);
+            if ui.input_int(im_str!("from"), &mut from).build() {
+                self.snapshots.from = from.clamp(0, max) as usize;
+            }
+            ui.set_next_item_width(96.0);
+            if ui.input_int(im_str!("to"), &mut to).build() {
+                self.snapshots.to = to.clamp(0, max) as usize;
+            }
+            ui.set_next_item_width(96.0);
+            if ui
+                .input_float(im_str!("beats"), &mut self.snapshots.beats)
+                .build()
+            {
+                self.snapshots.beats = self.snapshots.beats.max(0.0);
+            }
+
+            let beat = self.beat_sync.beat();
+            if ui.button_with_size(im_str!("Morph"), [128.0, 32.0])
+                && !self.snapshots.start_morph(beat)
+            {
+                log::warn!("Can't morph between empty snapshot slots");
+            }
+
+            let progress = self.snapshots.progress(beat).unwrap_or(0.0);
+            imgui::ProgressBar::new(progress).build(&ui);
+
+            window.end();
+        }
+
+        if let Some(layers) = &mut self.pipeline.layers {
+            if let Some(window) = imgui::Window::new(im_str!("Layers")).begin(&ui) {
+                for (k, layer) in layers.layers.iter_mut().enumerate() {
+                    let label = match layer.slider {
+                        Some(n) => format!("{} (slider{})", layer.pipeline, n),
+                        None => layer.pipeline.clone(),
+                    };
+
+                    let token = ui.push_id(k as i32);
+                    let cst = CString::new(label).unwrap();
+                    let ims = unsafe { imgui::ImStr::from_cstr_unchecked(&cst) };
+                    imgui::Slider::new(ims)
+                        .range(0.0..=1.0)
+                        .build(&ui, &mut layer.opacity);
+                    token.pop();
+                }
+
+                window.end();
+            }
+        }
+
+        if let Some(window) = imgui::Window::new(im_str!("Mixer")).begin(&ui) {
+            if ui.small_button(im_str!("reset")) {
+                self.mixer.reset();
+            }
+
+            let stages = self.pipeline.stages.iter().take(MIXER_CHANNELS);
+            for (k, stage) in stages.enumerate() {
+                let token = ui.push_id(k as i32);
+                ui.separator();
+                match &stage.target {
+                    Some(name) => ui.text(format!("{}: {}", k, name.to_string_lossy())),
+                    None => ui.text(format!("{}: screen", k)),
+                }
+
+                imgui::Slider::new(im_str!("opacity"))
+                    .range(0.0..=1.0)
+                    .build(&ui, &mut self.mixer.opacity[k]);
+
+                imgui::ColorEdit::new(im_str!("tint"), &mut self.mixer.tint[k]).build(&ui);
+
+                // -1 unlinks the channel from the midi sliders
+                let mut slider = self.mixer.sliders[k].map_or(-1, |n| n as i32);
+                if ui.input_int(im_str!("slider"), &mut slider).build() {
+                    self.mixer.sliders[k] = match slider {
+                        n if n >= 0 && (n as usize) < MIDI_N => Some(n as usize),
+                        _ => None,
+                    };
+                }
+
+                token.pop();
+            }
+
+            window.end();
+        }
+
         if let Some(window) = imgui::Window::new(im_str!("Audio")).begin(&ui) {
             ui.plot_lines(im_str!("left"), &self.audio.l_signal).build();
             ui.plot_lines(im_str!("right"), &self.audio.r_signal)
@@ -1331,6 +2557,39 @@ impl Jockey {
             ui.plot_lines(im_str!("nice R FFT"), self.audio.r_spectrum.as_slice())
                 .build();
 
+            ui.separator();
+            let mut latency = self.audio.latency();
+            if imgui::Slider::new(im_str!("latency [ms]"))
+                .range(0.0..=MAX_AUDIO_LATENCY)
+                .build(&ui, &mut latency)
+            {
+                self.audio.set_latency(latency);
+            }
+
+            match &mut self.calibration {
+                None => {
+                    if ui.small_button(im_str!("Calibrate")) {
+                        self.calibration = Some(LatencyCalibration::default());
+                    }
+                }
+                Some(calib) => {
+                    if ui.button_with_size(im_str!("Tap on the kick"), [128.0, 32.0]) {
+                        calib.tap(Instant::now());
+                    }
+                    ui.same_line();
+                    ui.text(format!("{} / {}", calib.matched(), CALIBRATION_TAPS));
+                    if ui.small_button(im_str!("Cancel")) {
+                        self.calibration = None;
+                    }
+                }
+            }
+
+            if let Some(correction) = self.calibration.as_ref().and_then(|c| c.correction()) {
+                self.audio.set_latency(self.audio.latency() + correction);
+                log::info!("Audio latency calibrated to {:.0} ms", self.audio.latency());
+                self.calibration = None;
+            }
+
             window.end();
         }
 
@@ -1347,6 +2606,12 @@ impl Jockey {
 
             imgui::ProgressBar::new(self.beat_sync.beat().fract()).build(&ui);
 
+            if let Some(kick) = &self.midi.kick {
+                imgui::ProgressBar::new(kick.value(Instant::now()))
+                    .overlay_text(im_str!("kick"))
+                    .build(&ui);
+            }
+
             window.end();
         }
 
@@ -1357,14 +2622,56 @@ impl Jockey {
                 frame_ms
             ));
 
-            ui.plot_lines(im_str!("dt [ms]"), &self.frame_perf.buffer)
-                .build();
+            draw_frame_plot(
+                &ui,
+                im_str!("dt [ms]"),
+                &self.frame_perf.buffer,
+                &self.frame_events,
+            );
+
+            let mut logging = self.perf_log.is_some();
+            if ui.checkbox(im_str!("log to file"), &mut logging) {
+                self.perf_log = match logging {
+                    true => open_perf_log(PerfLog::default_path()),
+                    false => None,
+                };
+            }
+
+            if let Some(perf_log) = &self.perf_log {
+                ui.same_line();
+                ui.text(perf_log.path().to_string_lossy());
+            }
+
+            if let Some(presenter) = &self.presenter {
+                ui.text(format!(
+                    "GPU: {:.2} ms, presenting every {} of {:.2} ms",
+                    presenter.gpu_ms.get(),
+                    presenter.interval,
+                    presenter.refresh_ms
+                ));
+            }
+
+            if let Some(quality) = &self.pipeline.quality {
+                ui.text(format!(
+                    "Quality: {:.2} (target {:.2} ms)",
+                    quality.value, quality.target_ms
+                ));
+            }
+
+            if let Some(warning) = &self.watchdog.warning {
+                ui.text_colored([1.0, 0.2, 0.2, 1.0], warning);
+            }
 
             let mut stage_sum_ms = 0.0;
             for (k, stage) in self.pipeline.stages.iter().enumerate() {
                 let stage_ms = stage.perf.get();
                 stage_sum_ms += stage_ms;
-                if let Some(tex_name) = stage.target.as_ref() {
+                if stage.disabled {
+                    ui.text_colored(
+                        [1.0, 0.2, 0.2, 1.0],
+                        format!("Stage {}: disabled by watchdog", k),
+                    );
+                } else if let Some(tex_name) = stage.target.as_ref() {
                     ui.text(format!(
                         "Stage {}: {:.4} ms (-> {:?})",
                         k, stage_ms, tex_name
@@ -1383,53 +2690,162 @@ impl Jockey {
             window.end();
         }
 
-        if let Some(window) = imgui::Window::new(im_str!("Build Output")).begin(&ui) {
-            ui.text(&self.console);
+        if let Some(scopes) = &mut self.scopes {
+            scopes.visible = false;
+            if let Some(window) = imgui::Window::new(im_str!("Scopes")).begin(&ui) {
+                scopes.visible = true;
+
+                let size = [ui.content_region_avail()[0], 64.0];
+                ui.plot_histogram(im_str!("luma"), &scopes.luma)
+                    .graph_size(size)
+                    .scale_min(0.0)
+                    .scale_max(1.0)
+                    .build();
+                ui.plot_histogram(im_str!("red"), &scopes.red)
+                    .graph_size(size)
+                    .scale_min(0.0)
+                    .scale_max(1.0)
+                    .build();
+                ui.plot_histogram(im_str!("green"), &scopes.green)
+                    .graph_size(size)
+                    .scale_min(0.0)
+                    .scale_max(1.0)
+                    .build();
+                ui.plot_histogram(im_str!("blue"), &scopes.blue)
+                    .graph_size(size)
+                    .scale_min(0.0)
+                    .scale_max(1.0)
+                    .build();
+
+                ui.separator();
+                ui.text("Waveform");
+                draw_density_plot(&ui, &scopes.waveform, WAVEFORM_COLUMNS, [size[0], 128.0]);
+
+                ui.text("Vectorscope");
+                draw_density_plot(&ui, &scopes.vectorscope, VECTORSCOPE_SIZE, [192.0, 192.0]);
+
+                window.end();
+            }
+        }
+
+        if let Some(window) = imgui::Window::new(im_str!("Sources")).begin(&ui) {
+            let requests = &self.pipeline.requested_ndi_sources;
+            if requests.is_empty() {
+                ui.text("No NDI sources requested");
+            } else if !self.ndi.is_available() {
+                ui.text_colored([1.0, 0.2, 0.2, 1.0], "NDI runtime not found");
+            }
+
+            let mut names = requests.iter().collect::<Vec<_>>();
+            names.sort_by_key(|(name, _)| *name);
+            let statuses = self
+                .ndi
+                .status(names.iter().map(|(_, input)| &input.source));
+            for ((tex_name, _), status) in names.iter().zip(statuses) {
+                ui.text_colored(status.state.color(), format!("[{}]", status.state.label()));
+                ui.same_line();
+                ui.text(format!(
+                    "{}: {}",
+                    tex_name.to_string_lossy(),
+                    status.request
+                ));
+
+                if let Some(source) = &status.source {
+                    let [w, h] = status.resolution;
+                    ui.text_disabled(format!("{}  {}x{}  {:.1} fps", source, w, h, status.fps));
+                }
+
+                if status.reconnects != 0 {
+                    ui.text_disabled(format!("{} reconnects", status.reconnects));
+                }
+            }
+
             window.end();
         }
 
-        // update ui
-        self.ctx
-            .platform
-            .prepare_render(&ui, self.ctx.ui_context.window());
+        if let Some(window) = imgui::Window::new(im_str!("Texture Cache")).begin(&ui) {
+            let to_mb = |bytes: usize| bytes as f32 / megabytes(1) as f32;
+            match Cache::budget() {
+                Some(budget) => ui.text(format!(
+                    "{:.1} of {:.1} MB used",
+                    to_mb(Cache::size()),
+                    to_mb(budget)
+                )),
+                None => ui.text(format!("{:.1} MB used", to_mb(Cache::size()))),
+            }
+            ui.text(format!(
+                "{} render targets lent to the build",
+                TargetPool::len()
+            ));
 
-        // render and swap buffers
-        self.ctx.renderer.render(ui);
-        self.ctx.ui_context.swap_buffers().unwrap();
-    }
+            if ui.small_button(im_str!("Clear cache")) {
+                Cache::clear();
+            }
 
-    pub fn save_frame(&mut self) {
-        take_mut::take(&mut self.ctx.context, |s| unsafe {
-            s.make_current().unwrap()
-        });
+            ui.separator();
+            for entry in Cache::entries() {
+                let text = format!("{:.1} MB  {}", to_mb(entry.bytes), entry.path);
+                match entry.in_use {
+                    true => ui.text(text),
+                    false => ui.text_disabled(text),
+                }
+            }
 
-        let screen_size = self.ctx.context.window().inner_size();
-        let (width, height) = (screen_size.width as u32, screen_size.height as u32);
+            window.end();
+        }
 
-        let mut img = image::ImageBuffer::<image::Rgb<u8>, Vec<u8>>::new(width, height);
-        let data = img.as_flat_samples_mut().as_mut_slice().as_mut_ptr();
+        if let Some(window) = imgui::Window::new(im_str!("Config")).begin(&ui) {
+            if ui.small_button(im_str!("Reload")) {
+                unsafe { PROJECT_STALE.store(true, Ordering::Release) };
+            }
 
-        unsafe {
-            gl::ReadnPixels(
-                0,
-                0,
-                width as _,
-                height as _,
-                gl::RGB,
-                gl::UNSIGNED_BYTE,
-                (3 * width * height) as _,
-                data as _,
-            );
+            ui.separator();
+            match self.config.sources.as_slice() {
+                [] => ui.text("No config file found"),
+                sources => {
+                    for path in sources {
+                        ui.text(path.to_string_lossy());
+                    }
+                }
+            }
+
+            ui.separator();
+            match serde_yaml::to_string(&self.config.effective) {
+                Ok(text) => ui.text(text),
+                Err(err) => ui.text(format!("Failed to show config: {}", err)),
+            }
+
+            window.end();
         }
 
-        image::imageops::flip_vertical_in_place(&mut img);
+        if let Some(window) = imgui::Window::new(im_str!("Build Output")).begin(&ui) {
+            if ui.small_button(im_str!("Export diagnostics")) {
+                let path = diagnostics_file_name();
+                match write_diagnostics_bundle(&path, None) {
+                    Ok(()) => log::info!("Diagnostics have been written to {}", path),
+                    Err(err) => log::error!("Failed to write diagnostics: {}", err),
+                }
+            }
+
+            ui.text(&self.console);
+            window.end();
+        }
 
-        let mut hasher = DefaultHasher::new();
-        Instant::now().hash(&mut hasher);
-        img.hash(&mut hasher);
-        let hash = hasher.finish();
+        // update ui
+        ui_ctx.platform.prepare_render(&ui, ui_ctx.context.window());
+
+        // render and swap buffers
+        ui_ctx.renderer.render(ui);
+        swap_buffers(&ui_ctx.context);
+    }
+
+    /// Saves a screenshot of the next frame to the working directory.
+    pub fn save_frame(&mut self) {
+        self.capture.request();
+    }
 
-        let file_name = format!("frame-{}.png", hash);
-        img.save(file_name).unwrap();
+    /// Writes the timings of every following frame to a file.
+    pub fn start_perf_log(&mut self, path: PathBuf) {
+        self.perf_log = open_perf_log(path);
     }
 }
@@ -0,0 +1,105 @@
+use std::time::Instant;
+
+use super::MIDI_N;
+
+/// Number of mutations which can be undone
+pub const MUTATE_UNDO_DEPTH: usize = 32;
+
+/// Randomizes selected sliders within their ranges.
+#[derive(Debug, Clone)]
+pub struct Mutator {
+    pub selected: [bool; MIDI_N],
+    pub ranges: [[f32; 2]; MIDI_N],
+    /// How far the sliders move towards the random values, between 0 and 1.
+    pub amount: f32,
+    /// Button which triggers a mutation when pressed.
+    pub button: Option<usize>,
+    undo: Vec<[f32; MIDI_N]>,
+    last_press: Option<(usize, u32)>,
+}
+
+impl Default for Mutator {
+    fn default() -> Self {
+        Self {
+            selected: [true; MIDI_N],
+            ranges: [[0.0, 1.0]; MIDI_N],
+            amount: 1.0,
+            button: None,
+            undo: Vec::new(),
+            last_press: None,
+        }
+    }
+}
+
+impl Mutator {
+    /// Moves the selected sliders towards random values within their ranges.
+    pub fn mutate(&mut self, sliders: &mut [f32; MIDI_N]) {
+        if self.undo.len() == MUTATE_UNDO_DEPTH {
+            self.undo.remove(0);
+        }
+        self.undo.push(*sliders);
+
+        for (k, slider) in sliders.iter_mut().enumerate() {
+            if !self.selected[k] {
+                continue;
+            }
+
+            let [lo, hi] = self.ranges[k];
+            let target = lo + rand::random::<f32>() * (hi - lo);
+            *slider += self.amount * (target - *slider);
+        }
+    }
+
+    /// Restores the sliders from before the last mutation.
+    ///
+    /// Returns false if there is nothing to undo.
+    pub fn undo(&mut self, sliders: &mut [f32; MIDI_N]) -> bool {
+        match self.undo.pop() {
+            Some(values) => {
+                *sliders = values;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks whether the trigger button was pressed since the last call.
+    pub fn triggered(&mut self, buttons: &[(f32, Instant, Instant, u32)]) -> bool {
+        let press = self.button.and_then(|k| Some((k, buttons.get(k)?.3)));
+
+        // switching to another button doesn't count as a press
+        let pressed = match (self.last_press, press) {
+            (Some((a, x)), Some((b, y))) => a == b && x != y,
+            _ => false,
+        };
+
+        self.last_press = press;
+        pressed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stays_in_range() {
+        let mut mutator = Mutator::default();
+        mutator.selected[1] = false;
+        mutator.ranges[0] = [0.25, 0.5];
+
+        let mut sliders = [0.75; MIDI_N];
+        for _ in 0..100 {
+            mutator.mutate(&mut sliders);
+            assert!((0.25..=0.5).contains(&sliders[0]), "{}", sliders[0]);
+            assert_eq!(sliders[1], 0.75);
+        }
+
+        for _ in 0..MUTATE_UNDO_DEPTH {
+            assert!(mutator.undo(&mut sliders));
+        }
+
+        assert!(!mutator.undo(&mut sliders));
+        assert_ne!(sliders[0], 0.75);
+    }
+}
@@ -9,12 +9,123 @@ use super::*;
 
 static NDI_RECEIVER_NAME: &str = "Sh4derJockey";
 
+/// Time without a new frame after which a source counts as stalled.
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Time without a new frame after which the receiver is recreated.
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often stalled and missing sources are checked.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceState {
+    /// No source with a matching name has been found.
+    Missing,
+    /// Connected, but no frame has arrived yet.
+    Connecting,
+    Connected,
+    /// No frame has arrived for a while.
+    Stalled,
+}
+
+impl SourceState {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Missing => "not found",
+            Self::Connecting => "connecting",
+            Self::Connected => "connected",
+            Self::Stalled => "stalled",
+        }
+    }
+
+    pub fn color(self) -> [f32; 4] {
+        match self {
+            Self::Missing => [1.0, 0.2, 0.2, 1.0],
+            Self::Connecting => [0.6, 0.6, 0.6, 1.0],
+            Self::Connected => [0.2, 0.9, 0.3, 1.0],
+            Self::Stalled => [1.0, 0.7, 0.1, 1.0],
+        }
+    }
+}
+
+/// Health of a requested source, as shown in the sources window.
+#[derive(Debug, Clone)]
+pub struct SourceStatus {
+    pub request: String,
+    /// Full name of the source the request matched.
+    pub source: Option<String>,
+    pub state: SourceState,
+    pub fps: f32,
+    pub resolution: [u32; 2],
+    pub reconnects: u32,
+}
+
+/// The latest frame of a source, shared with its receiver thread.
+///
+/// Frames are reference counted, so the render thread can hold on to them
+/// without blocking the receiver thread.
+#[derive(Debug)]
+struct Feed {
+    image: Arc<image::RgbaImage>,
+    /// The frame before `image`, kept for blending.
+    previous: Option<Arc<image::RgbaImage>>,
+    source: String,
+    connected_at: Instant,
+    last_frame: Option<Instant>,
+    fps: f32,
+}
+
+impl Feed {
+    fn new(source: String, now: Instant) -> Self {
+        Self {
+            image: Arc::new(image::ImageBuffer::new(1, 1)),
+            previous: None,
+            source,
+            connected_at: now,
+            last_frame: None,
+            fps: 0.0,
+        }
+    }
+
+    fn push(&mut self, image: image::RgbaImage, now: Instant) {
+        if let Some(last) = self.last_frame {
+            let rate = 1.0 / (now - last).as_secs_f32().max(1e-3);
+            self.fps = match self.fps {
+                x if x == 0.0 => rate,
+                x => 0.9 * x + 0.1 * rate,
+            };
+        }
+
+        self.previous = Some(std::mem::replace(&mut self.image, Arc::new(image)));
+        self.last_frame = Some(now);
+    }
+
+    /// Time since the last frame, or since connecting if there was none.
+    fn silence(&self, now: Instant) -> Duration {
+        now - self.last_frame.unwrap_or(self.connected_at)
+    }
+
+    fn state(&self, now: Instant) -> SourceState {
+        match (self.last_frame, self.silence(now) < STALL_TIMEOUT) {
+            (_, false) => SourceState::Stalled,
+            (Some(_), true) => SourceState::Connected,
+            (None, true) => SourceState::Connecting,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Ndi {
     sources: Arc<Mutex<Vec<ndi::Source>>>,
-    videos: HashMap<String, Arc<Mutex<image::DynamicImage>>>,
+    videos: HashMap<String, Arc<Mutex<Feed>>>,
+    reconnects: HashMap<String, u32>,
+    last_check: Instant,
     searching: bool,
     disabled: bool,
+    /// Blended frames are written into this, so it isn't allocated anew
+    /// every frame.
+    blend_buffer: Vec<u8>,
 }
 
 impl Ndi {
@@ -64,11 +175,19 @@ impl Ndi {
         Self {
             sources: Default::default(),
             videos: HashMap::new(),
+            reconnects: HashMap::new(),
+            last_check: Instant::now(),
             searching: false,
             disabled: !found,
+            blend_buffer: Vec::new(),
         }
     }
 
+    /// Returns false if the NDI runtime couldn't be loaded.
+    pub fn is_available(&self) -> bool {
+        !self.disabled
+    }
+
     fn search_sources(&self, blocking: bool) -> Result<(), FindCreateError> {
         let sources = self.sources.clone();
 
@@ -114,37 +233,38 @@ impl Ndi {
         Ok(())
     }
 
-    pub fn connect<I, T>(&mut self, requested: &I) -> Result<(), ndi::RecvCreateError>
+    /// Connects to the requested sources, returns the number of new
+    /// connections.
+    pub fn connect<'a, I>(&mut self, requested: &I) -> Result<usize, String>
     where
-        I: ExactSizeIterator<Item = T> + Clone,
-        T: AsRef<str>,
+        I: ExactSizeIterator<Item = &'a NdiInput> + Clone,
     {
         if self.disabled || requested.len() == 0 {
-            return Ok(());
+            return Ok(0);
         }
 
         let sources = if self.searching {
             self.sources.lock().unwrap()
         } else {
-            self.search_sources(true).unwrap();
+            let find_err = |e: FindCreateError| format!("Failed to search for sources: {:?}", e);
+            self.search_sources(true).map_err(find_err)?;
 
             // take lock before spawning the search thread
             let res = self.sources.lock().unwrap();
-            self.search_sources(false).unwrap();
+            self.search_sources(false).map_err(find_err)?;
             self.searching = true;
             res
         };
 
         log::info!("Found NDI sources: {:?}", sources);
 
-        let src: Vec<(String, &ndi::Source)> = sources
+        let src: Vec<(&NdiInput, &ndi::Source)> = sources
             .iter()
             .filter_map(|src| {
                 let src_name = src.get_name();
-                for pat in requested.clone() {
-                    let pat = pat.as_ref();
-                    if src_name.contains(pat) {
-                        return Some((pat.into(), src));
+                for input in requested.clone() {
+                    if src_name.contains(&input.source) {
+                        return Some((input, src));
                     }
                 }
                 None
@@ -158,26 +278,31 @@ impl Ndi {
         );
 
         self.videos
-            .retain(|pre_req, _| src.iter().find(|(req, _)| req == pre_req).is_some());
+            .retain(|pre_req, _| src.iter().find(|(req, _)| &req.source == pre_req).is_some());
 
-        for (req, source) in src {
-            if self.videos.get(&req).is_some() {
+        let mut connected = 0;
+        for (input, source) in src {
+            if self.videos.get(&input.source).is_some() {
                 continue;
             }
 
+            connected += 1;
+
             let mut recv = ndi::RecvBuilder::new()
                 .color_format(ndi::RecvColorFormat::RGBX_RGBA)
                 .ndi_recv_name(NDI_RECEIVER_NAME.to_string())
-                .build()?;
+                .build()
+                .map_err(|e| format!("Failed to create receiver: {:?}", e))?;
 
             recv.connect(&source);
 
-            let video = Arc::new(Mutex::new(image::DynamicImage::ImageRgba8(
-                image::ImageBuffer::new(1, 1),
-            )));
+            let feed = Feed::new(source.get_name(), Instant::now());
+            let video = Arc::new(Mutex::new(feed));
 
             let weak = Arc::downgrade(&video);
-            self.videos.insert(req, video);
+            self.videos.insert(input.source.clone(), video);
+            let (source_alpha, alpha) = (input.source_alpha, input.alpha);
+            let region = input.region;
 
             log::info!("Connected to NDI source: {}", source.get_name());
 
@@ -192,13 +317,16 @@ impl Ndi {
                         continue;
                     }
 
-                    let img = match video_data {
-                        Some(video) => convert_format(video).flipv(),
+                    let mut img = match video_data {
+                        Some(video) => region.apply(convert_format(video).flipv().into_rgba8()),
                         _ => continue,
                     };
 
+                    // frames without alpha arrive as opaque RGBX
+                    convert_alpha(&mut img, source_alpha, alpha);
+
                     if let Some(strong) = weak.upgrade() {
-                        *strong.lock().unwrap() = img;
+                        strong.lock().unwrap().push(img, Instant::now());
                     } else {
                         break;
                     }
@@ -208,26 +336,132 @@ impl Ndi {
             });
         }
 
-        Ok(())
+        Ok(connected)
+    }
+
+    /// Drops receivers which haven't delivered a frame for a while and
+    /// connects them again, together with requested sources which weren't
+    /// found before. Returns the number of new connections.
+    pub fn reconnect_stalled<'a, I>(&mut self, requested: &I) -> Result<usize, String>
+    where
+        I: ExactSizeIterator<Item = &'a NdiInput> + Clone,
+    {
+        if self.disabled || self.last_check.elapsed() < HEALTH_CHECK_INTERVAL {
+            return Ok(0);
+        }
+
+        let now = Instant::now();
+        self.last_check = now;
+
+        let reconnects = &mut self.reconnects;
+        self.videos.retain(|req, video| {
+            let video = video.lock().unwrap();
+            if video.silence(now) < RECONNECT_TIMEOUT {
+                return true;
+            }
+
+            log::warn!(
+                "NDI source {} sent no frames for {:.0?}, reconnecting",
+                video.source,
+                video.silence(now)
+            );
+            *reconnects.entry(req.clone()).or_default() += 1;
+            false
+        });
+
+        let missing = requested
+            .clone()
+            .any(|req| !self.videos.contains_key(&req.source));
+
+        match missing {
+            true => self.connect(requested),
+            false => Ok(0),
+        }
+    }
+
+    /// Describes the health of every requested source.
+    pub fn status<'a>(&self, requested: impl Iterator<Item = &'a String>) -> Vec<SourceStatus> {
+        let now = Instant::now();
+        requested
+            .map(|req| {
+                let reconnects = self.reconnects.get(req).copied().unwrap_or(0);
+                match self.videos.get(req) {
+                    Some(video) => {
+                        let video = video.lock().unwrap();
+                        SourceStatus {
+                            request: req.clone(),
+                            source: Some(video.source.clone()),
+                            state: video.state(now),
+                            fps: video.fps,
+                            resolution: video.image.dimensions().into(),
+                            reconnects,
+                        }
+                    }
+                    None => SourceStatus {
+                        request: req.clone(),
+                        source: None,
+                        state: SourceState::Missing,
+                        fps: 0.0,
+                        resolution: [0, 0],
+                        reconnects,
+                    },
+                }
+            })
+            .collect()
     }
 
-    pub fn update_texture(&self, tex_name: &String, tex: &mut Texture2D) {
-        if let Some(video) = self.videos.get(tex_name) {
-            let video = video.lock().unwrap().to_rgba8();
-            if tex.resolution() != [video.width(), video.height(), 0] {
-                *tex = Texture2D::with_params(
-                    [video.width(), video.height()],
-                    tex.min_filter,
-                    tex.mag_filter,
-                    tex.wrap_mode,
-                    tex.format,
-                    tex.mipmap,
-                    video.as_ptr() as _,
-                );
-            } else {
-                tex.write(video.as_ptr() as _);
+    /// Returns the seconds since the latest frame of a source arrived and
+    /// the frame rate of the source.
+    pub fn source_time(&self, source: &str) -> Option<[f32; 2]> {
+        let video = self.videos.get(source)?.lock().unwrap();
+        let last = video.last_frame?;
+        Some([last.elapsed().as_secs_f32(), video.fps])
+    }
+
+    /// Writes the latest frame of a source into its texture, returns true
+    /// if the texture had to be reallocated for a new resolution.
+    pub fn update_texture(&mut self, input: &NdiInput, tex: &mut Texture2D) -> bool {
+        // the feed is only locked to take out the frames, blending and
+        // uploading them doesn't block the receiver thread
+        let (image, previous, last_frame, fps) = match self.videos.get(&input.source) {
+            Some(s) => {
+                let video = s.lock().unwrap();
+                let image = Arc::clone(&video.image);
+                (image, video.previous.clone(), video.last_frame, video.fps)
+            }
+            None => return false,
+        };
+
+        let (width, height) = image.dimensions();
+        let pixels = match (previous, input.interpolation, last_frame) {
+            (Some(prev), FrameInterpolation::Blend, Some(last))
+                if prev.dimensions() == (width, height) && fps > 0.0 =>
+            {
+                // fade in the latest frame over one frame of the source
+                let t = last.elapsed().as_secs_f32() * fps;
+                self.blend_buffer.resize(prev.len(), 0);
+                blend_frames(&prev, &image, t, &mut self.blend_buffer);
+                self.blend_buffer.as_slice()
             }
+            _ => image.as_raw().as_slice(),
+        };
+
+        if tex.resolution() != [width, height, 0] {
+            *tex = Texture2D::with_params(
+                [width, height],
+                tex.min_filter,
+                tex.mag_filter,
+                tex.wrap_mode,
+                tex.format,
+                tex.mipmap,
+                pixels.as_ptr() as _,
+            );
+            return true;
         }
+
+        // both frames have the resolution of the texture
+        unsafe { tex.write(pixels.as_ptr() as _) };
+        false
     }
 }
 
@@ -280,3 +514,27 @@ fn convert_format(video: ndi::VideoData) -> image::DynamicImage {
         _ => panic!("Failed to convert image"),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn source_health() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut feed = Feed::new("CAM (Studio)".into(), start);
+        assert_eq!(feed.state(start + second), SourceState::Connecting);
+        assert_eq!(feed.state(start + 3 * second), SourceState::Stalled);
+
+        let frame = || image::ImageBuffer::new(4, 2);
+        for k in 1..=30 {
+            feed.push(frame(), start + k * second / 30);
+        }
+        assert!((feed.fps - 30.0).abs() < 0.5, "fps is {}", feed.fps);
+        assert_eq!(feed.state(start + 2 * second), SourceState::Connected);
+        assert_eq!(feed.state(start + 4 * second), SourceState::Stalled);
+        assert_eq!(feed.silence(start + 4 * second), 3 * second);
+        assert!(feed.previous.is_some());
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::FrameEvent;
+
+/// Timings of a single frame, as written to the log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PerfRecord<'a> {
+    pub frame: u64,
+    pub time: f32,
+    pub frame_ms: f32,
+    pub stage_ms: Vec<f32>,
+    pub events: &'a [FrameEvent],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PerfLogFormat {
+    Csv,
+    /// One JSON object per line, so the file stays readable after a crash.
+    Json,
+}
+
+/// Writes the frame time and the timings of every stage to a file, one
+/// record per frame.
+///
+/// Files ending in `.json` are written as JSON lines, everything else as
+/// CSV with the stage timings joined by semicolons.
+#[derive(Debug)]
+pub struct PerfLog {
+    path: PathBuf,
+    format: PerfLogFormat,
+    writer: BufWriter<File>,
+}
+
+impl PerfLog {
+    pub fn create(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let format = match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => PerfLogFormat::Json,
+            _ => PerfLogFormat::Csv,
+        };
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+        if format == PerfLogFormat::Csv {
+            writeln!(writer, "frame,time,frame_ms,stage_ms,events")?;
+        }
+
+        log::info!("Logging frame timings to {:?}", path);
+        Ok(Self {
+            path,
+            format,
+            writer,
+        })
+    }
+
+    /// Picks a file name in the working directory which includes the
+    /// current time.
+    pub fn default_path() -> PathBuf {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        PathBuf::from(format!("perf-{}.csv", stamp))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&mut self, record: &PerfRecord) -> std::io::Result<()> {
+        let line = match self.format {
+            PerfLogFormat::Csv => format_csv(record),
+            PerfLogFormat::Json => format_json(record),
+        };
+
+        writeln!(self.writer, "{}", line)
+    }
+}
+
+/// Creates a performance log, logging the error if that fails.
+pub fn open_perf_log(path: PathBuf) -> Option<PerfLog> {
+    match PerfLog::create(path) {
+        Ok(s) => Some(s),
+        Err(err) => {
+            log::error!("Failed to create performance log: {}", err);
+            None
+        }
+    }
+}
+
+fn format_csv(record: &PerfRecord) -> String {
+    let stages: Vec<_> = record
+        .stage_ms
+        .iter()
+        .map(|ms| format!("{:.4}", ms))
+        .collect();
+    let events: Vec<_> = record.events.iter().map(|e| e.name()).collect();
+    format!(
+        "{},{:.4},{:.4},{},{}",
+        record.frame,
+        record.time,
+        record.frame_ms,
+        stages.join(";"),
+        events.join(";")
+    )
+}
+
+fn format_json(record: &PerfRecord) -> String {
+    let stages: Vec<_> = record
+        .stage_ms
+        .iter()
+        .map(|ms| format!("{:.4}", ms))
+        .collect();
+    let events: Vec<_> = record
+        .events
+        .iter()
+        .map(|e| format!("{:?}", e.name()))
+        .collect();
+
+    format!(
+        "{{\"frame\":{},\"time\":{:.4},\"frame_ms\":{:.4},\"stage_ms\":[{}],\"events\":[{}]}}",
+        record.frame,
+        record.time,
+        record.frame_ms,
+        stages.join(","),
+        events.join(",")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_records() {
+        let record = PerfRecord {
+            frame: 7,
+            time: 1.5,
+            frame_ms: 16.0,
+            stage_ms: vec![0.25, 2.0],
+            events: &[FrameEvent::PipelineRebuild, FrameEvent::Screenshot],
+        };
+
+        assert_eq!(
+            format_csv(&record),
+            "7,1.5000,16.0000,0.2500;2.0000,pipeline rebuild;screenshot"
+        );
+        assert_eq!(
+            format_json(&record),
+            "{\"frame\":7,\"time\":1.5000,\"frame_ms\":16.0000,\"stage_ms\":[0.2500,2.0000],\
+             \"events\":[\"pipeline rebuild\",\"screenshot\"]}"
+        );
+    }
+}
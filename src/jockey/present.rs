@@ -0,0 +1,158 @@
+use std::ffi::CString;
+
+use gl::types::*;
+use lazy_static::lazy_static;
+
+use crate::util::*;
+
+/// Maximum number of display refreshes a single rendered frame is spread over
+const MAX_INTERVAL: u32 = 4;
+
+lazy_static! {
+    static ref PREVIOUS_FRAME_NAME: CString = CString::new("previous_frame").unwrap();
+    static ref CURRENT_FRAME_NAME: CString = CString::new("current_frame").unwrap();
+    static ref BLEND_NAME: CString = CString::new("blend").unwrap();
+}
+
+/// Smooths out the output if the pipeline is slower than the display.
+///
+/// The pipeline renders into one of two offscreen frames instead of the
+/// screen. The presenter measures how long the GPU takes for a frame and,
+/// if it doesn't fit into a single display refresh, only lets the pipeline
+/// render every few refreshes. In between, it re-presents the last two
+/// frames with a cheap cross-fade, so the output moves at an even pace
+/// instead of stuttering on vsync.
+#[derive(Debug)]
+pub struct Presenter {
+    frames: [FrameBuffer; 2],
+    current: usize,
+    pass: FullscreenPass,
+    query: GLuint,
+    query_pending: bool,
+    pub gpu_ms: RunningAverage<f32, 16>,
+    pub refresh_ms: f32,
+    pub interval: u32,
+    phase: u32,
+}
+
+impl Presenter {
+    pub fn new(width: u32, height: u32, refresh_ms: f32) -> Self {
+        const PRESENT_FRAG: &str = include_str!("shaders/present.frag");
+
+        let pass = FullscreenPass::new(PRESENT_FRAG).unwrap();
+
+        let mut query = 0;
+        unsafe {
+            gl::GenQueries(1, &mut query);
+            gl_debug_check!();
+        }
+
+        Self {
+            frames: [
+                FrameBuffer::new(width, height),
+                FrameBuffer::new(width, height),
+            ],
+            current: 0,
+            pass,
+            query,
+            query_pending: false,
+            gpu_ms: RunningAverage::new(),
+            refresh_ms,
+            interval: 1,
+            phase: 0,
+        }
+    }
+
+    /// Resizes both frames, if the screen size has changed.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let res = self.frames[0].resolution();
+        if res[0] == width.max(1) && res[1] == height.max(1) {
+            return;
+        }
+
+        self.frames = [
+            FrameBuffer::new(width, height),
+            FrameBuffer::new(width, height),
+        ];
+    }
+
+    /// Returns `true` if the pipeline should render a new frame.
+    pub fn should_render(&self) -> bool {
+        self.phase == 0
+    }
+
+    /// Prepares a new frame and returns the framebuffer to render it into.
+    pub fn begin_frame(&mut self) -> GLuint {
+        unsafe {
+            // fetch the gpu time of the previous frame
+            if self.query_pending {
+                let mut available = 0;
+                gl::GetQueryObjectiv(self.query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+                if available != 0 {
+                    let mut nanos = 0;
+                    gl::GetQueryObjectui64v(self.query, gl::QUERY_RESULT, &mut nanos);
+                    self.gpu_ms.push(nanos as f32 / 1_000_000.0);
+                    self.query_pending = false;
+                }
+            }
+
+            if !self.query_pending {
+                gl::BeginQuery(gl::TIME_ELAPSED, self.query);
+            }
+            gl_debug_check!();
+        }
+
+        // pick the number of refreshes this frame is spread over
+        let frames_needed = (self.gpu_ms.get() / self.refresh_ms).ceil() as u32;
+        self.interval = frames_needed.clamp(1, MAX_INTERVAL);
+
+        self.current = 1 - self.current;
+        self.frames[self.current].fb_id
+    }
+
+    pub fn end_frame(&mut self) {
+        if self.query_pending {
+            return;
+        }
+
+        unsafe {
+            gl::EndQuery(gl::TIME_ELAPSED);
+            gl_debug_check!();
+        }
+
+        self.query_pending = true;
+    }
+
+    /// Draws a blend of the last two frames into the given framebuffer.
+    pub fn present(&mut self, vao: GLuint, target_fb: GLuint, width: u32, height: u32) {
+        self.phase += 1;
+        let blend = self.phase as f32 / self.interval as f32;
+        if self.phase >= self.interval {
+            self.phase = 0;
+        }
+
+        let previous = &self.frames[1 - self.current];
+        let current = &self.frames[self.current];
+
+        self.pass
+            .draw(vao, target_fb, width, height, |pass| unsafe {
+                gl::ActiveTexture(gl::TEXTURE0);
+                previous.bind(0);
+                gl::Uniform1i(pass.location(&PREVIOUS_FRAME_NAME), 0);
+
+                gl::ActiveTexture(gl::TEXTURE1);
+                current.bind(1);
+                gl::Uniform1i(pass.location(&CURRENT_FRAME_NAME), 1);
+
+                gl::Uniform1f(pass.location(&BLEND_NAME), blend.min(1.0));
+            });
+    }
+}
+
+impl Drop for Presenter {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteQueries(1, &self.query);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::ffi::CString;
+
+use gl::types::*;
+use lazy_static::lazy_static;
+
+use crate::util::*;
+
+lazy_static! {
+    static ref EQUIRECT_NAME: CString = CString::new("equirect").unwrap();
+    static ref FOV_NAME: CString = CString::new("fov").unwrap();
+}
+
+/// The projection the output is meant for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectionMode {
+    Flat = 0,
+    Equirect = 1,
+    Fisheye = 2,
+}
+
+impl ProjectionMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "flat" => Some(Self::Flat),
+            "equirect" => Some(Self::Equirect),
+            "fisheye" => Some(Self::Fisheye),
+            _ => None,
+        }
+    }
+}
+
+/// Output projection settings from the config file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProjectionConfig {
+    pub mode: ProjectionMode,
+    pub fov: f32,
+    pub native: bool,
+}
+
+/// Warps an equirectangular image into a fisheye domemaster.
+///
+/// Stages rendering to the screen draw into an equirectangular buffer
+/// instead, which is then mapped onto the output by a built-in final pass.
+#[derive(Debug)]
+pub struct Projection {
+    pub config: ProjectionConfig,
+    buffer: FrameBuffer,
+    pass: FullscreenPass,
+}
+
+impl Projection {
+    pub fn new(config: ProjectionConfig, width: u32, height: u32) -> Self {
+        const PROJECTION_FRAG: &str = include_str!("shaders/projection.frag");
+
+        let pass = FullscreenPass::new(PROJECTION_FRAG).unwrap();
+
+        let (buffer_width, buffer_height) = Self::buffer_size_for(width, height);
+        let buffer = Self::equirect_buffer(buffer_width, buffer_height);
+
+        Self {
+            config,
+            buffer,
+            pass,
+        }
+    }
+
+    fn equirect_buffer(width: u32, height: u32) -> FrameBuffer {
+        FrameBuffer::with_params(
+            width,
+            height,
+            gl::LINEAR,
+            gl::LINEAR,
+            gl::REPEAT,
+            false,
+            false,
+        )
+    }
+
+    fn buffer_size_for(width: u32, height: u32) -> (u32, u32) {
+        let size = width.max(height).max(1);
+        (2 * size, size)
+    }
+
+    /// Resolution of the equirectangular buffer for a given output size
+    pub fn buffer_size(&self, width: u32, height: u32) -> (u32, u32) {
+        Self::buffer_size_for(width, height)
+    }
+
+    /// Resizes the equirectangular buffer, if the output size has changed.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let (buffer_width, buffer_height) = self.buffer_size(width, height);
+        let res = self.buffer.resolution();
+        if res[0] == buffer_width && res[1] == buffer_height {
+            return;
+        }
+
+        self.buffer = Self::equirect_buffer(buffer_width, buffer_height);
+    }
+
+    pub fn framebuffer(&self) -> GLuint {
+        self.buffer.fb_id
+    }
+
+    /// Draws the fisheye view of the equirectangular buffer.
+    pub fn warp(&self, vao: GLuint, target_fb: GLuint, width: u32, height: u32) {
+        self.pass
+            .draw(vao, target_fb, width, height, |pass| unsafe {
+                gl::ActiveTexture(gl::TEXTURE0);
+                self.buffer.bind(0);
+                gl::Uniform1i(pass.location(&EQUIRECT_NAME), 0);
+
+                gl::Uniform1f(pass.location(&FOV_NAME), self.config.fov.to_radians());
+            });
+    }
+}
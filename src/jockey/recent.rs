@@ -0,0 +1,75 @@
+use std::path::{Path, PathBuf};
+
+/// Maximum number of folders kept in the list of recent projects
+pub const RECENT_PROJECTS: usize = 10;
+
+/// The project folders which were opened last, newest first.
+#[derive(Debug, Default)]
+pub struct RecentProjects {
+    file: Option<PathBuf>,
+    pub folders: Vec<PathBuf>,
+}
+
+impl RecentProjects {
+    /// Loads the list from the config folder, skipping folders which no
+    /// longer exist.
+    pub fn load(base_path: Option<&Path>) -> Self {
+        let file = base_path.map(|path| path.join("recent-projects.yaml"));
+
+        let mut folders: Vec<PathBuf> = file
+            .as_ref()
+            .and_then(|path| std::fs::File::open(path).ok())
+            .and_then(|file| serde_yaml::from_reader(file).ok())
+            .unwrap_or_default();
+
+        folders.retain(|path| path.is_dir());
+        Self { file, folders }
+    }
+
+    /// Moves a folder to the top of the list and saves it.
+    pub fn push(&mut self, folder: PathBuf) {
+        if self.folders.first() == Some(&folder) {
+            return;
+        }
+
+        self.folders.retain(|path| *path != folder);
+        self.folders.insert(0, folder);
+        self.folders.truncate(RECENT_PROJECTS);
+
+        if let Some(path) = &self.file {
+            let result = std::fs::File::create(path)
+                .map_err(|e| e.to_string())
+                .and_then(|file| {
+                    serde_yaml::to_writer(file, &self.folders).map_err(|e| e.to_string())
+                });
+
+            if let Err(err) = result {
+                log::error!("Failed to store recent projects: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_newest_first() {
+        let mut recent = RecentProjects::default();
+        for name in ["a", "b", "a", "c"] {
+            recent.push(PathBuf::from(name));
+        }
+
+        assert_eq!(
+            recent.folders,
+            vec![PathBuf::from("c"), "a".into(), "b".into()]
+        );
+
+        for k in 0..2 * RECENT_PROJECTS {
+            recent.push(PathBuf::from(k.to_string()));
+        }
+
+        assert_eq!(recent.folders.len(), RECENT_PROJECTS);
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, format_err, Context, Result};
+
+use super::*;
+
+/// Settings for rendering a pipeline into a video file.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub out: PathBuf,
+    pub audio: Option<PathBuf>,
+    pub pipeline: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub subframes: u32,
+    pub duration: Option<f32>,
+}
+
+impl Jockey {
+    /// Renders the selected pipeline frame by frame into a video file.
+    ///
+    /// Frames are rendered offscreen with a fixed time step, so the result
+    /// does not depend on how fast the machine can keep up. If an audio file
+    /// is given, it drives all audio uniforms and textures and is muxed into
+    /// the output file. Encoding is done by piping raw frames into `ffmpeg`.
+    ///
+    /// Each output frame is the average of `subframes` frames rendered at
+    /// evenly spaced points in time, which gives a motion blur effect.
+    pub fn render_offline(&mut self, opts: &RenderOptions) -> Result<()> {
+        if opts.fps == 0 {
+            bail!("Frame rate must be greater than zero");
+        }
+
+        if opts.subframes == 0 {
+            bail!("Number of subframes must be greater than zero");
+        }
+
+        let track = match &opts.audio {
+            Some(path) => Some(
+                AudioTrack::load(path)
+                    .with_context(|| format!("Failed to load audio file {:?}", path))?,
+            ),
+            None => None,
+        };
+
+        let duration = match (opts.duration, &track) {
+            (Some(s), _) => s,
+            (None, Some(track)) => track.duration(),
+            (None, None) => bail!("Either an audio file or a duration is required"),
+        };
+
+        let frame_count = (duration * opts.fps as f32).ceil() as usize;
+        let subframes = opts.subframes as usize;
+        let step = 1.0 / (opts.fps as f32 * subframes as f32);
+
+        self.time_step = Some(step);
+        self.time = 0.0;
+        self.time_since_build = 0.0;
+        self.frame = 0;
+
+        let sample_rate = track.as_ref().map(|t| t.sample_rate).unwrap_or(44100);
+        self.audio = Audio::offline(self.audio.size, sample_rate as _);
+        self.build_offscreen(opts.pipeline.as_deref(), opts.width, opts.height)?;
+
+        let (width, height) = self.screen_size();
+        let mut ffmpeg = Command::new("ffmpeg");
+        ffmpeg.args(["-y", "-loglevel", "error"]);
+        ffmpeg.args(["-f", "rawvideo", "-pix_fmt", "rgba"]);
+        ffmpeg.args(["-s", &format!("{}x{}", width, height)]);
+        ffmpeg.args(["-r", &opts.fps.to_string()]);
+        ffmpeg.args(["-i", "-"]);
+        if let Some(path) = &opts.audio {
+            ffmpeg.arg("-i").arg(path);
+            ffmpeg.args(["-map", "0:v", "-map", "1:a", "-shortest"]);
+        }
+        ffmpeg.args(["-vf", "vflip", "-pix_fmt", "yuv420p"]);
+        ffmpeg.arg(&opts.out);
+
+        let mut child = ffmpeg
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("Failed to launch ffmpeg, please make sure it is installed")?;
+
+        let mut stdin = child.stdin.take().unwrap();
+        let mut pixels = vec![0_u8; 4 * width as usize * height as usize];
+        let mut accum = vec![0_u32; if subframes > 1 { pixels.len() } else { 0 }];
+        let mut fed = 0;
+        let start = Instant::now();
+
+        log::info!(
+            "Rendering {} frames at {}x{} to {:?}",
+            frame_count,
+            width,
+            height,
+            &opts.out
+        );
+
+        for k in 0..frame_count {
+            for j in 0..subframes {
+                // feed all samples up to the current point in time
+                if let Some(track) = &track {
+                    let time = (k * subframes + j) as f32 * step;
+                    let pos = (time * track.sample_rate as f32) as usize;
+                    let pos = pos.min(track.left.len());
+                    self.audio
+                        .feed(&track.left[fed..pos], &track.right[fed..pos]);
+                    fed = pos;
+                }
+
+                self.draw();
+                self.read_offscreen(&mut pixels);
+
+                if subframes > 1 {
+                    for (acc, &x) in accum.iter_mut().zip(pixels.iter()) {
+                        *acc += x as u32;
+                    }
+                }
+            }
+
+            // average subframes
+            if subframes > 1 {
+                let half = subframes as u32 / 2;
+                for (x, acc) in pixels.iter_mut().zip(accum.iter_mut()) {
+                    *x = ((*acc + half) / subframes as u32) as u8;
+                    *acc = 0;
+                }
+            }
+
+            if let Err(err) = stdin.write_all(&pixels) {
+                log::error!("Failed to write frame {} to ffmpeg: {}", k, err);
+                break;
+            }
+
+            if k % opts.fps as usize == 0 {
+                log::info!("Rendered frame {}/{}", k, frame_count);
+            }
+        }
+
+        drop(stdin);
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("ffmpeg exited with {}", status);
+        }
+
+        log::info!(
+            "Finished rendering {:?} in {}s",
+            &opts.out,
+            start.elapsed().as_secs_f32()
+        );
+
+        Ok(())
+    }
+
+    /// Hides all windows, redirects rendering into an offscreen target of
+    /// the given size and builds the selected pipeline to completion.
+    pub(super) fn build_offscreen(
+        &mut self,
+        pipeline: Option<&str>,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        self.ctx.context.window().set_visible(false);
+        if let Some(ui) = &self.ctx.ui {
+            ui.context.window().set_visible(false);
+        }
+        self.offscreen = Some(FrameBuffer::new(width, height));
+        self.presenter = None;
+
+        // select pipeline
+        self.update_pipeline();
+        if self.pipeline_files.is_empty() {
+            bail!("No pipeline file found in the current working directory");
+        }
+
+        if let Some(name) = pipeline {
+            self.pipeline_index = self
+                .pipeline_files
+                .iter()
+                .position(|s| s == name)
+                .ok_or_else(|| format_err!("Failed to find pipeline file {:?}", name))?;
+            self.update_pipeline();
+        }
+
+        // build pipeline to completion
+        while self.pipeline_partial.is_some() {
+            self.update_pipeline_incremental(Duration::from_secs(1));
+        }
+
+        if let Some(err) = &self.build_error {
+            bail!("Failed to build pipeline:\n{}", err);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the offscreen render target as tightly packed RGBA bytes.
+    fn read_offscreen(&self, pixels: &mut [u8]) {
+        let fb = self
+            .offscreen
+            .as_ref()
+            .expect("Offscreen target should exist");
+        let [width, height, _] = fb.resolution();
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fb.fb_id);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            debug_assert!(pixels.len() >= 4 * width as usize * height as usize);
+            gl::ReadPixels(
+                0,
+                0,
+                width as _,
+                height as _,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as _,
+            );
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            gl_debug_check!();
+        }
+    }
+}
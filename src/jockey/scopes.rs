@@ -0,0 +1,472 @@
+use std::ffi::CString;
+
+use gl::types::*;
+use lazy_static::lazy_static;
+
+use super::HISTOGRAM_BINS;
+use crate::util::*;
+
+/// Size of the downscaled copy of the output the scopes are computed from
+const SOURCE_SIZE: u32 = 256;
+
+/// Number of columns of the waveform monitor
+pub const WAVEFORM_COLUMNS: usize = 128;
+
+/// Number of luminance levels of the waveform monitor
+pub const WAVEFORM_ROWS: usize = 64;
+
+/// Number of cells along each axis of the vectorscope
+pub const VECTORSCOPE_SIZE: usize = 64;
+
+lazy_static! {
+    static ref SOURCE_NAME: CString = CString::new("source").unwrap();
+    static ref COUNTS_NAME: CString = CString::new("counts").unwrap();
+    static ref HISTOGRAM_NAME: CString = CString::new("histogram").unwrap();
+}
+
+/// A compute shader program together with its shader
+#[derive(Debug)]
+struct ComputePass {
+    prog_id: GLuint,
+    sh_id: GLuint,
+}
+
+impl ComputePass {
+    fn new(src: &str) -> Result<Self, String> {
+        let sh_id = compile_shader(src, gl::COMPUTE_SHADER)?;
+        let prog_id = link_program(&[sh_id])?;
+        Ok(Self { prog_id, sh_id })
+    }
+}
+
+impl Drop for ComputePass {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DetachShader(self.prog_id, self.sh_id);
+            gl::DeleteShader(self.sh_id);
+            gl::DeleteProgram(self.prog_id);
+        }
+    }
+}
+
+/// Video scopes of the output image.
+///
+/// The output is scaled down into a small buffer every frame, from which a
+/// histogram of the luminance and the color channels is computed on the GPU.
+/// The histogram is handed to shaders as a texture and read back
+/// asynchronously for the UI. The waveform monitor and vectorscope are only
+/// needed by the UI, so they are computed on the CPU from a read back copy
+/// of the scaled down output.
+#[derive(Debug)]
+pub struct Scopes {
+    source: FrameBuffer,
+    counts: GLuint,
+    histogram: Texture1D,
+    histogram_pass: ComputePass,
+    normalize_pass: ComputePass,
+    histogram_readback: PixelBuffer,
+    histogram_data: Vec<f32>,
+    source_readback: PixelBuffer,
+    source_data: Vec<u8>,
+    pub luma: Vec<f32>,
+    pub red: Vec<f32>,
+    pub green: Vec<f32>,
+    pub blue: Vec<f32>,
+    pub waveform: Vec<f32>,
+    pub vectorscope: Vec<f32>,
+    pub visible: bool,
+}
+
+impl Scopes {
+    /// Creates the scopes, fails if compute shaders are not supported.
+    pub fn new() -> Result<Self, String> {
+        const HISTOGRAM_COMP: &str = include_str!("shaders/histogram.comp");
+        const HISTOGRAM_NORM_COMP: &str = include_str!("shaders/histogram_norm.comp");
+
+        if !supports_compute() {
+            return Err("Video scopes require compute shaders (OpenGL 4.3)".into());
+        }
+
+        let histogram_pass = ComputePass::new(HISTOGRAM_COMP)?;
+        let normalize_pass = ComputePass::new(HISTOGRAM_NORM_COMP)?;
+
+        let source = FrameBuffer::with_params(
+            SOURCE_SIZE,
+            SOURCE_SIZE,
+            gl::NEAREST,
+            gl::NEAREST,
+            gl::CLAMP_TO_EDGE,
+            false,
+            false,
+        );
+
+        let mut counts = 0;
+        unsafe {
+            gl::GenTextures(1, &mut counts);
+            gl::BindTexture(gl::TEXTURE_2D, counts);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::R32UI as _,
+                HISTOGRAM_BINS as _,
+                4,
+                0,
+                gl::RED_INTEGER,
+                gl::UNSIGNED_INT,
+                std::ptr::null(),
+            );
+            gl_debug_check!();
+        }
+
+        let histogram = Texture1D::with_params(
+            [HISTOGRAM_BINS as _],
+            gl::LINEAR,
+            gl::LINEAR,
+            gl::CLAMP_TO_EDGE,
+            TextureFormat::RGBA32F,
+            false,
+            std::ptr::null(),
+        );
+
+        let source_size = 4 * (SOURCE_SIZE * SOURCE_SIZE) as usize;
+
+        Ok(Self {
+            source,
+            counts,
+            histogram,
+            histogram_pass,
+            normalize_pass,
+            histogram_readback: PixelBuffer::new(),
+            histogram_data: vec![0.0; 4 * HISTOGRAM_BINS],
+            source_readback: PixelBuffer::new(),
+            source_data: vec![0; source_size],
+            luma: vec![0.0; HISTOGRAM_BINS],
+            red: vec![0.0; HISTOGRAM_BINS],
+            green: vec![0.0; HISTOGRAM_BINS],
+            blue: vec![0.0; HISTOGRAM_BINS],
+            waveform: vec![0.0; WAVEFORM_COLUMNS * WAVEFORM_ROWS],
+            vectorscope: vec![0.0; VECTORSCOPE_SIZE * VECTORSCOPE_SIZE],
+            visible: false,
+        })
+    }
+
+    /// Computes the scopes of the given framebuffer.
+    ///
+    /// The histogram is also copied into `target`, if given.
+    pub fn update(&mut self, fb_id: GLuint, width: u32, height: u32, target: Option<GLuint>) {
+        self.poll();
+
+        if !self.visible && target.is_none() {
+            return;
+        }
+
+        unsafe {
+            // scale the output down
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fb_id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.source.fb_id);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                width as _,
+                height as _,
+                0,
+                0,
+                SOURCE_SIZE as _,
+                SOURCE_SIZE as _,
+                gl::COLOR_BUFFER_BIT,
+                gl::LINEAR,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl_debug_check!();
+
+            // clear bins
+            let zeros = [0_u32; 4 * HISTOGRAM_BINS];
+            gl::BindTexture(gl::TEXTURE_2D, self.counts);
+            gl::TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                HISTOGRAM_BINS as _,
+                4,
+                gl::RED_INTEGER,
+                gl::UNSIGNED_INT,
+                zeros.as_ptr() as _,
+            );
+            gl_debug_check!();
+
+            // count pixels
+            let prog_id = self.histogram_pass.prog_id;
+            gl::UseProgram(prog_id);
+            let source_loc = gl::GetUniformLocation(prog_id, SOURCE_NAME.as_ptr());
+            let counts_loc = gl::GetUniformLocation(prog_id, COUNTS_NAME.as_ptr());
+
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.source.tex_id);
+            gl::Uniform1i(source_loc, 0);
+            gl::BindImageTexture(0, self.counts, 0, gl::FALSE, 0, gl::READ_WRITE, gl::R32UI);
+            gl::Uniform1i(counts_loc, 0);
+            gl_debug_check!();
+
+            gl::DispatchCompute(SOURCE_SIZE / 16, SOURCE_SIZE / 16, 1);
+            gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT);
+            gl_debug_check!();
+
+            // normalize bins
+            let prog_id = self.normalize_pass.prog_id;
+            gl::UseProgram(prog_id);
+            let counts_loc = gl::GetUniformLocation(prog_id, COUNTS_NAME.as_ptr());
+            let histogram_loc = gl::GetUniformLocation(prog_id, HISTOGRAM_NAME.as_ptr());
+
+            gl::BindImageTexture(0, self.counts, 0, gl::FALSE, 0, gl::READ_ONLY, gl::R32UI);
+            gl::Uniform1i(counts_loc, 0);
+            gl::BindImageTexture(
+                1,
+                self.histogram.id,
+                0,
+                gl::FALSE,
+                0,
+                gl::WRITE_ONLY,
+                gl::RGBA32F,
+            );
+            gl::Uniform1i(histogram_loc, 1);
+            gl_debug_check!();
+
+            gl::DispatchCompute(1, 1, 1);
+            gl::MemoryBarrier(
+                gl::TEXTURE_FETCH_BARRIER_BIT
+                    | gl::TEXTURE_UPDATE_BARRIER_BIT
+                    | gl::PIXEL_BUFFER_BARRIER_BIT,
+            );
+            gl_debug_check!();
+
+            if let Some(target) = target {
+                gl::CopyImageSubData(
+                    self.histogram.id,
+                    gl::TEXTURE_1D,
+                    0,
+                    0,
+                    0,
+                    0,
+                    target,
+                    gl::TEXTURE_1D,
+                    0,
+                    0,
+                    0,
+                    0,
+                    HISTOGRAM_BINS as _,
+                    1,
+                    1,
+                );
+                gl_debug_check!();
+            }
+        }
+
+        if self.visible && self.histogram_readback.is_idle() {
+            let size = self.histogram_data.len() * std::mem::size_of::<f32>();
+            read_texture(
+                &mut self.histogram_readback,
+                size,
+                gl::TEXTURE_1D,
+                self.histogram.id,
+                gl::FLOAT,
+            );
+        }
+
+        if self.visible && self.source_readback.is_idle() {
+            read_texture(
+                &mut self.source_readback,
+                self.source_data.len(),
+                gl::TEXTURE_2D,
+                self.source.tex_id,
+                gl::UNSIGNED_BYTE,
+            );
+        }
+    }
+
+    /// Fetches the results of previous frames for the UI.
+    fn poll(&mut self) {
+        let data = &mut self.histogram_data;
+        let bytes =
+            unsafe { std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, data.len() * 4) };
+
+        if self.histogram_readback.poll(false) {
+            self.histogram_readback.read(bytes);
+
+            for (k, texel) in self.histogram_data.chunks(4).enumerate() {
+                self.red[k] = texel[0];
+                self.green[k] = texel[1];
+                self.blue[k] = texel[2];
+                self.luma[k] = texel[3];
+            }
+        }
+
+        if self.source_readback.poll(false) {
+            self.source_readback.read(&mut self.source_data);
+
+            let width = SOURCE_SIZE as usize;
+            self.waveform = waveform(&self.source_data, width);
+            self.vectorscope = vectorscope(&self.source_data);
+        }
+    }
+}
+
+/// Starts copying the RGBA texels of the given texture into the buffer.
+fn read_texture(
+    buffer: &mut PixelBuffer,
+    size: usize,
+    target: GLenum,
+    tex_id: GLuint,
+    type_: GLenum,
+) {
+    buffer.begin(size);
+    unsafe {
+        gl::BindTexture(target, tex_id);
+        gl::GetTexImage(target, 0, gl::RGBA, type_, std::ptr::null_mut());
+        gl_debug_check!();
+    }
+    buffer.end();
+}
+
+impl Drop for Scopes {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.counts);
+        }
+    }
+}
+
+/// Draws a grid of densities, as computed for the waveform and vectorscope.
+///
+/// The first row of the grid is drawn at the bottom.
+pub fn draw_density_plot(ui: &imgui::Ui, data: &[f32], columns: usize, size: [f32; 2]) {
+    let rows = data.len() / columns;
+    let origin = ui.cursor_screen_pos();
+    let cell = [size[0] / columns as f32, size[1] / rows as f32];
+
+    let draw_list = ui.get_window_draw_list();
+    draw_list
+        .add_rect(
+            origin,
+            [origin[0] + size[0], origin[1] + size[1]],
+            [0.05, 0.05, 0.05, 1.0],
+        )
+        .filled(true)
+        .build();
+
+    for (k, &density) in data.iter().enumerate() {
+        if density <= 0.0 {
+            continue;
+        }
+
+        let x = origin[0] + (k % columns) as f32 * cell[0];
+        let y = origin[1] + size[1] - (k / columns + 1) as f32 * cell[1];
+        draw_list
+            .add_rect(
+                [x, y],
+                [x + cell[0], y + cell[1]],
+                [0.4, 1.0, 0.4, density.sqrt()],
+            )
+            .filled(true)
+            .build();
+    }
+
+    ui.dummy(size);
+}
+
+fn rec709_luma(r: f32, g: f32, b: f32) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Scales all bins, such that the fullest one ends up at `1.0`.
+fn normalize(bins: &[u32]) -> Vec<f32> {
+    let peak = bins.iter().copied().max().unwrap_or(0).max(1) as f32;
+    bins.iter().map(|&n| n as f32 / peak).collect()
+}
+
+/// Computes a waveform monitor from RGBA8 pixels.
+///
+/// The result holds the distribution of luminance values for each column of
+/// the image, stored row by row with the lowest luminance first.
+pub fn waveform(pixels: &[u8], width: usize) -> Vec<f32> {
+    let mut bins = vec![0_u32; WAVEFORM_COLUMNS * WAVEFORM_ROWS];
+    for (k, px) in pixels.chunks_exact(4).enumerate() {
+        let [r, g, b] = [px[0], px[1], px[2]].map(|c| c as f32 / 255.0);
+        let luma = rec709_luma(r, g, b);
+
+        let x = (k % width) * WAVEFORM_COLUMNS / width;
+        let y = (luma * (WAVEFORM_ROWS - 1) as f32).round() as usize;
+        bins[y.min(WAVEFORM_ROWS - 1) * WAVEFORM_COLUMNS + x] += 1;
+    }
+
+    normalize(&bins)
+}
+
+/// Computes a vectorscope from RGBA8 pixels.
+///
+/// The result is a square grid over the Cb (x axis) and Cr (y axis) plane of
+/// BT.709, stored row by row with the lowest Cr first.
+pub fn vectorscope(pixels: &[u8]) -> Vec<f32> {
+    let mut bins = vec![0_u32; VECTORSCOPE_SIZE * VECTORSCOPE_SIZE];
+    for px in pixels.chunks_exact(4) {
+        let [r, g, b] = [px[0], px[1], px[2]].map(|c| c as f32 / 255.0);
+        let luma = rec709_luma(r, g, b);
+        let cb = (b - luma) / 1.8556;
+        let cr = (r - luma) / 1.5748;
+
+        let max = (VECTORSCOPE_SIZE - 1) as f32;
+        let x = ((cb + 0.5) * max).round().clamp(0.0, max) as usize;
+        let y = ((cr + 0.5) * max).round().clamp(0.0, max) as usize;
+        bins[y * VECTORSCOPE_SIZE + x] += 1;
+    }
+
+    normalize(&bins)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn image(color: [u8; 4], count: usize) -> Vec<u8> {
+        color.iter().copied().cycle().take(4 * count).collect()
+    }
+
+    #[test]
+    fn waveform_of_black_and_white() {
+        let mut pixels = image([0, 0, 0, 255], 2);
+        pixels.extend(image([255, 255, 255, 255], 2));
+
+        // a 4x1 image, dark on the left and bright on the right
+        let wave = waveform(&pixels, 4);
+        let step = WAVEFORM_COLUMNS / 4;
+        let last_row = (WAVEFORM_ROWS - 1) * WAVEFORM_COLUMNS;
+        assert_eq!(wave[0], 1.0);
+        assert_eq!(wave[step], 1.0);
+        assert_eq!(wave[last_row + 2 * step], 1.0);
+        assert_eq!(wave[last_row + 3 * step], 1.0);
+        assert_eq!(wave.iter().filter(|&&v| v > 0.0).count(), 4);
+    }
+
+    #[test]
+    fn gray_is_centered_in_vectorscope() {
+        let scope = vectorscope(&image([128, 128, 128, 255], 16));
+        let center = (VECTORSCOPE_SIZE - 1) / 2;
+        let hits: Vec<_> = (0..scope.len()).filter(|&k| scope[k] > 0.0).collect();
+        assert_eq!(hits.len(), 1);
+
+        let (x, y) = (hits[0] % VECTORSCOPE_SIZE, hits[0] / VECTORSCOPE_SIZE);
+        assert!((x as isize - center as isize).abs() <= 1);
+        assert!((y as isize - center as isize).abs() <= 1);
+    }
+
+    #[test]
+    fn red_points_towards_cr() {
+        let scope = vectorscope(&image([255, 0, 0, 255], 1));
+        let hit = scope.iter().position(|&v| v > 0.0).unwrap();
+        let (x, y) = (hit % VECTORSCOPE_SIZE, hit / VECTORSCOPE_SIZE);
+        assert!(y > VECTORSCOPE_SIZE / 2 + VECTORSCOPE_SIZE / 4);
+        assert!(x < VECTORSCOPE_SIZE / 2);
+    }
+}
@@ -0,0 +1,21 @@
+#version 140
+
+uniform sampler2D image;
+uniform sampler3D lut;
+uniform float lut_size;
+uniform vec3 domain_min;
+uniform vec3 domain_max;
+uniform float amount;
+
+out vec4 out_color;
+
+void main() {
+    vec4 color = texelFetch(image, ivec2(gl_FragCoord.xy), 0);
+
+    // map the domain onto the texel centers of the table
+    vec3 p = clamp((color.rgb - domain_min) / (domain_max - domain_min), 0.0, 1.0);
+    vec3 uvw = (p * (lut_size - 1.0) + 0.5) / lut_size;
+    vec3 graded = texture(lut, uvw).rgb;
+
+    out_color = vec4(mix(color.rgb, graded, amount), color.a);
+}
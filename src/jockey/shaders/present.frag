@@ -0,0 +1,15 @@
+#version 140
+
+uniform sampler2D previous_frame;
+uniform sampler2D current_frame;
+uniform vec2 resolution;
+uniform float blend;
+
+out vec4 out_color;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution;
+    vec4 previous = texture(previous_frame, uv);
+    vec4 current = texture(current_frame, uv);
+    out_color = mix(previous, current, blend);
+}
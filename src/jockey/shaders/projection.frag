@@ -0,0 +1,33 @@
+#version 140
+
+uniform sampler2D equirect;
+uniform vec2 resolution;
+uniform float fov;
+
+out vec4 out_color;
+
+#define PI 3.14159265359
+
+void main() {
+    // centered coordinates with the dome edge at a radius of 1
+    vec2 p = (2.0 * gl_FragCoord.xy - resolution) / min(resolution.x, resolution.y);
+    float r = length(p);
+
+    if (r > 1.0) {
+        out_color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    // ray direction with the zenith in the center, y is up
+    float theta = 0.5 * r * fov;
+    float phi = atan(p.y, p.x);
+    vec3 dir = vec3(sin(theta) * cos(phi), cos(theta), sin(theta) * sin(phi));
+
+    // sample equirectangular image
+    vec2 uv = vec2(
+        0.5 + atan(dir.z, dir.x) / (2.0 * PI),
+        0.5 + asin(clamp(dir.y, -1.0, 1.0)) / PI
+    );
+
+    out_color = texture(equirect, uv);
+}
@@ -0,0 +1,30 @@
+#version 140
+
+uniform sampler2D left_eye;
+uniform sampler2D right_eye;
+uniform vec2 resolution;
+uniform int mode;
+
+out vec4 out_color;
+
+// keep in sync with StereoMode
+#define SIDE_BY_SIDE 0
+#define TOP_BOTTOM 1
+#define ANAGLYPH 2
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution;
+
+    if (mode == SIDE_BY_SIDE) {
+        vec2 eye_uv = vec2(fract(2.0 * uv.x), uv.y);
+        out_color = uv.x < 0.5 ? texture(left_eye, eye_uv) : texture(right_eye, eye_uv);
+    } else if (mode == TOP_BOTTOM) {
+        vec2 eye_uv = vec2(uv.x, fract(2.0 * uv.y));
+        out_color = uv.y >= 0.5 ? texture(left_eye, eye_uv) : texture(right_eye, eye_uv);
+    } else {
+        // red-cyan color anaglyph
+        vec4 left = texture(left_eye, uv);
+        vec4 right = texture(right_eye, uv);
+        out_color = vec4(left.r, right.gb, max(left.a, right.a));
+    }
+}
@@ -0,0 +1,113 @@
+use super::MIDI_N;
+
+/// Number of slots for storing slider values
+pub const SNAPSHOT_SLOTS: usize = 8;
+
+/// A running transition between two sets of slider values.
+#[derive(Debug, Clone)]
+pub struct Morph {
+    pub start_beat: f32,
+    pub from: [f32; MIDI_N],
+    pub to: [f32; MIDI_N],
+}
+
+/// Stored slider values and the morph between two of them.
+#[derive(Debug, Clone)]
+pub struct Snapshots {
+    pub slots: [Option<[f32; MIDI_N]>; SNAPSHOT_SLOTS],
+    pub from: usize,
+    pub to: usize,
+    pub beats: f32,
+    pub morph: Option<Morph>,
+}
+
+impl Default for Snapshots {
+    fn default() -> Self {
+        Self {
+            slots: [None; SNAPSHOT_SLOTS],
+            from: 0,
+            to: 1,
+            beats: 4.0,
+            morph: None,
+        }
+    }
+}
+
+impl Snapshots {
+    /// Stores the current slider values in a slot.
+    pub fn store(&mut self, slot: usize, sliders: &[f32; MIDI_N]) {
+        self.slots[slot] = Some(*sliders);
+    }
+
+    /// Starts morphing from the `from` slot to the `to` slot.
+    ///
+    /// Returns false if one of the slots is empty.
+    pub fn start_morph(&mut self, beat: f32) -> bool {
+        match (self.slots[self.from], self.slots[self.to]) {
+            (Some(from), Some(to)) => {
+                self.morph = Some(Morph {
+                    start_beat: beat,
+                    from,
+                    to,
+                });
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Progress of the running morph between 0 and 1.
+    pub fn progress(&self, beat: f32) -> Option<f32> {
+        let morph = self.morph.as_ref()?;
+        let t = match self.beats > 0.0 {
+            true => (beat - morph.start_beat) / self.beats,
+            false => 1.0,
+        };
+
+        // tapping a new tempo resets the beat counter
+        Some(if t < 0.0 { 1.0 } else { t.min(1.0) })
+    }
+
+    /// Writes the interpolated values of the running morph into the sliders.
+    pub fn update(&mut self, beat: f32, sliders: &mut [f32; MIDI_N]) {
+        let t = match self.progress(beat) {
+            Some(t) => t,
+            None => return,
+        };
+
+        let morph = self.morph.as_ref().unwrap();
+        let s = t * t * (3.0 - 2.0 * t);
+        for (k, slider) in sliders.iter_mut().enumerate() {
+            *slider = morph.from[k] + s * (morph.to[k] - morph.from[k]);
+        }
+
+        if t >= 1.0 {
+            self.morph = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn morphs_over_beats() {
+        let mut snapshots = Snapshots::default();
+        let mut sliders = [0.0; MIDI_N];
+        assert!(!snapshots.start_morph(0.0));
+
+        snapshots.store(0, &sliders);
+        sliders[3] = 1.0;
+        snapshots.store(1, &sliders);
+
+        assert!(snapshots.start_morph(10.0));
+        snapshots.update(12.0, &mut sliders);
+        assert_eq!(sliders[3], 0.5);
+        assert!(snapshots.morph.is_some());
+
+        snapshots.update(14.0, &mut sliders);
+        assert_eq!(sliders[3], 1.0);
+        assert!(snapshots.morph.is_none());
+    }
+}
@@ -0,0 +1,569 @@
+// the shared memory helpers are only used on Windows, but tested everywhere
+#![cfg_attr(not(windows), allow(dead_code))]
+
+use std::ffi::c_void;
+
+use gl::types::*;
+
+/// Shared memory listing the names of all Spout senders.
+const SENDER_NAMES: &str = "SpoutSenderNames";
+
+/// Shared memory holding the name of the sender receivers pick by default.
+const ACTIVE_SENDER: &str = "ActiveSenderName";
+
+/// Number of senders the list has room for, Spout's default.
+const MAX_SENDERS: usize = 64;
+
+/// Size of a name in the sender list, including the terminating nul.
+const MAX_NAME_LEN: usize = 256;
+
+/// Size of the `SharedTextureInfo` struct every sender publishes.
+const TEXTURE_INFO_LEN: usize = 280;
+
+/// `DXGI_FORMAT_B8G8R8A8_UNORM`, the format of the shared texture.
+const SHARED_FORMAT: u32 = 87;
+
+/// Shares the output with other programs on the same GPU through Spout.
+///
+/// The final image is copied into a DirectX texture which Spout receivers
+/// like the Spout2 plugin for OBS open directly, without a round trip
+/// through the CPU.
+#[derive(Debug)]
+pub struct SpoutSender {
+    name: String,
+    #[cfg(windows)]
+    inner: windows::Sender,
+}
+
+impl SpoutSender {
+    /// Registers a sender, resolving the WGL interop functions with `loader`.
+    #[cfg(windows)]
+    pub fn new(name: &str, loader: impl Fn(&str) -> *const c_void) -> Result<Self, String> {
+        if name.is_empty() || name.len() >= MAX_NAME_LEN {
+            return Err(format!(
+                "Expected sender name to have 1 to {} characters",
+                MAX_NAME_LEN - 1
+            ));
+        }
+
+        let inner = windows::Sender::new(name, loader)?;
+        log::info!("Sharing the output as Spout sender {:?}", name);
+        Ok(Self {
+            name: name.to_owned(),
+            inner,
+        })
+    }
+
+    #[cfg(not(windows))]
+    pub fn new(_name: &str, _loader: impl Fn(&str) -> *const c_void) -> Result<Self, String> {
+        Err("Spout output is only available on Windows".into())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Copies the contents of framebuffer `fb` into the shared texture.
+    #[cfg(windows)]
+    pub fn send(&mut self, fb: GLuint, width: u32, height: u32) {
+        if let Err(err) = self.inner.send(fb, width, height) {
+            log::error!("Failed to send Spout frame: {}", err);
+        }
+    }
+
+    #[cfg(not(windows))]
+    pub fn send(&mut self, _fb: GLuint, _width: u32, _height: u32) {}
+}
+
+/// Reads the names of a Spout sender list, which are stored in slots of
+/// fixed size up to the first empty one.
+fn sender_names(list: &[u8]) -> Vec<String> {
+    list.chunks(MAX_NAME_LEN)
+        .map(|slot| {
+            let len = slot.iter().position(|&b| b == 0).unwrap_or(slot.len());
+            String::from_utf8_lossy(&slot[..len]).into_owned()
+        })
+        .take_while(|name| !name.is_empty())
+        .collect()
+}
+
+/// Writes sorted names into a Spout sender list, like Spout does.
+fn write_sender_names(list: &mut [u8], mut names: Vec<String>) {
+    names.sort();
+    list.iter_mut().for_each(|b| *b = 0);
+    for (slot, name) in list.chunks_mut(MAX_NAME_LEN).zip(names.iter()) {
+        let len = name.len().min(MAX_NAME_LEN - 1);
+        slot[..len].copy_from_slice(&name.as_bytes()[..len]);
+    }
+}
+
+/// Adds a name to a Spout sender list, returns false if the list is full.
+fn insert_sender_name(list: &mut [u8], name: &str) -> bool {
+    let mut names = sender_names(list);
+    if names.iter().any(|n| n == name) {
+        return true;
+    }
+
+    if names.len() >= list.len() / MAX_NAME_LEN {
+        return false;
+    }
+
+    names.push(name.to_owned());
+    write_sender_names(list, names);
+    true
+}
+
+fn remove_sender_name(list: &mut [u8], name: &str) {
+    let mut names = sender_names(list);
+    names.retain(|n| n != name);
+    write_sender_names(list, names);
+}
+
+/// Lays out the `SharedTextureInfo` struct receivers read the texture
+/// handle and size from.
+fn texture_info(handle: u32, width: u32, height: u32, description: &str) -> [u8; TEXTURE_INFO_LEN] {
+    let mut info = [0; TEXTURE_INFO_LEN];
+    info[0..4].copy_from_slice(&handle.to_le_bytes());
+    info[4..8].copy_from_slice(&width.to_le_bytes());
+    info[8..12].copy_from_slice(&height.to_le_bytes());
+    info[12..16].copy_from_slice(&SHARED_FORMAT.to_le_bytes());
+
+    // the description is followed by the partner id, which stays zero
+    let len = description.len().min(MAX_NAME_LEN - 1);
+    info[20..20 + len].copy_from_slice(&description.as_bytes()[..len]);
+    info
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{ffi::c_void, mem::transmute, ptr};
+
+    use gl::types::*;
+    use winapi::{
+        shared::{
+            dxgi::IDXGIResource,
+            dxgitype::DXGI_SAMPLE_DESC,
+            minwindef::BOOL,
+            winerror::{FAILED, HRESULT},
+        },
+        um::{
+            d3d11::*,
+            d3dcommon::D3D_DRIVER_TYPE_HARDWARE,
+            handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+            memoryapi::{CreateFileMappingW, MapViewOfFile, UnmapViewOfFile, FILE_MAP_ALL_ACCESS},
+            synchapi::{CreateMutexW, ReleaseMutex, WaitForSingleObject},
+            winbase::INFINITE,
+            winnt::{HANDLE, PAGE_READWRITE},
+        },
+        Interface,
+    };
+
+    use super::*;
+
+    const WGL_ACCESS_WRITE_DISCARD_NV: GLenum = 0x0002;
+
+    type DXOpenDevice = unsafe extern "system" fn(*mut c_void) -> HANDLE;
+    type DXCloseDevice = unsafe extern "system" fn(HANDLE) -> BOOL;
+    type DXRegisterObject =
+        unsafe extern "system" fn(HANDLE, *mut c_void, GLuint, GLenum, GLenum) -> HANDLE;
+    type DXUnregisterObject = unsafe extern "system" fn(HANDLE, HANDLE) -> BOOL;
+    type DXLockObjects = unsafe extern "system" fn(HANDLE, GLint, *mut HANDLE) -> BOOL;
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn check(result: HRESULT, what: &str) -> Result<(), String> {
+        match FAILED(result) {
+            true => Err(format!("{} failed with 0x{:08x}", what, result)),
+            false => Ok(()),
+        }
+    }
+
+    /// A named block of memory shared between processes, guarded by a mutex
+    /// named like Spout names it.
+    #[derive(Debug)]
+    struct SharedMemory {
+        mapping: HANDLE,
+        mutex: HANDLE,
+        view: *mut u8,
+        size: usize,
+    }
+
+    impl SharedMemory {
+        fn open(name: &str, size: usize) -> Result<Self, String> {
+            unsafe {
+                let mapping = CreateFileMappingW(
+                    INVALID_HANDLE_VALUE,
+                    ptr::null_mut(),
+                    PAGE_READWRITE,
+                    0,
+                    size as _,
+                    wide(name).as_ptr(),
+                );
+                if mapping.is_null() {
+                    return Err(format!("Failed to open shared memory {:?}", name));
+                }
+
+                let view = MapViewOfFile(mapping, FILE_MAP_ALL_ACCESS, 0, 0, size) as *mut u8;
+                if view.is_null() {
+                    CloseHandle(mapping);
+                    return Err(format!("Failed to map shared memory {:?}", name));
+                }
+
+                let mutex_name = wide(&format!("{}_mutex", name));
+                let mutex = CreateMutexW(ptr::null_mut(), 0, mutex_name.as_ptr());
+
+                Ok(Self {
+                    mapping,
+                    mutex,
+                    view,
+                    size,
+                })
+            }
+        }
+
+        fn with<T>(&mut self, f: impl FnOnce(&mut [u8]) -> T) -> T {
+            unsafe {
+                if !self.mutex.is_null() {
+                    WaitForSingleObject(self.mutex, INFINITE);
+                }
+
+                let out = f(std::slice::from_raw_parts_mut(self.view, self.size));
+
+                if !self.mutex.is_null() {
+                    ReleaseMutex(self.mutex);
+                }
+
+                out
+            }
+        }
+    }
+
+    impl Drop for SharedMemory {
+        fn drop(&mut self) {
+            unsafe {
+                UnmapViewOfFile(self.view as _);
+                CloseHandle(self.mapping);
+                if !self.mutex.is_null() {
+                    CloseHandle(self.mutex);
+                }
+            }
+        }
+    }
+
+    /// The functions of the `WGL_NV_DX_interop` extension.
+    #[derive(Debug)]
+    struct Interop {
+        open_device: DXOpenDevice,
+        close_device: DXCloseDevice,
+        register_object: DXRegisterObject,
+        unregister_object: DXUnregisterObject,
+        lock_objects: DXLockObjects,
+        unlock_objects: DXLockObjects,
+    }
+
+    impl Interop {
+        fn load(loader: impl Fn(&str) -> *const c_void) -> Result<Self, String> {
+            let get = |name: &str| match loader(name) {
+                p if p.is_null() => Err(format!(
+                    "WGL_NV_DX_interop is not supported, {} is missing",
+                    name
+                )),
+                p => Ok(p),
+            };
+
+            unsafe {
+                Ok(Self {
+                    open_device: transmute(get("wglDXOpenDeviceNV")?),
+                    close_device: transmute(get("wglDXCloseDeviceNV")?),
+                    register_object: transmute(get("wglDXRegisterObjectNV")?),
+                    unregister_object: transmute(get("wglDXUnregisterObjectNV")?),
+                    lock_objects: transmute(get("wglDXLockObjectsNV")?),
+                    unlock_objects: transmute(get("wglDXUnlockObjectsNV")?),
+                })
+            }
+        }
+    }
+
+    /// A DirectX texture which is shared with other processes and can be
+    /// drawn into with OpenGL.
+    #[derive(Debug)]
+    struct SharedTexture {
+        texture: *mut ID3D11Texture2D,
+        share_handle: u32,
+        gl_texture: GLuint,
+        fb_id: GLuint,
+        interop_object: HANDLE,
+        width: u32,
+        height: u32,
+    }
+
+    #[derive(Debug)]
+    pub struct Sender {
+        name: String,
+        device: *mut ID3D11Device,
+        context: *mut ID3D11DeviceContext,
+        interop: Interop,
+        interop_device: HANDLE,
+        texture: Option<SharedTexture>,
+        names: SharedMemory,
+        info: SharedMemory,
+        _active: SharedMemory,
+    }
+
+    impl Sender {
+        pub fn new(name: &str, loader: impl Fn(&str) -> *const c_void) -> Result<Self, String> {
+            let interop = Interop::load(loader)?;
+
+            let mut device = ptr::null_mut();
+            let mut context = ptr::null_mut();
+            unsafe {
+                check(
+                    D3D11CreateDevice(
+                        ptr::null_mut(),
+                        D3D_DRIVER_TYPE_HARDWARE,
+                        ptr::null_mut(),
+                        0,
+                        ptr::null(),
+                        0,
+                        D3D11_SDK_VERSION,
+                        &mut device,
+                        ptr::null_mut(),
+                        &mut context,
+                    ),
+                    "D3D11CreateDevice",
+                )?;
+            }
+
+            let release = || unsafe {
+                (*context).Release();
+                (*device).Release();
+            };
+
+            let interop_device = unsafe { (interop.open_device)(device as _) };
+            if interop_device.is_null() {
+                release();
+                return Err("Failed to share the DirectX device with OpenGL".into());
+            }
+
+            let open = || -> Result<_, String> {
+                let mut names = SharedMemory::open(SENDER_NAMES, MAX_SENDERS * MAX_NAME_LEN)?;
+                if !names.with(|list| insert_sender_name(list, name)) {
+                    return Err("The list of Spout senders is full".into());
+                }
+
+                let info = SharedMemory::open(name, TEXTURE_INFO_LEN)?;
+                let mut active = SharedMemory::open(ACTIVE_SENDER, MAX_NAME_LEN)?;
+                active.with(|slot| {
+                    // become the default sender if there is none
+                    if slot[0] == 0 {
+                        slot[..name.len()].copy_from_slice(name.as_bytes());
+                    }
+                });
+
+                Ok((names, info, active))
+            };
+
+            let (names, info, active) = match open() {
+                Ok(s) => s,
+                Err(err) => {
+                    unsafe { (interop.close_device)(interop_device) };
+                    release();
+                    return Err(err);
+                }
+            };
+
+            Ok(Self {
+                name: name.to_owned(),
+                device,
+                context,
+                interop,
+                interop_device,
+                texture: None,
+                names,
+                info,
+                _active: active,
+            })
+        }
+
+        fn create_texture(&self, width: u32, height: u32) -> Result<SharedTexture, String> {
+            let desc = D3D11_TEXTURE2D_DESC {
+                Width: width,
+                Height: height,
+                MipLevels: 1,
+                ArraySize: 1,
+                Format: SHARED_FORMAT,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Usage: D3D11_USAGE_DEFAULT,
+                BindFlags: D3D11_BIND_RENDER_TARGET | D3D11_BIND_SHADER_RESOURCE,
+                CPUAccessFlags: 0,
+                MiscFlags: D3D11_RESOURCE_MISC_SHARED,
+            };
+
+            unsafe {
+                let mut texture = ptr::null_mut();
+                check(
+                    (*self.device).CreateTexture2D(&desc, ptr::null(), &mut texture),
+                    "CreateTexture2D",
+                )?;
+
+                let mut resource: *mut IDXGIResource = ptr::null_mut();
+                let mut share_handle = ptr::null_mut();
+                let result = (*texture).QueryInterface(
+                    &IDXGIResource::uuidof(),
+                    &mut resource as *mut _ as *mut *mut c_void,
+                );
+                let result = match FAILED(result) {
+                    true => result,
+                    false => {
+                        let result = (*resource).GetSharedHandle(&mut share_handle);
+                        (*resource).Release();
+                        result
+                    }
+                };
+
+                if let Err(err) = check(result, "GetSharedHandle") {
+                    (*texture).Release();
+                    return Err(err);
+                }
+
+                let mut gl_texture = 0;
+                gl::GenTextures(1, &mut gl_texture);
+                let interop_object = (self.interop.register_object)(
+                    self.interop_device,
+                    texture as _,
+                    gl_texture,
+                    gl::TEXTURE_2D,
+                    WGL_ACCESS_WRITE_DISCARD_NV,
+                );
+
+                if interop_object.is_null() {
+                    gl::DeleteTextures(1, &gl_texture);
+                    (*texture).Release();
+                    return Err("Failed to share the texture with OpenGL".into());
+                }
+
+                let mut fb_id = 0;
+                gl::GenFramebuffers(1, &mut fb_id);
+
+                Ok(SharedTexture {
+                    texture,
+                    // shared handles of DirectX 11 textures fit into 32 bits
+                    share_handle: share_handle as usize as u32,
+                    gl_texture,
+                    fb_id,
+                    interop_object,
+                    width,
+                    height,
+                })
+            }
+        }
+
+        fn release_texture(&mut self, shared: SharedTexture) {
+            unsafe {
+                (self.interop.unregister_object)(self.interop_device, shared.interop_object);
+                gl::DeleteFramebuffers(1, &shared.fb_id);
+                gl::DeleteTextures(1, &shared.gl_texture);
+                (*shared.texture).Release();
+            }
+        }
+
+        pub fn send(&mut self, fb: GLuint, width: u32, height: u32) -> Result<(), String> {
+            let outdated = match &self.texture {
+                Some(s) => s.width != width || s.height != height,
+                None => true,
+            };
+
+            if outdated {
+                if let Some(old) = self.texture.take() {
+                    self.release_texture(old);
+                }
+
+                let shared = self.create_texture(width, height)?;
+                let exe = std::env::current_exe()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default();
+                let info = texture_info(shared.share_handle, width, height, &exe);
+                self.info.with(|mem| mem.copy_from_slice(&info));
+                self.texture = Some(shared);
+            }
+
+            let shared = self.texture.as_mut().unwrap();
+            unsafe {
+                let mut object = shared.interop_object;
+                if (self.interop.lock_objects)(self.interop_device, 1, &mut object) == 0 {
+                    return Err("Failed to lock the shared texture".into());
+                }
+
+                gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, shared.fb_id);
+                gl::FramebufferTexture2D(
+                    gl::DRAW_FRAMEBUFFER,
+                    gl::COLOR_ATTACHMENT0,
+                    gl::TEXTURE_2D,
+                    shared.gl_texture,
+                    0,
+                );
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fb);
+
+                // DirectX textures start at the top, so the image is flipped
+                let (w, h) = (width as GLint, height as GLint);
+                gl::BlitFramebuffer(0, 0, w, h, 0, h, w, 0, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+
+                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+                (self.interop.unlock_objects)(self.interop_device, 1, &mut object);
+                gl_debug_check!();
+            }
+
+            Ok(())
+        }
+    }
+
+    impl Drop for Sender {
+        fn drop(&mut self) {
+            let name = self.name.clone();
+            self.names.with(|list| remove_sender_name(list, &name));
+
+            if let Some(shared) = self.texture.take() {
+                self.release_texture(shared);
+            }
+
+            unsafe {
+                (self.interop.close_device)(self.interop_device);
+                (*self.context).Release();
+                (*self.device).Release();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sender_list() {
+        let mut list = vec![0; 3 * MAX_NAME_LEN];
+        assert!(insert_sender_name(&mut list, "b"));
+        assert!(insert_sender_name(&mut list, "a"));
+        assert!(insert_sender_name(&mut list, "b"));
+        assert_eq!(sender_names(&list), ["a", "b"]);
+        assert_eq!(&list[MAX_NAME_LEN..MAX_NAME_LEN + 2], b"b\0");
+
+        assert!(insert_sender_name(&mut list, "c"));
+        assert!(!insert_sender_name(&mut list, "d"));
+
+        remove_sender_name(&mut list, "a");
+        assert_eq!(sender_names(&list), ["b", "c"]);
+        assert!(list[2 * MAX_NAME_LEN..].iter().all(|&b| b == 0));
+
+        let info = texture_info(0x1234, 1920, 1080, "sj.exe");
+        assert_eq!(info[0..4], 0x1234_u32.to_le_bytes());
+        assert_eq!(info[8..12], 1080_u32.to_le_bytes());
+        assert_eq!(info[12..16], SHARED_FORMAT.to_le_bytes());
+        assert_eq!(&info[20..27], b"sj.exe\0");
+    }
+}
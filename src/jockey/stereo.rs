@@ -0,0 +1,115 @@
+use std::ffi::CString;
+
+use gl::types::*;
+use lazy_static::lazy_static;
+
+use crate::util::*;
+
+lazy_static! {
+    static ref LEFT_EYE_NAME: CString = CString::new("left_eye").unwrap();
+    static ref RIGHT_EYE_NAME: CString = CString::new("right_eye").unwrap();
+    static ref MODE_NAME: CString = CString::new("mode").unwrap();
+}
+
+/// How the two eyes are combined into a single output image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoMode {
+    SideBySide = 0,
+    TopBottom = 1,
+    Anaglyph = 2,
+}
+
+impl StereoMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "side_by_side" => Some(Self::SideBySide),
+            "top_bottom" => Some(Self::TopBottom),
+            "anaglyph" => Some(Self::Anaglyph),
+            _ => None,
+        }
+    }
+}
+
+/// Renders the screen stages once per eye and composites the result.
+#[derive(Debug)]
+pub struct Stereo {
+    pub mode: StereoMode,
+    eyes: [FrameBuffer; 2],
+    pass: FullscreenPass,
+}
+
+impl Stereo {
+    pub fn new(mode: StereoMode, width: u32, height: u32) -> Self {
+        const STEREO_FRAG: &str = include_str!("shaders/stereo.frag");
+
+        let pass = FullscreenPass::new(STEREO_FRAG).unwrap();
+
+        let (eye_width, eye_height) = Self::eye_size_for(mode, width, height);
+        let eyes = [
+            Self::eye_buffer(eye_width, eye_height),
+            Self::eye_buffer(eye_width, eye_height),
+        ];
+
+        Self { mode, eyes, pass }
+    }
+
+    fn eye_buffer(width: u32, height: u32) -> FrameBuffer {
+        FrameBuffer::with_params(
+            width,
+            height,
+            gl::LINEAR,
+            gl::LINEAR,
+            gl::CLAMP_TO_EDGE,
+            false,
+            false,
+        )
+    }
+
+    fn eye_size_for(mode: StereoMode, width: u32, height: u32) -> (u32, u32) {
+        match mode {
+            StereoMode::SideBySide => ((width / 2).max(1), height),
+            StereoMode::TopBottom => (width, (height / 2).max(1)),
+            StereoMode::Anaglyph => (width, height),
+        }
+    }
+
+    /// Resolution each eye is rendered at for a given output size
+    pub fn eye_size(&self, width: u32, height: u32) -> (u32, u32) {
+        Self::eye_size_for(self.mode, width, height)
+    }
+
+    /// Resizes both eyes, if the output size has changed.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let (eye_width, eye_height) = self.eye_size(width, height);
+        let res = self.eyes[0].resolution();
+        if res[0] == eye_width && res[1] == eye_height {
+            return;
+        }
+
+        self.eyes = [
+            Self::eye_buffer(eye_width, eye_height),
+            Self::eye_buffer(eye_width, eye_height),
+        ];
+    }
+
+    /// Framebuffer id of the given eye, 0 being the left one
+    pub fn eye_framebuffer(&self, eye: usize) -> GLuint {
+        self.eyes[eye].fb_id
+    }
+
+    /// Combines both eyes into the given framebuffer.
+    pub fn composite(&self, vao: GLuint, target_fb: GLuint, width: u32, height: u32) {
+        self.pass
+            .draw(vao, target_fb, width, height, |pass| unsafe {
+                gl::ActiveTexture(gl::TEXTURE0);
+                self.eyes[0].bind(0);
+                gl::Uniform1i(pass.location(&LEFT_EYE_NAME), 0);
+
+                gl::ActiveTexture(gl::TEXTURE1);
+                self.eyes[1].bind(1);
+                gl::Uniform1i(pass.location(&RIGHT_EYE_NAME), 1);
+
+                gl::Uniform1i(pass.location(&MODE_NAME), self.mode as _);
+            });
+    }
+}
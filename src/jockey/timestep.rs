@@ -0,0 +1,69 @@
+/// Upper limit of updates per frame, so a long stall doesn't snowball
+const MAX_STEPS: u32 = 8;
+
+/// Runs updates at a fixed rate, independent of the frame rate.
+///
+/// Without a rate, exactly one update runs per frame.
+#[derive(Debug, Clone, Default)]
+pub struct FixedTimestep {
+    step: Option<f32>,
+    accum: f32,
+}
+
+impl FixedTimestep {
+    pub fn new(rate: Option<f32>) -> Self {
+        Self {
+            step: rate.map(|hz| 1.0 / hz),
+            accum: 0.0,
+        }
+    }
+
+    /// Returns how many updates to run for a frame that took `delta` seconds.
+    pub fn advance(&mut self, delta: f32) -> u32 {
+        let step = match self.step {
+            Some(s) => s,
+            None => return 1,
+        };
+
+        self.accum += delta.max(0.0);
+        let steps = (self.accum / step).floor();
+        self.accum -= steps * step;
+
+        if steps > MAX_STEPS as f32 {
+            self.accum = 0.0;
+            return MAX_STEPS;
+        }
+
+        steps as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn once_per_frame_without_rate() {
+        let mut timestep = FixedTimestep::new(None);
+        assert_eq!(timestep.advance(0.1), 1);
+        assert_eq!(timestep.advance(0.001), 1);
+    }
+
+    #[test]
+    fn carries_remainder() {
+        let mut timestep = FixedTimestep::new(Some(100.0));
+        let steps: Vec<_> = [0.015, 0.015, 0.015, 0.015]
+            .iter()
+            .map(|&dt| timestep.advance(dt))
+            .collect();
+        assert_eq!(steps.iter().sum::<u32>(), 6);
+        assert_eq!(steps[0], 1);
+    }
+
+    #[test]
+    fn limits_steps_after_stall() {
+        let mut timestep = FixedTimestep::new(Some(60.0));
+        assert_eq!(timestep.advance(10.0), MAX_STEPS);
+        assert_eq!(timestep.advance(0.0), 0);
+    }
+}
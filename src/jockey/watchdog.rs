@@ -0,0 +1,116 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Default time after which a frame is considered stalled
+pub const WATCHDOG_THRESHOLD: f32 = 2.0;
+
+const NO_STAGE: usize = usize::MAX;
+const IDLE: u64 = u64::MAX;
+
+#[derive(Debug)]
+struct WatchdogState {
+    epoch: Instant,
+    frame_start: AtomicU64,
+    stage: AtomicUsize,
+    threshold: AtomicU64,
+    reported: AtomicBool,
+}
+
+impl WatchdogState {
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as _
+    }
+}
+
+/// A watchdog for frames that take way too long.
+///
+/// A background thread keeps an eye on the render loop and complains in the
+/// log while a frame is stuck, usually because of a runaway shader loop.
+/// Since the GPU may finish its work long after a stage has been submitted,
+/// the render loop synchronizes after every stage for a frame after a stall
+/// has been detected, so the offending stage can be identified reliably.
+#[derive(Debug)]
+pub struct Watchdog {
+    state: Arc<WatchdogState>,
+    pub threshold: Duration,
+    pub disable_stage: bool,
+    pub probing: bool,
+    pub warning: Option<String>,
+}
+
+impl Watchdog {
+    pub fn new(threshold: Duration, disable_stage: bool) -> Self {
+        let state = Arc::new(WatchdogState {
+            epoch: Instant::now(),
+            frame_start: AtomicU64::new(IDLE),
+            stage: AtomicUsize::new(NO_STAGE),
+            threshold: AtomicU64::new(threshold.as_millis() as _),
+            reported: AtomicBool::new(false),
+        });
+
+        let thread_state = Arc::clone(&state);
+        std::thread::spawn(move || Self::run(thread_state));
+
+        Self {
+            state,
+            threshold,
+            disable_stage,
+            probing: false,
+            warning: None,
+        }
+    }
+
+    fn run(state: Arc<WatchdogState>) {
+        // stop once the watchdog has been dropped
+        while Arc::strong_count(&state) > 1 {
+            std::thread::sleep(Duration::from_millis(100));
+
+            let start = state.frame_start.load(Ordering::Acquire);
+            if start == IDLE {
+                continue;
+            }
+
+            let elapsed = state.now().saturating_sub(start);
+            if elapsed < state.threshold.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            if !state.reported.swap(true, Ordering::AcqRel) {
+                let stage = state.stage.load(Ordering::Acquire);
+                log::error!(
+                    "Frame has been stalled for {:.1}s, last submitted stage was {}",
+                    elapsed as f32 / 1000.0,
+                    stage
+                );
+            }
+        }
+    }
+
+    pub fn set_threshold(&mut self, threshold: Duration) {
+        self.threshold = threshold;
+        self.state
+            .threshold
+            .store(threshold.as_millis() as _, Ordering::Relaxed);
+    }
+
+    pub fn frame_begin(&self) {
+        self.state.stage.store(NO_STAGE, Ordering::Release);
+        self.state.reported.store(false, Ordering::Release);
+        self.state
+            .frame_start
+            .store(self.state.now(), Ordering::Release);
+    }
+
+    pub fn stage_begin(&self, index: usize) {
+        self.state.stage.store(index, Ordering::Release);
+    }
+
+    pub fn frame_end(&self) {
+        self.state.frame_start.store(IDLE, Ordering::Release);
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use notify::{
+    event::{AccessKind, AccessMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+
+use super::{is_backup_path, WatchConfig};
+
+/// Files which changed since the last rebuild, with the time of their latest
+/// change.
+type Changes = Arc<Mutex<HashMap<PathBuf, Instant>>>;
+
+/// Watches the project folder and the included files outside of it.
+///
+/// A single watcher lives as long as the project, rebuilds only change the
+/// files it watches.
+pub struct ProjectWatcher {
+    watcher: RecommendedWatcher,
+    root: Option<PathBuf>,
+    includes: HashSet<PathBuf>,
+    dependencies: HashSet<PathBuf>,
+    changes: Changes,
+}
+
+impl ProjectWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let changes = Changes::default();
+
+        let shared = Arc::clone(&changes);
+        let event_fn = move |event: notify::Result<notify::Event>| {
+            let event = match event {
+                Ok(s) => s,
+                Err(_) => return,
+            };
+
+            // reading files doesn't change them
+            let write = EventKind::Access(AccessKind::Close(AccessMode::Write));
+            if matches!(event.kind, EventKind::Access(_)) && event.kind != write {
+                return;
+            }
+
+            let now = Instant::now();
+            let mut changes = shared.lock().unwrap();
+            for path in event.paths {
+                // writing backups must not trigger another rebuild
+                if !is_backup_path(&path) {
+                    changes.insert(path, now);
+                }
+            }
+        };
+
+        Ok(Self {
+            watcher: notify::recommended_watcher(event_fn)?,
+            root: None,
+            includes: HashSet::new(),
+            dependencies: HashSet::new(),
+            changes,
+        })
+    }
+
+    /// Watches the working directory, unless it is watched already.
+    ///
+    /// Opening another project moves the watch over to the new folder.
+    pub fn watch_project(&mut self) {
+        let cwd = match std::env::current_dir().and_then(|p| p.canonicalize()) {
+            Ok(s) => s,
+            Err(_) => return,
+        };
+
+        if self.root.as_ref() == Some(&cwd) {
+            return;
+        }
+
+        if let Some(old) = self.root.take() {
+            let _ = self.watcher.unwatch(&old);
+        }
+
+        match self.watcher.watch(&cwd, RecursiveMode::Recursive) {
+            Ok(()) => self.root = Some(cwd),
+            Err(err) => log::warn!("Failed to watch {:?} for changes: {}", cwd, err),
+        }
+    }
+
+    /// Remembers the files the pipeline was built from.
+    ///
+    /// Files outside of the project folder are watched separately, the
+    /// watcher stops watching those which are no longer needed.
+    pub fn set_dependencies<'a>(&mut self, files: impl IntoIterator<Item = &'a str>) {
+        let root = match &self.root {
+            Some(s) => s,
+            None => return,
+        };
+
+        self.dependencies = files
+            .into_iter()
+            .filter_map(|file| Path::new(file).canonicalize().ok())
+            .collect();
+
+        let wanted: HashSet<PathBuf> = self
+            .dependencies
+            .iter()
+            .filter(|path| !path.starts_with(root))
+            .cloned()
+            .collect();
+
+        for path in self.includes.difference(&wanted) {
+            let _ = self.watcher.unwatch(path);
+        }
+
+        for path in wanted.difference(&self.includes) {
+            if let Err(err) = self.watcher.watch(path, RecursiveMode::NonRecursive) {
+                log::warn!("Failed to watch {:?} for changes: {}", path, err);
+            }
+        }
+
+        self.includes = wanted;
+    }
+
+    /// Checks whether the pipeline should be rebuilt.
+    ///
+    /// Changes are collected until no file changed for the debounce time, so
+    /// editors writing several files at once only cause a single rebuild.
+    /// If the config asks for it, changes to files the pipeline doesn't
+    /// depend on are dropped, unless `any_file` is set.
+    pub fn rebuild_due(&mut self, config: &WatchConfig, any_file: bool) -> bool {
+        let mut changes = self.changes.lock().unwrap();
+        if config.only_dependencies && !any_file {
+            let deps = &self.dependencies;
+            changes.retain(|path, _| is_dependency(deps, path));
+        }
+
+        let debounce = Duration::from_millis(config.debounce);
+        match changes.values().max() {
+            Some(last) if last.elapsed() >= debounce => {
+                log::info!("Files changed: {:?}", changes.keys().collect::<Vec<_>>());
+                changes.clear();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Pipeline and config files are always relevant, as they decide which
+/// other files the pipeline depends on.
+fn is_dependency(deps: &HashSet<PathBuf>, path: &Path) -> bool {
+    let is_yaml = path
+        .extension()
+        .map_or(false, |ext| ext == "yaml" || ext == "yml");
+
+    is_yaml || deps.contains(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filters_dependencies() {
+        let mut deps = HashSet::new();
+        deps.insert(PathBuf::from("/project/scene.frag"));
+
+        assert!(is_dependency(&deps, Path::new("/project/scene.frag")));
+        assert!(is_dependency(&deps, Path::new("/project/pipeline.yaml")));
+        assert!(!is_dependency(&deps, Path::new("/project/.scene.frag.swp")));
+        assert!(!is_dependency(&deps, Path::new("/project/scene.frag~")));
+    }
+}
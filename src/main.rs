@@ -2,18 +2,20 @@
 #![warn(missing_debug_implementations)]
 
 #[macro_use]
-mod util;
+extern crate sh4der_jockey_core;
+
 mod jockey;
 
 use std::{
-    path::Path,
+    path::{Path, PathBuf},
     sync::atomic::{AtomicBool, Ordering},
     time::Duration,
 };
 
 use clap::Parser;
-use jockey::Jockey;
+use jockey::{BenchOptions, Jockey, LogTail, RenderOptions, WinitBackend};
 use lazy_static::lazy_static;
+use sh4der_jockey_core::util;
 use simplelog::*;
 
 lazy_static! {
@@ -34,6 +36,24 @@ struct Args {
     #[clap(short, long, action = clap::ArgAction::Count, global = true)]
     #[clap(help = "Use verbose output (can be applied multiple times)")]
     verbose: u8,
+
+    #[clap(long, global = true)]
+    #[clap(help = "Don't open the control panel, the tool is then controlled over MIDI only")]
+    no_ui: bool,
+
+    #[clap(long, global = true, value_enum)]
+    #[clap(help = "Windowing system to use on Linux (defaults to Wayland if available)")]
+    winit_backend: Option<WinitBackend>,
+
+    #[clap(long, global = true)]
+    #[clap(help = "Pipeline profile to use, e.g. \"rehearsal\" or \"show\"")]
+    profile: Option<String>,
+
+    #[clap(long, global = true)]
+    #[clap(
+        help = "Log frame and stage timings to a file (JSON lines if it ends in .json, CSV otherwise)"
+    )]
+    perf_log: Option<PathBuf>,
 }
 
 #[derive(Parser)]
@@ -45,6 +65,96 @@ enum SubCommand {
     #[clap(about = "Start the tool in the current working directory (default)")]
     #[command(alias("r"))]
     Run,
+
+    #[clap(about = "Render the pipeline offline into a video file using ffmpeg")]
+    Render {
+        #[clap(short, long)]
+        #[clap(help = "Path of the output video file")]
+        out: PathBuf,
+
+        #[clap(short, long)]
+        #[clap(help = "Audio file (wav) to react to and mux into the video")]
+        audio: Option<PathBuf>,
+
+        #[clap(short, long)]
+        #[clap(help = "Pipeline file to render (defaults to the first one)")]
+        pipeline: Option<String>,
+
+        #[clap(long, default_value_t = 1920)]
+        #[clap(help = "Width of the video in pixels")]
+        width: u32,
+
+        #[clap(long, default_value_t = 1080)]
+        #[clap(help = "Height of the video in pixels")]
+        height: u32,
+
+        #[clap(long, default_value_t = 60)]
+        #[clap(help = "Frames per second")]
+        fps: u32,
+
+        #[clap(long, default_value_t = 1)]
+        #[clap(help = "Number of subframes averaged into each frame for motion blur")]
+        subframes: u32,
+
+        #[clap(short, long)]
+        #[clap(help = "Length of the video in seconds (defaults to the audio length)")]
+        duration: Option<f32>,
+    },
+
+    #[clap(about = "Render the pipeline offscreen and print the timings of every stage")]
+    Bench {
+        #[clap(short, long)]
+        #[clap(help = "Pipeline file to benchmark (defaults to the first one)")]
+        pipeline: Option<String>,
+
+        #[clap(long, default_value_t = 1920)]
+        #[clap(help = "Width of the render target in pixels")]
+        width: u32,
+
+        #[clap(long, default_value_t = 1080)]
+        #[clap(help = "Height of the render target in pixels")]
+        height: u32,
+
+        #[clap(short, long, default_value_t = 600)]
+        #[clap(help = "Number of frames to measure")]
+        frames: u32,
+
+        #[clap(long, default_value_t = 60)]
+        #[clap(help = "Number of frames to render before measuring")]
+        warmup: u32,
+    },
+
+    #[clap(about = "Export a shader with all includes inlined as a single file")]
+    Export {
+        #[clap(help = "Path of the shader to export")]
+        shader: String,
+
+        #[clap(short, long)]
+        #[clap(help = "Path of the output file (defaults to stdout)")]
+        out: Option<PathBuf>,
+
+        #[clap(short, long)]
+        #[clap(help = "Shorten identifiers and remove all unnecessary whitespace")]
+        minify: bool,
+    },
+
+    #[clap(about = "Check graphics, audio, MIDI and NDI support and write a report")]
+    Doctor {
+        #[clap(short, long, default_value = "sh4der-jockey-doctor.txt")]
+        #[clap(help = "Path of the report")]
+        out: PathBuf,
+    },
+
+    #[clap(about = "Pack a pipeline and all files it uses into a zip archive")]
+    Bundle {
+        #[clap(short, long)]
+        #[clap(help = "Pipeline file to pack (defaults to the first one)")]
+        pipeline: Option<String>,
+
+        #[clap(short, long)]
+        #[clap(help = "Path of the archive (defaults to the name of the pipeline)")]
+        out: Option<PathBuf>,
+    },
 }
 
 fn main() {
@@ -60,15 +170,22 @@ fn main() {
 
     let mut config = ConfigBuilder::new();
     let log_utc = config.set_time_offset_to_local().is_err();
-
-    TermLogger::init(
-        log_level,
-        config.build(),
-        TerminalMode::Mixed,
-        ColorChoice::Always,
-    )
+    let clock_utc = !jockey::init_local_offset();
+
+    let config = config.build();
+    CombinedLogger::init(vec![
+        TermLogger::new(
+            log_level,
+            config.clone(),
+            TerminalMode::Mixed,
+            ColorChoice::Always,
+        ),
+        WriteLogger::new(LevelFilter::Info, config, LogTail),
+    ])
     .unwrap();
 
+    jockey::install_panic_hook();
+
     log::info!("Version: {}", VERSION.as_str());
     log::info!("Log level: {}", log_level);
 
@@ -76,6 +193,10 @@ fn main() {
         log::warn!("Failed to resolve local time, logging in UTC");
     }
 
+    if clock_utc {
+        log::warn!("Failed to resolve local time, the show clock shows UTC");
+    }
+
     if let Some(SubCommand::Init) = args.subcmd {
         let plf = Path::new("./pipeline.yaml");
         let shf = Path::new("./scene.frag");
@@ -100,6 +221,69 @@ fn main() {
         return;
     }
 
+    if let Some(SubCommand::Export {
+        shader,
+        out,
+        minify,
+    }) = &args.subcmd
+    {
+        let code = match util::export_shader(shader, *minify) {
+            Ok(code) => code,
+            Err(err) => {
+                log::error!("{}", err);
+                return;
+            }
+        };
+
+        match out {
+            Some(path) => {
+                if let Err(err) = std::fs::write(path, code) {
+                    log::error!("{}", err);
+                }
+            }
+            None => print!("{}", code),
+        }
+
+        return;
+    }
+
+    if let Some(SubCommand::Bundle { pipeline, out }) = &args.subcmd {
+        let pipeline = match pipeline
+            .clone()
+            .or_else(|| jockey::find_pipeline_files().into_iter().next())
+        {
+            Some(s) => s,
+            None => {
+                log::error!("No pipeline file found in the current working directory");
+                return;
+            }
+        };
+
+        let out = match out {
+            Some(path) => path.clone(),
+            None => Path::new(&pipeline).with_extension("zip"),
+        };
+
+        match jockey::write_project_bundle(&pipeline, &out) {
+            Ok(()) => log::info!("Project has been bundled into {}", out.to_string_lossy()),
+            Err(err) => log::error!("{:#}", err),
+        }
+
+        return;
+    }
+
+    if let Some(SubCommand::Doctor { out }) = &args.subcmd {
+        let report = jockey::run_doctor(args.winit_backend);
+        print!("{}", report);
+
+        match std::fs::write(out, &report) {
+            Ok(()) => println!("Report written to {}", out.to_string_lossy()),
+            Err(err) => log::error!("Failed to write report: {}", err),
+        }
+
+        return;
+    }
+
     // set termination signal handler
     let kill_signal: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
     ctrlc::set_handler(move || {
@@ -115,7 +299,63 @@ fn main() {
     .unwrap();
 
     // create the jockey
-    let mut jockey = Jockey::init();
+    let mut jockey = Jockey::init(!args.no_ui, args.winit_backend, args.profile);
+    if let Some(path) = args.perf_log {
+        jockey.start_perf_log(path);
+    }
+
+    if let Some(SubCommand::Render {
+        out,
+        audio,
+        pipeline,
+        width,
+        height,
+        fps,
+        subframes,
+        duration,
+    }) = args.subcmd
+    {
+        let opts = RenderOptions {
+            out,
+            audio,
+            pipeline,
+            width,
+            height,
+            fps,
+            subframes,
+            duration,
+        };
+
+        if let Err(err) = jockey.render_offline(&opts) {
+            log::error!("{:#}", err);
+        }
+
+        return;
+    }
+
+    if let Some(SubCommand::Bench {
+        pipeline,
+        width,
+        height,
+        frames,
+        warmup,
+    }) = args.subcmd
+    {
+        let opts = BenchOptions {
+            pipeline,
+            width,
+            height,
+            frames,
+            warmup,
+        };
+
+        match jockey.run_bench(&opts) {
+            Ok(report) => print!("{}", report),
+            Err(err) => log::error!("{:#}", err),
+        }
+
+        return;
+    }
 
     // close console window
     #[cfg(all(windows, not(debug_assertions)))]
@@ -0,0 +1,10 @@
+#version 140
+
+out vec4 out_color;
+
+uniform vec4 resolution;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution.xy;
+    out_color = vec4(uv, 0.25, 1);
+}